@@ -20,7 +20,11 @@ pub enum Ai {
 pub enum Behavior {
     Idle,
     Investigating(Pos),
-    Attacking(EntityId),
+    // the target, and the position the target was last seen at
+    Attacking(EntityId, Pos),
+    Fleeing(EntityId),
+    // the index of the next waypoint in the entity's patrol route
+    Patrol(usize),
 }
 
 impl fmt::Display for Behavior {
@@ -28,7 +32,9 @@ impl fmt::Display for Behavior {
         match self {
             Behavior::Idle => write!(f, "idle"),
             Behavior::Investigating(pos) => write!(f, "investigating {} {}", pos.x, pos.y),
-            Behavior::Attacking(entity_id) => write!(f, "attacking {}", entity_id),
+            Behavior::Attacking(entity_id, pos) => write!(f, "attacking {} {} {}", entity_id, pos.x, pos.y),
+            Behavior::Fleeing(entity_id) => write!(f, "fleeing {}", entity_id),
+            Behavior::Patrol(index) => write!(f, "patrolling {}", index),
         }
     }
 }
@@ -40,24 +46,55 @@ impl Default for Behavior {
 }
 
 impl Behavior {
+    /// A short player-facing description, used in the info panel.
     pub fn description(&self) -> String {
         match self {
             Behavior::Idle => "idle".to_string(),
             Behavior::Investigating(_position) => "investigating".to_string(),
-            Behavior::Attacking(_obj_id) => "attacking".to_string(),
+            Behavior::Attacking(_obj_id, _last_seen) => "attacking target".to_string(),
+            Behavior::Fleeing(_obj_id) => "fleeing".to_string(),
+            Behavior::Patrol(_index) => "patrolling".to_string(),
+        }
+    }
+
+    /// A single character code for each behavior, used in debug overlays.
+    pub fn short_code(&self) -> char {
+        match self {
+            Behavior::Idle => 'I',
+            Behavior::Investigating(_position) => 'V',
+            Behavior::Attacking(_obj_id, _last_seen) => 'A',
+            Behavior::Fleeing(_obj_id) => 'F',
+            Behavior::Patrol(_index) => 'P',
         }
     }
 
     pub fn is_aware(&self) -> bool {
-        return matches!(self, Behavior::Attacking(_));
+        return matches!(self, Behavior::Attacking(_, _));
     }
 }
 
+#[test]
+fn test_behavior_description() {
+    assert_eq!("idle", Behavior::Idle.description());
+    assert_eq!("investigating", Behavior::Investigating(Pos::new(1, 2)).description());
+    assert_eq!("attacking target", Behavior::Attacking(EntityId(3), Pos::new(1, 2)).description());
+    assert_eq!("fleeing", Behavior::Fleeing(EntityId(3)).description());
+    assert_eq!("patrolling", Behavior::Patrol(1).description());
+
+    assert_eq!('I', Behavior::Idle.short_code());
+    assert_eq!('V', Behavior::Investigating(Pos::new(1, 2)).short_code());
+    assert_eq!('A', Behavior::Attacking(EntityId(3), Pos::new(1, 2)).short_code());
+    assert_eq!('F', Behavior::Fleeing(EntityId(3)).short_code());
+    assert_eq!('P', Behavior::Patrol(1).short_code());
+}
+
+/// Take a turn for a monster. Dead monsters, and those that cannot act due to
+/// an effect such as being frozen, skip their turn.
 pub fn ai_take_turn(monster_id: EntityId,
                     data: &mut GameData,
                     config: &Config,
                     msg_log: &mut MsgLog) {
-    if data.entities.status[&monster_id].alive {
+    if data.entities.status[&monster_id].alive && data.entities.can_act(monster_id) {
         match data.entities.ai.get(&monster_id) {
             Some(Ai::Basic) => {
                 basic_ai_take_turn(monster_id, data, msg_log, config);
@@ -77,20 +114,31 @@ pub fn basic_ai_take_turn(monster_id: EntityId,
     let monster_pos = data.entities.pos[&monster_id];
 
     if data.map.is_within_bounds(monster_pos) {
-        if data.entities.status[&monster_id].frozen == 0 {
-            match data.entities.behavior[&monster_id] {
-                Behavior::Idle => {
-                    ai_idle(monster_id, data, msg_log, config);
-                }
+        match data.entities.behavior[&monster_id] {
+            Behavior::Idle => {
+                ai_idle(monster_id, data, msg_log, config);
+            }
 
-                Behavior::Investigating(target_pos) => {
-                    ai_investigate(target_pos, monster_id, data, msg_log, config);
-                }
+            Behavior::Investigating(target_pos) => {
+                ai_investigate(target_pos, monster_id, data, msg_log, config);
+            }
 
-                Behavior::Attacking(object_id) => {
+            Behavior::Attacking(object_id, _last_seen) => {
+                if ai_should_flee(monster_id, data, config) {
+                    // the state change continues the turn, now fleeing
+                    msg_log.log(Msg::StateChange(monster_id, Behavior::Fleeing(object_id)));
+                } else {
                     ai_attack(monster_id, object_id, data, msg_log, config);
                 }
             }
+
+            Behavior::Fleeing(object_id) => {
+                ai_flee(monster_id, object_id, data, msg_log, config);
+            }
+
+            Behavior::Patrol(waypoint_index) => {
+                ai_patrol(waypoint_index, monster_id, data, msg_log, config);
+            }
         }
     }
 }
@@ -108,24 +156,93 @@ pub fn ai_attack(monster_id: EntityId,
     msg_log.log(Msg::AiAttack(monster_id));
 }
 
-pub fn ai_idle(monster_id: EntityId,
+/// A monster flees once its health drops below a fraction of its maximum.
+pub fn ai_should_flee(monster_id: EntityId, data: &GameData, config: &Config) -> bool {
+    if let Some(fighter) = data.entities.fighter.get(&monster_id) {
+        let health_fraction = fighter.hp as f32 / fighter.max_hp as f32;
+        return fighter.hp > 0 && health_fraction < config.flee_hp_fraction;
+    }
+
+    return false;
+}
+
+/// Pick the position to flee to from a threat. Positions out of the threat's
+/// FOV are preferred, and otherwise the position furthest from the threat.
+pub fn ai_flee_pos(monster_id: EntityId,
+                   threat_id: EntityId,
+                   data: &GameData,
+                   config: &Config) -> Option<Pos> {
+    let monster_pos = data.entities.pos[&monster_id];
+    let threat_pos = data.entities.pos[&threat_id];
+
+    let current_dist = distance_squared(monster_pos, threat_pos);
+
+    let mut best: Option<((bool, i32), Pos)> = None;
+    for pos in data.map.reachable_neighbors(monster_pos) {
+        if !data.map.is_within_bounds(pos) || data.has_blocking_entity(pos).is_some() {
+            continue;
+        }
+
+        let dist = distance_squared(pos, threat_pos);
+        if dist <= current_dist {
+            continue;
+        }
+
+        let in_cover = !data.pos_in_fov(threat_id, pos, config);
+
+        let score = (in_cover, dist);
+        if best.map_or(true, |(best_score, _)| score > best_score) {
+            best = Some((score, pos));
+        }
+    }
+
+    return best.map(|(_score, pos)| pos);
+}
+
+pub fn ai_flee(monster_id: EntityId,
+               threat_id: EntityId,
                data: &mut GameData,
                msg_log: &mut MsgLog,
                config: &Config) {
-    let player_id = data.find_by_name(EntityName::Player).unwrap();
+    let monster_pos = data.entities.pos[&monster_id];
 
+    if let Some(flee_pos) = ai_flee_pos(monster_id, threat_id, data, config) {
+        if let Some(direction) = Direction::from_positions(monster_pos, flee_pos) {
+            msg_log.log(Msg::TryMove(monster_id, direction, 1, MoveMode::Walk));
+        }
+    } else {
+        // cornered, so there is nowhere to go
+        data.entities.took_turn[&monster_id] = true;
+    }
+}
+
+pub fn ai_idle(monster_id: EntityId,
+               data: &mut GameData,
+               msg_log: &mut MsgLog,
+               config: &Config) {
     if config.sound_golem_idle_radius > 0 {
         let should_animate = true;
         let monster_pos = data.entities.pos[&monster_id];
         msg_log.log(Msg::Sound(monster_id, monster_pos, config.sound_golem_idle_radius, should_animate));
     }
 
+    ai_notice(monster_id, data, msg_log, config);
+}
+
+/// React to seeing the player, being attacked, or hearing the player, changing
+/// to attacking or investigating. Returns whether the monster noticed anything.
+pub fn ai_notice(monster_id: EntityId,
+                 data: &mut GameData,
+                 msg_log: &mut MsgLog,
+                 config: &Config) -> bool {
+    let player_id = data.find_by_name(EntityName::Player).unwrap();
+
     if ai_is_in_fov(monster_id, player_id, data, config) {
         let player_pos = data.entities.pos[&player_id];
         msg_log.log(Msg::FaceTowards(monster_id, player_pos));
 
         if data.entities.attack.get(&monster_id).is_some() {
-            msg_log.log(Msg::StateChange(monster_id, Behavior::Attacking(player_id)));
+            msg_log.log(Msg::StateChange(monster_id, Behavior::Attacking(player_id, player_pos)));
         } else {
             msg_log.log(Msg::StateChange(monster_id, Behavior::Investigating(player_pos)));
         }
@@ -134,7 +251,7 @@ pub fn ai_idle(monster_id: EntityId,
         msg_log.log(Msg::FaceTowards(monster_id, entity_pos));
 
         if data.entities.attack.get(&monster_id).is_some() {
-            msg_log.log(Msg::StateChange(monster_id, Behavior::Attacking(entity_id)));
+            msg_log.log(Msg::StateChange(monster_id, Behavior::Attacking(entity_id, entity_pos)));
         } else {
             msg_log.log(Msg::StateChange(monster_id, Behavior::Investigating(entity_pos)));
         }
@@ -146,7 +263,42 @@ pub fn ai_idle(monster_id: EntityId,
         if needs_investigation {
             msg_log.log(Msg::FaceTowards(monster_id, sound_pos));
             msg_log.log(Msg::StateChange(monster_id, Behavior::Investigating(sound_pos)));
+        } else {
+            return false;
         }
+    } else {
+        return false;
+    }
+
+    return true;
+}
+
+/// Walk a patrol route, heading to each waypoint in turn and looping back to
+/// the first after the last. The monster pauses for a turn at each waypoint.
+pub fn ai_patrol(waypoint_index: usize,
+                 monster_id: EntityId,
+                 data: &mut GameData,
+                 msg_log: &mut MsgLog,
+                 config: &Config) {
+    if ai_notice(monster_id, data, msg_log, config) {
+        return;
+    }
+
+    let waypoints = data.entities.patrol.get(&monster_id).cloned().unwrap_or(Vec::new());
+    if waypoints.len() == 0 {
+        msg_log.log(Msg::StateChange(monster_id, Behavior::Idle));
+        return;
+    }
+
+    let monster_pos = data.entities.pos[&monster_id];
+    let waypoint_index = waypoint_index % waypoints.len();
+
+    if waypoints[waypoint_index] == monster_pos {
+        let next_index = (waypoint_index + 1) % waypoints.len();
+        data.entities.took_turn[&monster_id] = true;
+        msg_log.log(Msg::StateChange(monster_id, Behavior::Patrol(next_index)));
+    } else {
+        ai_move_towards_target(waypoints[waypoint_index], monster_id, data, msg_log);
     }
 }
 
@@ -167,7 +319,7 @@ pub fn ai_investigate(target_pos: Pos,
         msg_log.log(Msg::FaceTowards(monster_id, player_pos));
 
         if data.entities.attack.get(&monster_id).is_some() {
-            msg_log.log(Msg::StateChange(monster_id, Behavior::Attacking(player_id)));
+            msg_log.log(Msg::StateChange(monster_id, Behavior::Attacking(player_id, player_pos)));
         } else {
             // if the monster cannot attack, just keep walking towards the target.
             ai_move_towards_target(player_pos, monster_id, data, msg_log);
@@ -411,6 +563,11 @@ pub fn ai_move_to_attack_pos(monster_id: EntityId,
 
 // NOTE perhaps this should be merged into is_in_fov?
 pub fn ai_is_in_fov(monster_id: EntityId, target_id: EntityId, data: &mut GameData, config: &Config) -> bool {
+    // cloaked entities are hidden from monsters, even when in plain sight
+    if data.is_cloaked(target_id) {
+        return false;
+    }
+
     let monster_pos = data.entities.pos[&monster_id];
     let target_pos = data.entities.pos[&target_id];
 
@@ -1,16 +1,47 @@
-use std::fs::File;
+use std::fs::{self, File};
 use std::io::Read;
+use std::collections::HashSet;
+use std::thread;
+use std::time::Duration;
+use std::sync::mpsc::{channel, Receiver};
 
 use crate::types::*;
 use crate::map::*;
+use crate::movement::MoveMode;
 
 
 use serde_derive::*;
 use serde_yaml;
 
+use log::warn;
+
+
+/// A preset for the values which most affect how hard the game is.
+/// Fields given explicitly in the config file take precedence over the preset.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Difficulty {
+    Easy,
+    Normal,
+    Hard,
+}
+
+impl Default for Difficulty {
+    fn default() -> Difficulty {
+        return Difficulty::Normal;
+    }
+}
+
+/// An item the player starts the game holding, with the number to give.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StartingItem {
+    pub item: Item,
+    pub count: usize,
+}
 
 #[derive(Clone, Debug, Serialize, Deserialize, Default)]
 pub struct Config {
+    #[serde(default)]
+    pub difficulty: Difficulty,
     pub color_dark_brown: Color,
     pub color_medium_brown: Color,
     pub color_light_green: Color,
@@ -35,33 +66,52 @@ pub struct Config {
     pub color_shadow: Color,
     pub load_map_file_every_frame: bool,
     pub tile_noise_scaler: f64,
+    pub tile_noise_octaves: usize,
+    pub tile_noise_seed: u64,
     pub highlight_player_move: u8,
     pub highlight_alpha_attack: u8,
     pub sound_alpha: u8,
+    pub cloak_alpha: u8,
     pub grid_alpha: u8,
     pub grid_alpha_visible: u8,
     pub grid_alpha_overlay: u8,
     pub map_load: MapLoadConfig,
     pub map_file: String,
+    pub map_legend: Vec<MapLegendEntry>,
     pub idle_speed: f32,
     pub draw_mouse_line: bool,
     pub draw_star_path: bool,
+    pub draw_fps: bool,
     pub frame_rate: usize,
+    pub screen_width: u32,
+    pub screen_height: u32,
     pub item_throw_speed: f32,
+    pub bump_speed: f32,
     pub key_speed: f32,
     pub player_attack_speed: f32,
     pub player_attack_hammer_speed: f32,
     pub player_vault_sprite_speed: f32,
     pub player_vault_move_speed: f32,
     pub sound_timeout: f32,
+    pub sound_cooldown_ms: u64,
     pub yell_radius: usize,
     pub swap_radius: usize,
+    pub run_energy_cost: u32,
+    pub energy_regen_max: u32,
     pub fog_of_war: bool,
+    pub wrap_edges: bool,
+    #[serde(default)]
+    pub starting_inventory: Vec<StartingItem>,
+    #[serde(default)]
     pub player_health: i32,
+    #[serde(default)]
     pub explored_alpha: u8,
     pub fov_edge_alpha: u8,
+    pub fov_fade_alpha: u8,
     pub sound_rubble_radius: usize,
     pub sound_golem_idle_radius: usize,
+    pub flee_hp_fraction: f32,
+    pub cover_deflect_chance: f32,
     pub sound_grass_radius: usize,
     pub sound_radius_crushed: usize,
     pub sound_radius_attack: usize,
@@ -77,10 +127,25 @@ pub struct Config {
     pub overlay_fov_alg: bool,
     pub overlay_floodfill: bool,
     pub fov_radius_monster: i32,
+    pub monster_spacing: i32,
+    pub gol_count: Option<(usize, usize)>,
+    pub elf_count: Option<(usize, usize)>,
+    pub grass_clusters: Option<(usize, usize)>,
+    pub rubble_amount: Option<usize>,
+    #[serde(default)]
+    pub monster_count_scale: f32,
+    #[serde(default)]
     pub fov_radius_player: i32,
+    pub fov_radius_sneak: Option<i32>,
+    pub fov_radius_walk: Option<i32>,
+    pub fov_radius_run: Option<i32>,
+    pub monster_directional_fov: bool,
+    pub fov_corner_peek: FovCornerPeek,
     pub sound_radius_sneak: usize,
     pub sound_radius_walk: usize,
     pub sound_radius_run: usize,
+    pub momentum_max: i32,
+    pub momentum_accel: i32,
     pub dampen_blocked_tile: i32,
     pub dampen_short_wall: i32,
     pub dampen_tall_wall: i32,
@@ -89,6 +154,7 @@ pub struct Config {
     pub use_cursor: bool,
     pub cursor_long: i32,
     pub repeat_delay: f32,
+    pub move_repeat_interval: f32,
     pub write_map_distribution: bool,
     pub print_key_log: bool,
     pub idle_animations: bool,
@@ -96,19 +162,382 @@ pub struct Config {
     pub fire_speed: f32,
     pub beam_duration: usize,
     pub draw_directional_arrow: bool,
+    pub debug_render: bool,
+    pub debug_rng: bool,
 }
 
 impl Config {
     pub fn from_file(file_name: &str) -> Config {
+        return Config::try_from_file(file_name).unwrap_or_else(|err| panic!("{}", err));
+    }
+
+    pub fn try_from_file(file_name: &str) -> Result<Config, String> {
         let mut file =
-            File::open(file_name).expect(&format!("Could not open/parse config file {}", file_name));
+            File::open(file_name).map_err(|err| format!("Could not open config file {}: {}", file_name, err))?;
         let mut config_string = String::new();
         file.read_to_string(&mut config_string)
-            .expect(&format!("Could not read contents of {}", file_name));
+            .map_err(|err| format!("Could not read contents of {}: {}", file_name, err))?;
+
+        let config = Config::from_yaml_str(&config_string).map_err(|err| format!("Could not parse {} file: {}", file_name, err))?;
+
+        for warning in config.validate_colors() {
+            warn!("{}: {}", file_name, warning);
+        }
+
+        return Ok(config);
+    }
+
+    /// Parse a config from a YAML string, filling in any difficulty
+    /// controlled fields that the string does not set.
+    pub fn from_yaml_str(config_string: &str) -> Result<Config, String> {
+        let value: serde_yaml::Value =
+            serde_yaml::from_str(config_string).map_err(|err| format!("{}", err))?;
+
+        let mut explicit_fields = HashSet::new();
+        if let Some(mapping) = value.as_mapping() {
+            for (key, _) in mapping.iter() {
+                if let Some(key) = key.as_str() {
+                    explicit_fields.insert(key.to_string());
+                }
+            }
+        }
+
+        let mut config: Config =
+            serde_yaml::from_value(value).map_err(|err| format!("{}", err))?;
+        config.apply_difficulty(&explicit_fields);
+
+        return Ok(config);
+    }
+
+    /// Set the fields controlled by the difficulty preset, skipping any
+    /// field named in explicit_fields.
+    pub fn apply_difficulty(&mut self, explicit_fields: &HashSet<String>) {
+        let (player_health, fov_radius_player, explored_alpha, monster_count_scale) =
+            match self.difficulty {
+                Difficulty::Easy => (5, 5, 120, 0.5),
+                Difficulty::Normal => (3, 4, 160, 1.0),
+                Difficulty::Hard => (2, 3, 200, 1.5),
+            };
+
+        let is_set = |name: &str| explicit_fields.contains(name);
+
+        if !is_set("player_health") {
+            self.player_health = player_health;
+        }
+
+        if !is_set("fov_radius_player") {
+            self.fov_radius_player = fov_radius_player;
+        }
+
+        if !is_set("explored_alpha") {
+            self.explored_alpha = explored_alpha;
+        }
 
-        let config = serde_yaml::from_str(&config_string).expect(&format!("Could not parse {} file!", file_name));
+        if !is_set("monster_count_scale") {
+            self.monster_count_scale = monster_count_scale;
+        }
+    }
+
+    /// All named colors in the palette, by their field name.
+    pub fn colors(&self) -> Vec<(&'static str, Color)> {
+        let mut colors = Vec::new();
+
+        colors.push(("color_dark_brown", self.color_dark_brown));
+        colors.push(("color_medium_brown", self.color_medium_brown));
+        colors.push(("color_light_green", self.color_light_green));
+        colors.push(("color_tile_blue_light", self.color_tile_blue_light));
+        colors.push(("color_tile_blue_dark", self.color_tile_blue_dark));
+        colors.push(("color_light_brown", self.color_light_brown));
+        colors.push(("color_ice_blue", self.color_ice_blue));
+        colors.push(("color_dark_blue", self.color_dark_blue));
+        colors.push(("color_very_dark_blue", self.color_very_dark_blue));
+        colors.push(("color_orange", self.color_orange));
+        colors.push(("color_red", self.color_red));
+        colors.push(("color_medium_grey", self.color_medium_grey));
+        colors.push(("color_mint_green", self.color_mint_green));
+        colors.push(("color_blueish_grey", self.color_blueish_grey));
+        colors.push(("color_pink", self.color_pink));
+        colors.push(("color_rose_red", self.color_rose_red));
+        colors.push(("color_light_orange", self.color_light_orange));
+        colors.push(("color_bone_white", self.color_bone_white));
+        colors.push(("color_warm_grey", self.color_warm_grey));
+        colors.push(("color_soft_green", self.color_soft_green));
+        colors.push(("color_light_grey", self.color_light_grey));
+        colors.push(("color_shadow", self.color_shadow));
+
+        return colors;
+    }
+
+    /// Look up a palette color by name, with or without the 'color_' prefix.
+    pub fn color(&self, name: &str) -> Option<Color> {
+        let name = name.trim_start_matches("color_");
+
+        for (color_name, color) in self.colors() {
+            if color_name.trim_start_matches("color_") == name {
+                return Some(color);
+            }
+        }
+
+        return None;
+    }
+
+    /// Check the palette for colors which are likely mistakes, such as
+    /// fully transparent colors which would never be visible.
+    pub fn validate_colors(&self) -> Vec<String> {
+        let mut warnings = Vec::new();
+
+        for (color_name, color) in self.colors() {
+            if color.a == 0 {
+                warnings.push(format!("{} has an alpha of 0 and will not be visible", color_name));
+            }
+        }
+
+        return warnings;
+    }
+
+    /// The player's FOV radius for a given movement mode, falling back
+    /// to fov_radius_player when the mode does not have its own radius.
+    pub fn fov_radius(&self, move_mode: MoveMode) -> i32 {
+        let radius =
+            match move_mode {
+                MoveMode::Sneak => self.fov_radius_sneak,
+                MoveMode::Walk => self.fov_radius_walk,
+                MoveMode::Run => self.fov_radius_run,
+            };
+
+        return radius.unwrap_or(self.fov_radius_player);
+    }
+
+    /// Which groups of fields differ between this config and an older one,
+    /// so a reload only has to update what depends on the changed fields.
+    pub fn diff(&self, old: &Config) -> ConfigChanges {
+        let mut changes = ConfigChanges::default();
+
+        match (serde_yaml::to_value(self), serde_yaml::to_value(old)) {
+            (Ok(serde_yaml::Value::Mapping(new_fields)), Ok(serde_yaml::Value::Mapping(old_fields))) => {
+                for (key, value) in new_fields.iter() {
+                    if old_fields.get(key) != Some(value) {
+                        changes.field_changed(key.as_str().unwrap_or(""));
+                    }
+                }
+            }
 
-        return config
+            _ => {
+                changes.other = true;
+            }
+        }
+
+        return changes;
     }
 }
 
+/// The groups of config fields changed by a reload, from Config::diff.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub struct ConfigChanges {
+    pub colors: bool,
+    pub alphas: bool,
+    pub tile_noise: bool,
+    // the screen size and frame rate are only used at startup
+    pub screen: bool,
+    pub other: bool,
+}
+
+impl ConfigChanges {
+    pub fn field_changed(&mut self, field_name: &str) {
+        if field_name.starts_with("color_") {
+            self.colors = true;
+        } else if field_name.contains("alpha") {
+            self.alphas = true;
+        } else if field_name.starts_with("tile_noise") {
+            self.tile_noise = true;
+        } else if field_name == "screen_width" || field_name == "screen_height" || field_name == "frame_rate" {
+            self.screen = true;
+        } else {
+            self.other = true;
+        }
+    }
+
+    pub fn any(&self) -> bool {
+        return self.colors || self.alphas || self.tile_noise || self.screen || self.other;
+    }
+
+    /// Whether the map's static layer, which is only drawn when dirty, needs
+    /// to be drawn again to show the new config.
+    pub fn redraw_background(&self) -> bool {
+        return self.colors || self.alphas || self.tile_noise;
+    }
+}
+
+/// Reloads the config file on a background thread whenever it changes, so the
+/// game loop never blocks on file IO. Files that fail to parse are skipped,
+/// leaving the last good config in place.
+pub struct ConfigWatcher {
+    receiver: Receiver<Config>,
+}
+
+impl ConfigWatcher {
+    pub fn spawn(file_name: &str, poll_interval: Duration) -> ConfigWatcher {
+        let (sender, receiver) = channel();
+        let file_name = file_name.to_string();
+
+        thread::spawn(move || {
+            let modified = |file_name: &str| fs::metadata(file_name).and_then(|metadata| metadata.modified()).ok();
+            let mut modified_time = modified(&file_name);
+
+            loop {
+                thread::sleep(poll_interval);
+
+                let current_modified_time = modified(&file_name);
+                if current_modified_time.is_none() || current_modified_time == modified_time {
+                    continue;
+                }
+                modified_time = current_modified_time;
+
+                match Config::try_from_file(&file_name) {
+                    Ok(config) => {
+                        // the game has exited if no one is listening
+                        if sender.send(config).is_err() {
+                            return;
+                        }
+                    }
+
+                    Err(err) => {
+                        warn!("{}", err);
+                    }
+                }
+            }
+        });
+
+        return ConfigWatcher::from_receiver(receiver);
+    }
+
+    pub fn from_receiver(receiver: Receiver<Config>) -> ConfigWatcher {
+        return ConfigWatcher { receiver };
+    }
+
+    /// The most recently loaded config, if any were loaded since the last call.
+    /// This never blocks.
+    pub fn latest(&self) -> Option<Config> {
+        return self.receiver.try_iter().last();
+    }
+}
+
+#[test]
+fn test_fov_radius_move_mode() {
+    let mut config = Config::from_file("../config.yaml");
+
+    config.fov_radius_player = 4;
+    config.fov_radius_sneak = Some(3);
+    config.fov_radius_walk = Some(4);
+    config.fov_radius_run = Some(6);
+    assert_eq!(3, config.fov_radius(MoveMode::Sneak));
+    assert_eq!(4, config.fov_radius(MoveMode::Walk));
+    assert_eq!(6, config.fov_radius(MoveMode::Run));
+
+    config.fov_radius_run = None;
+    assert_eq!(4, config.fov_radius(MoveMode::Run));
+}
+
+#[test]
+fn test_config_color_lookup() {
+    let config = Config::from_file("../config.yaml");
+
+    assert_eq!(Some(config.color_red), config.color("red"));
+    assert_eq!(Some(config.color_red), config.color("color_red"));
+    assert_eq!(Some(config.color_light_grey), config.color("light_grey"));
+    assert_eq!(Some(config.color_shadow), config.color("shadow"));
+    assert_eq!(None, config.color("not_a_color"));
+
+    assert!(config.validate_colors().is_empty());
+
+    let mut config = config;
+    config.color_pink.a = 0;
+    assert_eq!(1, config.validate_colors().len());
+}
+
+#[test]
+fn test_config_watcher_latest() {
+    let (sender, receiver) = channel();
+    let watcher = ConfigWatcher::from_receiver(receiver);
+    assert!(watcher.latest().is_none());
+
+    // only the newest of several pending configs is applied
+    let mut config = Config::from_file("../config.yaml");
+    config.frame_rate = 10;
+    sender.send(config.clone()).unwrap();
+    config.frame_rate = 20;
+    sender.send(config.clone()).unwrap();
+
+    assert_eq!(20, watcher.latest().unwrap().frame_rate);
+    assert!(watcher.latest().is_none());
+
+    // a file that does not parse is an error rather than a panic
+    let bad_file = std::env::temp_dir().join("roguelike_bad_config.yaml");
+    fs::write(&bad_file, "frame_rate: [").unwrap();
+    assert!(Config::try_from_file(bad_file.to_str().unwrap()).is_err());
+    let _ = fs::remove_file(&bad_file);
+}
+
+#[test]
+fn test_difficulty_presets() {
+    let config_string = fs::read_to_string("../config.yaml").unwrap();
+
+    let easy = Config::from_yaml_str(&config_string.replace("difficulty: Normal", "difficulty: Easy")).unwrap();
+    assert_eq!(Difficulty::Easy, easy.difficulty);
+    assert_eq!(5, easy.player_health);
+    assert_eq!(5, easy.fov_radius_player);
+    assert_eq!(120, easy.explored_alpha);
+    assert_eq!(0.5, easy.monster_count_scale);
+
+    let normal = Config::from_yaml_str(&config_string).unwrap();
+    assert_eq!(Difficulty::Normal, normal.difficulty);
+    assert_eq!(3, normal.player_health);
+    assert_eq!(4, normal.fov_radius_player);
+    assert_eq!(160, normal.explored_alpha);
+    assert_eq!(1.0, normal.monster_count_scale);
+
+    let hard = Config::from_yaml_str(&config_string.replace("difficulty: Normal", "difficulty: Hard")).unwrap();
+    assert_eq!(Difficulty::Hard, hard.difficulty);
+    assert_eq!(2, hard.player_health);
+    assert_eq!(3, hard.fov_radius_player);
+    assert_eq!(200, hard.explored_alpha);
+    assert_eq!(1.5, hard.monster_count_scale);
+}
+
+#[test]
+fn test_difficulty_explicit_override() {
+    let config_string = fs::read_to_string("../config.yaml").unwrap();
+    let config_string = config_string.replace("difficulty: Normal", "difficulty: Hard");
+    let config_string = format!("{}\nplayer_health: 10\nexplored_alpha: 50\n", config_string);
+
+    let config = Config::from_yaml_str(&config_string).unwrap();
+
+    // explicit fields win over the preset
+    assert_eq!(10, config.player_health);
+    assert_eq!(50, config.explored_alpha);
+
+    // fields which are not given still come from the preset
+    assert_eq!(3, config.fov_radius_player);
+    assert_eq!(1.5, config.monster_count_scale);
+}
+
+#[test]
+fn test_config_diff() {
+    let old = Config::from_file("../config.yaml");
+
+    let mut config = old.clone();
+    assert_eq!(ConfigChanges::default(), config.diff(&old));
+    assert!(!config.diff(&old).any());
+
+    config.color_orange = Color::new(1, 2, 3, 255);
+    let changes = config.diff(&old);
+    assert_eq!(ConfigChanges { colors: true, ..ConfigChanges::default() }, changes);
+    assert!(changes.redraw_background());
+
+    let mut config = old.clone();
+    config.screen_width += 10;
+    config.yell_radius += 1;
+    let changes = config.diff(&old);
+    assert_eq!(ConfigChanges { screen: true, other: true, ..ConfigChanges::default() }, changes);
+    assert!(!changes.redraw_background());
+}
@@ -4,9 +4,6 @@ pub const CONFIG_FILE_NAME: &str = &"config.json";
 pub const FONT_WIDTH: i32 = 16;
 pub const FONT_HEIGHT: i32 = 16;
 
-pub const SCREEN_WIDTH: u32 = 1120;
-pub const SCREEN_HEIGHT: u32 = 800;
-
 pub const INVENTORY_WIDTH: usize = 300;
 pub const INVENTORY_HEIGHT: usize = 500;
 
@@ -16,6 +13,8 @@ pub const BLINK_RADIUS: usize = 4;
 pub const TILE_FILL_METRIC_DIST: usize = 3;
 pub const ILLUMINATE_FOV_RADIUS: i32 = 1000;
 pub const STAB_STUN_TURNS: usize = 3;
+pub const CLOAK_TURNS: usize = 5;
+pub const SCAR_WALL_KNOCKDOWN_CHANCE: f32 = 0.5;
 
 pub const GOLEM_ATTACK_DIAG: u8 = 180;
 pub const GOLEM_ATTACK_HORIZ: u8 = 181;
@@ -40,16 +39,21 @@ pub const ARMIL_MOVE_DISTANCE: usize = 1;
 pub const KEY_ATTACK_DISTANCE: usize = 3;
 pub const KEY_MOVE_DISTANCE: usize = 2;
 
-pub const MAX_MOMENTUM: i32 = 2;
+pub const DIJKSTRA_UNREACHABLE: i32 = std::i32::MAX;
 
 pub const SPIKE_DAMAGE: i32 = 20;
+pub const POISON_DAMAGE: i32 = 1;
 pub const HAMMER_DAMAGE: i32 = 40;
 pub const SWORD_DAMAGE: i32 = 20;
+pub const SPEAR_DAMAGE: i32 = 10;
+pub const SPEAR_REACH: usize = 2;
 
 pub const TRIGGER_WALL_DAMAGE: i32 = 20;
 
 pub const NOT_IN_FOV_COST: usize = 5;
 
+pub const PLACEMENT_TRIES_PER_ENTITY: usize = 50;
+
 /* Skill Settings */
 pub const SKILL_GRASS_SHOES_TURNS: usize = 6;
 pub const SKILL_GRASS_THROW_RADIUS: usize = 4;
@@ -62,6 +66,9 @@ pub const SKILL_SPRINT_AMOUNT: usize = 3;
 /* Item Settings */
 pub const LANTERN_ILLUMINATE_RADIUS: usize = 2;
 pub const ILLUMINATE_AMOUNT: usize = LANTERN_ILLUMINATE_RADIUS;
+pub const TORCH_FUEL: usize = 30;
+pub const TORCH_FOV_RADIUS: i32 = 2;
+pub const NUM_EQUIP_SLOTS: usize = 2;
 
 /* Trap Settings */
 pub const FREEZE_TRAP_NUM_TURNS: usize = 3;
@@ -74,10 +81,8 @@ pub const PLAYER_MAX_ITEMS: usize = 3;
 /* UI */
 pub const BAR_WIDTH: u32 = 20;
 pub const PANEL_HEIGHT: u32 = 7;
-pub const PANEL_Y: u32 = SCREEN_HEIGHT - PANEL_HEIGHT;
 
 pub const MSG_X: u32 = BAR_WIDTH + 2;
-pub const MSG_WIDTH: u32 = SCREEN_WIDTH - BAR_WIDTH - 2;
 pub const MSG_HEIGHT: u32 = PANEL_HEIGHT - 1;
 
 /* Map and View */
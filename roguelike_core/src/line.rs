@@ -137,6 +137,75 @@ pub fn line_between(start: Pos, end: Pos) -> Vec<Pos> {
     return points;
 }
 
+/// Every tile the line from start to end passes through, not including start.
+/// Unlike line, a step never cuts diagonally past a corner. When the line
+/// passes exactly through a corner, both tiles beside the corner are included.
+pub fn supercover_line(start: Pos, end: Pos) -> Vec<Pos> {
+    let mut points = Vec::new();
+
+    let num_x = (end.x - start.x).abs();
+    let num_y = (end.y - start.y).abs();
+    let step_x = (end.x - start.x).signum();
+    let step_y = (end.y - start.y).signum();
+
+    let mut pos = start;
+    let mut index_x = 0;
+    let mut index_y = 0;
+    while index_x < num_x || index_y < num_y {
+        // compare where the line crosses the next vertical and horizontal tile edges
+        let decision = (1 + 2 * index_x) * num_y - (1 + 2 * index_y) * num_x;
+
+        if decision == 0 {
+            points.push(Pos::new(pos.x + step_x, pos.y));
+            points.push(Pos::new(pos.x, pos.y + step_y));
+            pos.x += step_x;
+            pos.y += step_y;
+            index_x += 1;
+            index_y += 1;
+        } else if decision < 0 {
+            pos.x += step_x;
+            index_x += 1;
+        } else {
+            pos.y += step_y;
+            index_y += 1;
+        }
+
+        points.push(pos);
+    }
+
+    return points;
+}
+
+#[test]
+pub fn test_supercover_line() {
+    let start = Pos::new(0, 0);
+    let end = Pos::new(2, 5);
+
+    // the line skips diagonally between tiles
+    let line_points = line(start, end);
+    assert_eq!(5, line_points.len());
+
+    // the supercover line takes a single orthogonal step at a time
+    let supercover = supercover_line(start, end);
+    assert_eq!(7, supercover.len());
+    assert_eq!(end, supercover[supercover.len() - 1]);
+    let mut prev = start;
+    for pos in supercover.iter() {
+        assert_eq!(1, (pos.x - prev.x).abs() + (pos.y - prev.y).abs());
+        prev = *pos;
+    }
+
+    // every tile on the line is covered by the supercover line
+    assert!(line_points.iter().all(|pos| supercover.contains(pos)));
+
+    // an exact diagonal includes the tiles on both sides of each corner
+    let diagonal = supercover_line(start, Pos::new(2, 2));
+    assert_eq!(vec!(Pos::new(1, 0), Pos::new(0, 1), Pos::new(1, 1),
+                    Pos::new(2, 1), Pos::new(1, 2), Pos::new(2, 2)), diagonal);
+
+    assert!(supercover_line(start, start).is_empty());
+}
+
 #[test]
 pub fn test_lines() {
     let dist: i32 = 10; 
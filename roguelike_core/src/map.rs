@@ -1,9 +1,10 @@
 use std::ops::{Index, IndexMut};
-use std::collections::{HashSet, HashMap};
+use std::collections::{HashSet, HashMap, VecDeque};
 use std::iter;
 use std::cell::RefCell;
 use std::str::FromStr;
 use std::fmt;
+use std::convert::TryFrom;
 
 use oorandom::Rand32;
 
@@ -16,7 +17,7 @@ use smallvec::SmallVec;
 
 use itertools::Itertools;
 
-use log::trace;
+use log::{trace, warn};
 
 use euclid::*;
 
@@ -31,7 +32,7 @@ use crate::line::*;
 
 #[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
 pub enum TileType {
-    Empty,
+    Floor,
     ShortWall,
     Wall,
     Water,
@@ -41,7 +42,7 @@ pub enum TileType {
 impl fmt::Display for TileType {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            TileType::Empty => write!(f, "empty"),
+            TileType::Floor => write!(f, "floor"),
             TileType::ShortWall => write!(f, "shortwall"),
             TileType::Wall => write!(f, "wall"),
             TileType::Water => write!(f, "water"),
@@ -56,8 +57,9 @@ impl FromStr for TileType {
     fn from_str(string: &str) -> Result<Self, Self::Err> {
         let s: &mut str = &mut string.to_string();
         s.make_ascii_lowercase();
-        if s == "empty" {
-            return Ok(TileType::Empty);
+        // "empty" is accepted for maps written before floors were named
+        if s == "floor" || s == "empty" {
+            return Ok(TileType::Floor);
         } else if s == "shortwall" {
             return Ok(TileType::ShortWall);
         } else if s == "wall" {
@@ -73,9 +75,13 @@ impl FromStr for TileType {
 }
 
 impl TileType {
+    pub fn is_floor(&self) -> bool {
+        return *self == TileType::Floor;
+    }
+
     pub fn is_wall(&self) -> bool {
         match self {
-            TileType::Empty => false,
+            TileType::Floor => false,
             TileType::ShortWall => true,
             TileType::Wall => true,
             TileType::Water => false,
@@ -143,6 +149,43 @@ impl Blocked {
     }
 }
 
+/// Tile changes which a character in a map file's legend can make.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LegendTile {
+    Wall,
+    Water,
+    Grass,
+    Rubble,
+    ShortLeftWall,
+    ShortBottomWall,
+    TallLeftWall,
+    TallBottomWall,
+}
+
+impl LegendTile {
+    pub fn apply(&self, map: &mut Map, pos: Pos) {
+        match self {
+            LegendTile::Wall => map[pos] = Tile::wall(),
+            LegendTile::Water => map[pos] = Tile::water(),
+            LegendTile::Grass => map[pos].surface = Surface::Grass,
+            LegendTile::Rubble => map[pos].surface = Surface::Rubble,
+            LegendTile::ShortLeftWall => map[pos].left_wall = Wall::ShortWall,
+            LegendTile::ShortBottomWall => map[pos].bottom_wall = Wall::ShortWall,
+            LegendTile::TallLeftWall => map[pos].left_wall = Wall::TallWall,
+            LegendTile::TallBottomWall => map[pos].bottom_wall = Wall::TallWall,
+        }
+    }
+}
+
+/// An entry in the legend for map files, giving the tile and/or entity
+/// that a character places.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct MapLegendEntry {
+    pub chr: char,
+    pub tile: Option<LegendTile>,
+    pub entity: Option<EntityName>,
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub enum MapLoadConfig {
     Random,
@@ -271,8 +314,11 @@ impl BlockedType {
     }
 }
 
+/// A map tile. Every field is a single byte, so this is compact without
+/// needing a packed repr. Tiles are serialized as a single u32 (see Tile::to_bits).
 #[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
-#[repr(C, packed)]
+#[serde(try_from = "u32", into = "u32")]
+#[repr(C)]
 pub struct Tile {
     pub block_move: bool,
     pub block_sight: bool,
@@ -285,12 +331,19 @@ pub struct Tile {
 }
 
 impl Tile {
+    /// A blank tile used to fill a map before anything is placed in it. Code
+    /// that carves out walkable ground should use Tile::floor instead.
     pub fn empty() -> Self {
+        return Tile::floor();
+    }
+
+    /// Open, walkable ground with no walls.
+    pub fn floor() -> Self {
         Tile {
             block_move: false,
             block_sight: false,
             explored: false,
-            tile_type: TileType::Empty,
+            tile_type: TileType::Floor,
             bottom_wall: Wall::Empty,
             left_wall: Wall::Empty,
             chr: ' ' as u8,
@@ -312,13 +365,13 @@ impl Tile {
     }
 
     pub fn grass() -> Self {
-        let mut tile = Tile::empty();
+        let mut tile = Tile::floor();
         tile.surface = Surface::Grass;
         return tile;
     }
 
     pub fn rubble() -> Self {
-        let mut tile = Tile::empty();
+        let mut tile = Tile::floor();
         tile.surface = Surface::Rubble;
         return tile;
     }
@@ -369,6 +422,16 @@ impl Tile {
         }
     }
 
+    /// Whether this is walkable floor, not blocked by anything placed on it.
+    pub fn is_floor(&self) -> bool {
+        return self.tile_type.is_floor() && !self.block_move;
+    }
+
+    /// Whether this is a wall tile, either tall or short.
+    pub fn is_wall(&self) -> bool {
+        return self.tile_type.is_wall();
+    }
+
     pub fn does_tile_block(self, block_type: BlockedType) -> bool {
         match block_type {
             BlockedType::Fov => {
@@ -394,6 +457,278 @@ impl Tile {
             self.left_wall = Wall::ShortWall;
         }
     }
+
+    /// Pack a tile into a u32.
+    /// Bits 0-2 are the block_move, block_sight, and explored flags,
+    /// bits 3-5 the tile type, bits 6-7 the bottom wall, bits 8-9 the left wall,
+    /// bits 10-11 the surface, and bits 16-23 the character.
+    pub fn to_bits(&self) -> u32 {
+        let tile_type = match self.tile_type {
+            TileType::Floor => 0,
+            TileType::ShortWall => 1,
+            TileType::Wall => 2,
+            TileType::Water => 3,
+            TileType::Exit => 4,
+        };
+
+        let surface = match self.surface {
+            Surface::Floor => 0,
+            Surface::Rubble => 1,
+            Surface::Grass => 2,
+        };
+
+        let mut bits: u32 = 0;
+        bits |= self.block_move as u32;
+        bits |= (self.block_sight as u32) << 1;
+        bits |= (self.explored as u32) << 2;
+        bits |= tile_type << 3;
+        bits |= wall_to_bits(self.bottom_wall) << 6;
+        bits |= wall_to_bits(self.left_wall) << 8;
+        bits |= surface << 10;
+        bits |= (self.chr as u32) << 16;
+
+        return bits;
+    }
+
+    /// Unpack a tile from a u32, failing if any field holds a value that
+    /// Tile::to_bits never produces.
+    pub fn from_bits(bits: u32) -> Result<Tile, String> {
+        let tile_type = match (bits >> 3) & 0x7 {
+            0 => TileType::Floor,
+            1 => TileType::ShortWall,
+            2 => TileType::Wall,
+            3 => TileType::Water,
+            4 => TileType::Exit,
+            typ => return Err(format!("Unexpected tile type {} in tile bits!", typ)),
+        };
+
+        let surface = match (bits >> 10) & 0x3 {
+            0 => Surface::Floor,
+            1 => Surface::Rubble,
+            2 => Surface::Grass,
+            surface => return Err(format!("Unexpected surface {} in tile bits!", surface)),
+        };
+
+        return Ok(Tile {
+            block_move: (bits & 1) != 0,
+            block_sight: ((bits >> 1) & 1) != 0,
+            explored: ((bits >> 2) & 1) != 0,
+            tile_type,
+            bottom_wall: wall_from_bits((bits >> 6) & 0x3)?,
+            left_wall: wall_from_bits((bits >> 8) & 0x3)?,
+            chr: ((bits >> 16) & 0xFF) as u8,
+            surface,
+        });
+    }
+}
+
+impl From<Tile> for u32 {
+    fn from(tile: Tile) -> u32 {
+        return tile.to_bits();
+    }
+}
+
+impl TryFrom<u32> for Tile {
+    type Error = String;
+
+    fn try_from(bits: u32) -> Result<Tile, String> {
+        return Tile::from_bits(bits);
+    }
+}
+
+fn wall_to_bits(wall: Wall) -> u32 {
+    match wall {
+        Wall::Empty => 0,
+        Wall::ShortWall => 1,
+        Wall::TallWall => 2,
+    }
+}
+
+fn wall_from_bits(bits: u32) -> Result<Wall, String> {
+    match bits {
+        0 => Ok(Wall::Empty),
+        1 => Ok(Wall::ShortWall),
+        2 => Ok(Wall::TallWall),
+        _ => Err(format!("Unexpected wall {} in tile bits!", bits)),
+    }
+}
+
+/// Builds a tile from its parts. Tiles start as unblocked floor, and build
+/// warns about flag combinations which are likely mistakes.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TileBuilder {
+    tile: Tile,
+}
+
+impl TileBuilder {
+    pub fn new() -> TileBuilder {
+        return TileBuilder { tile: Tile::floor() };
+    }
+
+    pub fn tile_type(mut self, tile_type: TileType) -> TileBuilder {
+        self.tile.tile_type = tile_type;
+        return self;
+    }
+
+    pub fn blocked(mut self) -> TileBuilder {
+        self.tile.block_move = true;
+        return self;
+    }
+
+    pub fn blocks_sight(mut self) -> TileBuilder {
+        self.tile.block_sight = true;
+        return self;
+    }
+
+    pub fn surface(mut self, surface: Surface) -> TileBuilder {
+        self.tile.surface = surface;
+        return self;
+    }
+
+    pub fn chr(mut self, chr: char) -> TileBuilder {
+        self.tile.chr = chr as u8;
+        return self;
+    }
+
+    /// Check for flag combinations which do not make sense together.
+    pub fn validate(&self) -> Vec<String> {
+        let mut warnings = Vec::new();
+        let tile = &self.tile;
+
+        if tile.block_sight && !tile.block_move {
+            warnings.push(format!("{} tile blocks sight but can be walked through", tile.tile_type));
+        }
+
+        if tile.tile_type == TileType::Wall && !(tile.block_move && tile.block_sight) {
+            warnings.push("wall tile does not block both movement and sight".to_string());
+        }
+
+        if tile.tile_type == TileType::Water && !tile.block_move {
+            warnings.push("water tile does not block movement".to_string());
+        }
+
+        if tile.tile_type != TileType::Wall && tile.tile_type != TileType::Floor && tile.block_sight {
+            warnings.push(format!("{} tile blocks sight", tile.tile_type));
+        }
+
+        if !tile.tile_type.is_floor() && tile.surface != Surface::Floor {
+            warnings.push(format!("{} tile has a {} surface", tile.tile_type, tile.surface));
+        }
+
+        return warnings;
+    }
+
+    pub fn build(self) -> Tile {
+        for warning in self.validate() {
+            warn!("{}", warning);
+        }
+
+        return self.tile;
+    }
+}
+
+#[test]
+fn test_tile_builder() {
+    let water = TileBuilder::new().tile_type(TileType::Water).blocked().build();
+    assert!(water.block_move);
+    assert!(!water.block_sight);
+    assert_eq!(Tile::water(), water);
+
+    let short_wall = TileBuilder::new().tile_type(TileType::ShortWall).build();
+    assert!(!short_wall.block_move);
+    assert!(!short_wall.block_sight);
+    assert_eq!(Tile::short_wall(), short_wall);
+
+    let wall = TileBuilder::new().tile_type(TileType::Wall).blocked().blocks_sight().chr(MAP_WALL as char);
+    assert!(wall.validate().is_empty());
+    assert_eq!(Tile::wall(), wall.build());
+
+    let grass = TileBuilder::new().surface(Surface::Grass);
+    assert!(grass.validate().is_empty());
+    assert_eq!(Tile::grass(), grass.build());
+
+    // combinations which are likely mistakes are reported
+    assert!(!TileBuilder::new().tile_type(TileType::Wall).validate().is_empty());
+    assert!(!TileBuilder::new().tile_type(TileType::Water).validate().is_empty());
+    assert!(!TileBuilder::new().blocks_sight().validate().is_empty());
+    assert!(!TileBuilder::new().tile_type(TileType::ShortWall).blocked().blocks_sight().validate().is_empty());
+    assert!(!TileBuilder::new().tile_type(TileType::Water).blocked().surface(Surface::Rubble).validate().is_empty());
+}
+
+#[test]
+fn test_tile_predicates() {
+    assert!(Tile::floor().is_floor());
+    assert!(!Tile::floor().is_wall());
+    assert!(Tile::grass().is_floor());
+    assert!(Tile::rubble().is_floor());
+    assert_eq!(Tile::floor(), Tile::empty());
+
+    assert!(Tile::wall().is_wall());
+    assert!(!Tile::wall().is_floor());
+    assert!(Tile::short_wall().is_wall());
+    assert!(!Tile::short_wall().is_floor());
+
+    assert!(!Tile::water().is_floor());
+    assert!(!Tile::water().is_wall());
+    assert!(!Tile::exit().is_floor());
+    assert!(!Tile::exit().is_wall());
+
+    // floor blocked by something placed on it is not open floor
+    let mut statue = Tile::floor();
+    statue.block_move = true;
+    assert!(!statue.is_floor());
+
+    assert_eq!(Ok(TileType::Floor), "floor".parse::<TileType>());
+    assert_eq!(Ok(TileType::Floor), "empty".parse::<TileType>());
+}
+
+#[test]
+fn test_tile_bits_round_trip() {
+    let tile_types = [TileType::Floor, TileType::ShortWall, TileType::Wall, TileType::Water, TileType::Exit];
+    let walls = [Wall::Empty, Wall::ShortWall, Wall::TallWall];
+    let surfaces = [Surface::Floor, Surface::Rubble, Surface::Grass];
+    let flags = [false, true];
+
+    for tile_type in tile_types.iter() {
+        for bottom_wall in walls.iter() {
+            for left_wall in walls.iter() {
+                for surface in surfaces.iter() {
+                    for block_move in flags.iter() {
+                        for block_sight in flags.iter() {
+                            for explored in flags.iter() {
+                                let tile = Tile {
+                                    block_move: *block_move,
+                                    block_sight: *block_sight,
+                                    explored: *explored,
+                                    tile_type: *tile_type,
+                                    bottom_wall: *bottom_wall,
+                                    left_wall: *left_wall,
+                                    chr: MAP_WALL,
+                                    surface: *surface,
+                                };
+
+                                assert_eq!(Ok(tile), Tile::from_bits(tile.to_bits()));
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    let mut tile = Tile::empty();
+    for chr in 0..=255 {
+        tile.chr = chr;
+        assert_eq!(Ok(tile), Tile::from_bits(tile.to_bits()));
+    }
+
+    // bits that to_bits never produces are an error rather than a panic
+    assert!(Tile::from_bits(7 << 3).is_err());
+    assert!(Tile::from_bits(3 << 6).is_err());
+    assert!(Tile::from_bits(3 << 8).is_err());
+    assert!(Tile::from_bits(3 << 10).is_err());
+    assert!(serde_yaml::from_str::<Tile>("56").is_err());
+    assert_eq!(Tile::empty(), serde_yaml::from_str::<Tile>(&Tile::empty().to_bits().to_string()).unwrap());
 }
 
 
@@ -540,6 +875,116 @@ pub fn reorient_map(map: &Map, rotation: Rotation, mirror: bool) -> Map {
     return new_map;
 }
 
+/// Which half of a map is copied onto the other half by mirror_map.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum MirrorAxis {
+    LeftToRight,
+    TopToBottom,
+}
+
+/// Reflect one half of a map onto the other half, for symmetric arenas.
+/// For odd sizes the center column or row is kept as is. Walls are stored on
+/// the left and bottom of each tile, so walls along the mirrored direction are
+/// moved to the neighboring tile to stay on the reflected edge.
+pub fn mirror_map(map: &Map, axis: MirrorAxis) -> Map {
+    let (width, height) = map.size();
+    let mut new_map = map.clone();
+
+    match axis {
+        MirrorAxis::LeftToRight => {
+            for x in ((width + 1) / 2)..width {
+                for y in 0..height {
+                    let pos = Pos::new(x, y);
+                    new_map[pos] = map[mirror_in_x(pos, width)];
+
+                    // the edge between x - 1 and x reflects to the edge between
+                    // width - x - 1 and width - x, the left wall of width - x.
+                    new_map[pos].left_wall = map[Pos::new(width - x, y)].left_wall;
+                }
+            }
+        }
+
+        MirrorAxis::TopToBottom => {
+            for y in ((height + 1) / 2)..height {
+                for x in 0..width {
+                    let pos = Pos::new(x, y);
+                    new_map[pos] = map[mirror_in_y(pos, height)];
+                }
+            }
+
+            // the edge between y and y + 1 reflects to the edge between
+            // height - y - 2 and height - y - 1, the bottom wall of height - y - 2.
+            // This includes the center row of odd height maps.
+            for y in (height / 2)..height {
+                for x in 0..width {
+                    let mirror_y = height - y - 2;
+                    let mut wall = Wall::Empty;
+                    if mirror_y >= 0 {
+                        wall = map[Pos::new(x, mirror_y)].bottom_wall;
+                    }
+                    new_map[Pos::new(x, y)].bottom_wall = wall;
+                }
+            }
+        }
+    }
+
+    return new_map;
+}
+
+#[test]
+fn test_mirror_map_left_to_right() {
+    let mut map = Map::from_dims(4, 3);
+    map[(0, 0)] = Tile::wall();
+    map[(1, 1)] = Tile::water();
+    map[(1, 2)].left_wall = Wall::ShortWall;
+    map[(0, 1)].bottom_wall = Wall::TallWall;
+    // a wall on the axis is its own reflection
+    map[(2, 0)].left_wall = Wall::TallWall;
+    // the right half is overwritten
+    map[(3, 2)] = Tile::wall();
+
+    let mirrored = mirror_map(&map, MirrorAxis::LeftToRight);
+
+    assert!(mirrored[(3, 0)].block_move);
+    assert_eq!(TileType::Water, mirrored[(2, 1)].tile_type);
+    assert!(!mirrored[(3, 2)].block_move);
+
+    // the left wall of (1, 2) is the right edge of (2, 2), so the left wall of (3, 2)
+    assert_eq!(Wall::ShortWall, mirrored[(3, 2)].left_wall);
+    assert_eq!(Wall::Empty, mirrored[(2, 2)].left_wall);
+    assert_eq!(Wall::TallWall, mirrored[(3, 1)].bottom_wall);
+    assert_eq!(Wall::TallWall, mirrored[(2, 0)].left_wall);
+
+    // the source half is unchanged
+    assert!(mirrored[(0, 0)].block_move);
+    assert_eq!(Wall::ShortWall, mirrored[(1, 2)].left_wall);
+}
+
+#[test]
+fn test_mirror_map_top_to_bottom() {
+    let mut map = Map::from_dims(3, 5);
+    map[(0, 0)] = Tile::wall();
+    map[(2, 1)].left_wall = Wall::ShortWall;
+    map[(1, 0)].bottom_wall = Wall::ShortWall;
+    map[(1, 1)].bottom_wall = Wall::TallWall;
+    map[(0, 4)].bottom_wall = Wall::TallWall;
+
+    let mirrored = mirror_map(&map, MirrorAxis::TopToBottom);
+
+    assert!(mirrored[(0, 4)].block_move);
+    assert_eq!(Wall::ShortWall, mirrored[(2, 3)].left_wall);
+
+    // the bottom of row 0 is the top of row 4, so the bottom wall of row 3,
+    // and the bottom of row 1 is the bottom wall of the center row
+    assert_eq!(Wall::ShortWall, mirrored[(1, 3)].bottom_wall);
+    assert_eq!(Wall::TallWall, mirrored[(1, 2)].bottom_wall);
+    assert_eq!(Wall::Empty, mirrored[(1, 4)].bottom_wall);
+    assert_eq!(Wall::Empty, mirrored[(0, 4)].bottom_wall);
+
+    assert_eq!(Wall::ShortWall, mirrored[(1, 0)].bottom_wall);
+    assert_eq!(Wall::TallWall, mirrored[(1, 1)].bottom_wall);
+}
+
 
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub enum Obstacle {
@@ -549,12 +994,15 @@ pub enum Obstacle {
     Square,
     LShape,
     Building,
+    Cross,
+    Ring,
 }
 
 impl Obstacle {
     pub fn all_obstacles() -> Vec<Obstacle> {
         vec!(Obstacle::Block,  Obstacle::Wall,   Obstacle::ShortWall,
-             Obstacle::Square, Obstacle::LShape, Obstacle::Building)
+             Obstacle::Square, Obstacle::LShape, Obstacle::Building,
+             Obstacle::Cross,  Obstacle::Ring)
     }
 }
 
@@ -604,10 +1052,30 @@ impl Wall {
     }
 }
 
+/// How much can be seen around a corner. The line used for FOV can pass on
+/// either side of a corner depending on which end it is drawn from, so
+/// Permissive FOV needs a clear line in either direction while Restrictive
+/// FOV needs a clear line in both.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FovCornerPeek {
+    Permissive,
+    Restrictive,
+}
+
+impl Default for FovCornerPeek {
+    fn default() -> FovCornerPeek {
+        return FovCornerPeek::Permissive;
+    }
+}
+
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Map {
     pub tiles: Vec<Vec<Tile>>,
     pub fov_cache: RefCell<HashMap<Pos, Vec<Pos>>>,
+    #[serde(default)]
+    pub wrap_edges: bool,
+    #[serde(default)]
+    pub corner_peek: FovCornerPeek,
 }
 
 impl Map {
@@ -616,6 +1084,8 @@ impl Map {
             Map {
                 tiles,
                 fov_cache: RefCell::new(HashMap::new()),
+                wrap_edges: false,
+                corner_peek: FovCornerPeek::Permissive,
             };
 
         return map;
@@ -627,6 +1097,8 @@ impl Map {
             Map {
                 tiles,
                 fov_cache: RefCell::new(HashMap::new()),
+                wrap_edges: false,
+                corner_peek: FovCornerPeek::Permissive,
             };
 
         return map;
@@ -637,6 +1109,8 @@ impl Map {
             Map {
                 tiles: Vec::new(),
                 fov_cache: RefCell::new(HashMap::new()),
+                wrap_edges: false,
+                corner_peek: FovCornerPeek::Permissive,
             };
 
         return map;
@@ -644,46 +1118,115 @@ impl Map {
 
     pub fn blocked_left(&self, pos: Pos, blocked_type: BlockedType) -> bool {
         let offset = Pos::new(pos.x - 1, pos.y);
-        if !self.is_within_bounds(offset) || !self.is_within_bounds(pos) {
-            return true;
+        if let (Some(tile), Some(offset_tile)) = (self.get(pos), self.get(offset)) {
+            let blocking_wall = blocked_type.blocking(tile.left_wall);
+            let blocking_tile = offset_tile.does_tile_block(blocked_type);
+            return blocking_wall || blocking_tile;
         }
 
-        let blocking_wall = blocked_type.blocking(self[pos].left_wall);
-        let blocking_tile = self[offset].does_tile_block(blocked_type);
-        return blocking_wall || blocking_tile;
+        return true;
     }
 
     pub fn blocked_right(&self, pos: Pos, blocked_type: BlockedType) -> bool {
         let offset = Pos::new(pos.x + 1, pos.y);
-        if !self.is_within_bounds(pos) || !self.is_within_bounds(pos) { 
-            return true;
+        if let (Some(_tile), Some(offset_tile)) = (self.get(pos), self.get(offset)) {
+            let blocking_wall = blocked_type.blocking(offset_tile.left_wall);
+            let blocking_tile = offset_tile.does_tile_block(blocked_type);
+            return blocking_wall || blocking_tile;
         }
 
-        let blocking_wall = blocked_type.blocking(self[offset].left_wall);
-        let blocking_tile = self[offset].does_tile_block(blocked_type);
-        return blocking_wall || blocking_tile;
+        return true;
     }
 
     pub fn blocked_down(&self, pos: Pos, blocked_type: BlockedType) -> bool {
         let offset = Pos::new(pos.x, pos.y + 1);
-        if !self.is_within_bounds(pos) || !self.is_within_bounds(pos) {
-            return true;
+        if let (Some(tile), Some(offset_tile)) = (self.get(pos), self.get(offset)) {
+            let blocking_wall = blocked_type.blocking(tile.bottom_wall);
+            let blocking_tile = offset_tile.does_tile_block(blocked_type);
+            return blocking_wall || blocking_tile;
         }
 
-        let blocking_wall = blocked_type.blocking(self[pos].bottom_wall);
-        let blocking_tile = self[offset].does_tile_block(blocked_type);
-        return blocking_wall || blocking_tile;
+        return true;
     }
 
     pub fn blocked_up(&self, pos: Pos, blocked_type: BlockedType) -> bool {
         let offset = Pos::new(pos.x, pos.y - 1);
-        if !self.is_within_bounds(pos) || !self.is_within_bounds(pos) {
-            return true;
+        if let (Some(_tile), Some(offset_tile)) = (self.get(pos), self.get(offset)) {
+            let blocking_wall = blocked_type.blocking(offset_tile.bottom_wall);
+            let blocking_tile = offset_tile.does_tile_block(blocked_type);
+            return blocking_wall || blocking_tile;
+        }
+
+        return true;
+    }
+
+    /// Place a wall on one edge of a tile. Tiles only store their left and bottom
+    /// walls, so the top and right edges are written to the neighbor above or to
+    /// the right. Returns false if the tile holding that edge is off the map or
+    /// the direction is diagonal.
+    pub fn set_wall(&mut self, pos: Pos, dir: Direction, wall: Wall) -> bool {
+        if !self.is_within_bounds(self.wrapped_pos(pos)) {
+            return false;
+        }
+
+        match dir {
+            Direction::Left => {
+                if let Some(tile) = self.get_mut(pos) {
+                    tile.left_wall = wall;
+                    return true;
+                }
+            }
+
+            Direction::Right => {
+                if let Some(tile) = self.get_mut(Pos::new(pos.x + 1, pos.y)) {
+                    tile.left_wall = wall;
+                    return true;
+                }
+            }
+
+            Direction::Down => {
+                if let Some(tile) = self.get_mut(pos) {
+                    tile.bottom_wall = wall;
+                    return true;
+                }
+            }
+
+            Direction::Up => {
+                if let Some(tile) = self.get_mut(Pos::new(pos.x, pos.y - 1)) {
+                    tile.bottom_wall = wall;
+                    return true;
+                }
+            }
+
+            _ => {
+            }
         }
 
-        let blocking_wall = blocked_type.blocking(self[offset].bottom_wall);
-        let blocking_tile = self[offset].does_tile_block(blocked_type);
-        return blocking_wall || blocking_tile;
+        return false;
+    }
+
+    /// Scar the area around a position, such as after an explosion. Floor tiles
+    /// reachable from the center within the radius become rubble, and short walls
+    /// on those tiles may be knocked down. Tall walls and full wall tiles stop the
+    /// scarring and are left intact.
+    pub fn scar(&mut self, center: Pos, radius: usize, rng: &mut Rand32) {
+        if !self.is_within_bounds(center) {
+            return;
+        }
+
+        for pos in floodfill(self, center, radius) {
+            if self[pos].tile_type == TileType::Floor {
+                self[pos].surface = Surface::Rubble;
+            }
+
+            if self[pos].left_wall == Wall::ShortWall && rng_trial(rng, SCAR_WALL_KNOCKDOWN_CHANCE) {
+                self[pos].left_wall = Wall::Empty;
+            }
+
+            if self[pos].bottom_wall == Wall::ShortWall && rng_trial(rng, SCAR_WALL_KNOCKDOWN_CHANCE) {
+                self[pos].bottom_wall = Wall::Empty;
+            }
+        }
     }
 
     pub fn path_blocked_fov(&self, start_pos: Pos, end_pos: Pos) -> Option<Blocked> {
@@ -720,6 +1263,15 @@ impl Map {
 
         let mut blocked = Blocked::new(start_pos, end_pos, dir, false, Wall::Empty);
 
+        // moving across a wrapped edge only checks the tile being moved into
+        if self.wrap_edges && (!self.is_within_bounds(start_pos) || !self.is_within_bounds(end_pos)) {
+            if self[self.wrapped_pos(end_pos)].does_tile_block(blocked_type) {
+                blocked.blocked_tile = true;
+                return Some(blocked);
+            }
+            return None;
+        }
+
         // if the target position is out of bounds, we are blocked
         if !self.is_within_bounds(end_pos) {
             blocked.blocked_tile = true;
@@ -775,83 +1327,95 @@ impl Map {
 
             Direction::DownRight => {
                 if self.blocked_right(start_pos, blocked_type) && self.blocked_down(start_pos, blocked_type) {
-                    if start_pos.x > 10000 && start_pos.y > 10000 {
-                        dbg!(start_pos);
+                    if let Some(tile) = self.get(start_pos) {
+                        blocked.wall_type = tile.bottom_wall;
                     }
-                    blocked.wall_type = self[start_pos].bottom_wall;
                     found_blocker = true;
                 }
 
                 if self.blocked_right(move_y(start_pos, 1), blocked_type) &&
                    self.blocked_down(move_x(start_pos, 1), blocked_type) {
-                    let blocked_pos = add_pos(start_pos, Pos::new(1, 0));
-                    if self.is_within_bounds(blocked_pos) {
-                        blocked.wall_type = self[blocked_pos].bottom_wall;
+                    if let Some(tile) = self.get(add_pos(start_pos, Pos::new(1, 0))) {
+                        blocked.wall_type = tile.bottom_wall;
                     }
                     found_blocker = true;
                 }
 
                 if self.blocked_right(start_pos, blocked_type) &&
                    self.blocked_right(y_moved, blocked_type) {
-                    blocked.wall_type = self[move_x(start_pos, 1)].left_wall;
+                    if let Some(tile) = self.get(move_x(start_pos, 1)) {
+                        blocked.wall_type = tile.left_wall;
+                    }
                     found_blocker = true;
                 }
 
                 if self.blocked_down(start_pos, blocked_type) &&
                    self.blocked_down(x_moved, blocked_type) {
-                    blocked.wall_type = self[start_pos].bottom_wall;
+                    if let Some(tile) = self.get(start_pos) {
+                        blocked.wall_type = tile.bottom_wall;
+                    }
                     found_blocker = true;
                 }
             }
 
             Direction::UpRight => {
                 if self.blocked_up(start_pos, blocked_type) && self.blocked_right(start_pos, blocked_type) {
-                    blocked.wall_type = self[move_y(start_pos, -1)].bottom_wall;
+                    if let Some(tile) = self.get(move_y(start_pos, -1)) {
+                        blocked.wall_type = tile.bottom_wall;
+                    }
                     found_blocker = true;
                 }
 
                 if self.blocked_up(move_x(start_pos, 1), blocked_type) &&
                    self.blocked_right(move_y(start_pos, -1), blocked_type) {
-                    let blocked_pos = add_pos(start_pos, Pos::new(1, -1));
-                    if self.is_within_bounds(blocked_pos) {
-                        blocked.wall_type = self[blocked_pos].bottom_wall;
+                    if let Some(tile) = self.get(add_pos(start_pos, Pos::new(1, -1))) {
+                        blocked.wall_type = tile.bottom_wall;
                     }
                     found_blocker = true;
                 }
 
                 if self.blocked_right(start_pos, blocked_type) && self.blocked_right(y_moved, blocked_type) {
-                    blocked.wall_type = self[move_x(start_pos, 1)].left_wall;
+                    if let Some(tile) = self.get(move_x(start_pos, 1)) {
+                        blocked.wall_type = tile.left_wall;
+                    }
                     found_blocker = true;
                 }
 
                 if self.blocked_up(start_pos, blocked_type) && self.blocked_up(x_moved, blocked_type) {
-                    blocked.wall_type = self[move_y(start_pos, -1)].bottom_wall;
+                    if let Some(tile) = self.get(move_y(start_pos, -1)) {
+                        blocked.wall_type = tile.bottom_wall;
+                    }
                     found_blocker = true;
                 }
             }
 
             Direction::DownLeft => {
                 if self.blocked_left(start_pos, blocked_type) && self.blocked_down(start_pos, blocked_type) {
-                    blocked.wall_type = self[start_pos].left_wall;
+                    if let Some(tile) = self.get(start_pos) {
+                        blocked.wall_type = tile.left_wall;
+                    }
                     found_blocker = true;
                 }
 
                 if self.blocked_left(move_y(start_pos, 1), blocked_type) &&
                    self.blocked_down(move_x(start_pos, -1), blocked_type) {
-                    let blocked_pos = add_pos(start_pos, Pos::new(-1, 1));
-                    if self.is_within_bounds(blocked_pos) {
-                        blocked.wall_type = self[blocked_pos].left_wall;
+                    if let Some(tile) = self.get(add_pos(start_pos, Pos::new(-1, 1))) {
+                        blocked.wall_type = tile.left_wall;
                     }
                     found_blocker = true;
                 }
 
                 if self.blocked_left(start_pos, blocked_type) && self.blocked_left(y_moved, blocked_type) {
-                    blocked.wall_type = self[start_pos].left_wall;
+                    if let Some(tile) = self.get(start_pos) {
+                        blocked.wall_type = tile.left_wall;
+                    }
                     found_blocker = true;
                 }
 
                 if self.blocked_down(start_pos, blocked_type) && self.blocked_down(x_moved, blocked_type) {
-                    blocked.wall_type = self[start_pos].bottom_wall;
+                    if let Some(tile) = self.get(start_pos) {
+                        blocked.wall_type = tile.bottom_wall;
+                    }
                     found_blocker = true;
                 }
             }
@@ -859,27 +1423,29 @@ impl Map {
             Direction::UpLeft => {
                 if self.blocked_left(move_y(start_pos, -1), blocked_type) &&
                    self.blocked_up(move_x(start_pos, -1), blocked_type) {
-                    let blocked_pos = add_pos(start_pos, Pos::new(-1, -1));
-                    if self.is_within_bounds(blocked_pos) {
-                        blocked.wall_type = self[blocked_pos].left_wall;
+                    if let Some(tile) = self.get(add_pos(start_pos, Pos::new(-1, -1))) {
+                        blocked.wall_type = tile.left_wall;
                     }
                     found_blocker = true;
                 }
 
                 if self.blocked_left(start_pos, blocked_type) && self.blocked_up(start_pos, blocked_type) {
-                    blocked.wall_type = self[start_pos].left_wall;
+                    if let Some(tile) = self.get(start_pos) {
+                        blocked.wall_type = tile.left_wall;
+                    }
                     found_blocker = true;
                 }
 
                 if self.blocked_left(start_pos, blocked_type) && self.blocked_left(y_moved, blocked_type) {
-                    blocked.wall_type = self[start_pos].left_wall;
+                    if let Some(tile) = self.get(start_pos) {
+                        blocked.wall_type = tile.left_wall;
+                    }
                     found_blocker = true;
                 }
 
                 if self.blocked_up(start_pos, blocked_type) && self.blocked_up(x_moved, blocked_type) {
-                    let blocked_pos = move_y(start_pos, -1);
-                    if self.is_within_bounds(blocked_pos) {
-                        blocked.wall_type = self[blocked_pos].bottom_wall;
+                    if let Some(tile) = self.get(move_y(start_pos, -1)) {
+                        blocked.wall_type = tile.bottom_wall;
                     }
                     found_blocker = true;
                 }
@@ -897,9 +1463,6 @@ impl Map {
         let line = line(start_pos, end_pos);
         let positions = iter::once(start_pos).chain(line.into_iter());
         for (pos, target_pos) in positions.tuple_windows() {
-            if pos.x > 10000 && pos.y > 10000 {
-                dbg!(pos);
-            }
             let blocked = self.move_blocked(pos, target_pos, blocked_type);
             if blocked.is_some() {
                 return blocked;
@@ -910,7 +1473,7 @@ impl Map {
     }
 
     pub fn is_empty(&self, pos: Pos) -> bool {
-        return self[pos].tile_type == TileType::Empty;
+        return self[pos].tile_type.is_floor();
     }
 
     pub fn is_within_bounds(&self, pos: Pos) -> bool {
@@ -920,8 +1483,42 @@ impl Map {
         return x_bounds && y_bounds;
     }
 
+    /// The tile at a position, or None if the position is off the map.
+    pub fn get(&self, pos: Pos) -> Option<&Tile> {
+        let pos = self.wrapped_pos(pos);
+        if !self.is_within_bounds(pos) {
+            return None;
+        }
+
+        return Some(&self.tiles[pos.x as usize][pos.y as usize]);
+    }
+
+    pub fn get_mut(&mut self, pos: Pos) -> Option<&mut Tile> {
+        let pos = self.wrapped_pos(pos);
+        if !self.is_within_bounds(pos) {
+            return None;
+        }
+
+        self.fov_cache.borrow_mut().clear();
+        return Some(&mut self.tiles[pos.x as usize][pos.y as usize]);
+    }
+
+    /// The position on the map corresponding to the given position. When the
+    /// map wraps at its edges, positions off the map wrap around to the other
+    /// side. Otherwise, or for an empty map, the position is returned unchanged.
+    /// Indexing and is_within_bounds use positions as given, so wrapped
+    /// positions go through this function or Map::get.
+    pub fn wrapped_pos(&self, pos: Pos) -> Pos {
+        let (width, height) = self.size();
+        if !self.wrap_edges || width == 0 || height == 0 {
+            return pos;
+        }
+
+        return Pos::new(pos.x.rem_euclid(width), pos.y.rem_euclid(height));
+    }
+
     pub fn size(&self) -> (i32, i32) {
-        return (self.tiles.len() as i32, self.tiles[0].len() as i32);
+        return (self.width(), self.height());
     }
 
     pub fn width(&self) -> i32 {
@@ -929,10 +1526,34 @@ impl Map {
     }
 
     pub fn height(&self) -> i32 {
-        return self.tiles[0].len() as i32;
+        return self.tiles.first().map_or(0, |column| column.len()) as i32;
     }
 
+    /// Check whether end_pos is visible from start_pos within the given radius.
+    /// This is symmetric: a monster can see the player exactly when the player
+    /// can see the monster. The map's corner_peek setting decides whether one
+    /// or both directions need to be clear.
     pub fn is_in_fov(&self, start_pos: Pos, end_pos: Pos, radius: i32, low: bool) -> bool {
+        let within_radius = distance_maximum(start_pos, end_pos) <= radius;
+        if !within_radius {
+            return false;
+        }
+
+        let forward = self.is_in_fov_one_way(start_pos, end_pos, low);
+        match self.corner_peek {
+            FovCornerPeek::Permissive => {
+                return forward || self.is_in_fov_one_way(end_pos, start_pos, low);
+            }
+
+            FovCornerPeek::Restrictive => {
+                return forward && self.is_in_fov_one_way(end_pos, start_pos, low);
+            }
+        }
+    }
+
+    /// FOV check in a single direction, from start_pos to end_pos. The shadowcast and the
+    /// line along the path can differ in each direction, so this is not symmetric.
+    fn is_in_fov_one_way(&self, start_pos: Pos, end_pos: Pos, low: bool) -> bool {
         let alg_fov = self.is_in_fov_shadowcast(start_pos, end_pos);
         
         let path_fov =
@@ -942,8 +1563,6 @@ impl Map {
                 self.path_blocked_fov(start_pos, end_pos)
             };
 
-        let within_radius = distance_maximum(start_pos, end_pos) <= radius;
-
         // make sure there is a clear path, but allow the player to
         // see walls (blocking position is the end_pos tile)
         let mut clear_fov_path = true;
@@ -951,7 +1570,14 @@ impl Map {
             clear_fov_path = end_pos == blocked.end_pos && blocked.blocked_tile;
         } 
 
-        return alg_fov && within_radius && clear_fov_path;
+        return alg_fov && clear_fov_path;
+    }
+
+    /// FOV check which also treats the given positions, such as those of large
+    /// entities, as blocking sight. The start and end positions never block.
+    pub fn is_in_fov_with_blockers(&self, start_pos: Pos, end_pos: Pos, radius: i32, low: bool, blockers: &HashSet<Pos>) -> bool {
+        return self.is_in_fov(start_pos, end_pos, radius, low) &&
+               !sight_blocked_by(start_pos, end_pos, blockers);
     }
 
     pub fn is_in_fov_shadowcast(&self, start_pos: Pos, end_pos: Pos) -> bool {
@@ -997,8 +1623,11 @@ impl Map {
         }
     }
 
+    /// Check whether a line of fire from start to end is clear of blocking tiles.
+    /// This uses a supercover line, so a shot cannot slip diagonally between
+    /// two blocking tiles that touch at a corner.
     pub fn path_clear_of_obstacles(&self, start: Pos, end: Pos) -> bool {
-        let line = line(start, end);
+        let line = supercover_line(start, end);
 
         let path_blocked =
             line.into_iter().any(|point| self[Pos::from(point)].block_move);
@@ -1030,15 +1659,10 @@ impl Map {
     }
 
     pub fn neighbors(&self, pos: Pos) -> SmallVec<[Pos; 8]> {
-        let neighbors = [(1, 0),  (1, 1),  (0, 1), 
-                         (-1, 1), (-1, 0), (-1, -1),
-                         (0, -1), (1, -1)];
-
         let mut result = SmallVec::new();
-        for delta in neighbors.iter() {
-            let new_pos = pos + Vector2D::new(delta.0, delta.1);
-            if self.is_within_bounds(new_pos) {
-                result.push(new_pos);
+        for new_pos in pos.neighbors8().iter() {
+            if self.is_within_bounds(*new_pos) {
+                result.push(*new_pos);
             }
         }
 
@@ -1046,13 +1670,10 @@ impl Map {
     }
 
     pub fn cardinal_neighbors(&self, pos: Pos) -> SmallVec<[Pos; 4]> {
-        let neighbors = [(1, 0), (0, 1), (-1, 0), (0, -1),];
-
         let mut result = SmallVec::new();
-        for delta in neighbors.iter() {
-            let new_pos = pos + Vector2D::new(delta.0, delta.1);
-            if self.is_within_bounds(new_pos) {
-                result.push(new_pos);
+        for new_pos in pos.neighbors4().iter() {
+            if self.is_within_bounds(*new_pos) {
+                result.push(*new_pos);
             }
         }
 
@@ -1060,22 +1681,113 @@ impl Map {
     }
 
     pub fn reachable_neighbors(&self, pos: Pos) -> SmallVec<[Pos; 8]> {
-        let neighbors = [(1, 0),  (1, 1),  (0, 1), 
-                         (-1, 1), (-1, 0), (-1, -1),
-                         (0, -1), (1, -1)];
-
         let mut result = SmallVec::new();
 
-        for delta in neighbors.iter() {
-            let end_pos = Pos::new(pos.x + delta.0, pos.y + delta.1);
-            if self.path_blocked_move(pos, end_pos).is_none() {
-                result.push(pos + Vector2D::new(delta.0, delta.1));
+        for end_pos in pos.neighbors8().iter() {
+            if self.path_blocked_move(pos, *end_pos).is_none() {
+                result.push(self.wrapped_pos(*end_pos));
             }
         }
 
         return result;
     }
 
+    /// Compute the number of steps from each tile to the nearest goal, moving
+    /// only between reachable neighbors. Tiles that cannot reach a goal are
+    /// given DIJKSTRA_UNREACHABLE. The field is indexed as field[x][y].
+    pub fn dijkstra_map(&self, goals: &[Pos]) -> Vec<Vec<i32>> {
+        let (width, height) = self.size();
+        let mut field = vec![vec![DIJKSTRA_UNREACHABLE; height as usize]; width as usize];
+
+        let mut queue: VecDeque<Pos> = VecDeque::new();
+        for goal in goals.iter() {
+            if self.is_within_bounds(*goal) {
+                field[goal.x as usize][goal.y as usize] = 0;
+                queue.push_back(*goal);
+            }
+        }
+
+        while let Some(pos) = queue.pop_front() {
+            let next_dist = field[pos.x as usize][pos.y as usize] + 1;
+
+            for neighbor in self.reachable_neighbors(pos) {
+                if !self.is_within_bounds(neighbor) {
+                    continue;
+                }
+
+                if next_dist < field[neighbor.x as usize][neighbor.y as usize] {
+                    field[neighbor.x as usize][neighbor.y as usize] = next_dist;
+                    queue.push_back(neighbor);
+                }
+            }
+        }
+
+        return field;
+    }
+
+    /// Label the open areas of the map, returning the tiles of each region.
+    /// Two tiles are in the same region if one can be reached from the other
+    /// by moving between reachable neighbors.
+    pub fn connected_regions(&self) -> Vec<Vec<Pos>> {
+        let (width, height) = self.size();
+        let mut labeled = vec![vec![false; height as usize]; width as usize];
+        let mut regions = Vec::new();
+
+        for x in 0..width {
+            for y in 0..height {
+                let start = Pos::new(x, y);
+                if labeled[x as usize][y as usize] || self[start].block_move {
+                    continue;
+                }
+
+                let mut region = Vec::new();
+                let mut queue: VecDeque<Pos> = VecDeque::new();
+                labeled[x as usize][y as usize] = true;
+                queue.push_back(start);
+
+                while let Some(pos) = queue.pop_front() {
+                    region.push(pos);
+
+                    for neighbor in self.reachable_neighbors(pos) {
+                        if !self.is_within_bounds(neighbor) ||
+                           labeled[neighbor.x as usize][neighbor.y as usize] {
+                            continue;
+                        }
+
+                        labeled[neighbor.x as usize][neighbor.y as usize] = true;
+                        queue.push_back(neighbor);
+                    }
+                }
+
+                regions.push(region);
+            }
+        }
+
+        return regions;
+    }
+
+    /// Step downhill in a field from dijkstra_map, returning the reachable
+    /// neighbor with the lowest cost, or the given position if no neighbor
+    /// is closer to a goal.
+    pub fn descend(&self, pos: Pos, field: &Vec<Vec<i32>>) -> Pos {
+        let mut best_pos = pos;
+        let mut best_dist = field[pos.x as usize][pos.y as usize];
+
+        for neighbor in self.reachable_neighbors(pos) {
+            if !self.is_within_bounds(neighbor) {
+                continue;
+            }
+
+            let dist = field[neighbor.x as usize][neighbor.y as usize];
+            if dist < best_dist {
+                best_dist = dist;
+                best_pos = neighbor;
+            }
+        }
+
+        return best_pos;
+    }
+
     pub fn get_all_pos(&self) -> Vec<Pos> {
         let (width, height) = self.size();
         return (0..width).cartesian_product(0..height)
@@ -1106,6 +1818,23 @@ impl Map {
         let new_y = std::cmp::min(height - 1, std::cmp::max(0, pos.y));
         return Pos::new(new_x, new_y);
     }
+
+    /// Find the nearest tile to 'origin' that does not block movement, searching
+    /// outwards in rings up to 'max_radius' tiles away.
+    pub fn closest_unblocked(&self, origin: Pos, max_radius: i32) -> Option<Pos> {
+        for radius in 0..=max_radius {
+            let closest =
+                ring_positions(origin, radius).into_iter()
+                                              .filter(|pos| self.is_within_bounds(*pos))
+                                              .filter(|pos| !self[*pos].block_move)
+                                              .min_by_key(|pos| distance_squared(origin, *pos));
+            if closest.is_some() {
+                return closest;
+            }
+        }
+
+        return None;
+    }
 }
 
 impl Index<(i32, i32)> for Map {
@@ -1140,14 +1869,10 @@ impl IndexMut<Pos> for Map {
 
 
 pub fn near_tile_type(map: &Map, position: Pos, tile_type: TileType) -> bool {
-    let neighbor_offsets: Vec<(i32, i32)>
-        = vec!((1, 0), (1, 1), (0, 1), (-1, 1), (-1, 0), (-1, -1), (0, -1), (1, -1));
-
     let mut near_given_tile = false;
 
-    for offset in neighbor_offsets {
-        let offset = Pos::from(offset);
-        let neighbor_position = move_by(position, offset);
+    for neighbor_position in position.neighbors8().iter() {
+        let neighbor_position = *neighbor_position;
 
         if map.is_within_bounds(neighbor_position) &&
            map[neighbor_position].tile_type == tile_type {
@@ -1170,6 +1895,40 @@ pub fn pos_in_radius(pos: Pos, radius: i32, rng: &mut Rand32) -> Pos {
     return pos + offset;
 }
 
+/// Check whether any of the given blocking positions lie on the line
+/// between two positions, not including the positions themselves.
+pub fn sight_blocked_by(start_pos: Pos, end_pos: Pos, blockers: &HashSet<Pos>) -> bool {
+    if blockers.is_empty() {
+        return false;
+    }
+
+    return line(start_pos, end_pos).iter()
+                                   .any(|pos| *pos != start_pos && *pos != end_pos && blockers.contains(pos));
+}
+
+/// The positions exactly 'radius' tiles from 'origin' (by maximum distance),
+/// forming a square ring. A radius of 0 is just the origin.
+pub fn ring_positions(origin: Pos, radius: i32) -> Vec<Pos> {
+    let mut positions = Vec::new();
+
+    if radius == 0 {
+        positions.push(origin);
+        return positions;
+    }
+
+    for offset in -radius..=radius {
+        positions.push(Pos::new(origin.x + offset, origin.y - radius));
+        positions.push(Pos::new(origin.x + offset, origin.y + radius));
+    }
+
+    for offset in (-radius + 1)..radius {
+        positions.push(Pos::new(origin.x - radius, origin.y + offset));
+        positions.push(Pos::new(origin.x + radius, origin.y + offset));
+    }
+
+    return positions;
+}
+
 pub fn astar_path(map: &Map,
                   start: Pos,
                   end: Pos,
@@ -1213,6 +1972,156 @@ pub fn astar_neighbors(map: &Map, start: Pos, pos: Pos, max_dist: Option<i32>) -
          .collect::<SmallVec<[(Pos, i32); 8]>>()
 }
 
+/// The glyphs making up a tile, in the order they should be drawn: the base
+/// tile, the tile's character or water, and its surface. The walls on the
+/// tile's edges are given separately by wall_glyphs, as wall shadows are
+/// drawn between the two.
+pub fn tile_glyphs(map: &Map, pos: Pos) -> SmallVec<[(u8, Color); 8]> {
+    let mut glyphs = SmallVec::new();
+    let color = Color::white();
+
+    glyphs.push((MAP_EMPTY_CHAR, color));
+
+    let tile = map[pos];
+    if tile.tile_type == TileType::Water {
+        glyphs.push((MAP_WATER, color));
+    } else if tile.chr != MAP_EMPTY_CHAR && tile.chr != b' ' {
+        glyphs.push((tile.chr, color));
+    }
+
+    match tile.surface {
+        Surface::Rubble => glyphs.push((MAP_RUBBLE, color)),
+        Surface::Grass => glyphs.push((MAP_GRASS, color)),
+        Surface::Floor => {},
+    }
+
+    return glyphs;
+}
+
+/// The glyphs for the walls on each edge of a tile. Walls on the top and right
+/// edges are stored in the neighboring tiles, so they are looked up there.
+pub fn wall_glyphs(map: &Map, pos: Pos) -> SmallVec<[(u8, Color); 8]> {
+    let mut glyphs = SmallVec::new();
+    let color = Color::white();
+
+    let tile = map[pos];
+    match tile.bottom_wall {
+        Wall::ShortWall => glyphs.push((MAP_THIN_WALL_BOTTOM, color)),
+        Wall::TallWall => glyphs.push((MAP_THICK_WALL_BOTTOM, color)),
+        Wall::Empty => {},
+    }
+
+    match tile.left_wall {
+        Wall::ShortWall => glyphs.push((MAP_THIN_WALL_LEFT, color)),
+        Wall::TallWall => glyphs.push((MAP_THICK_WALL_LEFT, color)),
+        Wall::Empty => {},
+    }
+
+    if pos.x + 1 < map.width() {
+        match map[(pos.x + 1, pos.y)].left_wall {
+            Wall::ShortWall => glyphs.push((MAP_THIN_WALL_RIGHT, color)),
+            Wall::TallWall => glyphs.push((MAP_THICK_WALL_RIGHT, color)),
+            Wall::Empty => {},
+        }
+    }
+
+    if pos.y - 1 >= 0 {
+        match map[(pos.x, pos.y - 1)].bottom_wall {
+            Wall::ShortWall => glyphs.push((MAP_THIN_WALL_TOP, color)),
+            Wall::TallWall => glyphs.push((MAP_THICK_WALL_TOP, color)),
+            Wall::Empty => {},
+        }
+    }
+
+    return glyphs;
+}
+
+#[test]
+fn test_tile_glyphs() {
+    let mut map = Map::from_dims(5, 5);
+    let pos = Pos::new(2, 2);
+    let white = Color::white();
+
+    map[pos].bottom_wall = Wall::ShortWall;
+    map[pos].surface = Surface::Rubble;
+    let glyphs = tile_glyphs(&map, pos);
+    assert_eq!(vec!((MAP_EMPTY_CHAR, white), (MAP_RUBBLE, white)), glyphs.to_vec());
+    assert_eq!(vec!((MAP_THIN_WALL_BOTTOM, white)), wall_glyphs(&map, pos).to_vec());
+
+    // walls on the top and right edges come from the neighboring tiles
+    map[(3, 2)].left_wall = Wall::TallWall;
+    map[(2, 1)].bottom_wall = Wall::ShortWall;
+    let glyphs = wall_glyphs(&map, pos);
+    assert_eq!(&[(MAP_THICK_WALL_RIGHT, white), (MAP_THIN_WALL_TOP, white)], &glyphs[1..]);
+
+    // water replaces the tile's character
+    map[pos] = Tile::water();
+    assert_eq!((MAP_WATER, white), tile_glyphs(&map, pos)[1]);
+}
+
+#[test]
+fn test_set_wall() {
+    let mut map = Map::from_dims(10, 10);
+    let pos = Pos::new(5, 5);
+
+    // the top edge is the bottom wall of the tile above
+    assert!(map.set_wall(pos, Direction::Up, Wall::TallWall));
+    assert_eq!(Wall::TallWall, map[Pos::new(5, 4)].bottom_wall);
+    assert_eq!(Wall::Empty, map[pos].bottom_wall);
+    assert!(map.blocked_up(pos, BlockedType::Move));
+
+    // the right edge is the left wall of the tile to the right
+    assert!(map.set_wall(pos, Direction::Right, Wall::ShortWall));
+    assert_eq!(Wall::ShortWall, map[Pos::new(6, 5)].left_wall);
+    assert_eq!(Wall::Empty, map[pos].left_wall);
+
+    assert!(map.set_wall(pos, Direction::Left, Wall::ShortWall));
+    assert_eq!(Wall::ShortWall, map[pos].left_wall);
+    assert!(map.set_wall(pos, Direction::Down, Wall::ShortWall));
+    assert_eq!(Wall::ShortWall, map[pos].bottom_wall);
+
+    // edges whose tile is off the map, and diagonals, are not set
+    assert!(!map.set_wall(Pos::new(5, 0), Direction::Up, Wall::ShortWall));
+    assert!(!map.set_wall(Pos::new(9, 5), Direction::Right, Wall::ShortWall));
+    assert!(!map.set_wall(Pos::new(10, 5), Direction::Left, Wall::ShortWall));
+    assert!(!map.set_wall(pos, Direction::UpLeft, Wall::ShortWall));
+}
+
+#[test]
+fn test_scar() {
+    let mut map = Map::from_dims(6, 6);
+    let mut rng = Rand32::new(0);
+    let center = Pos::new(2, 2);
+
+    // a full wall tile, and a line of tall walls along the right of the area
+    map[(1, 1)] = Tile::wall();
+    for y in 0..6 {
+        map.set_wall(Pos::new(3, y), Direction::Left, Wall::TallWall);
+    }
+
+    map.scar(center, 1, &mut rng);
+
+    let scarred: Vec<Pos> =
+        map.get_all_pos().into_iter().filter(|pos| map[*pos].surface == Surface::Rubble).collect();
+    assert_eq!(5, scarred.len());
+    for pos in [(1, 2), (1, 3), (2, 1), (2, 2), (2, 3)].iter() {
+        assert!(scarred.contains(&Pos::new(pos.0, pos.1)));
+    }
+
+    // walls are not scarred, and tall walls stay standing
+    assert_eq!(TileType::Wall, map[(1, 1)].tile_type);
+    assert_eq!(Surface::Floor, map[(1, 1)].surface);
+    assert_eq!(Wall::TallWall, map[(3, 2)].left_wall);
+
+    // a short wall in the area is eventually knocked down
+    map.set_wall(center, Direction::Down, Wall::ShortWall);
+    for _ in 0..20 {
+        map.scar(center, 1, &mut rng);
+    }
+    assert_eq!(Wall::Empty, map[center].bottom_wall);
+    assert_eq!(Wall::TallWall, map[(3, 2)].left_wall);
+}
+
 #[test]
 fn test_blocked_by_wall_right() {
     let mut map = Map::from_dims(10, 10);
@@ -1328,6 +2237,20 @@ fn test_blocked_out_corners() {
     assert_eq!(blocked.map(|blocked| blocked.wall_type), Some(Wall::ShortWall));
 }
 
+#[test]
+fn test_path_clear_of_obstacles_corners() {
+    let mut map = Map::from_dims(5, 5);
+    let start = Pos::new(1, 1);
+    let end = Pos::new(3, 3);
+    assert!(map.path_clear_of_obstacles(start, end));
+
+    // two walls touching at a corner block the diagonal between them
+    map[(2, 1)] = Tile::wall();
+    map[(1, 2)] = Tile::wall();
+    assert!(!line(start, end).iter().any(|pos| map[*pos].block_move));
+    assert!(!map.path_clear_of_obstacles(start, end));
+}
+
 #[test]
 fn test_blocked_horiz_line() {
     let mut map = Map::from_dims(10, 10);
@@ -1389,7 +2312,7 @@ fn test_fov_blocked_by_wall_right() {
 
     for wall_y_pos in 2..8 {
         let pos: Pos = Pos::new(5, wall_y_pos);
-        map[pos] = Tile::empty();
+        map[pos] = Tile::floor();
         map[pos].block_sight = true;
     }
   
@@ -1403,7 +2326,7 @@ fn test_fov_blocked_by_wall_left() {
 
     for wall_y_pos in 2..8 {
         let pos: Pos = Pos::new(6, wall_y_pos);
-        map[pos] = Tile::empty();
+        map[pos] = Tile::floor();
         map[pos].block_sight = true;
         map[pos].block_move = true;
     }
@@ -1418,7 +2341,7 @@ fn test_fov_blocked_by_wall_up() {
 
     for wall_x_pos in 2..8 {
         let pos: (i32, i32) = (wall_x_pos, 6);
-        map[pos] = Tile::empty();
+        map[pos] = Tile::floor();
         map[pos].block_sight = true;
         map[pos].block_move = true;
     }
@@ -1433,7 +2356,7 @@ fn test_fov_blocked_by_wall_down() {
 
     for wall_x_pos in 2..8 {
         let pos: (i32, i32) = (wall_x_pos, 4);
-        map[pos] = Tile::empty();
+        map[pos] = Tile::floor();
         map[pos].block_sight = true;
         map[pos].block_move = true;
     }
@@ -1485,3 +2408,250 @@ fn test_path_blocked_all() {
     assert_eq!(Wall::TallWall, blocked_positions[3].wall_type);
 }
 
+#[test]
+fn test_ring_positions() {
+    let origin = Pos::new(5, 5);
+    assert_eq!(vec!(origin), ring_positions(origin, 0));
+
+    let ring = ring_positions(origin, 1);
+    assert_eq!(8, ring.len());
+    for pos in ring {
+        assert_eq!(1, distance_maximum(origin, pos));
+    }
+
+    assert_eq!(16, ring_positions(origin, 2).len());
+}
+
+#[test]
+fn test_closest_unblocked() {
+    let mut map = Map::from_dims(10, 10);
+
+    let origin = Pos::new(5, 5);
+    assert_eq!(Some(origin), map.closest_unblocked(origin, 3));
+
+    // surround the origin with walls, and block the other closest tiles
+    // so that only the tile below remains
+    map[origin] = Tile::wall();
+    for neighbor in map.neighbors(origin) {
+        map[neighbor] = Tile::wall();
+    }
+    map[(5, 3)] = Tile::wall();
+    map[(3, 5)] = Tile::wall();
+    map[(7, 5)] = Tile::wall();
+    assert_eq!(Some(Pos::new(5, 7)), map.closest_unblocked(origin, 3));
+
+    // too small a radius finds nothing
+    assert_eq!(None, map.closest_unblocked(origin, 1));
+}
+
+#[test]
+fn test_dijkstra_map() {
+    let mut map = Map::from_dims(5, 5);
+
+    // a wall in the middle column, open only at the bottom
+    map[(2, 0)] = Tile::wall();
+    map[(2, 1)] = Tile::wall();
+    map[(2, 2)] = Tile::wall();
+    map[(2, 3)] = Tile::wall();
+
+    let goal = Pos::new(0, 0);
+    let field = map.dijkstra_map(&[goal]);
+
+    assert_eq!(0, field[0][0]);
+    assert_eq!(1, field[1][1]);
+    assert_eq!(3, field[1][3]);
+    assert_eq!(4, field[2][4]);
+
+    // the far side of the wall must go around through the opening
+    assert_eq!(5, field[3][3]);
+    assert_eq!(6, field[3][2]);
+    assert_eq!(8, field[3][0]);
+    assert_eq!(DIJKSTRA_UNREACHABLE, field[2][1]);
+
+    // descending from behind the wall heads towards the opening
+    let mut pos = Pos::new(3, 0);
+    let mut steps = 0;
+    while pos != goal {
+        let next_pos = map.descend(pos, &field);
+        assert_eq!(field[pos.x as usize][pos.y as usize] - 1,
+                   field[next_pos.x as usize][next_pos.y as usize]);
+        pos = next_pos;
+        steps += 1;
+    }
+    assert_eq!(8, steps);
+}
+
+#[test]
+fn test_connected_regions() {
+    let mut map = Map::from_dims(7, 5);
+
+    // a full wall down the middle column splits the map into two rooms
+    for y in 0..5 {
+        map[(3, y)] = Tile::wall();
+    }
+
+    let regions = map.connected_regions();
+    assert_eq!(2, regions.len());
+    assert_eq!(15, regions[0].len());
+    assert_eq!(15, regions[1].len());
+    assert!(regions[0].contains(&Pos::new(0, 0)));
+    assert!(regions[1].contains(&Pos::new(6, 4)));
+
+    // opening the wall joins the rooms
+    map[(3, 2)] = Tile::floor();
+    assert_eq!(1, map.connected_regions().len());
+}
+
+#[test]
+fn test_wrapped_neighbors() {
+    let mut map = Map::from_dims(5, 5);
+
+    // without wrapping, neighbors off the map are not reachable
+    assert_eq!(3, map.reachable_neighbors(Pos::new(0, 0)).len());
+
+    map.wrap_edges = true;
+    assert_eq!(Pos::new(4, 0), map.wrapped_pos(Pos::new(-1, 0)));
+    assert_eq!(Pos::new(0, 4), map.wrapped_pos(Pos::new(5, -1)));
+
+    let neighbors = map.reachable_neighbors(Pos::new(0, 0));
+    assert_eq!(8, neighbors.len());
+    assert!(neighbors.contains(&Pos::new(4, 0)));
+    assert!(neighbors.contains(&Pos::new(4, 4)));
+    assert!(neighbors.contains(&Pos::new(0, 4)));
+
+    // a wall on the other side blocks the wrapped move
+    map[(4, 0)] = Tile::wall();
+    assert!(!map.reachable_neighbors(Pos::new(0, 0)).contains(&Pos::new(4, 0)));
+
+    // indexing and bounds checks take positions as given, while get wraps them
+    assert!(!map.is_within_bounds(Pos::new(5, 0)));
+    assert_eq!(map[Pos::new(0, 0)], map[(0, 0)]);
+    assert_eq!(Some(&map[(4, 0)]), map.get(Pos::new(-1, 0)));
+
+    // an empty map has nothing to wrap around
+    let mut empty = Map::from_dims(0, 0);
+    empty.wrap_edges = true;
+    assert_eq!((0, 0), empty.size());
+    assert_eq!(Pos::new(-1, 3), empty.wrapped_pos(Pos::new(-1, 3)));
+    assert!(!empty.is_within_bounds(Pos::new(0, 0)));
+    assert_eq!(None, empty.get(Pos::new(0, 0)));
+}
+
+#[test]
+fn test_map_get_out_of_bounds() {
+    let mut map = Map::from_dims(5, 5);
+
+    assert_eq!(Some(&Tile::empty()), map.get(Pos::new(0, 0)));
+    assert_eq!(Some(&Tile::empty()), map.get(Pos::new(4, 4)));
+    assert_eq!(None, map.get(Pos::new(-1, 0)));
+    assert_eq!(None, map.get(Pos::new(0, 5)));
+    assert_eq!(None, map.get(Pos::new(100, -100)));
+    assert!(map.get_mut(Pos::new(5, 0)).is_none());
+
+    map.get_mut(Pos::new(2, 2)).unwrap().block_move = true;
+    assert!(map[(2, 2)].block_move);
+
+    // moving diagonally out of a corner checks walls off the map without panicking
+    for pos in vec!(Pos::new(0, 0), Pos::new(4, 0), Pos::new(0, 4), Pos::new(4, 4)) {
+        for neighbor in pos.neighbors8().iter() {
+            map.move_blocked(pos, *neighbor, BlockedType::Move);
+        }
+    }
+}
+
+#[test]
+fn test_fov_symmetric() {
+    let mut rng = Rand32::new(0x5EED);
+
+    for _ in 0..5 {
+        let mut map = Map::from_dims(12, 12);
+
+        for x in 0..map.width() {
+            for y in 0..map.height() {
+                let pos = Pos::new(x, y);
+                if rng_trial(&mut rng, 0.15) {
+                    map[pos] = Tile::wall();
+                } else if rng_trial(&mut rng, 0.1) {
+                    map[pos].left_wall = Wall::ShortWall;
+                } else if rng_trial(&mut rng, 0.1) {
+                    map[pos].bottom_wall = Wall::TallWall;
+                }
+            }
+        }
+
+        for _ in 0..200 {
+            let start = Pos::new(rng_range_i32(&mut rng, 0, map.width()), rng_range_i32(&mut rng, 0, map.height()));
+            let end = Pos::new(rng_range_i32(&mut rng, 0, map.width()), rng_range_i32(&mut rng, 0, map.height()));
+            let radius = rng_range_i32(&mut rng, 1, 10);
+            let low = rng_bool(&mut rng);
+
+            assert_eq!(map.is_in_fov(start, end, radius, low),
+                       map.is_in_fov(end, start, radius, low),
+                       "fov not symmetric between {:?} and {:?}", start, end);
+        }
+    }
+}
+
+#[test]
+fn test_fov_corner_peek() {
+    let mut map = Map::from_dims(7, 7);
+    let viewer = Pos::new(2, 2);
+    map[(3, 2)] = Tile::wall();
+
+    let visible_tiles = |map: &Map| {
+        let mut visible = Vec::new();
+        for x in 0..map.width() {
+            for y in 0..map.height() {
+                let pos = Pos::new(x, y);
+                if map.is_in_fov(viewer, pos, 6, false) {
+                    visible.push(pos);
+                }
+            }
+        }
+        return visible;
+    };
+
+    map.corner_peek = FovCornerPeek::Permissive;
+    let permissive = visible_tiles(&map);
+
+    map.corner_peek = FovCornerPeek::Restrictive;
+    let restrictive = visible_tiles(&map);
+
+    // the line to (4, 3) clips the corner of the wall in one direction but
+    // not the other, so only permissive FOV peeks around it
+    assert!(permissive.contains(&Pos::new(4, 3)));
+    assert!(!restrictive.contains(&Pos::new(4, 3)));
+
+    assert!(restrictive.len() < permissive.len());
+    assert!(restrictive.iter().all(|pos| permissive.contains(pos)));
+
+    // the wall itself and open tiles are visible either way
+    assert!(restrictive.contains(&Pos::new(3, 2)));
+    assert!(restrictive.contains(&Pos::new(2, 4)));
+
+    // restrictive FOV is still symmetric
+    for pos in permissive.iter() {
+        assert_eq!(map.is_in_fov(viewer, *pos, 6, false), map.is_in_fov(*pos, viewer, 6, false));
+    }
+}
+
+#[test]
+fn test_fov_with_blockers() {
+    let map = Map::from_dims(10, 10);
+
+    let start = Pos::new(1, 5);
+    let end = Pos::new(5, 5);
+
+    let mut blockers = HashSet::new();
+    assert!(map.is_in_fov_with_blockers(start, end, 6, false, &blockers));
+
+    // an entity directly between the two positions blocks sight
+    blockers.insert(Pos::new(3, 5));
+    assert!(!map.is_in_fov_with_blockers(start, end, 6, false, &blockers));
+
+    // the blocker itself can still be seen
+    assert!(map.is_in_fov_with_blockers(start, Pos::new(3, 5), 6, false, &blockers));
+
+    // a blocker off of the line does not block sight
+    assert!(map.is_in_fov_with_blockers(start, Pos::new(5, 8), 6, false, &blockers));
+}
@@ -12,7 +12,8 @@ use crate::movement::Attack;
 
 #[derive(Copy, Clone, PartialEq, Debug, Deserialize, Serialize)]
 pub enum Msg {
-    StartTurn,
+    StartTurn(usize), // turn count
+    EndTurn(usize), // turn count
     Pass,
     Crushed(EntityId, Pos), // object that did the crushing, position
     Sound(EntityId, Pos, usize, bool), // object causing sound, location, radius, whether animation will play
@@ -23,6 +24,7 @@ pub enum Msg {
     FreezeTrapTriggered(EntityId, EntityId), // trap, entity
     GateTriggered(EntityId, EntityId), // trap, entity
     Froze(EntityId, usize), // entity, num turns
+    ApplyEffect(EntityId, EffectKind, usize), // entity, effect, num turns
     PlayerDeath,
     PickedUp(EntityId, EntityId), // entity, item
     PickUp(EntityId), // entity trying to pick up an item
@@ -34,12 +36,11 @@ pub enum Msg {
     Pushed(EntityId, EntityId, Direction, usize, bool), // attacker, attacked, direction, amount, move into pushed square
     TryMove(EntityId, Direction, usize, MoveMode),
     Moved(EntityId, MoveType, Pos),
-    Interact(EntityId, Pos),
     JumpWall(EntityId, Pos, Pos), // current pos, new pos
     WallKick(EntityId, Pos),
     StateChange(EntityId, Behavior),
-    Collided(EntityId, Pos),
-    Yell(EntityId),
+    Collided(EntityId, Pos), // entity, blocked position
+    Yell(EntityId, Pos, usize), // entity, position, radius
     ChangeMoveMode(EntityId, bool), // true = increase, false = decrease
     MoveMode(EntityId, MoveMode),
     TriedRunWithHeavyEquipment,
@@ -78,12 +79,14 @@ pub enum Msg {
     Facing(EntityId, Direction),
     AiAttack(EntityId),
     RemovedEntity(EntityId),
+    TorchBurnedOut(EntityId),
 }
 
 impl fmt::Display for Msg {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
-            Msg::StartTurn => write!(f, "startturn"),
+            Msg::StartTurn(turn) => write!(f, "startturn {}", turn),
+            Msg::EndTurn(turn) => write!(f, "endturn {}", turn),
             Msg::Pass => write!(f, "pass"),
             Msg::Crushed(entity_id, pos) => write!(f, "crushed {} {} {}", entity_id, pos.x, pos.y),
             Msg::Sound(entity_id, pos, radius, animate) => write!(f, "sound {} {} {} {} {}", entity_id, pos.x, pos.y, radius, animate),
@@ -94,6 +97,7 @@ impl fmt::Display for Msg {
             Msg::FreezeTrapTriggered(trap_id, entity_id) => write!(f, "freeze_trap_triggered {} {}", trap_id, entity_id),
             Msg::GateTriggered(trap_id, entity_id) => write!(f, "gate_triggered {} {}", trap_id, entity_id),
             Msg::Froze(entity_id, turns) => write!(f, "froze {} {}", entity_id, turns),
+            Msg::ApplyEffect(entity_id, kind, turns) => write!(f, "apply_effect {} {} {}", entity_id, kind, turns),
             Msg::PlayerDeath => write!(f, "player_death"),
             Msg::PickedUp(entity_id, item_id) => write!(f, "picked_up {} {}", entity_id, item_id),
             Msg::PickUp(entity_id) => write!(f, "pickup {}", entity_id),
@@ -111,18 +115,19 @@ impl fmt::Display for Msg {
             Msg::Pushed(entity_id, target_id, direction, amount, follow) => write!(f, "pushed {} {} {} {} {}", entity_id, target_id, direction, amount, follow),
             Msg::TryMove(entity_id, direction, amount, move_mode) => write!(f, "try_move {} {} {} {}", entity_id, direction, amount, move_mode),
             Msg::Moved(entity_id, move_type, pos) => write!(f, "moved {} {} {} {}", entity_id, move_type, pos.x, pos.y),
-            Msg::Interact(entity_id, pos) => write!(f, "interact {} {} {}", entity_id, pos.x, pos.y),
             Msg::JumpWall(entity_id, pos, new_pos) => write!(f, "jump_wall {} {} {} {} {}", entity_id, pos.x, pos.y, new_pos.x, new_pos.y),
-            Msg::WallKick(entity_id, pos) => write!(f, "wall_kick {} {} {}", entity_id, pos.y, pos.y),
+            Msg::WallKick(entity_id, pos) => write!(f, "wall_kick {} {} {}", entity_id, pos.x, pos.y),
             Msg::StateChange(entity_id, behavior) => {
                 match behavior {
                     Behavior::Idle => write!(f, "state_change_idle {}", entity_id),
                     Behavior::Investigating(pos) => write!(f, "state_change_investigating {} {} {}", entity_id, pos.x, pos.y),
-                    Behavior::Attacking(target_id) => write!(f, "state_change_attacking {} {}", entity_id, target_id),
+                    Behavior::Attacking(target_id, pos) => write!(f, "state_change_attacking {} {} {} {}", entity_id, target_id, pos.x, pos.y),
+                    Behavior::Fleeing(target_id) => write!(f, "state_change_fleeing {} {}", entity_id, target_id),
+                    Behavior::Patrol(index) => write!(f, "state_change_patrol {} {}", entity_id, index),
                 }
             }
             Msg::Collided(entity_id, pos) => write!(f, "collided {} {} {}", entity_id, pos.x, pos.y),
-            Msg::Yell(entity_id) => write!(f, "yell {}", entity_id),
+            Msg::Yell(entity_id, pos, radius) => write!(f, "yell {} {} {} {}", entity_id, pos.x, pos.y, radius),
             Msg::ChangeMoveMode(entity_id, upwards) => write!(f, "chage_move_mode {} {}", entity_id, upwards),
             Msg::MoveMode(entity_id, move_mode) => write!(f, "move_mode {} {}", entity_id, move_mode),
             Msg::TriedRunWithHeavyEquipment => write!(f, "tried_run_with_heavy_equipment"),
@@ -161,6 +166,7 @@ impl fmt::Display for Msg {
             Msg::Facing(entity_id, direction) => write!(f, "facing {} {}", entity_id, direction),
             Msg::AiAttack(entity_id) => write!(f, "ai_attack {}", entity_id),
             Msg::RemovedEntity(entity_id) => write!(f, "removed {}", entity_id),
+            Msg::TorchBurnedOut(torch_id) => write!(f, "torch_burned_out {}", torch_id),
         }
     }
 }
@@ -169,10 +175,14 @@ impl fmt::Display for Msg {
 impl Msg {
     pub fn msg_line(&self, data: &GameData) -> String {
         match self {
-            Msg::StartTurn => {
+            Msg::StartTurn(_turn) => {
                 return "Starting turn".to_string();
             }
 
+            Msg::EndTurn(_turn) => {
+                return "".to_string();
+            }
+
             Msg::Crushed(_obj_id, _pos) => {
                 return "An object has been crushed".to_string();
             }
@@ -186,8 +196,7 @@ impl Msg {
             }
 
             Msg::Blink(entity_id) => {
-                return format!("{:?} blinked",
-                               data.entities.name[entity_id].clone());
+                return format!("{} blinked", subject(data, *entity_id));
             }
 
             Msg::SoundTrapTriggered(_trap, _entity_id) => {
@@ -211,9 +220,7 @@ impl Msg {
             }
 
             Msg::PickedUp(entity_id, item_id) => {
-                return format!("{:?} picked up a {:?}",
-                               data.entities.name[entity_id].clone(),
-                               data.entities.name[item_id].clone());
+                return format!("{} picked up {}", subject(data, *entity_id), object(data, *item_id, false));
             }
 
             Msg::ItemThrow(_thrower, _item, _start, _end) => {
@@ -225,14 +232,14 @@ impl Msg {
             }
 
             Msg::Attack(attacker, attacked, damage) => {
-                return format!("{:?} attacked {:?} for {} damage",
-                               data.entities.name[attacker],
-                               data.entities.name[attacked],
+                return format!("{} attacked {} for {} damage",
+                               subject(data, *attacker),
+                               object(data, *attacked, true),
                                damage);
             }
 
             Msg::Killed(attacker, attacked, _damage) => {
-                return format!("{:?} killed {:?}", data.entities.name[attacker], data.entities.name[attacked]);
+                return format!("{} killed {}", subject(data, *attacker), object(data, *attacked, true));
             }
 
             Msg::Push(_attacker, _direction, _amount) => {
@@ -240,18 +247,14 @@ impl Msg {
             }
 
             Msg::Pushed(attacker, attacked, _direction, _amount, _move_into) => {
-                return format!("{:?} pushed {:?}", data.entities.name[attacker], data.entities.name[attacked]);
-            }
-
-            Msg::Interact(_entity_id, _pos) => {
-                return "".to_string();
+                return format!("{} pushed {}", subject(data, *attacker), object(data, *attacked, true));
             }
 
             Msg::Moved(entity_id, move_type, pos) => {
                 if let MoveType::Pass = move_type {
-                    return format!("{:?} passed their turn", data.entities.name[entity_id]);
+                    return format!("{} passed {} turn", subject(data, *entity_id), possessive(data, *entity_id));
                 } else {
-                    return format!("{:?} moved to {}", data.entities.name[entity_id], pos);
+                    return format!("{} moved to {}", subject(data, *entity_id), pos);
                 }
             }
 
@@ -271,8 +274,8 @@ impl Msg {
                 return format!("Changed state to {:?}", *behavior);
             }
 
-            Msg::Yell(entity_id) => {
-                return format!("{:?} yelled", data.entities.name[entity_id]);
+            Msg::Yell(entity_id, _pos, _radius) => {
+                return format!("{} yelled", subject(data, *entity_id));
             }
 
             Msg::Collided(_entity_id, _pos) => {
@@ -286,15 +289,15 @@ impl Msg {
             Msg::MoveMode(entity_id, move_mode) => {
                 match move_mode {
                     MoveMode::Sneak => {
-                        return format!("{:?} is now sneaking", data.entities.name[entity_id]);
+                        return format!("{} started sneaking", subject(data, *entity_id));
                     }
 
                     MoveMode::Walk => {
-                        return format!("{:?} is now walking", data.entities.name[entity_id]);
+                        return format!("{} started walking", subject(data, *entity_id));
                     }
 
                     MoveMode::Run => {
-                        return format!("{:?} is now running", data.entities.name[entity_id]);
+                        return format!("{} started running", subject(data, *entity_id));
                     }
                 }
             }
@@ -304,51 +307,54 @@ impl Msg {
             }
 
             Msg::SwordSwing(entity_id, _item_id, _pos) => {
-                return format!("{:?} swung their sword", data.entities.name[entity_id]);
+                return format!("{} swung {} sword", subject(data, *entity_id), possessive(data, *entity_id));
             }
 
             Msg::HammerSwing(entity_id, _item_id, _pos) => {
-                return format!("{:?} swung their hammer", data.entities.name[entity_id]);
+                return format!("{} swung {} hammer", subject(data, *entity_id), possessive(data, *entity_id));
             }
 
             Msg::HammerHitEntity(entity_id, hit_entity) => {
-                let entity_name = &data.entities.name[entity_id];
-                let hit_entity_name = &data.entities.name[hit_entity];
-                return format!("{:?} hit {:?} with their hammer", entity_name, hit_entity_name);
+                return format!("{} hit {} with {} hammer",
+                               subject(data, *entity_id),
+                               object(data, *hit_entity, true),
+                               possessive(data, *entity_id));
             }
 
             Msg::HammerHitWall(entity_id, _blocked) => {
-                return format!("{:?} hit a wall with their hammer", data.entities.name[entity_id]);
+                return format!("{} hit a wall with {} hammer", subject(data, *entity_id), possessive(data, *entity_id));
             }
 
             Msg::Stabbed(attacker, attacked) => {
-                let entity_name = &data.entities.name[attacker];
-                let hit_entity_name = &data.entities.name[attacked];
-                return format!("{:?} stabbed {:?} with a dagger!", entity_name, hit_entity_name);
+                return format!("{} stabbed {} with a dagger!", subject(data, *attacker), object(data, *attacked, true));
             }
 
             Msg::FailedBlink(entity_id) => {
-                return format!("{:?} failed to blink!", data.entities.name[entity_id]);
+                return format!("{} failed to blink!", subject(data, *entity_id));
             }
 
             Msg::NotEnoughEnergy(entity_id) => {
-                return format!("{:?} does not have enough energy for that", data.entities.name[entity_id]);
+                return format!("{} did not have enough energy for that", subject(data, *entity_id));
             }
 
             Msg::DropFailed(entity_id) => {
-                return format!("{:?} tried to drop an item, but its too crowded!", data.entities.name[entity_id]);
+                return format!("{} tried to drop an item, but it was too crowded!", subject(data, *entity_id));
             }
 
             Msg::Froze(entity_id, _num_turns) => {
-                return format!("{:?} was frozen!", data.entities.name[entity_id]);
+                return format!("{} froze!", subject(data, *entity_id));
+            }
+
+            Msg::ApplyEffect(entity_id, kind, _num_turns) => {
+                return format!("{} became {}!", subject(data, *entity_id), kind);
             }
 
             Msg::Triggered(_trap, entity_id) => {
-                return format!("{:?} triggered something!", data.entities.name[entity_id]);
+                return format!("{} triggered something!", subject(data, *entity_id));
             }
 
             Msg::Untriggered(_trap, entity_id) => {
-                return format!("{:?} stepped off something!", data.entities.name[entity_id]);
+                return format!("{} stepped off something!", subject(data, *entity_id));
             }
 
             Msg::AddClass(class) => {
@@ -359,74 +365,74 @@ impl Msg {
                 return format!("Player swapped primary and secondary item");
             }
 
-            Msg::DropItem(entity_id, item_index) => {
-                if let Some(item_id) = data.entities.inventory[entity_id].get(*item_index as usize) {
-                    return format!("{:?} dropped a {:?}!", data.entities.name[entity_id], item_id);
-                } else {
-                    return "".to_string();
-                }
+            Msg::DropItem(entity_id, item_id) => {
+                return format!("{} dropped {}!", subject(data, *entity_id), object(data, *item_id, false));
             }
 
             Msg::GrassThrow(entity_id, direction) => {
-                return format!("{:?} threw grass {}", data.entities.name[entity_id], direction);
+                return format!("{} threw grass {}", subject(data, *entity_id), direction);
             }
 
             Msg::GrassShoes(entity_id, _action_mode) => {
-                return format!("{:?} used grass shoes", data.entities.name[entity_id]);
+                return format!("{} used grass shoes", subject(data, *entity_id));
             }
 
             Msg::GrassBlade(entity_id, _action_mode, _direction) => {
-                return format!("{:?} used grass blade", data.entities.name[entity_id]);
+                return format!("{} used a grass blade", subject(data, *entity_id));
             }
 
             Msg::Illuminate(entity_id, _pos, _amount) => {
-                return format!("{:?} illuminated their surroundings", data.entities.name[entity_id]);
+                return format!("{} illuminated {} surroundings", subject(data, *entity_id), possessive(data, *entity_id));
             }
 
             Msg::Heal(entity_id, amount) => {
-                return format!("{:?} healed by {}", data.entities.name[entity_id], amount);
+                return format!("{} healed by {}", subject(data, *entity_id), amount);
             }
 
             Msg::FarSight(entity_id, amount) => {
-                return format!("{:?} has farsight ({})", data.entities.name[entity_id], amount);
+                return format!("{} gained farsight ({})", subject(data, *entity_id), amount);
             }
 
             Msg::Sprint(entity_id, _direction, _amount) => {
-                return format!("{:?} has sprinted!", data.entities.name[entity_id]);
+                return format!("{} sprinted!", subject(data, *entity_id));
             }
 
             Msg::Rubble(entity_id, pos) => {
-                return format!("{:?} turned a wall to rubble at {}", data.entities.name[entity_id], pos);
+                return format!("{} turned a wall to rubble at {}", subject(data, *entity_id), pos);
             }
 
             Msg::Reform(entity_id, _pos) => {
-                return format!("{:?} turns rubble into wall", data.entities.name[entity_id]);
+                return format!("{} turned rubble into a wall", subject(data, *entity_id));
             }
 
             Msg::Swap(entity_id, other_id) => {
-                return format!("{:?} swaps with {:?}", data.entities.name[entity_id], data.entities.name[other_id]);
+                return format!("{} swapped with {}", subject(data, *entity_id), object(data, *other_id, true));
             }
             
            Msg::PassWall(entity_id, pos) => { 
-                return format!("{:?} passes through {}", data.entities.name[entity_id], pos);
+                return format!("{} passed through {}", subject(data, *entity_id), pos);
             }
 
             Msg::UseItem(entity_id, pos, item_id) => {
-                return format!("{:?} used {:?} on {}", data.entities.name[entity_id], data.entities.name[item_id], pos);
+                return format!("{} used {} on {}", subject(data, *entity_id), object(data, *item_id, true), pos);
             }
 
             Msg::ArmDisarmTrap(entity_id, trap_id) => {
-                return format!("{:?} fiddles with {:?}", data.entities.name[entity_id], data.entities.name[trap_id]);
+                return format!("{} fiddled with {}", subject(data, *entity_id), object(data, *trap_id, true));
             }
 
             Msg::PlaceTrap(entity_id, pos, trap_id) => {
-                return format!("{:?} place {:?} at {}", data.entities.name[entity_id], data.entities.name[trap_id], pos);
+                return format!("{} placed {} at {}", subject(data, *entity_id), object(data, *trap_id, false), pos);
             }
 
             Msg::SpawnedObject(_entity_id, _entity_type, _pos, _entity_name, _facing) => {
                 return "".to_string();
             }
 
+            Msg::TorchBurnedOut(_torch_id) => {
+                return "The torch burns out".to_string();
+            }
+
             _ => {
                 return "".to_string();
             }
@@ -434,10 +440,59 @@ impl Msg {
     }
 }
 
+/// The name of an entity at the start of a message line, such as "The gol" or "You".
+fn subject(data: &GameData, entity_id: EntityId) -> String {
+    let name = object(data, entity_id, true);
+
+    let mut chars = name.chars();
+    if let Some(first) = chars.next() {
+        return first.to_uppercase().chain(chars).collect();
+    }
+
+    return name;
+}
+
+/// The name of an entity within a message line, such as "the gol" or "a dagger".
+fn object(data: &GameData, entity_id: EntityId, definite: bool) -> String {
+    if let Some(name) = data.entities.name.get(&entity_id) {
+        return name.display_name(definite);
+    }
+
+    return "something".to_string();
+}
+
+fn possessive(data: &GameData, entity_id: EntityId) -> &'static str {
+    if data.entities.name.get(&entity_id) == Some(&EntityName::Player) {
+        return "your";
+    }
+
+    return "its";
+}
+
+pub type MsgSubscriber = Box<dyn FnMut(&Msg, &GameData)>;
+
+/// Observers of the message log. Closures can't be cloned, so a cloned
+/// log starts without any subscribers.
+#[derive(Default)]
+pub struct Subscribers(Vec<MsgSubscriber>);
+
+impl Clone for Subscribers {
+    fn clone(&self) -> Subscribers {
+        return Subscribers(Vec::new());
+    }
+}
+
+impl fmt::Debug for Subscribers {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Subscribers({})", self.0.len())
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct MsgLog {
     pub messages: VecDeque<Msg>,
     pub turn_messages: VecDeque<Msg>,
+    pub subscribers: Subscribers,
 }
 
 impl MsgLog {
@@ -445,9 +500,23 @@ impl MsgLog {
         return MsgLog {
             messages: VecDeque::new(),
             turn_messages: VecDeque::new(),
+            subscribers: Subscribers::default(),
         };
     }
 
+    /// Register a subscriber to be called with each message as it is resolved.
+    pub fn subscribe(&mut self, subscriber: MsgSubscriber) {
+        self.subscribers.0.push(subscriber);
+    }
+
+    /// Call each subscriber with a message. This is done when a message is resolved,
+    /// rather than when it is logged, so subscribers can inspect the game state.
+    pub fn notify(&mut self, msg: &Msg, data: &GameData) {
+        for subscriber in self.subscribers.0.iter_mut() {
+            subscriber(msg, data);
+        }
+    }
+
     pub fn pop(&mut self) -> Option<Msg> {
         let msg = self.messages.pop_front();
         if let Some(msg) = msg {
@@ -470,3 +539,46 @@ impl MsgLog {
     }
 }
 
+#[test]
+fn test_msg_log_subscribe() {
+    use std::rc::Rc;
+    use std::cell::RefCell;
+
+    let data = GameData::new(Map::empty(), Entities::new());
+
+    let received: Rc<RefCell<Vec<Msg>>> = Rc::new(RefCell::new(Vec::new()));
+    let subscriber_received = received.clone();
+
+    let mut msg_log = MsgLog::new();
+    msg_log.subscribe(Box::new(move |msg, _data| subscriber_received.borrow_mut().push(*msg)));
+
+    let msgs = vec!(Msg::StartTurn(0), Msg::PlayerTurn, Msg::EndTurn(0), Msg::Froze(EntityId(0), 2));
+    for msg in msgs.iter() {
+        msg_log.log(*msg);
+    }
+
+    while let Some(msg) = msg_log.pop() {
+        msg_log.notify(&msg, &data);
+    }
+
+    assert_eq!(msgs, *received.borrow());
+
+    // subscribers are not carried over to a cloned log
+    let mut cloned_log = msg_log.clone();
+    cloned_log.notify(&Msg::StartTurn(0), &data);
+    assert_eq!(msgs.len(), received.borrow().len());
+}
+
+#[test]
+fn test_msg_line_names() {
+    let mut data = GameData::new(Map::from_dims(5, 5), Entities::new());
+    let player = data.entities.create_entity(0, 0, EntityType::Player, ' ', Color::white(), EntityName::Player, true);
+    let gol = data.entities.create_entity(1, 0, EntityType::Enemy, ' ', Color::white(), EntityName::Gol, true);
+    let armil = data.entities.create_entity(2, 0, EntityType::Enemy, ' ', Color::white(), EntityName::Armil, true);
+    let dagger = data.entities.create_entity(3, 0, EntityType::Item, ' ', Color::white(), EntityName::Dagger, false);
+
+    assert_eq!("The gol attacked you for 2 damage", Msg::Attack(gol, player, 2).msg_line(&data));
+    assert_eq!("You killed the armil", Msg::Killed(player, armil, 1).msg_line(&data));
+    assert_eq!("You picked up a dagger", Msg::PickedUp(player, dagger).msg_line(&data));
+    assert_eq!("You swung your sword", Msg::SwordSwing(player, dagger, Pos::new(1, 0)).msg_line(&data));
+}
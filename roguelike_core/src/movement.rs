@@ -9,9 +9,14 @@ use serde::{Serialize, Deserialize};
 use crate::types::*;
 use crate::utils::*;
 use crate::map::{Wall, Blocked, TileType};
+#[cfg(test)]
+use crate::map::Tile;
 use crate::line::*;
+use crate::config::Config;
 
 
+pub type Loudness = usize;
+
 #[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
 pub enum MoveMode {
     Sneak,
@@ -81,6 +86,24 @@ impl MoveMode {
             MoveMode::Run => 2,
         }
     }
+
+    /// The radius of the sound made when moving a single step in this mode.
+    pub fn loudness(&self, config: &Config) -> Loudness {
+        match self {
+            MoveMode::Sneak => config.sound_radius_sneak,
+            MoveMode::Walk => config.sound_radius_walk,
+            MoveMode::Run => config.sound_radius_run,
+        }
+    }
+}
+
+#[test]
+fn test_move_mode_loudness() {
+    let config = Config::from_file("../config.yaml");
+
+    assert_eq!(0, MoveMode::Sneak.loudness(&config));
+    assert!(MoveMode::Sneak.loudness(&config) < MoveMode::Walk.loudness(&config));
+    assert!(MoveMode::Walk.loudness(&config) < MoveMode::Run.loudness(&config));
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
@@ -174,6 +197,103 @@ impl Movement {
     }
 }
 
+/// Momentum built up by moving repeatedly in the same direction.
+/// Each axis accelerates towards the maximum while movement continues
+/// in the same direction, resets on reversal, and decays otherwise.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct Momentum {
+    pub mx: i32,
+    pub my: i32,
+    pub max: i32,
+    pub accel: i32,
+}
+
+impl Momentum {
+    pub fn new(max: i32, accel: i32) -> Momentum {
+        return Momentum { mx: 0, my: 0, max, accel };
+    }
+
+    pub fn from_config(config: &Config) -> Momentum {
+        return Momentum::new(config.momentum_max, config.momentum_accel);
+    }
+
+    pub fn moved(&mut self, dx: i32, dy: i32) {
+        self.mx = self.accelerate(self.mx, dx.signum());
+        self.my = self.accelerate(self.my, dy.signum());
+    }
+
+    /// Bleed off momentum on a turn without continued movement.
+    pub fn decay(&mut self) {
+        self.mx -= self.mx.signum();
+        self.my -= self.my.signum();
+    }
+
+    pub fn magnitude(&self) -> i32 {
+        return std::cmp::max(self.mx.abs(), self.my.abs());
+    }
+
+    fn accelerate(&self, current: i32, dir: i32) -> i32 {
+        if dir == 0 {
+            return current - current.signum();
+        }
+
+        // reversing direction loses all momentum on this axis
+        let current = if current.signum() == -dir { 0 } else { current };
+
+        let next = current + dir * self.accel;
+        return std::cmp::max(-self.max, std::cmp::min(self.max, next));
+    }
+}
+
+#[test]
+fn test_momentum_accelerate() {
+    let config = Config::from_file("../config.yaml");
+    let mut momentum = Momentum::from_config(&config);
+
+    for _ in 0..(config.momentum_max * 2) {
+        momentum.moved(1, 0);
+        assert!(momentum.mx <= config.momentum_max);
+    }
+    assert_eq!(config.momentum_max, momentum.mx);
+    assert_eq!(0, momentum.my);
+
+    // reversing resets before accelerating the other way
+    momentum.moved(-1, 0);
+    assert_eq!(-config.momentum_accel, momentum.mx);
+
+    let mut momentum = Momentum::new(6, 2);
+    momentum.moved(0, 1);
+    assert_eq!(2, momentum.my);
+    momentum.moved(0, 1);
+    momentum.moved(0, 1);
+    momentum.moved(0, 1);
+    assert_eq!(6, momentum.my);
+}
+
+#[test]
+fn test_momentum_decay() {
+    let mut momentum = Momentum::new(3, 1);
+    momentum.moved(1, -1);
+    momentum.moved(1, -1);
+    momentum.moved(1, -1);
+    assert_eq!(3, momentum.magnitude());
+
+    momentum.decay();
+    assert_eq!((2, -2), (momentum.mx, momentum.my));
+    momentum.decay();
+    momentum.decay();
+    assert_eq!((0, 0), (momentum.mx, momentum.my));
+
+    // decay does not overshoot zero
+    momentum.decay();
+    assert_eq!((0, 0), (momentum.mx, momentum.my));
+
+    // moving along one axis bleeds off the other
+    momentum.moved(1, 1);
+    momentum.moved(1, 0);
+    assert_eq!((2, 0), (momentum.mx, momentum.my));
+}
+
 
 #[derive(Clone, Copy, Debug, Eq, PartialEq, PartialOrd)]
 pub enum Cardinal {
@@ -314,6 +434,8 @@ impl Direction {
         }
     }
 
+    /// The direction to face to look from one position towards another,
+    /// or None if the positions are the same.
     pub fn from_positions(start: Pos, end: Pos) -> Option<Direction> {
         let dxy = sub_pos(end, start);
         return Direction::from_dxy(dxy.x, dxy.y);
@@ -432,6 +554,34 @@ impl Direction {
     }
 }
 
+#[test]
+pub fn test_direction_from_positions() {
+    let start = Pos::new(5, 5);
+
+    assert_eq!(None, Direction::from_positions(start, start));
+
+    assert_eq!(Some(Direction::Up), Direction::from_positions(start, Pos::new(5, 2)));
+    assert_eq!(Some(Direction::Down), Direction::from_positions(start, Pos::new(5, 9)));
+    assert_eq!(Some(Direction::Left), Direction::from_positions(start, Pos::new(1, 5)));
+    assert_eq!(Some(Direction::Right), Direction::from_positions(start, Pos::new(6, 5)));
+    assert_eq!(Some(Direction::UpLeft), Direction::from_positions(start, Pos::new(3, 4)));
+    assert_eq!(Some(Direction::UpRight), Direction::from_positions(start, Pos::new(8, 1)));
+    assert_eq!(Some(Direction::DownLeft), Direction::from_positions(start, Pos::new(4, 6)));
+    assert_eq!(Some(Direction::DownRight), Direction::from_positions(start, Pos::new(7, 9)));
+}
+
+#[test]
+pub fn test_direction_from_dxy_into_move() {
+    for dir in Direction::directions() {
+        let dxy = dir.into_move();
+        assert_eq!(Some(dir), Direction::from_dxy(dxy.x, dxy.y));
+    }
+
+    assert_eq!(Some(Direction::Right), Direction::from_dxy(1, 0));
+    assert_eq!(Some(Direction::Left), Direction::from_dxy(-1, 0));
+    assert_eq!(None, Direction::from_dxy(0, 0));
+}
+
 #[test]
 pub fn test_direction_turn_amount() {
     assert_eq!(-1, Direction::Up.turn_amount(Direction::UpLeft));
@@ -603,11 +753,19 @@ impl Reach {
         }
     }
 
+    /// The absolute positions reachable from the given start position,
+    /// without duplicates.
     pub fn reachables(&self, start: Pos) -> Vec<Pos> {
-        let offsets = self.offsets();
-        return offsets.iter()
-                      .map(|off| add_pos(start, *off))
-                      .collect::<Vec<Pos>>();
+        let mut positions: Vec<Pos> = Vec::new();
+
+        for offset in self.offsets() {
+            let pos = add_pos(start, offset);
+            if !positions.contains(&pos) {
+                positions.push(pos);
+            }
+        }
+
+        return positions;
     }
 
     pub fn offsets(&self) -> Vec<Pos> {
@@ -699,6 +857,51 @@ pub fn test_reach_offsets_single() {
     assert!(offsets.iter().all(|p| expected_pos.iter().any(|other| other == p)));
 }
 
+#[test]
+pub fn test_reachables_single() {
+    let start = Pos::new(5, 5);
+    let reachables = Reach::Single(2).reachables(start);
+
+    // the tiles passed through on the way to each end point are also reachable
+    let expected_pos =
+        vec!((7, 5), (5, 7), (3, 5), (5, 3), (7, 7), (3, 7), (7, 3), (3, 3),
+             (6, 5), (5, 6), (4, 5), (5, 4), (6, 6), (4, 6), (6, 4), (4, 4)).iter()
+                                              .map(|p| Pos::from(*p))
+                                              .collect::<Vec<Pos>>();
+
+    assert_eq!(expected_pos.len(), reachables.len());
+    assert!(expected_pos.iter().all(|p| reachables.contains(p)));
+}
+
+#[test]
+pub fn test_reachables_horiz() {
+    let start = Pos::new(5, 5);
+    let reachables = Reach::Horiz(3).reachables(start);
+
+    let mut expected_pos = Vec::new();
+    for dist in 1..=3 {
+        expected_pos.push(Pos::new(5 + dist, 5));
+        expected_pos.push(Pos::new(5 - dist, 5));
+        expected_pos.push(Pos::new(5, 5 + dist));
+        expected_pos.push(Pos::new(5, 5 - dist));
+    }
+
+    assert_eq!(expected_pos.len(), reachables.len());
+    assert!(expected_pos.iter().all(|p| reachables.contains(p)));
+}
+
+#[test]
+pub fn test_reachables_dedup() {
+    // the offsets repeat tiles, as each line out to distance 2 passes through
+    // the distance 1 end point
+    let reach = Reach::Horiz(2);
+    assert_eq!(20, reach.offsets().len());
+    assert_eq!(8, reach.reachables(Pos::new(5, 5)).len());
+
+    // a zero distance reach does not leave the start position
+    assert!(Reach::Single(0).reachables(Pos::new(1, 1)).is_empty());
+}
+
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub struct MoveResult {
     entity: Option<EntityId>,
@@ -720,6 +923,40 @@ impl MoveResult {
     }
 }
 
+#[test]
+pub fn test_movement_new() {
+    let pos = Pos::new(1, 2);
+
+    let movement = Movement::new(pos, MoveType::Move, None);
+    assert_eq!(Movement::step_to(pos), movement);
+    assert_eq!(Movement::pass(pos), Movement::new(pos, MoveType::Pass, None));
+
+    let attack = Attack::Attack(EntityId(0));
+    let movement = Movement::new(pos, MoveType::Move, Some(attack));
+    assert_eq!(Movement::attack(pos, MoveType::Move, attack), movement);
+}
+
+#[test]
+pub fn test_no_collision() {
+    let mut data = GameData::empty(10, 10);
+    let start = Pos::new(1, 1);
+
+    let move_result = check_collision(start, 1, 0, &data);
+    assert!(move_result.no_collision());
+    assert_eq!(Pos::new(2, 1), move_result.move_pos);
+
+    // a blocking tile is a collision
+    data.map[(2, 1)] = Tile::wall();
+    let move_result = check_collision(start, 1, 0, &data);
+    assert!(!move_result.no_collision());
+
+    // a blocking entity is a collision
+    let entity_id = data.entities.create_entity(1, 2, EntityType::Enemy, ' ', Color::white(), EntityName::Gol, true);
+    let move_result = check_collision(start, 0, 1, &data);
+    assert!(!move_result.no_collision());
+    assert_eq!(Some(entity_id), move_result.entity);
+}
+
 /// Moves the given object with a given offset, returning the square that it collides with, or None
 /// indicating no collision.
 /// NOTE if the movement carries multiple tiles, then the resulting MoveResult can report that
@@ -744,7 +981,7 @@ pub fn check_collision(pos: Pos,
         let move_line = line_inclusive(pos, Pos::new(pos.x + dx, pos.y + dy));
 
         for line_tuple in move_line {
-            let line_pos = Pos::from(line_tuple);
+            let line_pos = data.map.wrapped_pos(Pos::from(line_tuple));
 
             if let Some(key) = data.has_blocking_entity(line_pos) {
                 result.move_pos = last_pos;
@@ -764,6 +1001,8 @@ pub fn check_collision(pos: Pos,
         }
     }
 
+    result.move_pos = data.map.wrapped_pos(result.move_pos);
+
     return result;
 }
 
@@ -772,11 +1011,15 @@ pub fn entity_move_not_blocked(entity_id: EntityId, move_pos: Pos, delta_pos: Po
 
     let pos = data.entities.pos[&entity_id];
 
-    let next_pos = next_pos(pos, delta_pos);
+    let next_pos = data.map.wrapped_pos(next_pos(pos, delta_pos));
     if let Some(other_id) = data.has_blocking_entity(next_pos) {
         if can_stab(data, entity_id, other_id) {
            let attack = Attack::Stab(other_id, true);
            movement = Some(Movement::attack(move_pos, MoveType::Move, attack));
+       } else if can_spear(data, entity_id, other_id) {
+           // a spear thrust hits the target without moving
+           let attack = Attack::Attack(other_id);
+           movement = Some(Movement::attack(pos, MoveType::Pass, attack));
        } else {
           movement = Some(Movement::move_to(move_pos, MoveType::Move));
        }
@@ -812,6 +1055,8 @@ pub fn entity_move_blocked_by_wall(entity_id: EntityId, delta_pos: Pos, blocked:
                movement = Some(Movement::attack(new_pos, MoveType::JumpWall, attack));
            }
         }
+    } else if let Some(kick_pos) = wall_kick_pos(entity_id, blocked, data) {
+        movement = Some(Movement::move_to(kick_pos, MoveType::WallKick));
     } else {
         // else move up to the wall (start_pos is just before the colliding tile)
         movement = Some(Movement::move_to(blocked.start_pos, MoveType::Move));
@@ -820,6 +1065,53 @@ pub fn entity_move_blocked_by_wall(entity_id: EntityId, delta_pos: Pos, blocked:
     return movement;
 }
 
+/// A running entity with momentum that runs into a tall wall or blocked tile
+/// kicks off of it, landing one tile along the wall. The side matching the
+/// entity's momentum is preferred, and the kick fails if neither side is clear.
+pub fn wall_kick_pos(entity_id: EntityId, blocked: &Blocked, data: &GameData) -> Option<Pos> {
+    if data.entities.move_mode.get(&entity_id) != Some(&MoveMode::Run) ||
+       data.entities.stance.get(&entity_id) == Some(&Stance::Crouching) {
+        return None;
+    }
+
+    if !blocked.blocked_tile && blocked.wall_type != Wall::TallWall {
+        return None;
+    }
+
+    // only a straight run into a wall can kick off of it
+    if !blocked.direction.horiz() {
+        return None;
+    }
+
+    let momentum = data.entities.momentum.get(&entity_id)?;
+    if momentum.magnitude() == 0 {
+        return None;
+    }
+
+    let clockwise = blocked.direction.clockwise().clockwise();
+    let counterclockwise = blocked.direction.counterclockwise().counterclockwise();
+
+    // prefer the side the entity was already drifting towards
+    let drift = clockwise.into_move();
+    let sides = if drift.x * momentum.mx + drift.y * momentum.my < 0 {
+        [counterclockwise, clockwise]
+    } else {
+        [clockwise, counterclockwise]
+    };
+
+    for side in sides.iter() {
+        let kick_pos = data.map.wrapped_pos(side.offset_pos(blocked.start_pos, 1));
+
+        if data.map.is_within_bounds(kick_pos) &&
+           data.map.path_blocked_move(blocked.start_pos, kick_pos).is_none() &&
+           data.has_blocking_entity(kick_pos).is_none() {
+            return Some(kick_pos);
+        }
+    }
+
+    return None;
+}
+
 pub fn entity_move_blocked_by_entity(entity_id: EntityId,
                                      other_id: EntityId,
                                      move_pos: Pos,
@@ -833,7 +1125,7 @@ pub fn entity_move_blocked_by_entity(entity_id: EntityId,
         movement = Some(Movement::attack(move_pos, MoveType::Move, attack));
     } else if data.entities.blocks[&other_id] {
         let other_pos = data.entities.pos[&other_id];
-        let next = next_pos(pos, delta_pos);
+        let next = data.map.wrapped_pos(next_pos(pos, delta_pos));
         if !data.map.is_within_bounds(next) {
             return None;
         }
@@ -978,6 +1270,26 @@ pub fn calculate_move(dir: Direction,
     return movement;
 }
 
+#[test]
+pub fn test_calculate_move_into_entities() {
+    let mut data = GameData::empty(10, 10);
+    let player = data.entities.create_entity(1, 1, EntityType::Player, '@', Color::white(), EntityName::Player, true);
+    let reach = Reach::single(1);
+
+    // an open tile is simply moved into
+    let movement = calculate_move(Direction::Right, reach, player, &data);
+    assert_eq!(Some(Movement::move_to(Pos::new(2, 1), MoveType::Move)), movement);
+
+    // the player can't push enemies, so walking into one does not move the player
+    data.entities.create_entity(2, 1, EntityType::Enemy, 'g', Color::white(), EntityName::Gol, true);
+    assert_eq!(None, calculate_move(Direction::Right, reach, player, &data));
+
+    // columns can be pushed
+    let column = data.entities.create_entity(1, 2, EntityType::Column, '0', Color::white(), EntityName::Column, true);
+    let movement = calculate_move(Direction::Down, reach, player, &data).unwrap();
+    assert_eq!(Some(Attack::Push(column, Direction::Down, 1)), movement.attack);
+}
+
 pub fn direction(value: i32) -> i32 {
     if value == 0 {
         return 0;
@@ -87,6 +87,31 @@ impl Perlin {
         Perlin { perm: perm }
     }
 
+    /// Create a generator whose noise is the same every time for a given seed.
+    pub fn with_seed(seed: u64) -> Perlin {
+        let mut rng = Rand32::new(seed);
+        return Perlin::new(&mut rng);
+    }
+
+    /// 2D noise summed over a number of octaves, each at twice the frequency
+    /// and half the amplitude of the last. The result stays within [-1, 1].
+    pub fn fractal2d(&self, xin: f64, yin: f64, octaves: usize) -> f64 {
+        let mut total = 0.0;
+        let mut amplitude = 1.0;
+        let mut frequency = 1.0;
+        let mut max_total = 0.0;
+
+        for _ in 0..octaves.max(1) {
+            total += amplitude * self.noise2d(xin * frequency, yin * frequency);
+            max_total += amplitude;
+
+            amplitude /= 2.0;
+            frequency *= 2.0;
+        }
+
+        return total / max_total;
+    }
+
     pub fn noise1d(&self, xin: f64) -> f64 {
         let ix0: i64 = fast_floor(xin); // Integer part of x
         let fx0: f64 = xin - ix0 as f64; // Fractional part of x
@@ -0,0 +1,102 @@
+use std::fmt::Display;
+use std::ops::{Deref, DerefMut};
+
+use oorandom::Rand32;
+
+use crate::utils::{rng_range, rng_range_i32, rng_range_u32, rng_trial};
+
+
+/// The game's random number generator. Draws made through the labeled methods
+/// are logged at trace level when log_draws is set, so a generated map can be
+/// traced back to the draws that made it. Unlabeled draws can still be made
+/// through the inner Rand32, which this derefs to, but they are not logged.
+#[derive(Clone, Debug)]
+pub struct GameRng {
+    rng: Rand32,
+    pub log_draws: bool,
+}
+
+impl GameRng {
+    pub fn new(seed: u64) -> GameRng {
+        return GameRng { rng: Rand32::new(seed), log_draws: false };
+    }
+
+    /// Create a child generator seeded from this one. The parent only advances
+    /// by the draws used for the seed, so draws made by the child do not shift
+    /// the parent's later draws.
+    pub fn fork(&mut self, label: &str) -> GameRng {
+        let high = self.rng.rand_u32() as u64;
+        let low = self.rng.rand_u32() as u64;
+        let seed = (high << 32) | low;
+        self.log_draw(label, "fork", seed);
+
+        let mut child = GameRng::new(seed);
+        child.log_draws = self.log_draws;
+        return child;
+    }
+
+    pub fn range_u32(&mut self, label: &str, low: u32, high: u32) -> u32 {
+        let result = rng_range_u32(&mut self.rng, low, high);
+        self.log_draw(label, format!("{}..{}", low, high), result);
+        return result;
+    }
+
+    pub fn range_i32(&mut self, label: &str, low: i32, high: i32) -> i32 {
+        let result = rng_range_i32(&mut self.rng, low, high);
+        self.log_draw(label, format!("{}..{}", low, high), result);
+        return result;
+    }
+
+    pub fn range_f32(&mut self, label: &str, low: f32, high: f32) -> f32 {
+        let result = rng_range(&mut self.rng, low, high);
+        self.log_draw(label, format!("{}..{}", low, high), result);
+        return result;
+    }
+
+    pub fn trial(&mut self, label: &str, prob: f32) -> bool {
+        let result = rng_trial(&mut self.rng, prob);
+        self.log_draw(label, format!("p={}", prob), result);
+        return result;
+    }
+
+    fn log_draw<R: Display, T: Display>(&self, label: &str, range: R, result: T) {
+        if self.log_draws {
+            log::trace!("rng {} {} -> {}", label, range, result);
+        }
+    }
+}
+
+impl Deref for GameRng {
+    type Target = Rand32;
+
+    fn deref(&self) -> &Rand32 {
+        return &self.rng;
+    }
+}
+
+impl DerefMut for GameRng {
+    fn deref_mut(&mut self) -> &mut Rand32 {
+        return &mut self.rng;
+    }
+}
+
+#[test]
+fn test_fork_is_independent() {
+    let mut parent = GameRng::new(1);
+    let mut child = parent.fork("child");
+
+    // the parent's stream is the same whether or not the child is used
+    let mut unused = parent.clone();
+    let child_draws: Vec<u32> = (0..10).map(|_| child.range_u32("child", 0, 1000)).collect();
+    let parent_draws: Vec<u32> = (0..10).map(|_| parent.range_u32("parent", 0, 1000)).collect();
+    let unused_draws: Vec<u32> = (0..10).map(|_| unused.range_u32("parent", 0, 1000)).collect();
+    assert_eq!(parent_draws, unused_draws);
+
+    // the child does not repeat the parent's stream
+    assert_ne!(parent_draws, child_draws);
+
+    // forking is deterministic given the parent's seed
+    let mut other_child = GameRng::new(1).fork("child");
+    let other_draws: Vec<u32> = (0..10).map(|_| other_child.range_u32("child", 0, 1000)).collect();
+    assert_eq!(child_draws, other_draws);
+}
@@ -1,10 +1,10 @@
-use std::collections::VecDeque;
+use std::collections::{VecDeque, HashSet, HashMap};
 use std::sync::atomic::{AtomicU64, Ordering};
 use std::default::Default;
 use std::fmt;
 use std::str::FromStr;
 
-use serde::{Serialize, Deserialize};
+use serde::{Serialize, Deserialize, Serializer, Deserializer};
 
 use pathfinding::directed::astar::astar;
 
@@ -12,6 +12,8 @@ use indexmap::map::IndexMap;
 
 use symbol::Symbol;
 
+use smallvec::SmallVec;
+
 use euclid::Point2D;
 
 use crate::ai::{Ai, Behavior};
@@ -25,16 +27,156 @@ use crate::constants::*;
 
 pub type Name = Symbol;
 
-pub type EntityId = u64;
+/// The single id type for entities, used as the key of every component store.
+/// Ids display as '#42', and parse from either '#42' or '42'.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Default, Serialize, Deserialize)]
+pub struct EntityId(pub u64);
+
+impl EntityId {
+    pub fn new(id: u64) -> EntityId {
+        return EntityId(id);
+    }
+
+    pub fn value(&self) -> u64 {
+        return self.0;
+    }
+}
+
+impl fmt::Display for EntityId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "#{}", self.0)
+    }
+}
+
+impl FromStr for EntityId {
+    type Err = String;
+
+    fn from_str(string: &str) -> Result<Self, Self::Err> {
+        let digits = string.trim_start_matches('#');
+        return digits.parse::<u64>()
+                     .map(EntityId)
+                     .map_err(|_| format!("Could not parse '{}' as EntityId", string));
+    }
+}
+
+#[test]
+fn test_entity_id_display_parse() {
+    let id = EntityId::new(42);
+    assert_eq!("#42", id.to_string());
+    assert_eq!(Ok(id), "#42".parse::<EntityId>());
+    assert_eq!(Ok(id), "42".parse::<EntityId>());
+    assert_eq!(Ok(id), id.to_string().parse::<EntityId>());
+    assert!("".parse::<EntityId>().is_err());
+    assert!("#".parse::<EntityId>().is_err());
+    assert!("#x".parse::<EntityId>().is_err());
+}
 
 pub type CompStore<T> = IndexMap<EntityId, T>;
 
 pub type Pos = Point2D<i32, ()>;
 
+/// Neighborhood helpers for Pos. As Pos is an alias of a euclid type,
+/// these are provided through a trait.
+pub trait PosExt {
+    fn neighbors8(&self) -> [Pos; 8];
+    fn neighbors4(&self) -> [Pos; 4];
+    fn is_adjacent(&self, other: Pos) -> bool;
+}
+
+impl PosExt for Pos {
+    /// The eight surrounding positions, starting to the right and going clockwise
+    /// (with y increasing downwards).
+    fn neighbors8(&self) -> [Pos; 8] {
+        return [Pos::new(self.x + 1, self.y),     Pos::new(self.x + 1, self.y + 1),
+                Pos::new(self.x,     self.y + 1), Pos::new(self.x - 1, self.y + 1),
+                Pos::new(self.x - 1, self.y),     Pos::new(self.x - 1, self.y - 1),
+                Pos::new(self.x,     self.y - 1), Pos::new(self.x + 1, self.y - 1)];
+    }
+
+    /// The four orthogonally adjacent positions.
+    fn neighbors4(&self) -> [Pos; 4] {
+        return [Pos::new(self.x + 1, self.y), Pos::new(self.x, self.y + 1),
+                Pos::new(self.x - 1, self.y), Pos::new(self.x, self.y - 1)];
+    }
+
+    /// Whether the other position is one of the eight neighbors of this one.
+    fn is_adjacent(&self, other: Pos) -> bool {
+        let dx = (self.x - other.x).abs();
+        let dy = (self.y - other.y).abs();
+        return dx <= 1 && dy <= 1 && (dx + dy) > 0;
+    }
+}
+
+#[test]
+fn test_pos_neighbors() {
+    let pos = Pos::new(3, 3);
+
+    let neighbors8 = pos.neighbors8();
+    assert_eq!(Pos::new(4, 3), neighbors8[0]);
+    assert!(neighbors8.iter().all(|other| pos.is_adjacent(*other)));
+    for (index, other) in neighbors8.iter().enumerate() {
+        assert!(!neighbors8[index + 1..].contains(other));
+    }
+
+    let neighbors4 = pos.neighbors4();
+    assert!(neighbors4.iter().all(|other| neighbors8.contains(other)));
+    assert!(neighbors4.iter().all(|other| other.x == pos.x || other.y == pos.y));
+}
+
+#[test]
+fn test_pos_is_adjacent() {
+    let pos = Pos::new(3, 3);
+
+    assert!(pos.is_adjacent(Pos::new(2, 2)));
+    assert!(pos.is_adjacent(Pos::new(3, 4)));
+    assert!(pos.is_adjacent(Pos::new(4, 2)));
+
+    assert!(!pos.is_adjacent(pos));
+    assert!(!pos.is_adjacent(Pos::new(5, 3)));
+    assert!(!pos.is_adjacent(Pos::new(1, 1)));
+}
+
+#[test]
+fn test_pos_serialize_compact() {
+    // Pos serializes as an (x, y) tuple rather than a struct with field names
+    let pos = Pos::new(3, -4);
+    let value = serde_yaml::to_value(&pos).unwrap();
+    let expected = serde_yaml::Value::Sequence(vec!(serde_yaml::Value::from(3), serde_yaml::Value::from(-4)));
+    assert_eq!(expected, value);
+
+    let positions = vec!(vec!(pos, Pos::new(0, 0)), vec!(Pos::new(-1, 7)));
+    let positions_string = serde_yaml::to_string(&positions).unwrap();
+    assert!(!positions_string.contains("x"));
+    let loaded: Vec<Vec<Pos>> = serde_yaml::from_str(&positions_string).unwrap();
+    assert_eq!(positions, loaded);
+}
+
+/// Progress towards opening the level's exit. The exit opens once all of
+/// the required keys have been collected.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct GoalTracker {
+    pub required: usize,
+    pub collected: usize,
+}
+
+impl GoalTracker {
+    pub fn new(required: usize) -> GoalTracker {
+        return GoalTracker { required, collected: 0 };
+    }
+
+    /// The goals are complete once every required key is held. A level with no
+    /// required keys is never complete, as the exit always needs a key.
+    pub fn complete(&self) -> bool {
+        return self.required > 0 && self.collected >= self.required;
+    }
+}
+
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct GameData {
     pub map: Map,
     pub entities: Entities,
+    #[serde(default)]
+    pub goals: GoalTracker,
 }
 
 impl GameData {
@@ -42,6 +184,7 @@ impl GameData {
         GameData {
             map,
             entities,
+            goals: GoalTracker::default(),
         }
     }
 
@@ -49,6 +192,35 @@ impl GameData {
         return GameData::new(Map::from_dims(width, height), Entities::new());
     }
 
+    /// Move an entity during the game. This keeps the position index up to date,
+    /// and when the player moves their FOV is computed from the new position
+    /// right away, so the checks made for the rest of the turn use it.
+    pub fn move_entity(&mut self, entity_id: EntityId, pos: Pos) {
+        self.entities.set_pos(entity_id, pos);
+
+        let is_player = self.entities.typ.get(&entity_id) == Some(&EntityType::Player);
+        if is_player && self.map.is_within_bounds(pos) {
+            self.map.is_in_fov_shadowcast(pos, pos);
+        }
+    }
+
+    /// Find the nearest tile to 'origin' that is not blocked by the map or by a blocking entity.
+    pub fn closest_clear_pos(&self, origin: Pos, max_radius: i32) -> Option<Pos> {
+        for radius in 0..=max_radius {
+            let closest =
+                ring_positions(origin, radius).into_iter()
+                                              .filter(|pos| self.map.is_within_bounds(*pos))
+                                              .filter(|pos| !self.map[*pos].block_move)
+                                              .filter(|pos| self.has_blocking_entity(*pos).is_none())
+                                              .min_by_key(|pos| distance_squared(origin, *pos));
+            if closest.is_some() {
+                return closest;
+            }
+        }
+
+        return None;
+    }
+
     pub fn get_clear_pos(&self) -> Vec<Pos> {
         return self.map.get_empty_pos()
                        .iter()
@@ -117,44 +289,79 @@ impl GameData {
         return result;
     }
 
-    pub fn fov_radius(&self, entity_id: EntityId) -> i32 {
+    /// The distance an entity can see. The player's radius depends on how they are moving,
+    /// and any entity sees further with extra FOV from its status or a torch.
+    pub fn sight_radius(&self, entity_id: EntityId, config: &Config) -> i32 {
         let mut radius: i32 = self.entities.fov_radius[&entity_id];
 
+        if self.entities.typ[&entity_id] == EntityType::Player {
+            if let Some(move_mode) = self.entities.move_mode.get(&entity_id) {
+                radius = config.fov_radius(*move_mode);
+            }
+        }
+
         if let Some(status) = self.entities.status.get(&entity_id) {
             radius += status.extra_fov as i32;
         }
 
+        radius += self.torch_radius(entity_id);
+
         return radius;
     }
 
     pub fn is_in_fov(&self, entity_id: EntityId, other_id: EntityId, config: &Config) -> bool {
+        let radius = self.sight_radius(entity_id, config);
+        return self.is_in_fov_radius(entity_id, other_id, radius, config);
+    }
+
+    /// Check FOV using the given radius instead of the entity's sight radius.
+    pub fn is_in_fov_radius(&self, entity_id: EntityId, other_id: EntityId, radius: i32, config: &Config) -> bool {
         let stance = self.entities.stance[&entity_id];
         let other_stance = self.entities.stance.get(&other_id).unwrap_or(&Stance::Standing);
         let crouching = stance == Stance::Crouching || other_stance == &Stance::Crouching;
 
         let other_pos = self.entities.pos[&other_id];
 
-        return self.fov_check(entity_id, other_pos, crouching, config);
+        return self.fov_check(entity_id, other_pos, radius, crouching, config);
     }
 
     pub fn pos_in_fov(&self, entity_id: EntityId, other_pos: Pos, config: &Config) -> bool {
+        let radius = self.sight_radius(entity_id, config);
+        return self.pos_in_fov_radius(entity_id, other_pos, radius, config);
+    }
+
+    /// Check FOV of a position using the given radius instead of the entity's sight radius.
+    pub fn pos_in_fov_radius(&self, entity_id: EntityId, other_pos: Pos, radius: i32, config: &Config) -> bool {
         let stance = self.entities.stance[&entity_id];
         let crouching = stance == Stance::Crouching;
 
-        return self.fov_check(entity_id, other_pos, crouching, config);
+        return self.fov_check(entity_id, other_pos, radius, crouching, config);
+    }
+
+    /// Positions of entities large enough to block sight, other than the given entity.
+    pub fn sight_blockers(&self, entity_id: EntityId) -> HashSet<Pos> {
+        let mut blockers = HashSet::new();
+
+        for (id, blocks_sight) in self.entities.blocks_sight.iter() {
+            if *blocks_sight && *id != entity_id && !self.entities.needs_removal[id] {
+                blockers.insert(self.entities.pos[id]);
+            }
+        }
+
+        return blockers;
     }
 
-    fn fov_check(&self, entity_id: EntityId, other_pos: Pos, crouching: bool, _config: &Config) -> bool {
+    fn fov_check(&self, entity_id: EntityId, other_pos: Pos, radius: i32, crouching: bool, config: &Config) -> bool {
         if other_pos.x < 0 || other_pos.y < 0 {
             return false;
         }
 
         let pos = self.entities.pos[&entity_id];
 
-        let radius: i32 = self.fov_radius(entity_id);
+        let blockers = self.sight_blockers(entity_id);
 
         if self.entities.typ[&entity_id] == EntityType::Player {
-            let mut can_see = self.map.is_in_fov(pos, other_pos, radius, crouching);
+            let mut can_see = self.map.is_in_fov_with_blockers(pos, other_pos, radius, crouching, &blockers);
 
             for id in self.entities.ids.iter() {
                 if can_see {
@@ -167,7 +374,7 @@ impl GameData {
                     let illuminate_radius = self.entities.status[id].illuminate as i32;
                     let illuminated = self.map.is_in_fov(illuminate_pos, other_pos, illuminate_radius, crouching);
 
-                    let illuminated_see = self.map.is_in_fov(pos, other_pos, ILLUMINATE_FOV_RADIUS, crouching);
+                    let illuminated_see = self.map.is_in_fov_with_blockers(pos, other_pos, ILLUMINATE_FOV_RADIUS, crouching, &blockers);
                     let blocked = self.map[other_pos].block_sight;
 
                     can_see |= illuminated && illuminated_see && !blocked;
@@ -175,9 +382,12 @@ impl GameData {
             }
 
             return can_see;
+        } else if !config.monster_directional_fov {
+            return self.map.is_in_fov_with_blockers(pos, other_pos, radius, crouching, &blockers);
         } else {
             if let Some(dir) = self.entities.direction.get(&entity_id) {
-                return self.map.is_in_fov_direction(pos, other_pos, radius, *dir, crouching);
+                return self.map.is_in_fov_direction(pos, other_pos, radius, *dir, crouching) &&
+                       !sight_blocked_by(pos, other_pos, &blockers);
             } else {
                 panic!(format!("tried to perform is_in_fov on entity without facing"));
             }
@@ -197,11 +407,10 @@ impl GameData {
     pub fn get_entities_at_pos(&mut self, check_pos: Pos) -> Vec<EntityId> {
         let mut object_ids: Vec<EntityId> = Vec::new();
 
-        for key in self.entities.ids.iter() {
-            let pos = self.entities.pos[key];
+        for key in self.entities.ids_at(check_pos).iter() {
             let is_mouse = self.entities.name[key] == EntityName::Mouse;
 
-            if !is_mouse && check_pos == pos {
+            if !is_mouse {
                 object_ids.push(*key);
             }
         }
@@ -215,13 +424,16 @@ impl GameData {
         return self.clear_path(start, up_to, traps_block);
     }
 
+    /// Check whether the line from start to end (not including start) is clear.
+    /// Walls and blocking entities always block the path, and armed traps
+    /// block it only when traps_block is set. Disarmed traps never block.
     pub fn clear_path(&self, start: Pos, end: Pos, traps_block: bool) -> bool {
         let line = line_inclusive(start, end);
 
         let path_blocked =
             line.into_iter().any(|point| {
                 let pos = Pos::from(point);
-                return self.has_blocking_entity(pos).is_some() || (traps_block && self.has_trap(pos).is_some());
+                return self.has_blocking_entity(pos).is_some() || (traps_block && self.has_armed_trap(pos).is_some());
             });
 
         return !path_blocked && self.map.path_blocked_move(start, end).is_none();
@@ -252,31 +464,32 @@ impl GameData {
         return None;
     }
 
-    pub fn has_entities(&self, pos: Pos) -> Vec<EntityId> {
-        let mut entities = Vec::new();
-        for (key, other_pos) in self.entities.pos.iter() {
-            if *other_pos == pos {
-                entities.push(*key);
-            }
-        }
+    /// All entities at the given position, in the order they arrived there.
+    pub fn entities_at(&self, pos: Pos) -> SmallVec<[EntityId; 4]> {
+        return SmallVec::from_slice(self.entities.ids_at(pos));
+    }
 
-        return entities;
+    /// The first entity at the given position that blocks movement, if any.
+    pub fn blocking_entity_at(&self, pos: Pos) -> Option<EntityId> {
+        return self.entities_at(pos).into_iter().find(|key| self.entities.blocks[key]);
     }
 
-    pub fn has_entity(&self, pos: Pos) -> Option<EntityId> {
-        for (key, other_pos) in self.entities.pos.iter() {
-            if *other_pos == pos {
-                return Some(*key);
-            }
-        }
+    pub fn has_entities(&self, pos: Pos) -> Vec<EntityId> {
+        return self.entities_at(pos).to_vec();
+    }
 
-        return None;
+    pub fn has_entity(&self, pos: Pos) -> Option<EntityId> {
+        return self.entities_at(pos).first().copied();
     }
 
     pub fn has_blocking_entity(&self, pos: Pos) -> Option<EntityId> {
+        return self.blocking_entity_at(pos);
+    }
+
+    pub fn has_trap(&self, pos: Pos) -> Option<EntityId> {
         for (key, other_pos) in self.entities.pos.iter() {
             if *other_pos == pos {
-                if self.entities.blocks[key] {
+                if self.entities.trap.get(key).is_some() && self.entities.armed.get(key).is_some() {
                     return Some(*key);
                 }
             }
@@ -285,12 +498,10 @@ impl GameData {
         return None;
     }
 
-    pub fn has_trap(&self, pos: Pos) -> Option<EntityId> {
-        for (key, other_pos) in self.entities.pos.iter() {
-            if *other_pos == pos {
-                if self.entities.trap.get(key).is_some() && self.entities.armed.get(key).is_some() {
-                    return Some(*key);
-                }
+    pub fn has_armed_trap(&self, pos: Pos) -> Option<EntityId> {
+        if let Some(trap_id) = self.has_trap(pos) {
+            if self.entities.armed.get(&trap_id) == Some(&true) {
+                return Some(trap_id);
             }
         }
 
@@ -307,9 +518,14 @@ impl GameData {
         return None;
     }
 
+    /// Check whether an entity is wielding the given item, returning the item's id.
+    /// Items are wielded by being in one of the equip slots, which are the first
+    /// NUM_EQUIP_SLOTS positions of the inventory. Picking up a primary item puts it
+    /// at the front of the inventory, so an entity can wield two primary items at once,
+    /// such as a hammer and a shield.
     pub fn using(&self, entity_id: EntityId, item: Item) -> Option<EntityId> {
         if let Some(inventory) = self.entities.inventory.get(&entity_id) {
-            if let Some(item_id) = inventory.get(0) {
+            for item_id in inventory.iter().take(NUM_EQUIP_SLOTS) {
                 if self.entities.item[item_id] == item {
                     return Some(*item_id);
                 }
@@ -319,6 +535,42 @@ impl GameData {
         return None;
     }
 
+    /// The items in an entity's primary and secondary equip slots.
+    pub fn equipped(&self, entity_id: EntityId) -> (Option<Item>, Option<Item>) {
+        let slot = |index: usize| {
+            return self.entities.inventory.get(&entity_id)
+                                          .and_then(|inventory| inventory.get(index))
+                                          .map(|item_id| self.entities.item[item_id]);
+        };
+
+        return (slot(0), slot(1));
+    }
+
+    /// The attack reach of an entity, taking into account any weapon that
+    /// extends it.
+    pub fn attack_reach(&self, entity_id: EntityId) -> Option<Reach> {
+        if self.using(entity_id, Item::Spear).is_some() {
+            return Some(Reach::Horiz(SPEAR_REACH));
+        }
+
+        return self.entities.attack.get(&entity_id).map(|reach| *reach);
+    }
+
+    /// The extra fov radius an entity gets from holding a lit torch.
+    pub fn torch_radius(&self, entity_id: EntityId) -> i32 {
+        if let Some(torch_id) = self.using(entity_id, Item::Torch) {
+            if self.entities.torch.get(&torch_id).map_or(false, |torch| torch.fuel > 0) {
+                return TORCH_FOV_RADIUS;
+            }
+        }
+
+        return 0;
+    }
+
+    pub fn is_cloaked(&self, entity_id: EntityId) -> bool {
+        return self.entities.cloaked.get(&entity_id).map_or(false, |cloaked| cloaked.turns_left > 0);
+    }
+
     pub fn used_up_item(&mut self, entity_id: EntityId, item_id: EntityId) {
         if let Some(inventory) = self.entities.inventory.get_mut(&entity_id) {
             let item_index = inventory.iter()
@@ -386,6 +638,230 @@ impl GameData {
     }
 }
 
+#[test]
+fn test_entities_at() {
+    let mut data = GameData::new(Map::from_dims(10, 10), Entities::new());
+    let pos = Pos::new(3, 3);
+
+    assert!(data.entities_at(pos).is_empty());
+    assert_eq!(None, data.blocking_entity_at(pos));
+
+    // stacked entities are all returned, in creation order
+    let item = data.entities.create_entity(pos.x, pos.y, EntityType::Item, ' ', Color::white(), EntityName::Stone, false);
+    let trigger = data.entities.create_entity(pos.x, pos.y, EntityType::Trigger, ' ', Color::white(), EntityName::GateTrigger, false);
+    let column = data.entities.create_entity(pos.x, pos.y, EntityType::Column, ' ', Color::white(), EntityName::Column, true);
+    data.entities.create_entity(4, 3, EntityType::Column, ' ', Color::white(), EntityName::Column, true);
+
+    assert_eq!(&[item, trigger, column], data.entities_at(pos).as_slice());
+
+    // only the blocking entity is found when filtering to blocking ones
+    assert_eq!(Some(column), data.blocking_entity_at(pos));
+    assert_eq!(Some(item), data.has_entity(pos));
+    assert_eq!(vec!(item, trigger, column), data.has_entities(pos));
+}
+
+#[test]
+fn test_closest_clear_pos() {
+    let mut data = GameData::new(Map::from_dims(10, 10), Entities::new());
+    let origin = Pos::new(5, 5);
+    assert_eq!(Some(origin), data.closest_clear_pos(origin, 2));
+
+    // a wall at the origin and a blocking entity beside it are both skipped
+    data.map[origin] = Tile::wall();
+    data.entities.create_entity(5, 4, EntityType::Column, ' ', Color::white(), EntityName::Column, true);
+    data.entities.create_entity(4, 5, EntityType::Item, ' ', Color::white(), EntityName::Stone, false);
+    for pos in vec!(Pos::new(6, 5), Pos::new(5, 6)) {
+        data.map[pos] = Tile::wall();
+    }
+    assert_eq!(Some(Pos::new(4, 5)), data.closest_clear_pos(origin, 2));
+
+    // nothing clear within the radius
+    data.entities.create_entity(4, 5, EntityType::Column, ' ', Color::white(), EntityName::Column, true);
+    assert_eq!(None, data.closest_clear_pos(origin, 0));
+}
+
+#[test]
+fn test_move_entity_updates_player_fov() {
+    let mut data = GameData::empty(10, 10);
+    let player_id = data.entities.create_entity(1, 1, EntityType::Player, '@', Color::white(), EntityName::Player, true);
+    let gol = data.entities.create_entity(8, 8, EntityType::Enemy, 'g', Color::white(), EntityName::Gol, true);
+
+    let pos = Pos::new(5, 5);
+    data.move_entity(player_id, pos);
+    assert_eq!(pos, data.entities.pos[&player_id]);
+    assert_eq!(Some(player_id), data.blocking_entity_at(pos));
+
+    // the player's FOV now originates from their new position
+    assert!(data.map.fov_cache.borrow().contains_key(&pos));
+
+    // other entities compute their FOV only when it is needed
+    let gol_pos = Pos::new(7, 7);
+    data.move_entity(gol, gol_pos);
+    assert_eq!(Some(gol), data.blocking_entity_at(gol_pos));
+    assert!(!data.map.fov_cache.borrow().contains_key(&gol_pos));
+}
+
+#[test]
+fn test_pos_index_consistent() {
+    let mut data = GameData::new(Map::from_dims(10, 10), Entities::new());
+    let start = Pos::new(2, 2);
+    let end = Pos::new(5, 5);
+
+    let first = data.entities.create_entity(start.x, start.y, EntityType::Item, ' ', Color::white(), EntityName::Stone, false);
+    let second = data.entities.create_entity(start.x, start.y, EntityType::Column, ' ', Color::white(), EntityName::Column, true);
+    assert_eq!(vec!(first, second), data.entities_at(start).to_vec());
+
+    // moving updates both the old and new position
+    data.entities.set_pos(second, end);
+    assert_eq!(vec!(first), data.entities_at(start).to_vec());
+    assert_eq!(Some(second), data.blocking_entity_at(end));
+
+    // moving to the same position does not duplicate the entry
+    data.entities.set_pos(second, end);
+    assert_eq!(1, data.entities_at(end).len());
+
+    // removed entities are no longer indexed
+    data.entities.remove_entity(second);
+    assert!(data.entities_at(end).is_empty());
+    assert!(!data.entities.pos_index.contains_key(&end));
+
+    // cloned entities are indexed at their position
+    let mut other = Entities::new();
+    other.merge(&data.entities);
+    assert_eq!(1, other.ids_at(start).len());
+
+    // the index always agrees with the pos component
+    for (id, pos) in data.entities.pos.iter() {
+        assert!(data.entities.ids_at(*pos).contains(id));
+    }
+
+    // the index is not serialized, and is rebuilt when loading
+    let entities_string = serde_yaml::to_string(&data.entities).unwrap();
+    let loaded: Entities = serde_yaml::from_str(&entities_string).unwrap();
+    assert_eq!(vec!(first), loaded.ids_at(start).to_vec());
+    assert_eq!(data.entities.pos_index, loaded.pos_index);
+}
+
+#[test]
+fn test_effects() {
+    let mut entities = Entities::new();
+    let gol = entities.create_entity(1, 1, EntityType::Enemy, ' ', Color::white(), EntityName::Gol, true);
+
+    assert!(entities.can_act(gol));
+    assert_eq!(1, entities.actions_per_turn(gol));
+
+    // reapplying an effect keeps the longer duration
+    entities.add_effect(gol, EffectKind::Frozen, 3);
+    entities.add_effect(gol, EffectKind::Frozen, 1);
+    assert_eq!(3, entities.effect_turns_left(gol, EffectKind::Frozen));
+    assert_eq!(1, entities.effects[&gol].len());
+    assert!(!entities.can_act(gol));
+
+    entities.effects[&gol].clear();
+    entities.add_effect(gol, EffectKind::Hasted, 2);
+    assert_eq!(2, entities.actions_per_turn(gol));
+
+    // slowed entities act on alternating turns
+    entities.add_effect(gol, EffectKind::Slowed, 2);
+    assert!(entities.can_act(gol));
+    entities.effects[&gol].iter_mut().for_each(|effect| effect.turns_left -= 1);
+    assert!(!entities.can_act(gol));
+
+    // an effect with no turns is not applied
+    entities.add_effect(gol, EffectKind::Poisoned, 0);
+    assert!(!entities.has_effect(gol, EffectKind::Poisoned));
+}
+
+#[test]
+fn test_remove_entity_in_inventory() {
+    let mut entities = Entities::new();
+
+    let holder = entities.create_entity(1, 1, EntityType::Player, ' ', Color::white(), EntityName::Player, true);
+    let stone = entities.create_entity(1, 1, EntityType::Item, ' ', Color::white(), EntityName::Stone, false);
+    let dagger = entities.create_entity(1, 1, EntityType::Item, ' ', Color::white(), EntityName::Dagger, false);
+    entities.inventory.insert(holder, VecDeque::new());
+    entities.item.insert(stone, Item::Stone);
+    entities.item.insert(dagger, Item::Dagger);
+    entities.pick_up_item(holder, stone);
+    entities.pick_up_item(holder, dagger);
+    entities.selected_item.insert(holder, stone);
+
+    entities.remove_entity(stone);
+
+    // the removed item is purged from the holder's inventory and selection
+    assert_eq!(1, entities.inventory[&holder].len());
+    assert!(entities.inventory[&holder].contains(&dagger));
+    assert!(entities.selected_item.get(&holder).is_none());
+    assert!(!entities.ids.contains(&stone));
+    assert!(entities.ids_at(Pos::new(-1, -1)).contains(&dagger));
+
+    // monsters attacking or fleeing from a removed entity go back to idle
+    let attacker = entities.create_entity(2, 2, EntityType::Enemy, ' ', Color::white(), EntityName::Gol, true);
+    let fleeing = entities.create_entity(3, 3, EntityType::Enemy, ' ', Color::white(), EntityName::Gol, true);
+    entities.behavior.insert(attacker, Behavior::Attacking(holder, Pos::new(1, 1)));
+    entities.behavior.insert(fleeing, Behavior::Fleeing(holder));
+    entities.remove_entity(holder);
+    assert_eq!(Behavior::Idle, entities.behavior[&attacker]);
+    assert_eq!(Behavior::Idle, entities.behavior[&fleeing]);
+    assert!(!entities.is_referenced(holder));
+}
+
+#[test]
+fn test_using_two_items() {
+    let mut data = GameData::new(Map::from_dims(10, 10), Entities::new());
+
+    let holder = data.entities.create_entity(1, 1, EntityType::Player, ' ', Color::white(), EntityName::Player, true);
+    data.entities.inventory.insert(holder, VecDeque::new());
+    assert_eq!((None, None), data.equipped(holder));
+
+    let mut pick_up = |name: EntityName, item: Item| {
+        let item_id = data.entities.create_entity(1, 1, EntityType::Item, ' ', Color::white(), name, false);
+        data.entities.item.insert(item_id, item);
+        data.entities.pick_up_item(holder, item_id);
+        return item_id;
+    };
+    let stone = pick_up(EntityName::Stone, Item::Stone);
+    let hammer = pick_up(EntityName::Hammer, Item::Hammer);
+    let shield = pick_up(EntityName::Shield, Item::Shield);
+
+    // both primary items are wielded, and the stone is carried but not used
+    assert_eq!((Some(Item::Shield), Some(Item::Hammer)), data.equipped(holder));
+    assert_eq!(Some(hammer), data.using(holder, Item::Hammer));
+    assert_eq!(Some(shield), data.using(holder, Item::Shield));
+    assert_eq!(None, data.using(holder, Item::Stone));
+    assert!(data.entities.inventory[&holder].contains(&stone));
+    assert_eq!(None, data.using(holder, Item::Sword));
+}
+
+#[test]
+fn test_clear_path_traps_block() {
+    let mut data = GameData::new(Map::from_dims(10, 3), Entities::new());
+    let start = Pos::new(0, 1);
+    let end = Pos::new(6, 1);
+
+    let trap = data.entities.create_entity(3, 1, EntityType::Item, ' ', Color::white(), EntityName::SpikeTrap, false);
+    data.entities.trap.insert(trap, Trap::Spikes);
+    data.entities.armed.insert(trap, true);
+
+    // an armed trap only blocks the path when traps are considered
+    assert!(data.clear_path(start, end, false));
+    assert!(!data.clear_path(start, end, true));
+
+    // a disarmed trap never blocks
+    data.entities.armed[&trap] = false;
+    assert!(data.clear_path(start, end, true));
+
+    // walls block regardless of traps
+    data.map[(5, 1)].block_move = true;
+    assert!(!data.clear_path(start, end, false));
+    assert!(!data.clear_path(start, end, true));
+    data.map[(5, 1)].block_move = false;
+
+    // as do blocking entities
+    data.entities.create_entity(4, 1, EntityType::Column, ' ', Color::white(), EntityName::Column, true);
+    assert!(!data.clear_path(start, end, false));
+}
+
 #[derive(Clone, Copy, Debug, Eq, PartialEq, PartialOrd, Ord, Serialize, Deserialize)]
 pub enum Trap {
     Spikes,
@@ -435,6 +911,70 @@ impl Color {
                       a,
         };
     }
+
+    pub fn with_alpha(&self, a: u8) -> Color {
+        return Color::new(self.r, self.g, self.b, a);
+    }
+
+    /// Multiply the alpha by a factor, such as 0.5 for half as opaque.
+    pub fn scale_alpha(&self, factor: f32) -> Color {
+        let a = (self.a as f32 * factor).round().max(0.0).min(255.0) as u8;
+        return self.with_alpha(a);
+    }
+
+    /// Composite this color over a background color (the 'over' operator),
+    /// giving the color seen when this color is drawn on top of the background.
+    pub fn over(&self, background: Color) -> Color {
+        let alpha = self.a as f32 / 255.0;
+        let background_alpha = background.a as f32 / 255.0;
+
+        let out_alpha = alpha + background_alpha * (1.0 - alpha);
+        if out_alpha <= 0.0 {
+            return Color::new(0, 0, 0, 0);
+        }
+
+        let blend = |channel: u8, background_channel: u8| {
+            let value = (channel as f32 * alpha +
+                         background_channel as f32 * background_alpha * (1.0 - alpha)) / out_alpha;
+            return value.round() as u8;
+        };
+
+        return Color::new(blend(self.r, background.r),
+                          blend(self.g, background.g),
+                          blend(self.b, background.b),
+                          (out_alpha * 255.0).round() as u8);
+    }
+}
+
+#[test]
+fn test_color_scale_alpha() {
+    let color = Color::new(10, 20, 30, 200);
+
+    assert_eq!(Color::new(10, 20, 30, 50), color.with_alpha(50));
+    assert_eq!(Color::new(10, 20, 30, 100), color.scale_alpha(0.5));
+    assert_eq!(Color::new(10, 20, 30, 25), color.scale_alpha(1.0 / 8.0));
+    assert_eq!(0, color.scale_alpha(0.0).a);
+
+    // scaling up is clamped to fully opaque
+    assert_eq!(255, color.scale_alpha(4.0).a);
+}
+
+#[test]
+fn test_color_over() {
+    let red = Color::new(255, 0, 0, 255);
+    let blue = Color::new(0, 0, 255, 255);
+
+    // opaque colors hide the background, and transparent ones show it unchanged
+    assert_eq!(red, red.over(blue));
+    assert_eq!(blue, red.with_alpha(0).over(blue));
+
+    // half transparent colors mix evenly with an opaque background
+    assert_eq!(Color::new(128, 0, 127, 255), red.with_alpha(128).over(blue));
+
+    // two half transparent colors give a more opaque result
+    let mixed = red.with_alpha(128).over(blue.with_alpha(128));
+    assert_eq!(192, mixed.a);
+    assert!(mixed.r > mixed.b);
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Ord, PartialOrd, Serialize, Deserialize)]
@@ -445,7 +985,10 @@ pub enum GameState {
     Inventory,
     SkillMenu,
     ClassMenu,
+    OverlayMenu,
+    SettingsMenu,
     ConfirmQuit,
+    Editor,
     Exit,
 }
 
@@ -458,7 +1001,10 @@ impl fmt::Display for GameState {
             GameState::Inventory => write!(f, "inventory"),
             GameState::SkillMenu => write!(f, "skillmenu"),
             GameState::ClassMenu => write!(f, "classmenu"),
+            GameState::OverlayMenu => write!(f, "overlaymenu"),
+            GameState::SettingsMenu => write!(f, "settingsmenu"),
             GameState::ConfirmQuit => write!(f, "confirmquit"),
+            GameState::Editor => write!(f, "editor"),
             GameState::Exit => write!(f, "exit"),
         }
     }
@@ -474,7 +1020,9 @@ impl GameState {
     pub fn is_menu(self) -> bool {
         return self == GameState::Inventory ||
                self == GameState::SkillMenu ||
-               self == GameState::ClassMenu;
+               self == GameState::ClassMenu ||
+               self == GameState::OverlayMenu ||
+               self == GameState::SettingsMenu;
     }
 }
 
@@ -519,6 +1067,9 @@ pub enum Item {
     Shield,
     Hammer,
     Sword,
+    Spear,
+    Cloak,
+    Torch,
     Lantern,
     SpikeTrap,
     SoundTrap,
@@ -535,6 +1086,9 @@ impl fmt::Display for Item {
             Item::Shield => write!(f, "shield"),
             Item::Hammer => write!(f, "hammer"),
             Item::Sword => write!(f, "sword"),
+            Item::Spear => write!(f, "spear"),
+            Item::Cloak => write!(f, "cloak"),
+            Item::Torch => write!(f, "torch"),
             Item::Lantern => write!(f, "lantern"),
             Item::SpikeTrap => write!(f, "spiketrap"),
             Item::SoundTrap => write!(f, "soundtrap"),
@@ -562,6 +1116,12 @@ impl FromStr for Item {
             return Ok(Item::Hammer);
         } else if s == "sword" {
             return Ok(Item::Sword);
+        } else if s == "spear" {
+            return Ok(Item::Spear);
+        } else if s == "cloak" {
+            return Ok(Item::Cloak);
+        } else if s == "torch" {
+            return Ok(Item::Torch);
         } else if s == "spiketrap" {
             return Ok(Item::SpikeTrap);
         } else if s == "soundtrap" {
@@ -585,6 +1145,9 @@ impl Item {
             Item::Shield => ItemClass::Primary,
             Item::Hammer => ItemClass::Primary,
             Item::Sword => ItemClass::Primary,
+            Item::Spear => ItemClass::Primary,
+            Item::Cloak => ItemClass::Secondary,
+            Item::Torch => ItemClass::Secondary,
             Item::Lantern => ItemClass::Secondary,
             Item::SpikeTrap => ItemClass::Secondary,
             Item::SoundTrap => ItemClass::Secondary,
@@ -593,6 +1156,26 @@ impl Item {
         }
     }
 
+    /// The group an item is listed under in the inventory.
+    pub fn group(&self) -> ItemGroup {
+        match self {
+            Item::Stone => ItemGroup::Consumable,
+            Item::Key => ItemGroup::Key,
+            Item::Dagger => ItemGroup::Weapon,
+            Item::Shield => ItemGroup::Weapon,
+            Item::Hammer => ItemGroup::Weapon,
+            Item::Sword => ItemGroup::Weapon,
+            Item::Spear => ItemGroup::Weapon,
+            Item::Cloak => ItemGroup::Tool,
+            Item::Torch => ItemGroup::Tool,
+            Item::Lantern => ItemGroup::Tool,
+            Item::SpikeTrap => ItemGroup::Consumable,
+            Item::SoundTrap => ItemGroup::Consumable,
+            Item::BlinkTrap => ItemGroup::Consumable,
+            Item::FreezeTrap => ItemGroup::Consumable,
+        }
+    }
+
     pub fn name(&self) -> EntityName {
         match self {
             Item::Stone => EntityName::Stone,
@@ -601,6 +1184,9 @@ impl Item {
             Item::Shield => EntityName::Shield,
             Item::Hammer => EntityName::Hammer,
             Item::Sword => EntityName::Sword,
+            Item::Spear => EntityName::Spear,
+            Item::Cloak => EntityName::Cloak,
+            Item::Torch => EntityName::Torch,
             Item::Lantern => EntityName::Lantern,
             Item::SpikeTrap => EntityName::SpikeTrap,
             Item::SoundTrap => EntityName::SoundTrap,
@@ -616,6 +1202,26 @@ pub enum ItemClass {
     Secondary,
 }
 
+/// Groups of items used to organize the inventory, listed in display order.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Ord, PartialOrd, Serialize, Deserialize)]
+pub enum ItemGroup {
+    Weapon,
+    Tool,
+    Consumable,
+    Key,
+}
+
+impl fmt::Display for ItemGroup {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ItemGroup::Weapon => write!(f, "weapons"),
+            ItemGroup::Tool => write!(f, "tools"),
+            ItemGroup::Consumable => write!(f, "consumables"),
+            ItemGroup::Key => write!(f, "keys"),
+        }
+    }
+}
+
 pub type Hp = i32;
 
 #[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
@@ -696,6 +1302,9 @@ pub enum EntityName {
     Dagger,
     Hammer,
     Sword,
+    Spear,
+    Cloak,
+    Torch,
     Shield,
     Lantern,
     Spire,
@@ -718,6 +1327,47 @@ impl Default for EntityName {
     }
 }
 
+impl EntityName {
+    /// A lowercase name with an article for use in messages, such as "a gol",
+    /// "an armil", or "the gol" when definite. The player is always "you".
+    pub fn display_name(&self, definite: bool) -> String {
+        if *self == EntityName::Player {
+            return "you".to_string();
+        }
+
+        // split the variant name into words, so SpikeTrap becomes "spike trap"
+        let mut noun = String::new();
+        for chr in format!("{:?}", self).chars() {
+            if chr.is_uppercase() && !noun.is_empty() {
+                noun.push(' ');
+            }
+            noun.push(chr.to_ascii_lowercase());
+        }
+
+        let article =
+            if definite {
+                "the"
+            } else if noun.starts_with(|chr: char| "aeiou".contains(chr)) {
+                "an"
+            } else {
+                "a"
+            };
+
+        return format!("{} {}", article, noun);
+    }
+}
+
+#[test]
+fn test_entity_name_display_name() {
+    assert_eq!("a gol", EntityName::Gol.display_name(false));
+    assert_eq!("the gol", EntityName::Gol.display_name(true));
+    assert_eq!("an armil", EntityName::Armil.display_name(false));
+    assert_eq!("an energy", EntityName::Energy.display_name(false));
+    assert_eq!("a spike trap", EntityName::SpikeTrap.display_name(false));
+    assert_eq!("you", EntityName::Player.display_name(false));
+    assert_eq!("you", EntityName::Player.display_name(true));
+}
+
 impl fmt::Display for EntityName {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
@@ -731,6 +1381,9 @@ impl fmt::Display for EntityName {
             EntityName::Dagger => write!(f, "dagger"),
             EntityName::Hammer => write!(f, "hammer"),
             EntityName::Sword => write!(f, "sword"),
+            EntityName::Spear => write!(f, "spear"),
+            EntityName::Cloak => write!(f, "cloak"),
+            EntityName::Torch => write!(f, "torch"),
             EntityName::Lantern => write!(f, "lantern"),
             EntityName::Shield => write!(f, "shield"),
             EntityName::Spire => write!(f, "spire"),
@@ -776,6 +1429,12 @@ impl FromStr for EntityName {
             return Ok(EntityName::Hammer);
         } else if s == "sword" {
             return Ok(EntityName::Sword);
+        } else if s == "spear" {
+            return Ok(EntityName::Spear);
+        } else if s == "cloak" {
+            return Ok(EntityName::Cloak);
+        } else if s == "torch" {
+            return Ok(EntityName::Torch);
         } else if s == "lantern" {
             return Ok(EntityName::Lantern);
         } else if s == "shield" {
@@ -905,7 +1564,6 @@ impl EntityClass {
 
 #[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Ord, PartialOrd, Serialize, Deserialize)]
 pub struct StatusEffect {
-    pub frozen: usize, // turns
     pub soft_steps: usize, // turns
     pub illuminate: usize, // radius
     pub extra_fov: usize, // amount
@@ -914,6 +1572,66 @@ pub struct StatusEffect {
     pub alive: bool,
 }
 
+/// An active cloak hides an entity from monsters for a number of turns.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Cloaked {
+    pub turns_left: usize,
+}
+
+impl Cloaked {
+    pub fn new(turns_left: usize) -> Cloaked {
+        return Cloaked { turns_left };
+    }
+}
+
+/// The kinds of timed effects an entity can be under.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Ord, PartialOrd, Serialize, Deserialize)]
+pub enum EffectKind {
+    Frozen,
+    Poisoned,
+    Hasted,
+    Slowed,
+}
+
+impl fmt::Display for EffectKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EffectKind::Frozen => write!(f, "frozen"),
+            EffectKind::Poisoned => write!(f, "poisoned"),
+            EffectKind::Hasted => write!(f, "hasted"),
+            EffectKind::Slowed => write!(f, "slowed"),
+        }
+    }
+}
+
+/// A timed effect on an entity. Effects count down at the end of each turn
+/// and are removed once no turns are left.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Effect {
+    pub kind: EffectKind,
+    pub turns_left: usize,
+}
+
+impl Effect {
+    pub fn new(kind: EffectKind, turns_left: usize) -> Effect {
+        return Effect { kind, turns_left };
+    }
+}
+
+/// A torch gives light while it has fuel, burning one unit each turn. Torches
+/// start unlit, are lit by holding them, and stay lit if put down.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Torch {
+    pub fuel: usize,
+    pub lit: bool,
+}
+
+impl Torch {
+    pub fn new(fuel: usize) -> Torch {
+        return Torch { fuel, lit: false };
+    }
+}
+
 #[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
 pub enum Message {
     Sound(EntityId, Pos),
@@ -923,7 +1641,10 @@ pub enum Message {
 // ensure that each entity has a unique ID, up to 2^64 entities
 static OBJECT_ID_COUNT: AtomicU64 = AtomicU64::new(0);
 
+// serialization goes through the impls below, so the position index can be
+// rebuilt when the entities are loaded.
 #[derive(Clone, Debug, PartialEq, Default, Serialize, Deserialize)]
+#[serde(remote = "Self")]
 pub struct Entities {
     pub ids: Vec<EntityId>,
     pub pos: CompStore<Pos>,
@@ -951,16 +1672,42 @@ pub struct Entities {
     pub gate_pos: CompStore<Option<Pos>>,
     pub stance: CompStore<Stance>,
     pub took_turn: CompStore<bool>,
+    pub momentum: CompStore<Momentum>,
+    pub cloaked: CompStore<Cloaked>,
+    pub torch: CompStore<Torch>,
+    pub patrol: CompStore<Vec<Pos>>,
+    // where an entity was placed when its map file was loaded
+    pub spawn_pos: CompStore<Pos>,
+    pub effects: CompStore<Vec<Effect>>,
 
     // NOTE not sure about keeping these ones, or packaging into larger ones
     pub sound: CompStore<Pos>, // source position
     pub typ: CompStore<EntityType>,
     pub color: CompStore<Color>,
     pub blocks: CompStore<bool>,
+    pub blocks_sight: CompStore<bool>,
     pub needs_removal: CompStore<bool>,
 
     // NOTE should be able to remove this
     pub messages: CompStore<Vec<Message>>,
+
+    // index of entities by position, kept in sync with pos by set_pos
+    #[serde(skip)]
+    pub pos_index: HashMap<Pos, SmallVec<[EntityId; 4]>>,
+}
+
+impl Serialize for Entities {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        return Entities::serialize(self, serializer);
+    }
+}
+
+impl<'de> Deserialize<'de> for Entities {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Entities, D::Error> {
+        let mut entities = Entities::deserialize(deserializer)?;
+        entities.rebuild_pos_index();
+        return Ok(entities);
+    }
 }
 
 impl Entities {
@@ -1003,11 +1750,12 @@ impl Entities {
     }
 
     pub fn create_entity(&mut self, x: i32, y: i32, typ: EntityType, chr: char, color: Color, name: EntityName, blocks: bool) -> EntityId {
-        let id = OBJECT_ID_COUNT.fetch_add(1, Ordering::SeqCst);
+        let id = EntityId(OBJECT_ID_COUNT.fetch_add(1, Ordering::SeqCst));
         self.ids.push(id);
 
         // add fields that all entities share
         self.pos.insert(id, Pos::new(x, y));
+        self.index_pos(id, Pos::new(x, y));
         self.typ.insert(id, typ);
         self.chr.insert(id, chr);
         self.color.insert(id, color);
@@ -1023,11 +1771,54 @@ impl Entities {
     }
 
     pub fn set_xy(&mut self, entity_id: EntityId, x: i32, y: i32) {
-        self.pos[&entity_id] = Pos::new(x, y);
+        self.set_pos(entity_id, Pos::new(x, y));
     }
 
+    /// Move an entity, keeping the position index up to date. Entity positions
+    /// should always be changed through this function rather than through pos.
+    /// Moves made during the game go through GameData::move_entity instead.
     pub fn set_pos(&mut self, entity_id: EntityId, pos: Pos) {
+        let old_pos = self.pos[&entity_id];
+        self.unindex_pos(entity_id, old_pos);
+
         self.pos[&entity_id] = pos;
+        self.index_pos(entity_id, pos);
+    }
+
+    /// The entities at a position, in the order they arrived there.
+    pub fn ids_at(&self, pos: Pos) -> &[EntityId] {
+        if let Some(ids) = self.pos_index.get(&pos) {
+            return ids.as_slice();
+        }
+
+        return &[];
+    }
+
+    /// Index every entity at its current position, replacing the existing index.
+    pub fn rebuild_pos_index(&mut self) {
+        self.pos_index.clear();
+
+        for index in 0..self.ids.len() {
+            let entity_id = self.ids[index];
+            if let Some(pos) = self.pos.get(&entity_id) {
+                let pos = *pos;
+                self.index_pos(entity_id, pos);
+            }
+        }
+    }
+
+    fn index_pos(&mut self, entity_id: EntityId, pos: Pos) {
+        self.pos_index.entry(pos).or_insert_with(SmallVec::new).push(entity_id);
+    }
+
+    fn unindex_pos(&mut self, entity_id: EntityId, pos: Pos) {
+        if let Some(ids) = self.pos_index.get_mut(&pos) {
+            ids.retain(|id| *id != entity_id);
+
+            if ids.is_empty() {
+                self.pos_index.remove(&pos);
+            }
+        }
     }
 
     pub fn move_next_to(&mut self, entity_id: EntityId, pos: Pos) {
@@ -1103,18 +1894,16 @@ impl Entities {
     }
 
     pub fn face(&mut self, entity_id: EntityId, face_pos: Pos) {
-        let diff = sub_pos(face_pos, self.pos[&entity_id]);
-        if let Some(dir) = Direction::from_dxy(diff.x, diff.y) {
+        if let Some(dir) = Direction::from_positions(self.pos[&entity_id], face_pos) {
             self.direction[&entity_id] = dir;
         }
     }
 
     pub fn face_to(&mut self, entity_id: EntityId, face_pos: Pos) -> Direction {
-        let diff = sub_pos(face_pos, self.pos[&entity_id]);
-        if let Some(dir) = Direction::from_dxy(diff.x, diff.y) {
+        if let Some(dir) = Direction::from_positions(self.pos[&entity_id], face_pos) {
             return dir;
         }
-        panic!(format!("Facing with diff ({}, {}) not valid!", diff.x, diff.y));
+        panic!(format!("Facing from {:?} to {:?} not valid!", self.pos[&entity_id], face_pos));
     }
 
     pub fn target(&self, entity_id: EntityId) -> Option<Pos> {
@@ -1122,7 +1911,7 @@ impl Entities {
             return Some(*target_pos);
         }
 
-        if let Some(Behavior::Attacking(target_id)) = self.behavior.get(&entity_id) {
+        if let Some(Behavior::Attacking(target_id, _last_seen)) = self.behavior.get(&entity_id) {
             let target_pos = self.pos[target_id];
             return Some(target_pos);
         }
@@ -1130,6 +1919,55 @@ impl Entities {
         return None;
     }
 
+    /// Apply a timed effect to an entity. An effect the entity is already under
+    /// lasts for the longer of its remaining turns and the new turns.
+    pub fn add_effect(&mut self, entity_id: EntityId, kind: EffectKind, turns: usize) {
+        if turns == 0 {
+            return;
+        }
+
+        if self.effects.get(&entity_id).is_none() {
+            self.effects.insert(entity_id, Vec::new());
+        }
+
+        let effects = &mut self.effects[&entity_id];
+        if let Some(effect) = effects.iter_mut().find(|effect| effect.kind == kind) {
+            effect.turns_left = std::cmp::max(effect.turns_left, turns);
+        } else {
+            effects.push(Effect::new(kind, turns));
+        }
+    }
+
+    /// The turns left on an effect, or 0 if the entity is not under it.
+    pub fn effect_turns_left(&self, entity_id: EntityId, kind: EffectKind) -> usize {
+        return self.effects.get(&entity_id)
+                           .and_then(|effects| effects.iter().find(|effect| effect.kind == kind))
+                           .map_or(0, |effect| effect.turns_left);
+    }
+
+    pub fn has_effect(&self, entity_id: EntityId, kind: EffectKind) -> bool {
+        return self.effect_turns_left(entity_id, kind) > 0;
+    }
+
+    /// Whether an entity may act this turn. Frozen entities cannot act, and
+    /// slowed entities only act on every other turn of the effect.
+    pub fn can_act(&self, entity_id: EntityId) -> bool {
+        if self.has_effect(entity_id, EffectKind::Frozen) {
+            return false;
+        }
+
+        return self.effect_turns_left(entity_id, EffectKind::Slowed) % 2 == 0;
+    }
+
+    /// The number of actions an entity takes in a turn, which is doubled while hasted.
+    pub fn actions_per_turn(&self, entity_id: EntityId) -> usize {
+        if self.has_effect(entity_id, EffectKind::Hasted) {
+            return 2;
+        }
+
+        return 1;
+    }
+
     pub fn is_dead(&self, entity_id: EntityId) -> bool {
         return !self.ids.contains(&entity_id) || self.needs_removal[&entity_id] ||
             matches!(self.fighter.get(&entity_id), Some(Fighter { hp: 0, .. } ));
@@ -1183,7 +2021,7 @@ impl Entities {
     // NOTE cloning entities may not remap all entity ids that an entity tracks!
     // this could cause subtle problems, so this is really only for level generation.
     pub fn clone_entity(&mut self, other: &Entities, entity_id: EntityId) {
-        let new_id = OBJECT_ID_COUNT.fetch_add(1, Ordering::SeqCst);
+        let new_id = EntityId(OBJECT_ID_COUNT.fetch_add(1, Ordering::SeqCst));
 
         self.ids.push(new_id);
 
@@ -1198,6 +2036,10 @@ impl Entities {
         }
 
         move_component!(pos);
+        if let Some(pos) = self.pos.get(&new_id) {
+            let pos = *pos;
+            self.index_pos(new_id, pos);
+        }
         move_component!(chr);
         move_component!(name);
         move_component!(fighter);
@@ -1210,6 +2052,7 @@ impl Entities {
         move_component!(movement);
         move_component!(attack);
         move_component!(trap);
+        move_component!(armed);
         move_component!(energy);
         move_component!(count_down);
         move_component!(move_mode);
@@ -1222,8 +2065,15 @@ impl Entities {
         move_component!(status);
         move_component!(gate_pos);
         move_component!(took_turn);
+        move_component!(momentum);
+        move_component!(cloaked);
+        move_component!(torch);
+        move_component!(patrol);
+        move_component!(spawn_pos);
+        move_component!(effects);
         move_component!(color);
         move_component!(blocks);
+        move_component!(blocks_sight);
         move_component!(needs_removal);
         move_component!(messages);
 
@@ -1243,7 +2093,9 @@ impl Entities {
         let ix_pos = self.ids.iter().position(|val| *val == id).unwrap();
         self.ids.remove(ix_pos);
 
-        self.pos.remove(&id);
+        if let Some(pos) = self.pos.remove(&id) {
+            self.unindex_pos(id, pos);
+        }
         self.chr.remove(&id);
         self.name.remove(&id);
         self.fighter.remove(&id);
@@ -1270,10 +2122,87 @@ impl Entities {
         self.status.remove(&id);
         self.gate_pos.remove(&id);
         self.took_turn.remove(&id);
+        self.momentum.remove(&id);
+        self.cloaked.remove(&id);
+        self.torch.remove(&id);
+        self.patrol.remove(&id);
+        self.spawn_pos.remove(&id);
+        self.effects.remove(&id);
         self.color.remove(&id);
         self.blocks.remove(&id);
+        self.blocks_sight.remove(&id);
         self.needs_removal.remove(&id);
         self.messages.remove(&id);
+
+        self.remove_references(id);
+
+        debug_assert!(!self.is_referenced(id), "entity {} still referenced after removal", id);
+    }
+
+    /// Remove references to an entity held by other entities' components, such as
+    /// an item in an inventory or a monster attacking the removed entity.
+    fn remove_references(&mut self, id: EntityId) {
+        for inventory in self.inventory.values_mut() {
+            inventory.retain(|item_id| *item_id != id);
+        }
+
+        self.selected_item.retain(|_, item_id| *item_id != id);
+
+        for behavior in self.behavior.values_mut() {
+            if behavior_references(behavior, id) {
+                *behavior = Behavior::Idle;
+            }
+        }
+
+        for messages in self.messages.values_mut() {
+            messages.retain(|message| {
+                match message {
+                    Message::Sound(source_id, _) => *source_id != id,
+                    Message::Attack(attacker_id) => *attacker_id != id,
+                }
+            });
+        }
+    }
+
+    fn is_referenced(&self, id: EntityId) -> bool {
+        // this macro checks whether a component store has an entry for the id
+        macro_rules! has_component {
+            ($($comp_name:ident),*) => {
+                false $(|| self.$comp_name.contains_key(&id))*
+            }
+        }
+
+        let has_any_component =
+            has_component!(pos, chr, name, fighter, ai, behavior, fov_radius, attack_type, item,
+                           movement, attack, inventory, trap, armed, energy, count_down, move_mode,
+                           direction, selected_item, class, skills, status, gate_pos, stance,
+                           took_turn, momentum, cloaked, torch, patrol, spawn_pos, effects, sound, typ, color,
+                           blocks, blocks_sight, needs_removal, messages);
+
+        let held_by_component =
+            self.inventory.values().any(|inventory| inventory.contains(&id)) ||
+            self.selected_item.values().any(|item_id| *item_id == id) ||
+            self.behavior.values().any(|behavior| behavior_references(behavior, id)) ||
+            self.messages.values().any(|messages| messages.iter().any(|message| {
+                match message {
+                    Message::Sound(source_id, _) => *source_id == id,
+                    Message::Attack(attacker_id) => *attacker_id == id,
+                }
+            }));
+
+        return self.ids.contains(&id) ||
+               self.pos_index.values().any(|ids| ids.contains(&id)) ||
+               has_any_component ||
+               held_by_component;
+    }
+}
+
+/// Whether a behavior targets the given entity.
+fn behavior_references(behavior: &Behavior, id: EntityId) -> bool {
+    match behavior {
+        Behavior::Attacking(target_id, _) => return *target_id == id,
+        Behavior::Fleeing(target_id) => return *target_id == id,
+        _ => return false,
     }
 }
 
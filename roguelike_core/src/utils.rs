@@ -1,19 +1,21 @@
 use std::collections::HashSet;
 use std::collections::HashMap;
 use std::collections::hash_map::DefaultHasher;
+#[cfg(test)]
+use std::collections::VecDeque;
 use std::hash::{Hash, Hasher};
 
 use oorandom::Rand32;
 
 use crate::ai::Behavior;
-use crate::constants::{HAMMER_DAMAGE, SWORD_DAMAGE, TILE_FILL_METRIC_DIST};
+use crate::constants::{HAMMER_DAMAGE, SWORD_DAMAGE, SPEAR_DAMAGE, TILE_FILL_METRIC_DIST};
 use crate::map::{Surface};
 use crate::types::*;
 use crate::movement::{Reach, MoveMode, check_collision, MoveType, Direction};
 use crate::messaging::*;
 use crate::line::*;
 use crate::config::Config;
-use crate::map::{Map, AoeEffect, Aoe, Wall, astar_neighbors, TileType};
+use crate::map::{Map, AoeEffect, Aoe, Wall, TileType};
 use crate::constants::*;
 
 
@@ -80,6 +82,12 @@ pub fn distance_maximum(pos1: Pos, pos2: Pos) -> i32 {
     return std::cmp::max((pos1.x - pos2.x).abs(), (pos1.y - pos2.y).abs());
 }
 
+pub fn distance_squared(pos1: Pos, pos2: Pos) -> i32 {
+    let dx = pos1.x - pos2.x;
+    let dy = pos1.y - pos2.y;
+    return dx * dx + dy * dy;
+}
+
 pub fn pos_mag(pos: Pos) -> i32 {
     return distance(Pos::new(0, 0), pos);
 }
@@ -162,8 +170,8 @@ pub fn push_attack(entity_id: EntityId,
 
         msg_log.log_front(Msg::Moved(target, MoveType::Move, past_pos));
     } else {
-        if data.entities.status[&target].frozen == 0 {
-            data.entities.status[&target].frozen = config.push_stun_turns;
+        if !data.entities.has_effect(target, EffectKind::Frozen) {
+            data.entities.add_effect(target, EffectKind::Frozen, config.push_stun_turns);
         } else {
             // otherwise crush them against the wall/entity
             damage = data.entities.fighter[&target].hp;
@@ -225,8 +233,8 @@ pub fn attack(entity: EntityId, target: EntityId, data: &mut GameData, msg_log:
 
         if !data.map.path_blocked_move(other_pos, Pos::new(x_diff, y_diff)).is_some() &&
            !data.has_blocking_entity(past_pos).is_some() {
-            data.entities.set_pos(target, past_pos);
-            data.entities.set_pos(entity, other_pos);
+            data.move_entity(target, past_pos);
+            data.move_entity(entity, other_pos);
 
             data.entities.messages[&target].push(Message::Attack(entity));
         }
@@ -235,25 +243,38 @@ pub fn attack(entity: EntityId, target: EntityId, data: &mut GameData, msg_log:
         msg_log.log(Msg::Killed(entity, target, SWORD_DAMAGE));
     } else {
         // NOTE could add another section for the sword- currently the same as normal attacks
-        let damage = data.entities.fighter.get(&entity).map_or(0, |f| f.power) -
+        // the spear does fixed damage, and otherwise goes through the normal attack
+        let damage;
+        if data.using(entity, Item::Spear).is_some() {
+            damage = SPEAR_DAMAGE;
+        } else {
+            damage = data.entities.fighter.get(&entity).map_or(0, |f| f.power) -
                      data.entities.fighter.get(&target).map_or(0, |f| f.defense);
-        if damage > 0 && data.entities.status[&target].alive {
-            data.entities.take_damage(target, damage);
+        }
 
+        if damage > 0 && data.entities.status[&target].alive {
             msg_log.log(Msg::Attack(entity, target, damage));
             // TODO consider moving this to the Attack msg
-            if data.entities.fighter[&target].hp <= 0 {
-                data.entities.status[&target].alive = false;
-                data.entities.blocks[&target] = false;
-
-                msg_log.log(Msg::Killed(entity, target, damage));
-            }
+            deal_damage(entity, target, damage, data, msg_log);
 
             data.entities.messages[&target].push(Message::Attack(entity));
         }
     }
 }
 
+/// Apply damage to an entity, killing it if its hp runs out. The source is the
+/// entity credited with the kill.
+pub fn deal_damage(source: EntityId, target: EntityId, damage: i32, data: &mut GameData, msg_log: &mut MsgLog) {
+    data.entities.take_damage(target, damage);
+
+    if data.entities.fighter[&target].hp <= 0 {
+        data.entities.status[&target].alive = false;
+        data.entities.blocks[&target] = false;
+
+        msg_log.log(Msg::Killed(source, target, damage));
+    }
+}
+
 pub fn stab(entity_id: EntityId, target: EntityId, entities: &mut Entities, msg_log: &mut MsgLog) {
     let damage = entities.fighter.get(&target).map_or(0, |f| f.hp);
 
@@ -289,6 +310,46 @@ pub fn item_primary_at(entity_id: EntityId, entities: &mut Entities, index: usiz
     return is_primary;
 }
 
+/// An entity's inventory ordered by item group, and then by item name. Items
+/// which sort equally keep their inventory order. The inventory itself is not
+/// changed, as its order determines which items are equipped.
+pub fn sort_inventory(entities: &Entities, entity_id: EntityId) -> Vec<EntityId> {
+    let mut items = entities.inventory[&entity_id].iter().map(|id| *id).collect::<Vec<EntityId>>();
+
+    items.sort_by_key(|item_id| {
+        let item = entities.item[item_id];
+        return (item.group(), item.to_string());
+    });
+
+    return items;
+}
+
+#[test]
+fn test_sort_inventory() {
+    let mut entities = Entities::new();
+    let holder = entities.create_entity(0, 0, EntityType::Player, ' ', Color::white(), EntityName::Player, true);
+    entities.inventory.insert(holder, VecDeque::new());
+
+    let mut add_item = |name: EntityName, item: Item| {
+        let item_id = entities.create_entity(0, 0, EntityType::Item, ' ', Color::white(), name, false);
+        entities.item.insert(item_id, item);
+        entities.inventory[&holder].push_back(item_id);
+        return item_id;
+    };
+    let key = add_item(EntityName::Key, Item::Key);
+    let stone = add_item(EntityName::Stone, Item::Stone);
+    let sword = add_item(EntityName::Sword, Item::Sword);
+    let torch = add_item(EntityName::Torch, Item::Torch);
+    let spike_trap = add_item(EntityName::SpikeTrap, Item::SpikeTrap);
+    let dagger = add_item(EntityName::Dagger, Item::Dagger);
+
+    let sorted = sort_inventory(&entities, holder);
+    assert_eq!(vec!(dagger, sword, torch, spike_trap, stone, key), sorted);
+
+    // the inventory order is unchanged
+    assert_eq!(key, entities.inventory[&holder][0]);
+}
+
 pub fn add_pos(pos1: Pos, pos2: Pos) -> Pos {
     return Pos::new(pos1.x + pos2.x, pos1.y + pos2.y);
 }
@@ -358,10 +419,12 @@ pub fn reach_by_mode(move_mode: MoveMode) -> Reach {
 pub fn map_fill_metric(map: &Map) -> HashMap<Pos, usize> {
     let mut metric_map: HashMap<Pos, usize> = HashMap::new();
 
+    // the flood buffer is shared between tiles to avoid an allocation per tile
+    let mut flood = Vec::new();
     for y in 0..map.height() {
         for x in 0..map.width() {
             let pos = Pos::new(x, y);
-            let fill_metric = tile_fill_metric(map, pos);
+            let fill_metric = tile_fill_metric_into(map, pos, &mut flood);
             metric_map.insert(pos, fill_metric);
         }
     }
@@ -370,10 +433,14 @@ pub fn map_fill_metric(map: &Map) -> HashMap<Pos, usize> {
 }
 
 pub fn tile_fill_metric(map: &Map, pos: Pos) -> usize {
+    return tile_fill_metric_into(map, pos, &mut Vec::new());
+}
+
+fn tile_fill_metric_into(map: &Map, pos: Pos, flood: &mut Vec<Pos>) -> usize {
     if !map[pos].block_move && map[pos].tile_type != TileType::Water {
-        let near_count = floodfill(map, pos, TILE_FILL_METRIC_DIST).len();
+        floodfill_into(map, pos, TILE_FILL_METRIC_DIST, flood);
 
-        return near_count;
+        return flood.len();
     }
     return 0
 }
@@ -436,6 +503,16 @@ pub fn next_pos(pos: Pos, delta_pos: Pos) -> Pos {
     return next_pos;
 }
 
+/// Running costs energy each step, so an entity with too little energy
+/// cannot run. Entities without energy, such as monsters, can always run.
+pub fn can_run(data: &GameData, entity: EntityId, config: &Config) -> bool {
+    if let Some(energy) = data.entities.energy.get(&entity) {
+        return *energy >= config.run_energy_cost;
+    }
+
+    return true;
+}
+
 pub fn can_stab(data: &GameData, entity: EntityId, target: EntityId) -> bool {
     let entity_pos = data.entities.pos[&entity];
     let target_pos = data.entities.pos[&target];
@@ -444,11 +521,26 @@ pub fn can_stab(data: &GameData, entity: EntityId, target: EntityId) -> bool {
     let is_enemy = data.entities.typ[&target] == EntityType::Enemy;
     let using_dagger = data.using(entity, Item::Dagger).is_some();
     let clear_path = data.clear_path_up_to(entity_pos, target_pos, false);
-    let not_attacking = !matches!(data.entities.behavior.get(&target), Some(Behavior::Attacking(_)));
+    let not_attacking = !matches!(data.entities.behavior.get(&target), Some(Behavior::Attacking(_, _)));
 
     return is_enemy && using_dagger && clear_path && not_attacking;
 }
 
+/// A spear thrust reaches an enemy two tiles away in a straight line, as
+/// long as nothing is in between.
+pub fn can_spear(data: &GameData, entity: EntityId, target: EntityId) -> bool {
+    let entity_pos = data.entities.pos[&entity];
+    let target_pos = data.entities.pos[&target];
+
+    let is_enemy = data.entities.typ[&target] == EntityType::Enemy;
+    let using_spear = data.using(entity, Item::Spear).is_some();
+    let in_reach = Reach::Horiz(SPEAR_REACH).offsets().contains(&sub_pos(target_pos, entity_pos));
+    let clear_path = data.clear_path_up_to(entity_pos, target_pos, false) &&
+                     data.map.path_blocked_move(entity_pos, target_pos).is_none();
+
+    return is_enemy && using_spear && in_reach && clear_path;
+}
+
 pub fn dxy(start_pos: Pos, end_pos: Pos) -> (i32, i32) {
     return (end_pos.x - start_pos.x, end_pos.y - start_pos.y);
 }
@@ -747,30 +839,54 @@ fn test_floodfill_sound_not_through_blocked() {
 
 pub fn floodfill(map: &Map, start: Pos, radius: usize) -> Vec<Pos> {
     let mut flood: Vec<Pos> = Vec::new();
+    floodfill_into(map, start, radius, &mut flood);
+    return flood;
+}
 
-    let mut seen: Vec<Pos> = Vec::new();
-    let mut current: Vec<Pos> = Vec::new();
-    current.push(start);
-    seen.push(start);
+/// Floodfill into an existing buffer, which is cleared first. Callers which
+/// fill many times, such as once per tile, can reuse one buffer instead of
+/// allocating for each fill.
+pub fn floodfill_into(map: &Map, start: Pos, radius: usize, flood: &mut Vec<Pos>) {
+    floodfill_capped_into(map, start, radius, usize::MAX, flood);
+}
+
+/// Floodfill into an existing buffer, stopping once max_size positions are
+/// found. This is useful when only checking whether an area is large enough.
+pub fn floodfill_capped_into(map: &Map, start: Pos, radius: usize, max_size: usize, flood: &mut Vec<Pos>) {
+    flood.clear();
     flood.push(start);
 
+    // each ring of the fill is a range of the flood buffer, so no separate
+    // frontier list is needed.
+    let mut ring_start = 0;
     for _index in 0..radius {
-        let last = current.clone();
-        current.clear();
-        for pos in last.iter() {
-            let adj = astar_neighbors(map, start, *pos, Some(radius as i32));
-            for (next_pos, _cost) in adj {
-                if !seen.contains(&next_pos) {
-                    // record having seen this position.
-                    seen.push(next_pos);
-                    current.push(next_pos);
+        let ring_end = flood.len();
+
+        for ring_index in ring_start..ring_end {
+            let pos = flood[ring_index];
+            for next_pos in map.reachable_neighbors(pos) {
+                if flood.len() >= max_size {
+                    return;
+                }
+
+                // the radius limits each new position's distance from the start,
+                // while the ring count limits the number of steps taken
+                if distance(start, next_pos) > radius as i32 {
+                    continue;
+                }
+
+                if !flood.contains(&next_pos) {
                     flood.push(next_pos);
                 }
             }
         }
-    }
 
-    return flood;
+        // stop early if this ring found nothing new
+        if flood.len() == ring_end {
+            break;
+        }
+        ring_start = ring_end;
+    }
 }
 
 #[test]
@@ -809,6 +925,82 @@ fn test_floodfill() {
     assert_eq!(6, flood.len());
 }
 
+#[test]
+fn test_floodfill_radius() {
+    let mut map = Map::from_dims(10, 10);
+    let start = Pos::new(5, 5);
+
+    let square = |min_x: i32, max_x: i32, min_y: i32, max_y: i32| {
+        let mut positions = HashSet::new();
+        for x in min_x..=max_x {
+            for y in min_y..=max_y {
+                positions.insert(Pos::new(x, y));
+            }
+        }
+        return positions;
+    };
+
+    let flood = floodfill(&map, start, 2).into_iter().collect::<HashSet<Pos>>();
+    assert_eq!(square(3, 7, 3, 7), flood);
+
+    let flood = floodfill(&map, start, 3).into_iter().collect::<HashSet<Pos>>();
+    assert_eq!(square(2, 8, 2, 8), flood);
+
+    // a column of blocking tiles cuts the fill off on the right
+    for y in 0..10 {
+        map[(6, y)].block_move = true;
+    }
+
+    let flood = floodfill(&map, start, 2).into_iter().collect::<HashSet<Pos>>();
+    assert_eq!(square(3, 5, 3, 7), flood);
+
+    let flood = floodfill(&map, start, 3).into_iter().collect::<HashSet<Pos>>();
+    assert_eq!(square(2, 5, 2, 8), flood);
+}
+
+#[test]
+fn test_floodfill_into() {
+    // a 5x5 map with a blocked column at x = 2, open only at the bottom row
+    let mut map = Map::from_dims(5, 5);
+    for y in 0..4 {
+        map[(2, y)].block_move = true;
+    }
+
+    let positions = |list: &[(i32, i32)]| {
+        return list.iter().map(|(x, y)| Pos::new(*x, *y)).collect::<HashSet<Pos>>();
+    };
+
+    // the buffer starts with stale positions, which are cleared by the fill
+    let mut flood = vec!(Pos::new(4, 4), Pos::new(3, 3));
+    floodfill_into(&map, Pos::new(0, 0), 2, &mut flood);
+    assert_eq!(6, flood.len());
+    assert_eq!(positions(&[(0, 0), (1, 0), (0, 1), (1, 1), (0, 2), (1, 2)]),
+               flood.iter().map(|pos| *pos).collect::<HashSet<Pos>>());
+
+    // the same buffer is reused for a larger fill
+    floodfill_into(&map, Pos::new(0, 0), 3, &mut flood);
+    assert_eq!(8, flood.len());
+    assert_eq!(positions(&[(0, 0), (1, 0), (0, 1), (1, 1), (0, 2), (1, 2), (0, 3), (1, 3)]),
+               flood.iter().map(|pos| *pos).collect::<HashSet<Pos>>());
+
+    // and for a fill on the other side of the column
+    floodfill_into(&map, Pos::new(4, 4), 1, &mut flood);
+    assert_eq!(4, flood.len());
+    assert_eq!(positions(&[(3, 3), (4, 3), (3, 4), (4, 4)]),
+               flood.iter().map(|pos| *pos).collect::<HashSet<Pos>>());
+
+    // a capped fill stops at the cap, keeping the fill order
+    let mut map = Map::from_dims(10, 10);
+    map[(4, 4)].block_move = true;
+    let full = floodfill(&map, Pos::new(5, 5), 3);
+    floodfill_capped_into(&map, Pos::new(5, 5), 3, 10, &mut flood);
+    assert_eq!(10, flood.len());
+    assert_eq!(&full[0..10], &flood[..]);
+
+    floodfill_capped_into(&map, Pos::new(5, 5), 3, 1000, &mut flood);
+    assert_eq!(full, flood);
+}
+
 pub fn visible_in_direction(start_pos: Pos, end_pos: Pos, dir: Direction) -> bool {
     let pos_diff = sub_pos(end_pos, start_pos);
     let x_sig = pos_diff.x.signum();
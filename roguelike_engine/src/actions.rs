@@ -3,16 +3,23 @@ use std::fmt;
 
 use serde::{Serialize, Deserialize};
 
+use log::trace;
+
 use roguelike_core::movement::{Direction, Reach, MoveMode, MoveType};
 use roguelike_core::types::*;
 use roguelike_core::messaging::{Msg, MsgLog};
 use roguelike_core::constants::*;
 use roguelike_core::config::Config;
-use roguelike_core::utils::{scale_pos, distance, sub_pos, add_pos, next_from_to};
+use roguelike_core::utils::{scale_pos, distance, add_pos, next_from_to};
+#[cfg(test)]
+use roguelike_core::map::MapLoadConfig;
+#[cfg(test)]
+use crate::generation::{make_gol, make_stone};
 
 use crate::game::*;
 use crate::input::*;
 use crate::make_map;
+use crate::editor;
 
 
 
@@ -36,10 +43,12 @@ pub enum InputAction {
     CursorMove(Direction, bool, bool), // move direction, is relative, is long
     CursorReturn,
     CursorToggle,
+    CycleTarget,
     CursorApplyItem(ActionMode, usize),
     CursorApplySkill(ActionMode, usize),
     CursorApplyMove(ActionMode),
     MapClick(Pos, Pos), // map loc, map cell
+    MapRightClick(Pos, Pos), // map loc, map cell
     MouseButton(MouseClick, KeyDir),
     Inventory,
     SkillMenu,
@@ -54,7 +63,12 @@ pub enum InputAction {
     DecreaseMoveMode,
     OverlayOn,
     OverlayOff,
+    OverlayMenu,
+    SettingsMenu,
     SelectItem(usize),
+    EditorMode,
+    CycleBrush,
+    SaveMap,
     None,
 }
 
@@ -75,6 +89,7 @@ impl fmt::Display for InputAction {
             },
             InputAction::Pass(move_mode) => write!(f, "pass {}", move_mode),
             InputAction::MapClick(loc, cell) => write!(f, "click {} {} {} {}", loc.x, loc.y, cell.x, cell.y),
+            InputAction::MapRightClick(loc, cell) => write!(f, "rightclick {} {} {} {}", loc.x, loc.y, cell.x, cell.y),
             InputAction::MouseButton(click, keydir) => write!(f, "mousebutton {:?} {:?}", click, keydir),
             InputAction::Pickup => write!(f, "pickup"),
             InputAction::DropItem => write!(f, "drop"),
@@ -93,6 +108,8 @@ impl fmt::Display for InputAction {
             InputAction::DecreaseMoveMode => write!(f, "slower"),
             InputAction::OverlayOn => write!(f, "overlayon"),
             InputAction::OverlayOff => write!(f, "overlayoff"),
+            InputAction::OverlayMenu => write!(f, "overlaymenu"),
+            InputAction::SettingsMenu => write!(f, "settingsmenu"),
             InputAction::SelectItem(item) => write!(f, "selectitem {}", item),
             InputAction::UseItem(dir, target) => write!(f, "use, {:?} {}", dir, target),
             InputAction::Interact(dir) => write!(f, "interact {:?}", dir),
@@ -102,6 +119,10 @@ impl fmt::Display for InputAction {
             InputAction::CursorMove(dir, relative, long) => write!(f, "cursormove {:?} {} {}", dir, relative, long),
             InputAction::CursorReturn => write!(f, "cursorreturn"),
             InputAction::CursorToggle => write!(f, "cursortoggle"),
+            InputAction::CycleTarget => write!(f, "cycletarget"),
+            InputAction::EditorMode => write!(f, "editor"),
+            InputAction::CycleBrush => write!(f, "cyclebrush"),
+            InputAction::SaveMap => write!(f, "savemap"),
             InputAction::None => write!(f, "none"),
         }
     }
@@ -171,6 +192,12 @@ impl FromStr for InputAction {
             let cell_x = args[3].parse::<i32>().unwrap();
             let cell_y = args[4].parse::<i32>().unwrap();
             return Ok(InputAction::MapClick(Pos::new(loc_x, loc_y), Pos::new(cell_x, cell_y)));
+        } else if s.starts_with("rightclick") {
+            let loc_x = args[1].parse::<i32>().unwrap();
+            let loc_y = args[2].parse::<i32>().unwrap();
+            let cell_x = args[3].parse::<i32>().unwrap();
+            let cell_y = args[4].parse::<i32>().unwrap();
+            return Ok(InputAction::MapRightClick(Pos::new(loc_x, loc_y), Pos::new(cell_x, cell_y)));
         } else if args[0] == "skill" {
             return Ok(InputAction::SkillMenu);
         } else if args[0] == "class" {
@@ -203,6 +230,18 @@ impl FromStr for InputAction {
             return Ok(InputAction::CursorApplyMove(mode));
         } else if args[0] == "cursortoggle" {
             return Ok(InputAction::CursorToggle);
+        } else if args[0] == "cycletarget" {
+            return Ok(InputAction::CycleTarget);
+        } else if args[0] == "overlaymenu" {
+            return Ok(InputAction::OverlayMenu);
+        } else if args[0] == "settingsmenu" {
+            return Ok(InputAction::SettingsMenu);
+        } else if args[0] == "editor" {
+            return Ok(InputAction::EditorMode);
+        } else if args[0] == "cyclebrush" {
+            return Ok(InputAction::CycleBrush);
+        } else if args[0] == "savemap" {
+            return Ok(InputAction::SaveMap);
         } else {
             return Err(format!("Could not parse '{}' as InputAction", s));
         }
@@ -240,41 +279,151 @@ pub fn handle_input_universal(input_action: InputAction, game: &mut Game) -> boo
         }
 
         InputAction::ForceExit => {
-            change_state(&mut game.settings, GameState::Exit);
+            set_state(&mut game.settings, GameState::Exit);
             return true;
         }
 
         InputAction::Exit => {
-            if game.settings.state != GameState::ConfirmQuit {
-                change_state(&mut game.settings, GameState::ConfirmQuit);
+            if game.settings.state() != GameState::ConfirmQuit &&
+               game.settings.state() != GameState::Lose {
+                push_state(&mut game.settings, GameState::ConfirmQuit);
                 return true;
             } else {
                 return false;
             }
         }
 
+        InputAction::OverlayMenu => {
+            if game.settings.state() == GameState::OverlayMenu {
+                pop_state(&mut game.settings);
+            } else {
+                push_state(&mut game.settings, GameState::OverlayMenu);
+            }
+            return true;
+        }
+
+        InputAction::SettingsMenu => {
+            if game.settings.state() == GameState::SettingsMenu {
+                pop_state(&mut game.settings);
+            } else {
+                push_state(&mut game.settings, GameState::SettingsMenu);
+            }
+            return true;
+        }
+
+        InputAction::EditorMode => {
+            if game.settings.state() == GameState::Editor {
+                pop_state(&mut game.settings);
+            } else {
+                push_state(&mut game.settings, GameState::Editor);
+            }
+            return true;
+        }
+
         _ => {
+            // the overlay menu is handled here as it modifies the config
+            if game.settings.state() == GameState::OverlayMenu {
+                handle_input_overlay_menu(input_action, &mut game.settings, &mut game.config);
+                return true;
+            }
+
+            // the editor is handled here as it modifies the map
+            if game.settings.state() == GameState::Editor {
+                editor::handle_input_editor(input_action, game);
+                return true;
+            }
+
             return false;
         }
     }
 }
 
+pub const OVERLAY_NAMES: [&str; 5] = ["overlay", "directions", "player fov", "fov algorithm", "floodfill"];
+
+/// The current on/off state of each overlay, in the order of OVERLAY_NAMES.
+pub fn overlay_flags(settings: &GameSettings, config: &Config) -> [bool; 5] {
+    return [settings.overlay,
+            config.overlay_directions,
+            config.overlay_player_fov,
+            config.overlay_fov_alg,
+            config.overlay_floodfill];
+}
+
+pub fn toggle_overlay(overlay_index: usize, settings: &mut GameSettings, config: &mut Config) {
+    match overlay_index {
+        0 => settings.overlay = !settings.overlay,
+        1 => config.overlay_directions = !config.overlay_directions,
+        2 => config.overlay_player_fov = !config.overlay_player_fov,
+        3 => config.overlay_fov_alg = !config.overlay_fov_alg,
+        4 => config.overlay_floodfill = !config.overlay_floodfill,
+        _ => {},
+    }
+}
+
+pub fn handle_input_overlay_menu(input: InputAction, settings: &mut GameSettings, config: &mut Config) {
+    match input {
+        InputAction::SelectItem(overlay_index) => {
+            toggle_overlay(overlay_index, settings, config);
+        }
+
+        InputAction::Esc => {
+            pop_state(settings);
+        }
+
+        _ => {
+        }
+    }
+}
+
+pub const SETTINGS_NAMES: [&str; 3] = ["god mode", "overlay", "render map"];
+
+/// The current on/off state of each setting, in the order of SETTINGS_NAMES.
+pub fn settings_flags(settings: &GameSettings) -> [bool; 3] {
+    return [settings.god_mode,
+            settings.overlay,
+            settings.render_map];
+}
+
+pub fn toggle_setting(setting_index: usize, settings: &mut GameSettings) {
+    match setting_index {
+        0 => settings.god_mode = !settings.god_mode,
+        1 => settings.overlay = !settings.overlay,
+        2 => settings.render_map = !settings.render_map,
+        _ => {},
+    }
+}
+
+pub fn handle_input_settings_menu(input: InputAction, settings: &mut GameSettings) {
+    match input {
+        InputAction::SelectItem(setting_index) => {
+            toggle_setting(setting_index, settings);
+        }
+
+        InputAction::Esc => {
+            pop_state(settings);
+        }
+
+        _ => {
+        }
+    }
+}
+
 pub fn handle_input_inventory(input: InputAction, settings: &mut GameSettings) {
     match input {
         InputAction::Inventory => {
-            change_state(settings, GameState::Playing);
+            pop_state(settings);
         }
 
         InputAction::Esc => {
-            change_state(settings, GameState::Playing);
+            pop_state(settings);
         }
 
         InputAction::SkillMenu => {
-            change_state(settings, GameState::SkillMenu);
+            push_state(settings, GameState::SkillMenu);
         }
 
         InputAction::ClassMenu => {
-            change_state(settings, GameState::ClassMenu);
+            push_state(settings, GameState::ClassMenu);
         }
 
         _ => {
@@ -289,24 +438,24 @@ pub fn handle_input_skill_menu(input: InputAction,
                                config: &Config) {
     match input {
         InputAction::Inventory => {
-            change_state(settings, GameState::Inventory);
+            push_state(settings, GameState::Inventory);
         }
 
         InputAction::SkillMenu => {
-            change_state(settings, GameState::Playing);
+            pop_state(settings);
         }
 
         InputAction::ClassMenu => {
-            change_state(settings, GameState::ClassMenu);
+            push_state(settings, GameState::ClassMenu);
         }
 
         InputAction::SelectItem(skill_index) => {
             handle_skill(skill_index, ActionLoc::None, ActionMode::Primary, data, settings, msg_log, config);
-            change_state(settings, GameState::Playing);
+            set_state(settings, GameState::Playing);
         }
 
         InputAction::Esc => {
-            change_state(settings, GameState::Playing);
+            pop_state(settings);
         }
 
         _ => {
@@ -320,15 +469,15 @@ pub fn handle_input_class_menu(input: InputAction,
                                msg_log: &mut MsgLog) {
     match input {
         InputAction::Inventory => {
-            change_state(settings, GameState::Inventory);
+            push_state(settings, GameState::Inventory);
         }
 
         InputAction::ClassMenu => {
-            change_state(settings, GameState::Playing);
+            pop_state(settings);
         }
 
         InputAction::SkillMenu => {
-            change_state(settings, GameState::SkillMenu);
+            push_state(settings, GameState::SkillMenu);
         }
 
         InputAction::SelectItem(class_index) => {
@@ -337,12 +486,12 @@ pub fn handle_input_class_menu(input: InputAction,
                 // give player skills from a particular class
                 msg_log.log(Msg::AddClass(classes[class_index]));
 
-                change_state(settings, GameState::Playing);
+                set_state(settings, GameState::Playing);
             }
         }
 
         InputAction::Esc => {
-            change_state(settings, GameState::Playing);
+            pop_state(settings);
         }
 
         _ => {
@@ -353,11 +502,11 @@ pub fn handle_input_class_menu(input: InputAction,
 pub fn handle_input_confirm_quit(input: InputAction, settings: &mut GameSettings) {
     match input {
         InputAction::Esc => {
-            change_state(settings, GameState::Playing);
+            pop_state(settings);
         }
 
         InputAction::Exit => {
-            change_state(settings, GameState::Exit);
+            set_state(settings, GameState::Exit);
         }
 
         _ => {
@@ -370,7 +519,7 @@ pub fn handle_input(input_action: InputAction,
                     settings: &mut GameSettings,
                     msg_log: &mut MsgLog,
                     config: &Config) {
-    match settings.state {
+    match settings.state() {
         GameState::Playing => {
             handle_input_playing(input_action, data, settings, msg_log, config);
         }
@@ -379,6 +528,10 @@ pub fn handle_input(input_action: InputAction,
         }
 
         GameState::Lose => {
+            // the game is over, so quitting does not need confirmation
+            if input_action == InputAction::Exit {
+                set_state(settings, GameState::Exit);
+            }
         }
 
         GameState::Inventory => {
@@ -393,15 +546,70 @@ pub fn handle_input(input_action: InputAction,
             handle_input_class_menu(input_action, data, settings, msg_log);
         }
 
+        GameState::OverlayMenu => {
+            // handled in handle_input_universal
+        }
+
+        GameState::SettingsMenu => {
+            handle_input_settings_menu(input_action, settings);
+        }
+
         GameState::ConfirmQuit => {
             handle_input_confirm_quit(input_action, settings);
         }
 
+        GameState::Editor => {
+            // handled in handle_input_universal
+        }
+
         GameState::Exit => {
         }
     }
 }
 
+/// Living enemies in the player's FOV, ordered by distance and then by angle
+/// around the player so that cycling through them is deterministic.
+pub fn visible_enemies(data: &GameData, player_id: EntityId, config: &Config) -> Vec<EntityId> {
+    let player_pos = data.entities.pos[&player_id];
+
+    let mut enemies =
+        data.entities.ids.iter()
+                         .filter(|id| data.entities.typ[*id] == EntityType::Enemy)
+                         .filter(|id| data.entities.status[*id].alive)
+                         .filter(|id| data.is_in_fov(player_id, **id, config))
+                         .map(|id| *id)
+                         .collect::<Vec<EntityId>>();
+
+    let angle = |id: &EntityId| {
+        let pos = data.entities.pos[id];
+        return ((pos.y - player_pos.y) as f32).atan2((pos.x - player_pos.x) as f32);
+    };
+
+    enemies.sort_by(|first, second| {
+        let first_dist = distance(player_pos, data.entities.pos[first]);
+        let second_dist = distance(player_pos, data.entities.pos[second]);
+        return first_dist.cmp(&second_dist)
+                         .then(angle(first).partial_cmp(&angle(second)).unwrap());
+    });
+
+    return enemies;
+}
+
+/// The target after the current one, wrapping around. If the current target is
+/// no longer in the list the first target is chosen.
+pub fn next_target(targets: &Vec<EntityId>, current: Option<EntityId>) -> Option<EntityId> {
+    if targets.is_empty() {
+        return None;
+    }
+
+    let index =
+        current.and_then(|target_id| targets.iter().position(|id| *id == target_id))
+               .map(|index| (index + 1) % targets.len())
+               .unwrap_or(0);
+
+    return Some(targets[index]);
+}
+
 pub fn handle_input_playing(input_action: InputAction,
                             data: &GameData,
                             settings: &mut GameSettings,
@@ -419,6 +627,16 @@ pub fn handle_input_playing(input_action: InputAction,
             }
         }
 
+        (InputAction::CycleTarget, true) => {
+            let targets = visible_enemies(data, player_id, config);
+            settings.target = next_target(&targets, settings.target);
+
+            // move the cursor onto the target so items can be thrown at it
+            if let (Some(target_id), Some(_)) = (settings.target, settings.cursor) {
+                settings.cursor = Some(data.entities.pos[&target_id]);
+            }
+        }
+
         (InputAction::CursorMove(dir, relative, long), _) => {
             if let Some(cursor_pos) = settings.cursor {
                 let dist =
@@ -465,11 +683,13 @@ pub fn handle_input_playing(input_action: InputAction,
         (InputAction::CursorToggle, true) => {
             if settings.cursor.is_none() {
                 settings.cursor = Some(player_pos);
-                dbg!();
             } else {
-                dbg!();
                 settings.cursor = None;
             }
+
+            if config.debug_render {
+                trace!(target: "debug_render", "cursor toggled to {:?}", settings.cursor);
+            }
         }
 
         (InputAction::DropItemByIndex(target), true) => {
@@ -491,7 +711,7 @@ pub fn handle_input_playing(input_action: InputAction,
 
         (InputAction::DropItem, true) => {
             settings.inventory_action = InventoryAction::Drop;
-            change_state(settings, GameState::Inventory);
+            push_state(settings, GameState::Inventory);
         }
 
         (InputAction::Pickup, true) => {
@@ -503,7 +723,8 @@ pub fn handle_input_playing(input_action: InputAction,
         }
 
         (InputAction::Yell, true) => {
-            msg_log.log(Msg::Yell(player_id));
+            let player_pos = data.entities.pos[&player_id];
+            msg_log.log(Msg::Yell(player_id, player_pos, config.yell_radius));
         }
 
         (InputAction::IncreaseMoveMode, true) => {
@@ -524,28 +745,21 @@ pub fn handle_input_playing(input_action: InputAction,
 
         (InputAction::Inventory, true) => {
             settings.inventory_action = InventoryAction::Use;
-            change_state(settings, GameState::Inventory);
+            push_state(settings, GameState::Inventory);
         }
 
         (InputAction::SkillMenu, true) => {
-            change_state(settings, GameState::SkillMenu);
+            push_state(settings, GameState::SkillMenu);
         }
 
         (InputAction::ClassMenu, true) => {
-            change_state(settings, GameState::ClassMenu);
+            push_state(settings, GameState::ClassMenu);
         }
 
         (InputAction::Interact(dir), _) => {
-        let pos = data.entities.pos[&player_id];
-
-        let interact_pos = 
-            if let Some(dir) = dir {
-                dir.offset_pos(pos, 1)
-            } else {
-                pos
-            };
-
-            msg_log.log(Msg::Interact(player_id, interact_pos));
+            if let Some(msg) = resolve_interaction(player_id, dir, data) {
+                msg_log.log(msg);
+            }
         }
 
         (InputAction::UseItem(dir, target), _) => {
@@ -561,6 +775,51 @@ pub fn handle_input_playing(input_action: InputAction,
     }
 }
 
+/// Determine the action to take when interacting with the facing tile.
+/// With no direction the player interacts with their own tile, picking up
+/// any item there. Otherwise traps are armed or disarmed, enemies are attacked,
+/// and items or exits are stepped onto.
+pub fn resolve_interaction(player_id: EntityId,
+                           dir: Option<Direction>,
+                           data: &GameData) -> Option<Msg> {
+    let pos = data.entities.pos[&player_id];
+
+    let dir =
+        match dir {
+            Some(dir) => dir,
+            None => {
+                if data.item_at_pos(pos).is_some() {
+                    return Some(Msg::PickUp(player_id));
+                }
+                return None;
+            }
+        };
+
+    let interact_pos = dir.offset_pos(pos, 1);
+    let move_mode = data.entities.move_mode[&player_id];
+
+    if let Some(trap_id) = data.has_trap(interact_pos) {
+        return Some(Msg::ArmDisarmTrap(player_id, trap_id));
+    }
+
+    if let Some(other_id) = data.has_blocking_entity(interact_pos) {
+        if data.entities.typ[&other_id] == EntityType::Enemy {
+            // attacks are made by moving into an enemy
+            return Some(Msg::TryMove(player_id, dir, 1, move_mode));
+        }
+    }
+
+    let has_exit =
+        data.has_entities(interact_pos)
+            .iter()
+            .any(|id| data.entities.name[id] == EntityName::Exit);
+    if has_exit || data.item_at_pos(interact_pos).is_some() {
+        return Some(Msg::TryMove(player_id, dir, 1, move_mode));
+    }
+
+    return None;
+}
+
 pub fn handle_skill(skill_index: usize,
                     action_loc: ActionLoc,
                     action_mode: ActionMode,
@@ -610,19 +869,18 @@ pub fn handle_skill(skill_index: usize,
 
     let player_id = data.find_by_name(EntityName::Player).unwrap();
     let player_pos = data.entities.pos[&player_id];
-    let dxy = sub_pos(skill_pos, player_pos);
-    let direction: Option<Direction> = Direction::from_dxy(dxy.x, dxy.y);
+    let direction: Option<Direction> = Direction::from_positions(player_pos, skill_pos);
 
     /* Carry Out Skill */
     match data.entities.skills[&player_id][skill_index] {
         Skill::GrassThrow => {
-            if let Some(direction) = Direction::from_dxy(dxy.x, dxy.y) {
+            if let Some(direction) = direction {
                 msg_log.log(Msg::GrassThrow(player_id, direction));
             }
         }
 
         Skill::GrassBlade => {
-            if let Some(direction) = Direction::from_dxy(dxy.x, dxy.y) {
+            if let Some(direction) = direction {
                 msg_log.log(Msg::GrassBlade(player_id, action_mode, direction));
             }
         }
@@ -742,8 +1000,7 @@ fn chord_move(loc: ActionLoc,
             if pos == player_pos {
                 msg_log.log(Msg::Moved(player_id, MoveType::Pass, pos));
             } else {
-                let dxy = sub_pos(pos, player_pos);
-                let direction = Direction::from_dxy(dxy.x, dxy.y).unwrap();
+                let direction = Direction::from_positions(player_pos, pos).unwrap();
 
                 let move_amount = move_mode.move_amount();
                 msg_log.log(Msg::TryMove(player_id, direction, move_amount, move_mode));
@@ -807,10 +1064,27 @@ fn chord_item(loc: ActionLoc,
     }
 }
 
+fn push_state(settings: &mut GameSettings, new_state: GameState) {
+    let prev_state = settings.state();
+    settings.push_state(new_state);
+    print_state_change(prev_state, settings.state());
+}
+
+fn pop_state(settings: &mut GameSettings) {
+    let prev_state = settings.state();
+    settings.pop_state();
+    print_state_change(prev_state, settings.state());
+}
+
+fn set_state(settings: &mut GameSettings, new_state: GameState) {
+    let prev_state = settings.state();
+    settings.set_state(new_state);
+    print_state_change(prev_state, settings.state());
+}
+
 // TODO is this println okay to leave in? seems like it should be in stderr?
-fn change_state(settings: &mut GameSettings, new_state: GameState) {
-    if new_state != settings.state {
-        settings.state = new_state;
+fn print_state_change(prev_state: GameState, new_state: GameState) {
+    if new_state != prev_state {
         match new_state {
             GameState::Inventory => {
                 println!("CONSOLE: Opened Inventory");
@@ -827,10 +1101,22 @@ fn change_state(settings: &mut GameSettings, new_state: GameState) {
                 println!("CONSOLE: Selecting a class");
             }
 
+            GameState::OverlayMenu => {
+                println!("CONSOLE: Selecting overlays");
+            }
+
+            GameState::SettingsMenu => {
+                println!("CONSOLE: Changing settings");
+            }
+
             GameState::ConfirmQuit => {
                 println!("CONSOLE: Confirm quit");
             }
 
+            GameState::Editor => {
+                println!("CONSOLE: Editing map");
+            }
+
             GameState::Win => {
                 println!("CONSOLE: Won Level!");
             }
@@ -846,3 +1132,146 @@ fn change_state(settings: &mut GameSettings, new_state: GameState) {
     }
 }
 
+#[test]
+fn test_overlay_menu_toggle() {
+    let config = Config::from_file("../config.yaml");
+    let mut game = Game::new(0, config);
+    make_map::make_map(&MapLoadConfig::Empty, &mut game);
+
+    game.step_game(InputAction::OverlayMenu, 0.1);
+    assert_eq!(GameState::OverlayMenu, game.settings.state());
+
+    for overlay_index in 0..OVERLAY_NAMES.len() {
+        let before = overlay_flags(&game.settings, &game.config)[overlay_index];
+        game.step_game(InputAction::SelectItem(overlay_index), 0.1);
+        let after = overlay_flags(&game.settings, &game.config)[overlay_index];
+        assert_eq!(!before, after);
+    }
+
+    game.step_game(InputAction::Esc, 0.1);
+    assert_eq!(GameState::Playing, game.settings.state());
+}
+
+#[test]
+fn test_settings_menu_toggle() {
+    let config = Config::from_file("../config.yaml");
+    let mut game = Game::new(0, config);
+    make_map::make_map(&MapLoadConfig::Empty, &mut game);
+
+    game.step_game(InputAction::SettingsMenu, 0.1);
+    assert_eq!(GameState::SettingsMenu, game.settings.state());
+
+    for setting_index in 0..SETTINGS_NAMES.len() {
+        let before = settings_flags(&game.settings)[setting_index];
+        game.step_game(InputAction::SelectItem(setting_index), 0.1);
+        let after = settings_flags(&game.settings)[setting_index];
+        assert_eq!(!before, after);
+    }
+
+    game.step_game(InputAction::SettingsMenu, 0.1);
+    assert_eq!(GameState::Playing, game.settings.state());
+}
+
+#[test]
+fn test_resolve_interaction() {
+    let config = Config::from_file("../config.yaml");
+    let mut game = Game::new(0, config);
+    make_map::make_map(&MapLoadConfig::Empty, &mut game);
+
+    let player_id = game.data.find_by_name(EntityName::Player).unwrap();
+    let player_pos = Pos::new(5, 5);
+    game.data.entities.set_pos(player_id, player_pos);
+    let move_mode = game.data.entities.move_mode[&player_id];
+
+    // empty
+    assert_eq!(None, resolve_interaction(player_id, None, &game.data));
+    assert_eq!(None, resolve_interaction(player_id, Some(Direction::Right), &game.data));
+
+    // item under the player is picked up
+    make_stone(&mut game.data.entities, &game.config, player_pos, &mut game.msg_log);
+    assert_eq!(Some(Msg::PickUp(player_id)),
+               resolve_interaction(player_id, None, &game.data));
+
+    // item in the facing tile is stepped onto
+    make_stone(&mut game.data.entities, &game.config, Pos::new(6, 5), &mut game.msg_log);
+    assert_eq!(Some(Msg::TryMove(player_id, Direction::Right, 1, move_mode)),
+               resolve_interaction(player_id, Some(Direction::Right), &game.data));
+
+    // enemy in the facing tile is attacked
+    make_gol(&mut game.data.entities, &game.config, Pos::new(4, 5), &mut game.msg_log);
+    assert_eq!(Some(Msg::TryMove(player_id, Direction::Left, 1, move_mode)),
+               resolve_interaction(player_id, Some(Direction::Left), &game.data));
+}
+
+#[test]
+fn test_cycle_target() {
+    let config = Config::from_file("../config.yaml");
+    let mut game = Game::new(0, config);
+    make_map::make_map(&MapLoadConfig::Empty, &mut game);
+
+    let player_id = game.data.find_by_name(EntityName::Player).unwrap();
+    game.data.entities.set_pos(player_id, Pos::new(1, 1));
+
+    let near = make_gol(&mut game.data.entities, &game.config, Pos::new(2, 1), &mut game.msg_log);
+    let middle = make_gol(&mut game.data.entities, &game.config, Pos::new(1, 3), &mut game.msg_log);
+    let far = make_gol(&mut game.data.entities, &game.config, Pos::new(4, 4), &mut game.msg_log);
+    game.msg_log.clear();
+
+    assert_eq!(vec!(near, middle, far), visible_enemies(&game.data, player_id, &game.config));
+
+    // cycling advances through the enemies in order, and then wraps
+    let mut targets = Vec::new();
+    for _ in 0..4 {
+        handle_input_playing(InputAction::CycleTarget, &game.data, &mut game.settings, &mut game.msg_log, &game.config);
+        targets.push(game.settings.target.unwrap());
+    }
+    assert_eq!(vec!(near, middle, far, near), targets);
+
+    // with the cursor active it moves to the target
+    game.settings.cursor = Some(Pos::new(1, 1));
+    handle_input_playing(InputAction::CycleTarget, &game.data, &mut game.settings, &mut game.msg_log, &game.config);
+    assert_eq!(Some(Pos::new(1, 3)), game.settings.cursor);
+}
+
+#[cfg(test)]
+static DEBUG_RENDER_LOG: std::sync::Mutex<Vec<String>> = std::sync::Mutex::new(Vec::new());
+
+/// Logger which records only debug_render messages, so tests can check what was emitted.
+#[cfg(test)]
+struct DebugRenderLogger;
+
+#[cfg(test)]
+impl log::Log for DebugRenderLogger {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        return metadata.target() == "debug_render";
+    }
+
+    fn log(&self, record: &log::Record) {
+        if self.enabled(record.metadata()) {
+            DEBUG_RENDER_LOG.lock().unwrap().push(format!("{}", record.args()));
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+#[test]
+fn test_debug_render_logging() {
+    let _ = log::set_logger(&DebugRenderLogger);
+    log::set_max_level(log::LevelFilter::Trace);
+
+    let mut config = Config::from_file("../config.yaml");
+    config.debug_render = false;
+    let mut game = Game::new(0, config);
+    make_map::make_map(&MapLoadConfig::Empty, &mut game);
+
+    // with the flag off, toggling the cursor is silent
+    handle_input_playing(InputAction::CursorToggle, &game.data, &mut game.settings, &mut game.msg_log, &game.config);
+    handle_input_playing(InputAction::CursorToggle, &game.data, &mut game.settings, &mut game.msg_log, &game.config);
+    assert!(DEBUG_RENDER_LOG.lock().unwrap().is_empty());
+
+    // with the flag on, the cursor position is logged
+    game.config.debug_render = true;
+    handle_input_playing(InputAction::CursorToggle, &game.data, &mut game.settings, &mut game.msg_log, &game.config);
+    assert_eq!(1, DEBUG_RENDER_LOG.lock().unwrap().len());
+}
@@ -0,0 +1,231 @@
+use std::fmt;
+
+use serde::{Serialize, Deserialize};
+
+use roguelike_core::types::*;
+use roguelike_core::map::*;
+use roguelike_core::movement::Direction;
+#[cfg(test)]
+use roguelike_core::config::Config;
+
+use crate::game::*;
+use crate::actions::InputAction;
+use crate::make_map::write_map_xp;
+#[cfg(test)]
+use crate::make_map::make_map;
+
+
+/// The file the editor's save command writes to.
+pub const EDITOR_MAP_FILE: &str = "resources/editor_map.xp";
+
+
+/// What a left click paints onto the map in editor mode. Wall edges are
+/// placed on either the left or bottom side of the clicked tile, matching
+/// how walls are stored in each Tile.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum EditorBrush {
+    Wall,
+    ShortWallLeft,
+    ShortWallBottom,
+    TallWallLeft,
+    TallWallBottom,
+    Water,
+    Grass,
+}
+
+impl EditorBrush {
+    /// The brush after this one, wrapping around to the first brush.
+    pub fn next(self) -> EditorBrush {
+        match self {
+            EditorBrush::Wall => EditorBrush::ShortWallLeft,
+            EditorBrush::ShortWallLeft => EditorBrush::ShortWallBottom,
+            EditorBrush::ShortWallBottom => EditorBrush::TallWallLeft,
+            EditorBrush::TallWallLeft => EditorBrush::TallWallBottom,
+            EditorBrush::TallWallBottom => EditorBrush::Water,
+            EditorBrush::Water => EditorBrush::Grass,
+            EditorBrush::Grass => EditorBrush::Wall,
+        }
+    }
+}
+
+impl Default for EditorBrush {
+    fn default() -> EditorBrush {
+        return EditorBrush::Wall;
+    }
+}
+
+impl fmt::Display for EditorBrush {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EditorBrush::Wall => write!(f, "wall"),
+            EditorBrush::ShortWallLeft => write!(f, "short wall left"),
+            EditorBrush::ShortWallBottom => write!(f, "short wall bottom"),
+            EditorBrush::TallWallLeft => write!(f, "tall wall left"),
+            EditorBrush::TallWallBottom => write!(f, "tall wall bottom"),
+            EditorBrush::Water => write!(f, "water"),
+            EditorBrush::Grass => write!(f, "grass"),
+        }
+    }
+}
+
+/// Paint a brush onto a tile. Edits go through the map's IndexMut, which
+/// clears the FOV cache so visibility reflects the new tile.
+pub fn apply_brush(map: &mut Map, pos: Pos, brush: EditorBrush) {
+    if !map.is_within_bounds(pos) {
+        return;
+    }
+
+    let explored = map[pos].explored;
+
+    match brush {
+        EditorBrush::Wall => {
+            map[pos] = Tile::wall();
+        }
+
+        EditorBrush::ShortWallLeft => {
+            map.set_wall(pos, Direction::Left, Wall::ShortWall);
+        }
+
+        EditorBrush::ShortWallBottom => {
+            map.set_wall(pos, Direction::Down, Wall::ShortWall);
+        }
+
+        EditorBrush::TallWallLeft => {
+            map.set_wall(pos, Direction::Left, Wall::TallWall);
+        }
+
+        EditorBrush::TallWallBottom => {
+            map.set_wall(pos, Direction::Down, Wall::TallWall);
+        }
+
+        EditorBrush::Water => {
+            map[pos] = Tile::water();
+        }
+
+        EditorBrush::Grass => {
+            // grass is a surface, so a full wall is cleared to floor first
+            if map[pos].block_move {
+                map[pos] = Tile::floor();
+            }
+            map[pos].surface = Surface::Grass;
+        }
+    }
+
+    map[pos].explored = explored;
+}
+
+/// Erase a tile back to plain floor, removing its walls and surface.
+pub fn erase_tile(map: &mut Map, pos: Pos) {
+    if !map.is_within_bounds(pos) {
+        return;
+    }
+
+    let explored = map[pos].explored;
+    map[pos] = Tile::floor();
+    map[pos].explored = explored;
+}
+
+pub fn handle_input_editor(input: InputAction, game: &mut Game) {
+    match input {
+        InputAction::MapClick(_map_loc, map_cell) => {
+            apply_brush(&mut game.data.map, map_cell, game.settings.editor_brush);
+        }
+
+        InputAction::MapRightClick(_map_loc, map_cell) => {
+            erase_tile(&mut game.data.map, map_cell);
+        }
+
+        InputAction::CycleBrush => {
+            game.settings.editor_brush = game.settings.editor_brush.next();
+            println!("CONSOLE: Brush {}", game.settings.editor_brush);
+        }
+
+        InputAction::SaveMap => {
+            match write_map_xp(&game.config, &game.data.map, &game.data.entities, EDITOR_MAP_FILE) {
+                Ok(()) => println!("CONSOLE: Saved map to {}", EDITOR_MAP_FILE),
+                Err(err) => println!("CONSOLE: Could not save map: {}", err),
+            }
+        }
+
+        InputAction::Esc => {
+            game.settings.pop_state();
+        }
+
+        _ => {
+        }
+    }
+}
+
+#[test]
+fn test_apply_brush() {
+    let mut map = Map::from_dims(5, 5);
+    let pos = Pos::new(2, 2);
+    map[pos].explored = true;
+
+    apply_brush(&mut map, pos, EditorBrush::Wall);
+    assert!(map[pos].block_move);
+    assert!(map[pos].explored);
+
+    apply_brush(&mut map, pos, EditorBrush::Grass);
+    assert!(!map[pos].block_move);
+    assert_eq!(Surface::Grass, map[pos].surface);
+
+    apply_brush(&mut map, pos, EditorBrush::Water);
+    assert_eq!(TileType::Water, map[pos].tile_type);
+
+    erase_tile(&mut map, pos);
+    assert_eq!(TileType::Floor, map[pos].tile_type);
+    assert_eq!(Surface::Floor, map[pos].surface);
+    assert!(map[pos].explored);
+
+    // positions off the map are ignored
+    apply_brush(&mut map, Pos::new(10, 10), EditorBrush::Wall);
+}
+
+#[test]
+fn test_apply_brush_wall_edges() {
+    let mut map = Map::from_dims(5, 5);
+    let pos = Pos::new(2, 2);
+
+    apply_brush(&mut map, pos, EditorBrush::ShortWallLeft);
+    assert_eq!(Wall::ShortWall, map[pos].left_wall);
+    assert_eq!(Wall::Empty, map[pos].bottom_wall);
+    assert!(!map[pos].block_move);
+
+    apply_brush(&mut map, pos, EditorBrush::TallWallBottom);
+    assert_eq!(Wall::ShortWall, map[pos].left_wall);
+    assert_eq!(Wall::TallWall, map[pos].bottom_wall);
+
+    // a tall left wall now blocks sight across the left edge
+    assert!(map.is_in_fov(Pos::new(1, 2), Pos::new(3, 2), 5, false));
+    apply_brush(&mut map, pos, EditorBrush::TallWallLeft);
+    assert!(!map.is_in_fov(Pos::new(1, 2), Pos::new(3, 2), 5, false));
+
+    erase_tile(&mut map, pos);
+    assert_eq!(Wall::Empty, map[pos].left_wall);
+    assert_eq!(Wall::Empty, map[pos].bottom_wall);
+    assert!(map.is_in_fov(Pos::new(1, 2), Pos::new(3, 2), 5, false));
+}
+
+#[test]
+fn test_editor_mode_input() {
+    let config = Config::from_file("../config.yaml");
+    let mut game = Game::new(0, config);
+    make_map(&MapLoadConfig::Empty, &mut game);
+
+    game.step_game(InputAction::EditorMode, 0.1);
+    assert_eq!(GameState::Editor, game.settings.state());
+
+    game.step_game(InputAction::CycleBrush, 0.1);
+    assert_eq!(EditorBrush::ShortWallLeft, game.settings.editor_brush);
+
+    let pos = Pos::new(1, 1);
+    game.step_game(InputAction::MapClick(Pos::new(0, 0), pos), 0.1);
+    assert_eq!(Wall::ShortWall, game.data.map[pos].left_wall);
+
+    game.step_game(InputAction::MapRightClick(Pos::new(0, 0), pos), 0.1);
+    assert_eq!(Wall::Empty, game.data.map[pos].left_wall);
+
+    game.step_game(InputAction::Esc, 0.1);
+    assert_eq!(GameState::Playing, game.settings.state());
+}
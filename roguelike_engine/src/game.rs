@@ -1,13 +1,12 @@
 use std::default::Default;
 
-use oorandom::Rand32;
-
 use serde::{Serialize, Deserialize};
 
 use roguelike_core::types::*;
 use roguelike_core::config::*;
 use roguelike_core::map::*;
 use roguelike_core::messaging::MsgLog;
+use roguelike_core::rng::GameRng;
 
 use crate::actions;
 use crate::actions::InputAction;
@@ -16,6 +15,7 @@ use crate::make_map::make_map;
 use crate::step::step_logic;
 use crate::input::*;
 use crate::vault::*;
+use crate::editor::EditorBrush;
 
 
 #[derive(Clone, Debug)]
@@ -24,7 +24,7 @@ pub struct Game {
     pub data: GameData,
     pub settings: GameSettings,
     pub msg_log: MsgLog,
-    pub rng: Rand32,
+    pub rng: GameRng,
     pub vaults: Vec<Vault>,
     pub input: Input,
 }
@@ -32,7 +32,8 @@ pub struct Game {
 impl Game {
     pub fn new(seed: u64, config: Config) -> Game {
         let entities = Entities::new();
-        let rng: Rand32 = Rand32::new(seed);
+        let mut rng = GameRng::new(seed);
+        rng.log_draws = config.debug_rng;
 
         let mut msg_log = MsgLog::new();
 
@@ -41,7 +42,8 @@ impl Game {
         let mut data = GameData::new(map, entities);
 
         let player_id = make_player(&mut data.entities, &config, &mut msg_log);
-        data.entities.pos[&player_id] = Pos::new(-1, -1);
+        data.entities.set_pos(player_id, Pos::new(-1, -1));
+        make_starting_inventory(&mut data.entities, &config, player_id, &mut msg_log);
 
         let vaults: Vec<Vault> = Vec::new();
 
@@ -70,7 +72,7 @@ impl Game {
         }
     }
 
-    pub fn step_game(&mut self, input_action: InputAction, dt: f32) -> bool {
+    pub fn step_game(&mut self, input_action: InputAction, dt: f32) -> GameResult {
         self.settings.dt = dt;
         self.settings.time += dt;
 
@@ -84,20 +86,25 @@ impl Game {
                                   &self.config);
         }
 
+        let mut result = GameResult::Continue;
+
         if self.msg_log.messages.len() > 0 {
-            let finished_level = step_logic(self);
+            result = step_logic(self);
 
-            if finished_level {
+            if result == GameResult::Win {
                 // NOTE this is not a very general way to handle ending a level.
                 let player_id = self.data.find_by_name(EntityName::Player).unwrap();
-                let key_id = self.data.is_in_inventory(player_id, Item::Key).expect("Won level without key!");
-                self.data.entities.remove_item(player_id, key_id);
+                while let Some(key_id) = self.data.is_in_inventory(player_id, Item::Key) {
+                    self.data.entities.remove_item(player_id, key_id);
+                }
 
-                self.settings.state = GameState::Playing;
+                self.settings.set_state(GameState::Playing);
 
                 self.settings.level_num += 1;
 
                 make_map(&self.config.map_load.clone(), self);
+            } else if result == GameResult::Lose {
+                self.settings.set_state(GameState::Lose);
             }
         }
 
@@ -115,16 +122,31 @@ impl Game {
             }
         }
 
-        return self.settings.state != GameState::Exit;
+        if self.settings.state() == GameState::Exit {
+            result = GameResult::Stop;
+        } else if self.settings.state() == GameState::Lose {
+            result = GameResult::Lose;
+        }
+
+        return result;
     }
 }
 
+/// The outcome of a single step of the game.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum GameResult {
+    Continue,
+    Win,
+    Lose,
+    Stop,
+}
+
 #[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
 pub struct GameSettings {
     pub turn_count: usize,
     pub god_mode: bool,
     pub map_type: MapGenType,
-    pub state: GameState,
+    pub states: Vec<GameState>,
     pub overlay: bool,
     pub time: f32,
     pub dt: f32,
@@ -133,6 +155,10 @@ pub struct GameSettings {
     pub level_num: usize,
     pub running: bool,
     pub cursor: Option<Pos>,
+    #[serde(default)]
+    pub target: Option<EntityId>,
+    #[serde(default)]
+    pub editor_brush: EditorBrush,
 }
 
 impl GameSettings {
@@ -141,7 +167,7 @@ impl GameSettings {
             turn_count,
             god_mode,
             map_type: MapGenType::Island,
-            state: GameState::Playing,
+            states: vec!(GameState::Playing),
             overlay: false,
             time: 0.0,
             dt: 0.0,
@@ -150,7 +176,126 @@ impl GameSettings {
             level_num: 0,
             running: true,
             cursor: None,
+            target: None,
+            editor_brush: EditorBrush::default(),
         };
     }
+
+    /// The current state, which is the top of the state stack.
+    pub fn state(&self) -> GameState {
+        return *self.states.last().unwrap_or(&GameState::Playing);
+    }
+
+    /// Enter a state on top of the current one, such as opening a menu from
+    /// another menu. If the state is already on the stack, the states above it
+    /// are removed instead, so moving between menus does not grow the stack.
+    pub fn push_state(&mut self, state: GameState) {
+        if let Some(index) = self.states.iter().position(|stack_state| *stack_state == state) {
+            self.states.truncate(index + 1);
+        } else {
+            self.states.push(state);
+        }
+    }
+
+    /// Leave the current state, returning to the one below it. The bottom of
+    /// the stack is always Playing. Returns the new current state.
+    pub fn pop_state(&mut self) -> GameState {
+        self.states.pop();
+
+        if self.states.is_empty() {
+            self.states.push(GameState::Playing);
+        }
+
+        return self.state();
+    }
+
+    /// Replace the whole state stack with a single state, such as when
+    /// closing all menus or ending the game.
+    pub fn set_state(&mut self, state: GameState) {
+        self.states.clear();
+        self.states.push(state);
+    }
+
+    pub fn save(&self, file_name: &str) -> Result<(), String> {
+        let settings_string = serde_yaml::to_string(self).map_err(|err| format!("Could not serialize settings: {}", err))?;
+        std::fs::write(file_name, settings_string).map_err(|err| format!("Could not write {}: {}", file_name, err))?;
+        return Ok(());
+    }
+
+    pub fn load(file_name: &str) -> Result<GameSettings, String> {
+        let settings_string = std::fs::read_to_string(file_name).map_err(|err| format!("Could not read {}: {}", file_name, err))?;
+        let settings = serde_yaml::from_str(&settings_string).map_err(|err| format!("Could not parse {}: {}", file_name, err))?;
+        return Ok(settings);
+    }
+}
+
+#[test]
+fn test_starting_inventory() {
+    let mut config = Config::from_file("../config.yaml");
+    config.starting_inventory = vec!(StartingItem { item: Item::Stone, count: 2 },
+                                     StartingItem { item: Item::Dagger, count: 1 });
+    let game = Game::new(0, config);
+
+    let player_id = game.data.find_by_name(EntityName::Player).unwrap();
+    let inventory = &game.data.entities.inventory[&player_id];
+    assert_eq!(3, inventory.len());
+
+    // the dagger is a primary item, so it is placed at the front
+    assert_eq!(Item::Dagger, game.data.entities.item[&inventory[0]]);
+    assert_eq!(Item::Stone, game.data.entities.item[&inventory[1]]);
+    assert_eq!(Item::Stone, game.data.entities.item[&inventory[2]]);
+
+    // the items are held, not placed on the map
+    for item_id in inventory.iter() {
+        assert_eq!(Pos::new(-1, -1), game.data.entities.pos[item_id]);
+    }
 }
 
+#[test]
+fn test_game_settings_round_trip() {
+    let mut settings = GameSettings::new(12, true);
+    settings.push_state(GameState::SettingsMenu);
+    settings.overlay = true;
+    settings.render_map = false;
+    settings.level_num = 3;
+    settings.cursor = Some(Pos::new(4, 5));
+
+    let settings_string = serde_yaml::to_string(&settings).unwrap();
+    let loaded: GameSettings = serde_yaml::from_str(&settings_string).unwrap();
+    assert_eq!(settings, loaded);
+
+    let file_name = std::env::temp_dir().join("test_game_settings_round_trip.yaml");
+    let file_name = file_name.to_str().unwrap();
+    settings.save(file_name).unwrap();
+    let reloaded = GameSettings::load(file_name);
+    std::fs::remove_file(file_name).unwrap();
+    assert_eq!(settings, reloaded.unwrap());
+}
+
+#[test]
+fn test_game_state_stack() {
+    let mut settings = GameSettings::new(0, false);
+    assert_eq!(GameState::Playing, settings.state());
+
+    // menus nest, and popping returns to the previous menu
+    settings.push_state(GameState::SkillMenu);
+    settings.push_state(GameState::Inventory);
+    assert_eq!(GameState::Inventory, settings.state());
+    assert_eq!(GameState::SkillMenu, settings.pop_state());
+    assert_eq!(GameState::Playing, settings.pop_state());
+
+    // popping the last state returns to Playing
+    assert_eq!(GameState::Playing, settings.pop_state());
+    assert_eq!(vec!(GameState::Playing), settings.states);
+
+    // moving back to a menu already on the stack unwinds to it
+    settings.push_state(GameState::SkillMenu);
+    settings.push_state(GameState::Inventory);
+    settings.push_state(GameState::SkillMenu);
+    assert_eq!(vec!(GameState::Playing, GameState::SkillMenu), settings.states);
+
+    // setting a state replaces the stack
+    settings.set_state(GameState::Lose);
+    assert_eq!(GameState::Lose, settings.state());
+    assert_eq!(GameState::Playing, settings.pop_state());
+}
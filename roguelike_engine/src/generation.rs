@@ -1,7 +1,5 @@
 use std::collections::VecDeque;
 
-use oorandom::Rand32;
-
 use serde::{Serialize, Deserialize};
 
 use roguelike_core::ai::{Behavior, Ai};
@@ -11,7 +9,9 @@ use roguelike_core::constants::*;
 use roguelike_core::movement::*;
 use roguelike_core::messaging::*;
 use roguelike_core::config::*;
-use roguelike_core::utils::{rand_from_pos, distance, rng_range_u32, rng_range_i32, choose};
+use roguelike_core::line::line_between;
+use roguelike_core::utils::{rand_from_pos, distance, choose};
+use roguelike_core::rng::GameRng;
 
 use crate::game::*;
 use crate::procgen::*;
@@ -44,6 +44,7 @@ pub fn make_player(entities: &mut Entities, config: &Config, msg_log: &mut MsgLo
     entities.inventory.insert(entity_id,  VecDeque::new());
     entities.stance.insert(entity_id,  Stance::Standing);
     entities.fov_radius.insert(entity_id,  config.fov_radius_player);
+    entities.momentum.insert(entity_id,  Momentum::from_config(config));
 
     let skill_set = Vec::new();
     entities.skills.insert(entity_id,  skill_set);
@@ -57,6 +58,19 @@ pub fn make_player(entities: &mut Entities, config: &Config, msg_log: &mut MsgLo
     return entity_id;
 }
 
+/// Give an entity the starting items listed in the config, creating each item
+/// and placing it in the entity's inventory.
+pub fn make_starting_inventory(entities: &mut Entities, config: &Config, entity_id: EntityId, msg_log: &mut MsgLog) {
+    let pos = entities.pos[&entity_id];
+
+    for starting_item in config.starting_inventory.iter() {
+        for _ in 0..starting_item.count {
+            let item_id = make_item(entities, config, starting_item.item, pos, msg_log);
+            entities.pick_up_item(entity_id, item_id);
+        }
+    }
+}
+
 pub fn make_column(entities: &mut Entities, _config: &Config, pos: Pos, msg_log: &mut MsgLog) -> EntityId {
     let entity_id = entities.create_entity(pos.x, pos.y, EntityType::Column, MAP_COLUMN as char, Color::white(), EntityName::Column, true);
 
@@ -102,6 +116,37 @@ pub fn make_sword(entities: &mut Entities, _config: &Config, pos: Pos, msg_log:
     return entity_id;
 }
 
+pub fn make_spear(entities: &mut Entities, _config: &Config, pos: Pos, msg_log: &mut MsgLog) -> EntityId {
+    let entity_id = entities.create_entity(pos.x, pos.y, EntityType::Item, ENTITY_POLAXE as char, Color::white(), EntityName::Spear, false);
+
+    entities.item.insert(entity_id,  Item::Spear);
+
+    msg_log.log(Msg::SpawnedObject(entity_id, entities.typ[&entity_id], pos, EntityName::Spear, entities.direction[&entity_id]));
+
+    return entity_id;
+}
+
+pub fn make_cloak(entities: &mut Entities, _config: &Config, pos: Pos, msg_log: &mut MsgLog) -> EntityId {
+    let entity_id = entities.create_entity(pos.x, pos.y, EntityType::Item, ENTITY_CLOAK_GUY as char, Color::white(), EntityName::Cloak, false);
+
+    entities.item.insert(entity_id,  Item::Cloak);
+
+    msg_log.log(Msg::SpawnedObject(entity_id, entities.typ[&entity_id], pos, EntityName::Cloak, entities.direction[&entity_id]));
+
+    return entity_id;
+}
+
+pub fn make_torch(entities: &mut Entities, _config: &Config, pos: Pos, msg_log: &mut MsgLog) -> EntityId {
+    let entity_id = entities.create_entity(pos.x, pos.y, EntityType::Item, ENTITY_LANTERN as char, Color::white(), EntityName::Torch, false);
+
+    entities.item.insert(entity_id,  Item::Torch);
+    entities.torch.insert(entity_id,  Torch::new(TORCH_FUEL));
+
+    msg_log.log(Msg::SpawnedObject(entity_id, entities.typ[&entity_id], pos, EntityName::Torch, entities.direction[&entity_id]));
+
+    return entity_id;
+}
+
 pub fn make_shield(entities: &mut Entities, _config: &Config, pos: Pos, msg_log: &mut MsgLog) -> EntityId {
     let entity_id = entities.create_entity(pos.x, pos.y, EntityType::Item, ENTITY_SHIELD as char, Color::white(), EntityName::Shield, false);
 
@@ -348,6 +393,10 @@ pub fn make_entity(entities: &mut Entities, config: &Config, entity_name: Entity
         EntityName::Dagger => make_dagger(entities, config, pos, msg_log),
         EntityName::Hammer => make_hammer(entities, config, pos, msg_log),
         EntityName::Sword => make_sword(entities, config, pos, msg_log),
+        EntityName::Spear => make_spear(entities, config, pos, msg_log),
+        EntityName::Cloak => make_cloak(entities, config, pos, msg_log),
+        EntityName::Torch => make_torch(entities, config, pos, msg_log),
+        EntityName::Lantern => make_lantern(entities, config, pos, msg_log),
         EntityName::Shield => make_shield(entities, config, pos, msg_log),
         EntityName::Key => make_key(entities, config, pos, msg_log),
         EntityName::Mouse => make_mouse(entities, config, msg_log),
@@ -371,7 +420,7 @@ pub fn make_entity(entities: &mut Entities, config: &Config, entity_name: Entity
 pub fn make_island(data: &mut GameData,
                    config: &Config,
                    msg_log: &mut MsgLog,
-                   rng: &mut Rand32) -> Pos {
+                   rng: &mut GameRng) -> Pos {
     let center = Pos::new(data.map.width() / 2, data.map.height() / 2);
 
     let mut water_tile_positions = Vec::new();
@@ -383,7 +432,7 @@ pub fn make_island(data: &mut GameData,
             let pos = Pos::new(x, y);
 
             if distance(pos, center) <= ISLAND_RADIUS {
-                data.map[pos] = Tile::empty();
+                data.map[pos] = Tile::floor();
             } else {
                 data.map[pos] = Tile::water();
                 water_tile_positions.push((x, y));
@@ -407,7 +456,7 @@ pub fn make_island(data: &mut GameData,
     }
 
     /* add buildings */
-    for _ in 0..rng_range_u32(rng, 3, 5) {
+    for _ in 0..rng.range_u32("island_buildings", 3, 5) {
         let rand_pos = random_offset(rng, ISLAND_RADIUS);
         let pos = Pos::new(center.x + rand_pos.x, center.y + rand_pos.y);
         add_obstacle(&mut data.map, pos, Obstacle::Building, rng);
@@ -418,7 +467,7 @@ pub fn make_island(data: &mut GameData,
         let pos = pos_in_radius(center, ISLAND_RADIUS, rng);
 
         if data.map[pos].tile_type == TileType::Wall {
-            data.map[pos] = Tile::empty();
+            data.map[pos] = Tile::floor();
         }
     }
 
@@ -475,8 +524,8 @@ pub fn make_island(data: &mut GameData,
         }
     }
 
-    let x = rng_range_i32(rng, 0, data.map.width());
-    let y = rng_range_i32(rng, 0, data.map.height());
+    let x = rng.range_i32("island_key_x", 0, data.map.width());
+    let y = rng.range_i32("island_key_y", 0, data.map.height());
     let pos = Pos::new(x, y);
 
     if !data.has_blocking_entity(pos).is_some()  {
@@ -506,7 +555,7 @@ pub fn make_island(data: &mut GameData,
         }
     }
     // choose a random edge position
-    let edge_pos = edge_positions[rng_range_u32(rng, 0, edge_positions.len() as u32) as usize];
+    let edge_pos = edge_positions[rng.range_u32("island_exit", 0, edge_positions.len() as u32) as usize];
 
     // make the random edge position the exit
     data.map.tiles[edge_pos.x as usize][edge_pos.y as usize] = Tile::exit();
@@ -527,13 +576,13 @@ pub fn make_player_test_map(entities: &mut Entities,
 
     for wall_y_pos in 2..8 {
         let pos: (i32, i32) = (5, wall_y_pos);
-        map[pos] = Tile::empty();
+        map[pos] = Tile::floor();
         map[pos].left_wall = Wall::ShortWall;
     }
 
     for wall_x_pos in 3..7 {
         let pos: (i32, i32) = (wall_x_pos, 5);
-        map[pos] = Tile::empty();
+        map[pos] = Tile::floor();
         map[pos].bottom_wall = Wall::ShortWall;
     }
 
@@ -602,7 +651,7 @@ pub fn make_wall_test_map(entities: &mut Entities,
 
     for wall_y_pos in 2..8 {
         let pos: (i32, i32) = (5, wall_y_pos);
-        map[pos] = Tile::empty();
+        map[pos] = Tile::floor();
         map[pos].left_wall = Wall::ShortWall;
     }
     map[(4, 7)].bottom_wall = Wall::ShortWall;
@@ -636,7 +685,7 @@ pub fn make_corner_test_map(entities: &mut Entities,
     let y_end = 8;
     for wall_y_pos in y_start..=y_end {
         let pos: (i32, i32) = (x_pos, wall_y_pos);
-        map[pos] = Tile::empty();
+        map[pos] = Tile::floor();
         map[pos].left_wall = Wall::ShortWall;
     }
     map[(x_pos - 1, y_end)].bottom_wall = Wall::ShortWall;
@@ -669,3 +718,103 @@ pub fn make_trap_test_map(entities: &mut Entities,
     return (map, Pos::from(position));
 }
 
+
+/// Chainable drawing operations for building maps by hand, such as for test maps
+/// and vaults. Positions outside of the map are skipped. The positions changed by
+/// each operation are collected so they can be decorated further.
+pub struct MapBrush<'a> {
+    map: &'a mut Map,
+    pub positions: Vec<Pos>,
+}
+
+impl<'a> MapBrush<'a> {
+    pub fn new(map: &'a mut Map) -> MapBrush<'a> {
+        return MapBrush { map, positions: Vec::new() };
+    }
+
+    fn paint(&mut self, pos: Pos, tile: Tile) {
+        if self.map.is_within_bounds(pos) {
+            self.map[pos] = tile;
+            self.positions.push(pos);
+        }
+    }
+
+    /// Fill the rectangle with corners at start and end, inclusive.
+    pub fn rect(mut self, start: Pos, end: Pos, tile: Tile) -> MapBrush<'a> {
+        for x in start.x.min(end.x)..=start.x.max(end.x) {
+            for y in start.y.min(end.y)..=start.y.max(end.y) {
+                self.paint(Pos::new(x, y), tile);
+            }
+        }
+
+        return self;
+    }
+
+    /// Draw a line from start to end, including both end points.
+    pub fn line(mut self, start: Pos, end: Pos, tile: Tile) -> MapBrush<'a> {
+        let mut line = line_between(start, end);
+        if line.last() != Some(&end) {
+            line.push(end);
+        }
+
+        for pos in line {
+            self.paint(pos, tile);
+        }
+
+        return self;
+    }
+
+    pub fn fill(self, tile: Tile) -> MapBrush<'a> {
+        let end = Pos::new(self.map.width() - 1, self.map.height() - 1);
+        return self.rect(Pos::new(0, 0), end, tile);
+    }
+
+    /// Draw the outer edge of the map.
+    pub fn border(mut self, tile: Tile) -> MapBrush<'a> {
+        let (width, height) = self.map.size();
+
+        for x in 0..width {
+            self.paint(Pos::new(x, 0), tile);
+            self.paint(Pos::new(x, height - 1), tile);
+        }
+
+        for y in 1..(height - 1) {
+            self.paint(Pos::new(0, y), tile);
+            self.paint(Pos::new(width - 1, y), tile);
+        }
+
+        return self;
+    }
+
+    /// Finish drawing, returning the positions changed.
+    pub fn positions(self) -> Vec<Pos> {
+        return self.positions;
+    }
+}
+
+#[test]
+fn test_map_brush_room() {
+    let mut map = Map::from_dims(8, 6);
+
+    let border = MapBrush::new(&mut map).fill(Tile::floor())
+                                        .border(Tile::wall())
+                                        .positions();
+    // the fill and border positions are both collected
+    assert_eq!(8 * 6 + 2 * 8 + 2 * 4, border.len());
+
+    let count_walls = |map: &Map| {
+        return map.get_all_pos().iter().filter(|pos| map[**pos].tile_type == TileType::Wall).count();
+    };
+    assert_eq!(2 * 8 + 2 * 4, count_walls(&map));
+    assert_eq!(TileType::Floor, map[(1, 1)].tile_type);
+
+    // a pillar in the room, and a line clipped by the map bounds
+    let positions = MapBrush::new(&mut map).rect(Pos::new(3, 2), Pos::new(4, 3), Tile::wall()).positions();
+    assert_eq!(4, positions.len());
+    assert_eq!(2 * 8 + 2 * 4 + 4, count_walls(&map));
+
+    let positions = MapBrush::new(&mut map).line(Pos::new(1, 1), Pos::new(1, 10), Tile::water()).positions();
+    assert!(positions.iter().all(|pos| map.is_within_bounds(*pos)));
+    assert_eq!(TileType::Water, map[(1, 1)].tile_type);
+    assert_eq!(TileType::Water, map[(1, 5)].tile_type);
+}
@@ -1,4 +1,6 @@
 use std::time::Instant;
+#[cfg(test)]
+use std::time::Duration;
 use std::collections::HashMap;
 use std::cmp::Ord;
 use std::str::FromStr;
@@ -117,6 +119,8 @@ impl Input {
         };
     }
 
+    /// The move mode for a single move, from the held modifiers. Shift runs
+    /// and Ctrl sneaks, with Shift taking priority when both are held.
     pub fn move_mode(&self) -> MoveMode {
         if self.shift {
             return MoveMode::Run;
@@ -164,7 +168,11 @@ impl Input {
             }
 
             InputEvent::Tab => {
-                action = InputAction::CursorReturn;
+                if self.shift {
+                    action = InputAction::CursorReturn;
+                } else {
+                    action = InputAction::CycleTarget;
+                }
             }
 
             InputEvent::Ctrl(dir) => {
@@ -216,7 +224,11 @@ impl Input {
             }
 
             MouseClick::Right => {
-                action = InputAction::MouseButton(clicked, dir);
+                if down {
+                    if let Some(target_pos) = target_pos {
+                        action = InputAction::MapRightClick(mouse_pos, target_pos);
+                    }
+                }
             }
         }
 
@@ -225,10 +237,11 @@ impl Input {
 
     fn handle_char_up(&mut self, chr: char, settings: &GameSettings) -> InputAction {
         // if key was held, do nothing when it is up to avoid a final press
-        if self.is_held(chr) {
+        let was_held = self.is_held(chr);
+        self.char_held.remove(&chr);
+        if was_held {
             return InputAction::None;
         }
-        self.char_held.remove(&chr);
 
         match chr {
             'z' => return self.use_item(0),
@@ -301,12 +314,21 @@ impl Input {
             if new_repeats > held_state.repetitions {
                 action = self.key_to_action(chr, settings);
 
+                // movement is repeated on a timer by repeat_held_move instead
+                if is_repeatable(action) {
+                    return InputAction::None;
+                }
+
                 if action == InputAction::OverlayOff   ||
                    action == InputAction::Inventory    ||
                    action == InputAction::SkillMenu    ||
                    action == InputAction::Exit         ||
                    action == InputAction::CursorToggle ||
-                   action == InputAction::ClassMenu {
+                   action == InputAction::ClassMenu    ||
+                   action == InputAction::OverlayMenu  ||
+                   action == InputAction::SettingsMenu ||
+                   action == InputAction::EditorMode   ||
+                   action == InputAction::SaveMap {
                     action = InputAction::None;
                 } else {
                     self.char_held.insert(chr, held_state.repeated());
@@ -317,6 +339,42 @@ impl Input {
         return action;
     }
 
+    /// Repeat movement while a direction key is held down. This is called once
+    /// a frame, so held movement does not depend on the OS key repeat rate.
+    /// The first repeat happens after repeat_delay, and then every
+    /// move_repeat_interval seconds until the key is released.
+    pub fn repeat_held_move(&mut self, time: Instant, settings: &GameSettings, config: &Config) -> InputAction {
+        if settings.state() != GameState::Playing {
+            return InputAction::None;
+        }
+
+        // only the most recently pressed direction is repeated
+        let held_key =
+            self.char_held.iter()
+                          .filter(|(chr, _)| from_digit(**chr).is_some())
+                          .max_by_key(|(_, held_state)| held_state.down_time)
+                          .map(|(chr, held_state)| (*chr, *held_state));
+
+        if let Some((chr, held_state)) = held_key {
+            let time_since = time.duration_since(held_state.down_time).as_secs_f32();
+            if time_since < config.repeat_delay {
+                return InputAction::None;
+            }
+
+            let repeats = 1 + ((time_since - config.repeat_delay) / config.move_repeat_interval) as usize;
+            if repeats > held_state.repetitions {
+                let action = self.key_to_action(chr, settings);
+
+                if is_repeatable(action) {
+                    self.char_held.insert(chr, HeldState::new(held_state.down_time, repeats));
+                    return action;
+                }
+            }
+        }
+
+        return InputAction::None;
+    }
+
     fn handle_char(&mut self, chr: char, dir: KeyDir, time: Instant, settings: &GameSettings, config: &Config) -> InputAction {
         match dir {
             KeyDir::Up => {
@@ -338,7 +396,7 @@ impl Input {
 
         // handle numeric characters first
         if chr.is_ascii_digit() {
-            if settings.state.is_menu() {
+            if settings.state().is_menu() {
                 action = InputAction::SelectItem(chr.to_digit(10).unwrap() as usize);
             } else if chr == '5' {
                 if self.alt {
@@ -430,6 +488,26 @@ pub fn alpha_up_to_action(chr: char) -> InputAction {
             input_action = InputAction::ClassMenu;
         }
 
+        'u' => {
+            input_action = InputAction::OverlayMenu;
+        }
+
+        'm' => {
+            input_action = InputAction::SettingsMenu;
+        }
+
+        'k' => {
+            input_action = InputAction::EditorMode;
+        }
+
+        'b' => {
+            input_action = InputAction::CycleBrush;
+        }
+
+        'n' => {
+            input_action = InputAction::SaveMap;
+        }
+
         _ => {
             input_action = InputAction::None;
         }
@@ -438,6 +516,10 @@ pub fn alpha_up_to_action(chr: char) -> InputAction {
     return input_action;
 }
 
+fn is_repeatable(action: InputAction) -> bool {
+    return matches!(action, InputAction::Move(_, _) | InputAction::CursorMove(_, _, _));
+}
+
 fn from_digit(chr: char) -> Option<Direction> {
     match chr {
         '4' => Some(Direction::Left),
@@ -452,3 +534,70 @@ fn from_digit(chr: char) -> Option<Direction> {
     }
 }
 
+#[test]
+fn test_repeat_held_move() {
+    let mut config = Config::from_file("../config.yaml");
+    config.repeat_delay = 0.25;
+    config.move_repeat_interval = 0.125;
+
+    let mut settings = GameSettings::new(0, false);
+    let mut input = Input::new();
+    let start = Instant::now();
+
+    input.handle_event(&mut settings, InputEvent::Char('6', KeyDir::Down), start, &config);
+
+    // holding right for a second moves once after the delay, and then every interval
+    let mut moves = 0;
+    for ms in 0..=1000 {
+        let time = start + Duration::from_millis(ms);
+        let action = input.repeat_held_move(time, &settings, &config);
+        if action == InputAction::Move(Direction::Right, MoveMode::Walk) {
+            moves += 1;
+        } else {
+            assert_eq!(InputAction::None, action);
+        }
+    }
+    assert_eq!(7, moves);
+
+    // releasing the key stops the repeat, and does not move again
+    let end = start + Duration::from_millis(1000);
+    let action = input.handle_event(&mut settings, InputEvent::Char('6', KeyDir::Up), end, &config);
+    assert_eq!(InputAction::None, action);
+    let later = end + Duration::from_millis(500);
+    assert_eq!(InputAction::None, input.repeat_held_move(later, &settings, &config));
+
+    // non-movement keys are not repeated
+    input.handle_event(&mut settings, InputEvent::Char('g', KeyDir::Down), end, &config);
+    assert_eq!(InputAction::None, input.repeat_held_move(later, &settings, &config));
+}
+
+#[test]
+fn test_move_mode_modifiers() {
+    let config = Config::from_file("../config.yaml");
+    let mut settings = GameSettings::new(0, false);
+    let mut input = Input::new();
+    let time = Instant::now();
+
+    let press = |input: &mut Input, settings: &mut GameSettings, chr: char| {
+        input.handle_event(settings, InputEvent::Char(chr, KeyDir::Down), time, &config);
+        return input.handle_event(settings, InputEvent::Char(chr, KeyDir::Up), time, &config);
+    };
+
+    assert_eq!(InputAction::Move(Direction::Right, MoveMode::Walk), press(&mut input, &mut settings, '6'));
+
+    input.handle_event(&mut settings, InputEvent::Shift(KeyDir::Down), time, &config);
+    assert_eq!(InputAction::Move(Direction::UpLeft, MoveMode::Run), press(&mut input, &mut settings, '7'));
+    assert_eq!(InputAction::Pass(MoveMode::Run), press(&mut input, &mut settings, '5'));
+
+    // shift takes priority over ctrl
+    input.handle_event(&mut settings, InputEvent::Ctrl(KeyDir::Down), time, &config);
+    assert_eq!(InputAction::Move(Direction::Left, MoveMode::Run), press(&mut input, &mut settings, '4'));
+
+    input.handle_event(&mut settings, InputEvent::Shift(KeyDir::Up), time, &config);
+    assert_eq!(InputAction::Move(Direction::Down, MoveMode::Sneak), press(&mut input, &mut settings, '2'));
+    assert_eq!(InputAction::Pass(MoveMode::Sneak), press(&mut input, &mut settings, '5'));
+
+    // releasing the modifier only affects the moves made while it was held
+    input.handle_event(&mut settings, InputEvent::Ctrl(KeyDir::Up), time, &config);
+    assert_eq!(InputAction::Move(Direction::Up, MoveMode::Walk), press(&mut input, &mut settings, '8'));
+}
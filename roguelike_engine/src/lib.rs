@@ -7,4 +7,5 @@ pub mod procgen;
 pub mod step;
 pub mod input;
 pub mod vault;
+pub mod editor;
 pub mod log;
@@ -1,19 +1,22 @@
 use std::fmt;
 use std::fs::File;
-use std::io::{stdout, Write};
+use std::io::{stdout, Write, BufWriter};
 
 use serde::{Serialize, Deserialize};
 
 use crate::actions::*;
 
 pub struct Log {
-    pub action_log: File,
+    pub action_log: BufWriter<File>,
     pub message_log: File,
+    pub actions_since_flush: usize,
 }
 
 impl Log {
     pub const ACTION_LOG_NAME: &'static str = "action_log.txt";
     pub const MESSAGE_LOG_NAME: &'static str = "message_log.txt";
+    pub const ACTION_FLUSH_COUNT: usize = 10;
+    pub const SEED_PREFIX: &'static str = "seed";
 
     pub fn new() -> Log {
         let action_log = File::create(Log::ACTION_LOG_NAME)
@@ -22,8 +25,9 @@ impl Log {
                                .expect("Could not open messages.txt");
 
         let log = Log {
-            action_log,
+            action_log: BufWriter::new(action_log),
             message_log,
+            actions_since_flush: 0,
         };
 
         return log;
@@ -33,9 +37,25 @@ impl Log {
         if input_action != InputAction::None {
             self.action_log.write(input_action.to_string().as_bytes()).unwrap();
             self.action_log.write("\n".as_bytes()).unwrap();
+
+            self.actions_since_flush += 1;
+            if self.actions_since_flush >= Log::ACTION_FLUSH_COUNT {
+                self.flush();
+            }
         }
     }
 
+    /// Record the seed used to create the game, so a replay can start from the same RNG state.
+    pub fn log_seed(&mut self, seed: u64) {
+        self.action_log.write(format!("{} {}\n", Log::SEED_PREFIX, seed).as_bytes()).unwrap();
+        self.flush();
+    }
+
+    pub fn flush(&mut self) {
+        self.action_log.flush().unwrap();
+        self.actions_since_flush = 0;
+    }
+
     pub fn log_output(&mut self, log_message: &str) {
         self.log(LogMsgType::Output, log_message);
     }
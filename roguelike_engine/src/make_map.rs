@@ -4,7 +4,7 @@ use std::io::Write;
 
 use rexpaint::*;
 
-use log::trace;
+use log::{trace, warn, error};
 
 use roguelike_core::constants::*;
 use roguelike_core::messaging::*;
@@ -40,7 +40,8 @@ pub fn make_map(map_load_config: &MapLoadConfig, game: &mut Game) {
 
         MapLoadConfig::ProcGen(procgen_params) => {
             let file_name = format!("resources/procgen/{}", procgen_params);
-            let cmds = ProcCmd::from_file(&file_name);
+            let mut cmds = ProcCmd::from_file(&file_name);
+            ProcCmd::apply_config(&mut cmds, &game.config);
 
             let mut template_file = "resources/wfc_seed_2.png".to_string();
             for param in cmds.iter() {
@@ -49,7 +50,13 @@ pub fn make_map(map_load_config: &MapLoadConfig, game: &mut Game) {
                 }
             }
 
-            game.data.map = generate_bare_map(20, 20, &template_file, &mut game.rng);
+            match generate_bare_map(20, 20, &template_file, &mut game.rng.fork("bare_map")) {
+                Ok(map) => game.data.map = map,
+                Err(err) => {
+                    error!("{}, starting from an empty map", err);
+                    game.data.map = Map::from_dims(20, 20);
+                }
+            }
             player_position = saturate_map(game, &cmds);
         }
 
@@ -105,12 +112,21 @@ pub fn make_map(map_load_config: &MapLoadConfig, game: &mut Game) {
             }
 
             let map_name = format!("resources/{}", maps[game.settings.level_num]);
-            let mut position =
-                read_map_xp(&game.config, &mut game.data, &mut game.msg_log, &map_name);
-            if position == (0, 0) {
-                position = (game.data.map.width() / 2, game.data.map.height() / 2);
+            match read_map_xp(&game.config, &mut game.data, &mut game.msg_log, &map_name) {
+                Ok(mut position) => {
+                    if position == (0, 0) {
+                        position = (game.data.map.width() / 2, game.data.map.height() / 2);
+                    }
+                    player_position = Pos::from(position);
+                }
+
+                Err(err) => {
+                    error!("{}, generating a random map instead", err);
+                    game.data.map = Map::from_dims(MAP_WIDTH as u32, MAP_HEIGHT as u32);
+                    let starting_position = make_island(&mut game.data, &game.config, &mut game.msg_log, &mut game.rng);
+                    player_position = Pos::from(starting_position);
+                }
             }
-            player_position = Pos::from(position);
         }
 
         MapLoadConfig::Random => {
@@ -144,13 +160,24 @@ pub fn make_map(map_load_config: &MapLoadConfig, game: &mut Game) {
         }
     }
 
+    game.data.map.wrap_edges = game.config.wrap_edges;
+    game.data.map.corner_peek = game.config.fov_corner_peek;
+
     if game.data.find_by_name(EntityName::Mouse).is_none() {
         make_mouse(&mut game.data.entities, &game.config, &mut game.msg_log);
     }
 
     let player_id = game.data.find_by_name(EntityName::Player).unwrap();
     //game.msg_log.log(Msg::Moved(player_id, MoveType::Move, player_position));
-    game.data.entities.set_pos(player_id, player_position);
+    game.data.move_entity(player_id, player_position);
+
+    // every key placed on the map is needed to open the exit
+    let player_inventory = &game.data.entities.inventory[&player_id];
+    let required_keys =
+        game.data.entities.item.iter()
+                               .filter(|(id, item)| **item == Item::Key && !player_inventory.contains(*id))
+                               .count();
+    game.data.goals = GoalTracker::new(required_keys);
 
     if game.config.write_map_distribution {
         let max = (2 * TILE_FILL_METRIC_DIST + 1).pow(2);
@@ -168,21 +195,184 @@ pub fn make_map(map_load_config: &MapLoadConfig, game: &mut Game) {
     }
 }
 
+/// Read a REXPaint map file into the game's map, adding the file's entities.
 pub fn read_map_xp(config: &Config,
                    data: &mut GameData,
                    msg_log: &mut MsgLog,
-                   file_name: &str) -> (i32, i32) {
+                   file_name: &str) -> Result<(i32, i32), String> {
+    let (map_data, player_position) = parse_map_xp(config, file_name)?;
+
+    data.map = map_data.map;
+
+    // entities are given new ids when merged, so announce them with those ids
+    let first_new_index = data.entities.ids.len();
+    data.entities.merge(&map_data.entities);
+    for index in first_new_index..data.entities.ids.len() {
+        let entity_id = data.entities.ids[index];
+        let pos = data.entities.pos[&entity_id];
+        data.entities.spawn_pos.insert(entity_id, pos);
+
+        msg_log.log(Msg::SpawnedObject(entity_id,
+                                       data.entities.typ[&entity_id],
+                                       pos,
+                                       data.entities.name[&entity_id],
+                                       data.entities.direction[&entity_id]));
+    }
+
+    return Ok(player_position);
+}
+
+/// Changes needed to bring the game's entities in line with a reloaded map file.
+/// Kept and removed ids are from the game's entities, and added ids are from the
+/// reloaded entities.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct EntityDiff {
+    pub keep: Vec<EntityId>,
+    pub remove: Vec<EntityId>,
+    pub add: Vec<EntityId>,
+}
+
+/// Match entities by name and position between the current entities and those
+/// reloaded from a map file. Current entities are matched by the position they were
+/// spawned at when the map was loaded, so entities that have since moved are still
+/// kept. Entities that did not come from a map file are matched by their current
+/// position. The given exceptions, such as the player, are neither kept nor removed.
+pub fn diff_entities(current: &Entities, reloaded: &Entities, exceptions: &[EntityId]) -> EntityDiff {
+    let mut diff = EntityDiff::default();
+    let mut matched: Vec<EntityId> = Vec::new();
+
+    for entity_id in current.ids.iter() {
+        if exceptions.contains(entity_id) {
+            continue;
+        }
+
+        let name = current.name[entity_id];
+        let pos = *current.spawn_pos.get(entity_id).unwrap_or(&current.pos[entity_id]);
+        let found = reloaded.ids.iter().find(|other_id| {
+            return !matched.contains(other_id) &&
+                   reloaded.name[*other_id] == name &&
+                   reloaded.pos[*other_id] == pos;
+        });
+
+        if let Some(other_id) = found {
+            matched.push(*other_id);
+            diff.keep.push(*entity_id);
+        } else {
+            diff.remove.push(*entity_id);
+        }
+    }
+
+    for other_id in reloaded.ids.iter() {
+        if !matched.contains(other_id) {
+            diff.add.push(*other_id);
+        }
+    }
+
+    return diff;
+}
+
+/// Reload a REXPaint map file, keeping the state of entities which did not change in
+/// the file and only adding or removing entities that did. The player, their inventory,
+/// and the mouse and cursor are left alone.
+pub fn reload_map_xp(config: &Config,
+                     data: &mut GameData,
+                     msg_log: &mut MsgLog,
+                     file_name: &str) -> Result<(i32, i32), String> {
+    let (map_data, player_position) = parse_map_xp(config, file_name)?;
+
+    data.map = map_data.map;
+
+    let mut exceptions = Vec::new();
+    for name in [EntityName::Player, EntityName::Mouse, EntityName::Cursor].iter() {
+        if let Some(entity_id) = data.find_by_name(*name) {
+            exceptions.push(entity_id);
+            if let Some(inventory) = data.entities.inventory.get(&entity_id) {
+                exceptions.extend(inventory.iter());
+            }
+        }
+    }
+
+    let diff = diff_entities(&data.entities, &map_data.entities, &exceptions);
+
+    for entity_id in diff.remove.iter() {
+        data.entities.remove_entity(*entity_id);
+    }
+
+    for other_id in diff.add.iter() {
+        data.entities.clone_entity(&map_data.entities, *other_id);
+
+        let entity_id = *data.entities.ids.last().unwrap();
+        let pos = data.entities.pos[&entity_id];
+        data.entities.spawn_pos.insert(entity_id, pos);
+
+        msg_log.log(Msg::SpawnedObject(entity_id,
+                                       data.entities.typ[&entity_id],
+                                       data.entities.pos[&entity_id],
+                                       data.entities.name[&entity_id],
+                                       data.entities.direction[&entity_id]));
+    }
+
+    return Ok(player_position);
+}
+
+#[test]
+fn test_diff_entities() {
+    let config = Config::from_file("../config.yaml");
+    let mut msg_log = MsgLog::new();
+
+    let mut current = Entities::new();
+    let player = make_player(&mut current, &config, &mut msg_log);
+    let kept_gol = make_gol(&mut current, &config, Pos::new(1, 1), &mut msg_log);
+    let moved_gol = make_gol(&mut current, &config, Pos::new(2, 2), &mut msg_log);
+    let removed_key = make_key(&mut current, &config, Pos::new(3, 3), &mut msg_log);
+    current.fighter[&kept_gol].hp = 1;
+
+    // a gol spawned at (4, 4) which has since walked away is still the same gol
+    let wandering_gol = make_gol(&mut current, &config, Pos::new(4, 4), &mut msg_log);
+    current.spawn_pos.insert(wandering_gol, Pos::new(4, 4));
+    current.set_pos(wandering_gol, Pos::new(6, 4));
+
+    let mut reloaded = Entities::new();
+    make_gol(&mut reloaded, &config, Pos::new(1, 1), &mut msg_log);
+    make_gol(&mut reloaded, &config, Pos::new(4, 4), &mut msg_log);
+    let new_gol = make_gol(&mut reloaded, &config, Pos::new(2, 4), &mut msg_log);
+    let new_pawn = make_pawn(&mut reloaded, &config, Pos::new(5, 5), &mut msg_log);
+
+    let diff = diff_entities(&current, &reloaded, &[player]);
+
+    assert_eq!(vec!(kept_gol, wandering_gol), diff.keep);
+    assert_eq!(vec!(moved_gol, removed_key), diff.remove);
+    assert_eq!(vec!(new_gol, new_pawn), diff.add);
+
+    // the kept entity's state is untouched
+    assert_eq!(1, current.fighter[&kept_gol].hp);
+}
+
+/// Parse a REXPaint map file into a GameData containing only the map and its entities,
+/// along with the player's starting position. Characters in the configured legend are
+/// checked before the built in characters for each layer. Returns an error naming the
+/// file if it cannot be opened or is not a valid map.
+pub fn parse_map_xp(config: &Config, file_name: &str) -> Result<(GameData, (i32, i32)), String> {
     trace!("opening map {}", file_name);
-    let file = File::open(file_name).unwrap();
+    let file = File::open(file_name).map_err(|err| format!("Could not open map {}: {}", file_name, err))?;
 
     let mut buf_reader = BufReader::new(file);
 
     trace!("reading in map data");
-    let xp = XpFile::read(&mut buf_reader).unwrap();
+    let xp = XpFile::read(&mut buf_reader).map_err(|err| format!("Could not read map {}: {}", file_name, err))?;
 
-    data.map = Map::from_dims(xp.layers[0].width as u32, xp.layers[0].height as u32);
+    if xp.layers.is_empty() {
+        return Err(format!("Map {} has no layers", file_name));
+    }
+
+    let map = Map::from_dims(xp.layers[0].width as u32, xp.layers[0].height as u32);
+    let mut data = GameData::new(map, Entities::new());
     let mut player_position = (0, 0);
 
+    // messages for spawned entities are logged when the entities are merged into the game
+    let mut msg_log = MsgLog::new();
+    let msg_log = &mut msg_log;
+
     for (layer_index, layer) in xp.layers.iter().enumerate() {
         let width = layer.width as i32;
         let height = layer.height as i32;
@@ -196,6 +386,20 @@ pub fn read_map_xp(config: &Config,
 
                 let chr = std::char::from_u32(cell.ch).unwrap();
 
+                if let Some(entry) = config.map_legend.iter().find(|entry| entry.chr == chr) {
+                    if let Some(tile) = entry.tile {
+                        tile.apply(&mut data.map, pos);
+                    }
+
+                    match entry.entity {
+                        Some(EntityName::Player) => player_position = (x, y),
+                        Some(entity_name) => { make_entity(&mut data.entities, config, entity_name, pos, msg_log); },
+                        None => {},
+                    }
+
+                    continue;
+                }
+
                 match layer_index {
                     MAP_LAYER_GROUND => {
                         match chr as u8 {
@@ -219,7 +423,7 @@ pub fn read_map_xp(config: &Config,
                             }
 
                             _ => {
-                                dbg!(format!("Unexpected character {} in ground layer!", chr as u8));
+                                warn!("Unexpected character {} in ground layer!", chr as u8);
                             }
                         }
                     }
@@ -439,7 +643,7 @@ pub fn read_map_xp(config: &Config,
                     }
 
                     _ => {
-                        panic!(format!("Layer {} not expected in map file!", layer_index));
+                        return Err(format!("Layer {} not expected in map {}", layer_index, file_name));
                     }
                 }
             }
@@ -448,9 +652,251 @@ pub fn read_map_xp(config: &Config,
 
     trace!("map read finished");
 
-    trace!("map updated");
+    return Ok((data, player_position));
+}
+
+#[test]
+fn test_parse_map_xp_legend() {
+    let mut config = Config::from_file("../config.yaml");
+    config.map_legend = vec!(MapLegendEntry { chr: '#', tile: Some(LegendTile::Wall), entity: None },
+                             MapLegendEntry { chr: 'g', tile: None, entity: Some(EntityName::Gol) },
+                             MapLegendEntry { chr: '=', tile: Some(LegendTile::TallBottomWall), entity: None },
+                             MapLegendEntry { chr: '@', tile: None, entity: Some(EntityName::Player) });
+
+    let (width, height) = (6, 5);
+    let mut xp = XpFile::new(width, height);
+    xp.layers.push(XpLayer::new(width, height));
+    xp.layers.push(XpLayer::new(width, height));
+    for (layer_index, layer) in xp.layers.iter_mut().enumerate() {
+        for cell in layer.cells.iter_mut() {
+            cell.ch = if layer_index == MAP_LAYER_GROUND { 0 } else { MAP_EMPTY as u32 };
+        }
+    }
+
+    let set_chr = |xp: &mut XpFile, layer: usize, x: usize, y: usize, chr: char| {
+        xp.layers[layer].cells[y + height * x].ch = chr as u32;
+    };
+    set_chr(&mut xp, MAP_LAYER_ENVIRONMENT, 1, 1, '#');
+    set_chr(&mut xp, MAP_LAYER_ENVIRONMENT, 3, 1, '=');
+    set_chr(&mut xp, MAP_LAYER_ENTITIES, 2, 2, 'g');
+    set_chr(&mut xp, MAP_LAYER_ENTITIES, 4, 3, '@');
+
+    let map_path = std::env::temp_dir().join("test_parse_map_xp_legend.xp");
+    let map_file = map_path.to_str().unwrap();
+    xp.write(&mut File::create(map_file).unwrap()).unwrap();
+
+    let (data, player_position) = parse_map_xp(&config, map_file).unwrap();
+
+    assert_eq!((width as i32, height as i32), data.map.size());
+    assert_eq!(TileType::Wall, data.map[(1, 1)].tile_type);
+    assert_eq!(Wall::TallWall, data.map[(3, 1)].bottom_wall);
+    assert_eq!(TileType::Floor, data.map[(2, 2)].tile_type);
+    assert_eq!((4, 3), player_position);
+
+    assert_eq!(1, data.entities.ids.len());
+    let gol = data.entities.ids[0];
+    assert_eq!(EntityName::Gol, data.entities.name[&gol]);
+    assert_eq!(Pos::new(2, 2), data.entities.pos[&gol]);
+
+    std::fs::remove_file(map_file).unwrap();
+}
+
+#[test]
+fn test_read_map_xp_missing_file() {
+    let config = Config::from_file("../config.yaml");
+    let mut data = GameData::new(Map::from_dims(5, 5), Entities::new());
+    let mut msg_log = MsgLog::new();
+
+    let map_path = std::env::temp_dir().join("test_read_map_xp_missing_file.xp");
+    let map_file = map_path.to_str().unwrap();
+
+    let result = read_map_xp(&config, &mut data, &mut msg_log, map_file);
+    let err = result.unwrap_err();
+    assert!(err.contains(map_file));
+
+    // the current map is left alone
+    assert_eq!((5, 5), data.map.size());
+}
+
+/// The character for a tile's walls in the environment layer, if it has any.
+/// A tile with one short and one tall wall is written with two tall walls, as
+/// there is no character for mixed wall heights.
+fn wall_chr(tile: &Tile) -> Option<u8> {
+    let chr =
+        match (tile.left_wall, tile.bottom_wall) {
+            (Wall::Empty, Wall::Empty) => return None,
+            (Wall::ShortWall, Wall::Empty) => MAP_THIN_WALL_LEFT,
+            (Wall::Empty, Wall::ShortWall) => MAP_THIN_WALL_BOTTOM,
+            (Wall::ShortWall, Wall::ShortWall) => MAP_THIN_WALL_BOTTOM_LEFT,
+            (Wall::TallWall, Wall::Empty) => MAP_THICK_WALL_LEFT,
+            (Wall::Empty, Wall::TallWall) => MAP_THICK_WALL_BOTTOM,
+            (Wall::TallWall, Wall::TallWall) => MAP_THICK_WALL_BOTTOM_LEFT,
+            _ => {
+                warn!("Mixed wall heights are written as tall walls");
+                MAP_THICK_WALL_BOTTOM_LEFT
+            }
+        };
+
+    return Some(chr);
+}
+
+/// The character for an entity in a map file, and the layer it goes in.
+/// Entities without a built in character use the configured legend, and
+/// entities with neither are not written.
+fn entity_chr(config: &Config, name: EntityName) -> Option<(usize, u8)> {
+    let chr =
+        match name {
+            EntityName::Player => ENTITY_PLAYER,
+            EntityName::Gol => ENTITY_GOL,
+            EntityName::Exit => ENTITY_EXIT,
+            EntityName::Pawn => ENTITY_ELF,
+            EntityName::Dagger => ENTITY_DAGGER,
+            EntityName::Key => ENTITY_KEY,
+            EntityName::Stone => ENTITY_STONE,
+            EntityName::Shield => ENTITY_SHIELD,
+            EntityName::Hammer => ENTITY_HAMMER,
+            EntityName::SpikeTrap => ENTITY_SPIKE_TRAP,
+            EntityName::Column => return Some((MAP_LAYER_ENVIRONMENT, MAP_COLUMN)),
+            _ => {
+                let entry = config.map_legend.iter().find(|entry| entry.entity == Some(name) && entry.tile.is_none())?;
+                return Some((MAP_LAYER_ENTITIES, entry.chr as u8));
+            }
+        };
+
+    return Some((MAP_LAYER_ENTITIES, chr));
+}
+
+/// Write a map and the positions of its entities to a REXPaint file, using the
+/// same layers and characters that parse_map_xp reads. Entities which are not
+/// on the map, such as items in an inventory, are not written.
+pub fn write_map_xp(config: &Config, map: &Map, entities: &Entities, file_name: &str) -> Result<(), String> {
+    let (width, height) = map.size();
+    let (width, height) = (width as usize, height as usize);
+
+    let mut xp = XpFile::new(width, height);
+    xp.layers.push(XpLayer::new(width, height));
+    xp.layers.push(XpLayer::new(width, height));
+
+    let set_chr = |xp: &mut XpFile, layer: usize, pos: Pos, chr: u8| {
+        xp.layers[layer].cells[pos.y as usize + height * pos.x as usize].ch = chr as u32;
+    };
+
+    for pos in map.get_all_pos() {
+        let tile = &map[pos];
+
+        let ground_chr =
+            if tile.tile_type == TileType::Water {
+                MAP_WATER
+            } else if tile.surface == Surface::Rubble {
+                MAP_RUBBLE
+            } else if tile.surface == Surface::Grass {
+                MAP_GRASS
+            } else {
+                MAP_GROUND
+            };
+        set_chr(&mut xp, MAP_LAYER_GROUND, pos, ground_chr);
+
+        let environment_chr =
+            if tile.tile_type == TileType::Water {
+                MAP_EMPTY
+            } else if tile.block_move {
+                if tile.chr == 0 { MAP_WALL } else { tile.chr }
+            } else if let Some(chr) = wall_chr(tile) {
+                chr
+            } else {
+                MAP_EMPTY
+            };
+        set_chr(&mut xp, MAP_LAYER_ENVIRONMENT, pos, environment_chr);
+
+        set_chr(&mut xp, MAP_LAYER_ENTITIES, pos, MAP_EMPTY);
+    }
+
+    for entity_id in entities.ids.iter() {
+        let pos = entities.pos[entity_id];
+        if !map.is_within_bounds(pos) {
+            continue;
+        }
+
+        match entity_chr(config, entities.name[entity_id]) {
+            Some((layer, chr)) => set_chr(&mut xp, layer, pos, chr),
+            None => warn!("{:?} has no map character, so it was not written", entities.name[entity_id]),
+        }
+    }
+
+    let mut file = File::create(file_name).map_err(|err| format!("Could not create map file {}: {}", file_name, err))?;
+    xp.write(&mut file).map_err(|err| format!("Could not write map file {}: {}", file_name, err))?;
+
+    return Ok(());
+}
+
+#[test]
+fn test_write_map_xp_round_trip() {
+    let config = Config::from_file("../config.yaml");
+
+    // build a fixture file with each kind of tile and some entities
+    let (width, height) = (8, 6);
+    let mut xp = XpFile::new(width, height);
+    xp.layers.push(XpLayer::new(width, height));
+    xp.layers.push(XpLayer::new(width, height));
+    for (layer_index, layer) in xp.layers.iter_mut().enumerate() {
+        for cell in layer.cells.iter_mut() {
+            cell.ch = if layer_index == MAP_LAYER_GROUND { MAP_GROUND as u32 } else { MAP_EMPTY as u32 };
+        }
+    }
+
+    let set_chr = |xp: &mut XpFile, layer: usize, x: usize, y: usize, chr: u8| {
+        xp.layers[layer].cells[y + height * x].ch = chr as u32;
+    };
+    set_chr(&mut xp, MAP_LAYER_GROUND, 0, 0, MAP_WATER);
+    set_chr(&mut xp, MAP_LAYER_GROUND, 1, 0, MAP_GRASS);
+    set_chr(&mut xp, MAP_LAYER_GROUND, 2, 0, MAP_RUBBLE);
+    set_chr(&mut xp, MAP_LAYER_ENVIRONMENT, 1, 1, MAP_WALL);
+    set_chr(&mut xp, MAP_LAYER_ENVIRONMENT, 2, 1, MAP_STATUE_1);
+    set_chr(&mut xp, MAP_LAYER_ENVIRONMENT, 3, 1, MAP_THIN_WALL_LEFT);
+    set_chr(&mut xp, MAP_LAYER_ENVIRONMENT, 4, 1, MAP_THIN_WALL_BOTTOM);
+    set_chr(&mut xp, MAP_LAYER_ENVIRONMENT, 5, 1, MAP_THIN_WALL_BOTTOM_LEFT);
+    set_chr(&mut xp, MAP_LAYER_ENVIRONMENT, 3, 2, MAP_THICK_WALL_LEFT);
+    set_chr(&mut xp, MAP_LAYER_ENVIRONMENT, 4, 2, MAP_THICK_WALL_BOTTOM);
+    set_chr(&mut xp, MAP_LAYER_ENVIRONMENT, 5, 2, MAP_THICK_WALL_BOTTOM_LEFT);
+    set_chr(&mut xp, MAP_LAYER_ENVIRONMENT, 6, 4, MAP_COLUMN);
+    set_chr(&mut xp, MAP_LAYER_ENTITIES, 1, 4, ENTITY_PLAYER);
+    set_chr(&mut xp, MAP_LAYER_ENTITIES, 2, 4, ENTITY_GOL);
+    set_chr(&mut xp, MAP_LAYER_ENTITIES, 3, 4, ENTITY_KEY);
+    set_chr(&mut xp, MAP_LAYER_ENTITIES, 4, 4, ENTITY_SPIKE_TRAP);
+
+    let fixture_path = std::env::temp_dir().join("test_write_map_xp_fixture.xp");
+    let fixture_file = fixture_path.to_str().unwrap();
+    xp.write(&mut File::create(fixture_file).unwrap()).unwrap();
+
+    let (data, player_position) = parse_map_xp(&config, fixture_file).unwrap();
+
+    // write the map back out, including the player, and read it again
+    let mut entities = data.entities.clone();
+    make_player(&mut entities, &config, &mut MsgLog::new());
+    let player_id = *entities.ids.last().unwrap();
+    entities.set_pos(player_id, Pos::new(player_position.0, player_position.1));
+
+    let written_path = std::env::temp_dir().join("test_write_map_xp_written.xp");
+    let written_file = written_path.to_str().unwrap();
+    write_map_xp(&config, &data.map, &entities, written_file).unwrap();
+
+    let (reread, reread_player_position) = parse_map_xp(&config, written_file).unwrap();
+
+    assert_eq!(data.map.size(), reread.map.size());
+    for pos in data.map.get_all_pos() {
+        assert_eq!(data.map[pos], reread.map[pos], "tile at {:?} changed", pos);
+    }
+
+    assert_eq!(player_position, reread_player_position);
+    let names_and_positions = |entities: &Entities| {
+        return entities.ids.iter()
+                           .map(|id| (entities.name[id], entities.pos[id]))
+                           .collect::<Vec<(EntityName, Pos)>>();
+    };
+    assert_eq!(names_and_positions(&data.entities), names_and_positions(&reread.entities));
 
-    return player_position;
+    std::fs::remove_file(fixture_file).unwrap();
+    std::fs::remove_file(written_file).unwrap();
 }
 
 /// Read file into a vector of lines
@@ -6,7 +6,6 @@ use serde::{Serialize, Deserialize};
 
 use pathfinding::directed::astar::astar;
 
-use oorandom::Rand32;
 use rand::rngs::SmallRng;
 use rand::{SeedableRng};
 
@@ -16,15 +15,19 @@ use image::GenericImageView;
 
 use euclid::*;
 
+use log::{trace, warn};
+
 use roguelike_core::constants::*;
 use roguelike_core::map::*;
 use roguelike_core::types::*;
 use roguelike_core::utils::*;
 use roguelike_core::line::*;
+use roguelike_core::rng::GameRng;
 
 use crate::generation::*;
 use crate::game::*;
 use crate::vault::*;
+use roguelike_core::config::Config;
 
 
 #[derive(Clone, Copy, PartialEq, Eq, Ord, PartialOrd, Debug)]
@@ -60,7 +63,7 @@ pub enum ProcCmd {
     MaxGates(usize),
     Traps(Trap, usize, usize),
     MaxTraps(usize),
-    Grass((usize, usize), i32), // (min, max), disperse distance
+    Grass((usize, usize), i32), // (min, max) inclusive, disperse distance
     Rubble(usize),
     Columns(usize),
     SeedFile(String),
@@ -79,14 +82,49 @@ impl ProcCmd {
         
         return cmds;
     }
+
+    /// Replace the monster, grass, and rubble counts in a command list with any
+    /// given in the config, so they can be tuned without editing the procgen files.
+    pub fn apply_config(cmds: &mut Vec<ProcCmd>, config: &Config) {
+        if let Some((min, max)) = config.gol_count {
+            cmds.retain(|cmd| !matches!(cmd, ProcCmd::Entities(EntityName::Gol, _, _)));
+            cmds.push(ProcCmd::Entities(EntityName::Gol, min, max));
+        }
+
+        if let Some((min, max)) = config.elf_count {
+            cmds.retain(|cmd| !matches!(cmd, ProcCmd::Entities(EntityName::Pawn, _, _)));
+            cmds.push(ProcCmd::Entities(EntityName::Pawn, min, max));
+        }
+
+        if let Some(range) = config.grass_clusters {
+            // keep the disperse distance from the file
+            let disperse = cmds.iter().filter_map(|cmd| {
+                if let ProcCmd::Grass(_, disperse) = cmd {
+                    return Some(*disperse);
+                }
+                return None;
+            }).next().unwrap_or(0);
+
+            cmds.retain(|cmd| !matches!(cmd, ProcCmd::Grass(_, _)));
+            cmds.push(ProcCmd::Grass(range, disperse));
+        }
+
+        if let Some(amount) = config.rubble_amount {
+            cmds.retain(|cmd| !matches!(cmd, ProcCmd::Rubble(_)));
+            cmds.push(ProcCmd::Rubble(amount));
+        }
+    }
 }
 
-pub fn generate_bare_map(width: u32, height: u32, template_file: &str, rng: &mut Rand32) -> Map {
+/// Generate a map of walls and floor using wave function collapse, seeded by the
+/// given template image. Returns an error naming the template if it cannot be
+/// loaded, or if no map could be generated from it.
+pub fn generate_bare_map(width: u32, height: u32, template_file: &str, rng: &mut GameRng) -> Result<Map, String> {
     let mut new_map = Map::from_dims(width, height);
 
-    let file = File::open(template_file).unwrap();
+    let file = File::open(template_file).map_err(|err| format!("Could not open template {}: {}", template_file, err))?;
     let reader = BufReader::new(file);
-    let seed_image = image::load(reader, image::ImageFormat::Png).unwrap();
+    let seed_image = image::load(reader, image::ImageFormat::Png).map_err(|err| format!("Could not load template {}: {}", template_file, err))?;
     let orientations = [Orientation::Original,
                         Orientation::Clockwise90,
                         Orientation::Clockwise180,
@@ -96,7 +134,7 @@ pub fn generate_bare_map(width: u32, height: u32, template_file: &str, rng: &mut
                         Orientation::DiagonallyFlippedClockwise180,
                         Orientation::DiagonallyFlippedClockwise270];
 
-    let seed: [u8; 32] = [rng.rand_u32() as u8; 32];
+    let seed: [u8; 32] = [rng.range_u32("wfc_seed", 0, 256) as u8; 32];
     let mut small_rng = SmallRng::from_seed(seed);
     let map_image = 
         wfc_image::generate_image_with_rng(&seed_image,
@@ -106,8 +144,11 @@ pub fn generate_bare_map(width: u32, height: u32, template_file: &str, rng: &mut
                                            wfc_image::wrap::WrapNone,
                                            ForbidNothing,
                                            wfc_image::retry::NumTimes(3),
-                                           &mut small_rng).unwrap();
-    map_image.save("wfc_map.png").unwrap();
+                                           &mut small_rng)
+                 .map_err(|err| format!("Could not generate a map from template {}: {:?}", template_file, err))?;
+    if let Err(err) = map_image.save("wfc_map.png") {
+        warn!("Could not save wfc_map.png: {}", err);
+    }
 
     for x in 0..width {
         for y in 0..height {
@@ -119,15 +160,13 @@ pub fn generate_bare_map(width: u32, height: u32, template_file: &str, rng: &mut
          }
     }
 
-    return new_map;
+    return Ok(new_map);
 }
 
 fn check_map(game: &Game) {
     for wall_pos in game.data.map.get_wall_pos() {
-        for id in game.data.entities.ids.iter() {
-            if wall_pos == game.data.entities.pos[id] {
-                panic!("A wall overlapped with an entity!");
-            }
+        if !game.data.entities_at(wall_pos).is_empty() {
+            panic!("A wall overlapped with an entity!");
         }
     }
 }
@@ -161,17 +200,7 @@ pub fn saturate_map(game: &mut Game, cmds: &Vec<ProcCmd>) -> Pos {
     modify_structures(game, cmds, &mut structures);
 
     // lay down grass with a given dispersion and range from the found tile
-    let range_disperse =
-        cmds.iter().filter_map(|cmd| {
-            if let ProcCmd::Grass(range, disperse) = cmd {
-                return Some((range, disperse)) 
-            };
-            return None;
-    }).next().unwrap_or((&(0, 0), &0));
-    let high = (range_disperse.0).1 as u32;
-    let low = (range_disperse.0).0 as u32;
-    let num_grass_to_place = rng_range_u32(&mut game.rng, low, high) as usize;
-    place_grass(game, num_grass_to_place, *range_disperse.1);
+    place_grass_clusters(game, cmds);
 
     // clear about the island again to ensure tiles haven't been placed outside
     clear_island(game, island_radius);
@@ -179,12 +208,14 @@ pub fn saturate_map(game: &mut Game, cmds: &Vec<ProcCmd>) -> Pos {
     // find a place to put the player
     let player_id = game.data.find_by_name(EntityName::Player).unwrap();
     let player_pos = find_available_tile(game).unwrap();
-    game.data.entities.pos[&player_id] = player_pos;
+    game.data.entities.set_pos(player_id, player_pos);
 
     clear_island(game, island_radius);
 
     // find a place to put the key and goal, ensuring that they are reachable
-    place_key_and_goal(game, player_pos);
+    if place_key_and_goal(game, player_pos) {
+        trace!("carved a path to the key or goal");
+    }
 
     place_items(game, cmds);
 
@@ -215,35 +246,45 @@ fn modify_structures(game: &mut Game, cmds: &Vec<ProcCmd>, structures: &mut Vec<
     }).map(|r| *r).next().unwrap_or(0);
     let mut num_rubbles = 0;
 
+    let max_columns =
+        cmds.iter().filter_map(|cmd| {
+            if let ProcCmd::Columns(num_columns) = cmd {
+                return Some(num_columns) 
+            };
+            return None;
+    }).map(|c| *c).next().unwrap_or(0);
+    let mut num_columns = 0;
+
     let mut to_remove: Vec<usize> = Vec::new();
     for (index, structure) in structures.iter().enumerate() {
         // turn some lone single-tile walls into columns
         if structure.typ == StructureType::Single {
-            if rng_range(&mut game.rng, 0.0, 1.0) < 0.3 {
+            if num_columns < max_columns && game.rng.trial("column", 0.3) {
                 make_column(&mut game.data.entities, &game.config, structure.blocks[0], &mut game.msg_log);
                 to_remove.push(index);
+                num_columns += 1;
             }
-        } else if rng_range(&mut game.rng, 0.0, 1.0) < 0.3 {
+        } else if game.rng.trial("rubble", 0.3) {
             if num_rubbles < max_rubbles {
-                let index = rng_range_u32(&mut game.rng, 0, structure.blocks.len() as u32) as usize;
+                let index = game.rng.range_u32("rubble_block", 0, structure.blocks.len() as u32) as usize;
                 let block = structure.blocks[index];
-                game.data.map[block] = Tile::empty();
+                game.data.map[block] = Tile::floor();
                 game.data.map[block].surface = Surface::Rubble;
                 num_rubbles += 1;
             }
         }
 
         // turn some structures into short or tall walls
-        if structure.typ != StructureType::Single && rng_range(&mut game.rng, 0.0, 1.0) < 0.7 {
+        if structure.typ != StructureType::Single && game.rng.trial("structure_walls", 0.7) {
            let wall_type;
-           if rng_range(&mut game.rng, 0.0, 1.0) < 1.0 {
+           if game.rng.trial("short_wall", 1.0) {
                wall_type = Wall::ShortWall;
            } else {
                wall_type = Wall::TallWall;
            }
 
            for pos in structure.blocks.iter() {
-               game.data.map[*pos] = Tile::empty();
+               game.data.map[*pos] = Tile::floor();
 
                for neighbor in game.data.map.cardinal_neighbors(*pos) {
                    if game.data.map.is_within_bounds(neighbor) &&
@@ -265,7 +306,7 @@ fn modify_structures(game: &mut Game, cmds: &Vec<ProcCmd>, structures: &mut Vec<
     to_remove.reverse();
     for index in to_remove.iter() {
         for block in structures[*index].blocks.iter() {
-            game.data.map[*block] = Tile::empty();
+            game.data.map[*block] = Tile::floor();
         }
         structures.swap_remove(*index);
     }
@@ -328,12 +369,12 @@ fn place_items(game: &mut Game, cmds: &Vec<ProcCmd>) {
 
     for cmd in cmds.iter() {
         if let ProcCmd::Items(typ, min, max) = cmd {
-            let num_gen = rng_range_u32(&mut game.rng, *min as u32, (*max + 1) as u32) as usize;
+            let num_gen = game.rng.range_u32("num_items", *min as u32, (*max + 1) as u32) as usize;
             for _ in 0..num_gen {
-                num_items += 1;
                 if num_items >= max_items {
                     return;
                 }
+                num_items += 1;
 
                 let len = potential_pos.len();
 
@@ -341,12 +382,15 @@ fn place_items(game: &mut Game, cmds: &Vec<ProcCmd>) {
                     break;
                 }
 
-                let index = rng_range_u32(&mut game.rng, 0, len as u32) as usize;
+                let index = game.rng.range_u32("item_pos", 0, len as u32) as usize;
                 let pos = potential_pos[index];
 
                 match typ {
                     Item::Dagger => { make_dagger(&mut game.data.entities, &game.config, pos, &mut game.msg_log); },
                     Item::Sword => { make_sword(&mut game.data.entities, &game.config, pos, &mut game.msg_log); },
+                    Item::Spear => { make_spear(&mut game.data.entities, &game.config, pos, &mut game.msg_log); },
+                    Item::Cloak => { make_cloak(&mut game.data.entities, &game.config, pos, &mut game.msg_log); },
+                    Item::Torch => { make_torch(&mut game.data.entities, &game.config, pos, &mut game.msg_log); },
                     Item::Shield => { make_shield(&mut game.data.entities, &game.config, pos, &mut game.msg_log); },
                     Item::Hammer => { make_hammer(&mut game.data.entities, &game.config, pos, &mut game.msg_log); },
                     Item::Stone => { make_stone(&mut game.data.entities, &game.config, pos, &mut game.msg_log); },
@@ -386,7 +430,7 @@ fn place_triggers(game: &mut Game, cmds: &Vec<ProcCmd>) {
     }
 
     for _ in 0..max_gates {
-        let gate_pos_index = rng_range_u32(&mut game.rng, 0, gate_positions.len() as u32) as usize;
+        let gate_pos_index = game.rng.range_u32("gate_pos", 0, gate_positions.len() as u32) as usize;
         let gate_pos = gate_positions[gate_pos_index];
         gate_positions.swap_remove(gate_pos_index);
 
@@ -408,7 +452,7 @@ fn place_traps(game: &mut Game, cmds: &Vec<ProcCmd>) {
 
     for cmd in cmds.iter() {
         if let ProcCmd::Traps(typ, min, max) = cmd {
-            let num_gen = rng_range_u32(&mut game.rng, *min as u32, (*max + 1) as u32);
+            let num_gen = game.rng.range_u32("num_traps", *min as u32, (*max + 1) as u32);
             for _ in 0..num_gen {
                 num_traps += 1;
                 if num_traps >= max_traps {
@@ -421,7 +465,7 @@ fn place_traps(game: &mut Game, cmds: &Vec<ProcCmd>) {
                     return;
                 }
 
-                let index = rng_range_u32(&mut game.rng, 0, len as u32) as usize;
+                let index = game.rng.range_u32("trap_pos", 0, len as u32) as usize;
                 let pos = potential_pos[index];
 
                 match typ {
@@ -437,49 +481,76 @@ fn place_traps(game: &mut Game, cmds: &Vec<ProcCmd>) {
 
 fn place_monsters(game: &mut Game, player_id: EntityId, cmds: &Vec<ProcCmd>) {
     let player_pos = game.data.entities.pos[&player_id];
+    let spacing = game.config.monster_spacing;
 
     // get empty positions, but make sure they are not close to the player
-    let mut potential_pos = 
+    let potential_pos = 
         game.data.get_clear_pos()
                  .iter()
-                 .filter(|p| distance(player_pos, **p) > 4)
+                 .filter(|p| distance(player_pos, **p) > std::cmp::max(4, spacing))
                  .map(|p| *p)
                  .collect::<Vec<Pos>>();
 
+    let mut monster_types = Vec::new();
     for cmd in cmds.iter() {
         if let ProcCmd::Entities(typ, min, max) = cmd {
-            let num_gen = rng_range_u32(&mut game.rng, *min as u32, *max as u32) as usize;
+            let num_gen = game.rng.range_u32("num_monsters", *min as u32, (*max + 1) as u32) as usize;
+            let num_gen = (num_gen as f32 * game.config.monster_count_scale).round() as usize;
 
             for _ in 0..num_gen {
-                let len = potential_pos.len();
+                monster_types.push(*typ);
+            }
+        }
+    }
 
-                if len == 0 {
-                    break;
-                }
+    let monster_positions = place_with_spacing(&potential_pos, monster_types.len(), spacing, &mut game.rng);
+
+    for (typ, pos) in monster_types.iter().zip(monster_positions) {
+        // spawn on the closest tile that is still clear of walls and blocking entities
+        let pos = match game.data.closest_clear_pos(pos, std::cmp::max(1, spacing)) {
+            Some(pos) => pos,
+            None => continue,
+        };
+
+        let id;
+        match typ {
+            EntityName::Gol => { id = Some(make_gol(&mut game.data.entities, &game.config, pos, &mut game.msg_log)); },
+            EntityName::Pawn => { id = Some(make_pawn(&mut game.data.entities, &game.config, pos, &mut game.msg_log)); },
+            EntityName::Spire => { id = Some(make_spire(&mut game.data.entities, &game.config, pos, &mut game.msg_log)); },
+            EntityName::Armil => { id = Some(make_armil(&mut game.data.entities, &game.config, pos, &mut game.msg_log)); },
+            EntityName::Rook => { id = Some(make_rook(&mut game.data.entities, &game.config, pos, &mut game.msg_log)); },
+            _ => { id = None; },
+        }
+        if let Some(id) = id {
+            if game.data.is_in_fov(id, player_id, &game.config) {
+                game.data.entities.direction[&id] = 
+                    game.data.entities.direction[&id].reverse();
+            }
+        }
+    }
+}
 
-                let index = rng_range_u32(&mut game.rng, 0, len as u32) as usize;
-                let pos = potential_pos[index];
+/// Choose up to count positions such that each is at least min_dist from the
+/// others. Candidates that are too close are discarded, and the search gives up
+/// after a bounded number of tries, so fewer positions may be returned.
+pub fn place_with_spacing(positions: &Vec<Pos>, count: usize, min_dist: i32, rng: &mut GameRng) -> Vec<Pos> {
+    let mut candidates = positions.clone();
+    let mut placed: Vec<Pos> = Vec::new();
 
-                let id;
-                match typ {
-                    EntityName::Gol => { id = Some(make_gol(&mut game.data.entities, &game.config, pos, &mut game.msg_log)); },
-                    EntityName::Pawn => { id = Some(make_pawn(&mut game.data.entities, &game.config, pos, &mut game.msg_log)); },
-                    EntityName::Spire => { id = Some(make_spire(&mut game.data.entities, &game.config, pos, &mut game.msg_log)); },
-                    EntityName::Armil => { id = Some(make_armil(&mut game.data.entities, &game.config, pos, &mut game.msg_log)); },
-                    EntityName::Rook => { id = Some(make_rook(&mut game.data.entities, &game.config, pos, &mut game.msg_log)); },
-                    _ => { id = None; },
-                }
-                if let Some(id) = id {
-                    if game.data.is_in_fov(id, player_id, &game.config) {
-                        game.data.entities.direction[&id] = 
-                            game.data.entities.direction[&id].reverse();
-                    }
-                }
+    let max_tries = count * PLACEMENT_TRIES_PER_ENTITY;
+    let mut tries = 0;
+    while placed.len() < count && candidates.len() > 0 && tries < max_tries {
+        tries += 1;
 
-                potential_pos.remove(index);
-            }
+        let index = rng.range_u32("spacing_candidate", 0, candidates.len() as u32) as usize;
+        let pos = candidates.swap_remove(index);
+
+        if placed.iter().all(|other| distance(*other, pos) >= min_dist) {
+            placed.push(pos);
         }
     }
+
+    return placed;
 }
 
 // TODO choose based on frequency given by tags
@@ -488,11 +559,11 @@ fn place_vaults(game: &mut Game, cmds: &Vec<ProcCmd>) {
         if let ProcCmd::Vaults(max) = cmd {
             for _ in 0..*max {
                 let (width, height) = game.data.map.size();
-                let x = rng_range_i32(&mut game.rng, 0, width);
-                let y = rng_range_i32(&mut game.rng, 0, height);
+                let x = game.rng.range_i32("vault_x", 0, width);
+                let y = game.rng.range_i32("vault_y", 0, height);
                 let offset = Pos::new(x, y);
 
-                let vault_index = rng_range_u32(&mut game.rng, 0, game.vaults.len() as u32) as usize;
+                let vault_index = game.rng.range_u32("vault_index", 0, game.vaults.len() as u32) as usize;
                 let vault = &game.vaults[vault_index];
                 eprintln!("Placing vault {} at {}", vault_index, offset);
                 place_vault(&mut game.data, vault, offset, &mut game.rng);
@@ -502,13 +573,13 @@ fn place_vaults(game: &mut Game, cmds: &Vec<ProcCmd>) {
 }
 
 // TODO rotate and mirror according to tags
-pub fn place_vault(data: &mut GameData, vault: &Vault, offset: Pos, rng: &mut Rand32) {
+pub fn place_vault(data: &mut GameData, vault: &Vault, offset: Pos, rng: &mut GameRng) {
                         
-    let mirror = !vault.tags.contains(&VaultTag::NoMirror) && rng_range(rng, 0.0, 1.0) < 0.5;
+    let mirror = !vault.tags.contains(&VaultTag::NoMirror) && rng.trial("vault_mirror", 0.5);
 
     let mut rotation = Rotation::Degrees0;
-    if !vault.tags.contains(&VaultTag::NoRotate) && rng_range(rng, 0.0, 1.0) < 0.5 {
-        let rand = rng_range(rng, 0.0, 3.0).round();
+    if !vault.tags.contains(&VaultTag::NoRotate) && rng.trial("vault_rotate", 0.5) {
+        let rand = rng.range_f32("vault_rotation", 0.0, 3.0).round();
         let index = rand as usize;
         let rotations = &[Rotation::Degrees0, Rotation::Degrees90, Rotation::Degrees180, Rotation::Degrees270];
         rotation = rotations[index];
@@ -531,7 +602,7 @@ pub fn place_vault_with(data: &mut GameData, vault: &Vault, offset: Pos, rotatio
 
             for entity_id in data.get_entities_at_pos(map_pos) {
                 if data.entities.typ[&entity_id] == EntityType::Player {
-                    data.map[vault_pos] = Tile::empty();
+                    data.map[vault_pos] = Tile::floor();
                 } else {
                     data.entities.remove_entity(entity_id);
                 }
@@ -552,7 +623,7 @@ pub fn place_vault_with(data: &mut GameData, vault: &Vault, offset: Pos, rotatio
         entity_pos = rotation.rotate(entity_pos, width, height);
         entity_pos = add_pos(offset, entity_pos);
         if data.map.is_within_bounds(entity_pos) && !data.map[entity_pos].block_move {
-            entities.pos[id] = entity_pos;
+            entities.set_pos(*id, entity_pos);
         } else {
             vault_entities_to_remove.push(*id);
             continue;
@@ -580,17 +651,35 @@ pub fn place_vault_with(data: &mut GameData, vault: &Vault, offset: Pos, rotatio
     data.entities.merge(&entities);
 }
 
-fn place_grass(game: &mut Game, num_grass_to_place: usize, disperse: i32) {
+/// Place a number of grass clusters given by the Grass command, returning the number placed.
+fn place_grass_clusters(game: &mut Game, cmds: &Vec<ProcCmd>) -> usize {
+    let range_disperse =
+        cmds.iter().filter_map(|cmd| {
+            if let ProcCmd::Grass(range, disperse) = cmd {
+                return Some((range, disperse)) 
+            };
+            return None;
+    }).next().unwrap_or((&(0, 0), &0));
+    let high = (range_disperse.0).1 as u32;
+    let low = (range_disperse.0).0 as u32;
+    let num_grass_to_place = game.rng.range_u32("num_grass", low, high + 1) as usize;
+    return place_grass(game, num_grass_to_place, *range_disperse.1);
+}
+
+fn place_grass(game: &mut Game, num_grass_to_place: usize, disperse: i32) -> usize {
     let (width, height) = game.data.map.size();
 
+    let max_count = 35;
+    let mut flood = Vec::new();
     let mut potential_grass_pos = Vec::new();
     for x in 0..width {
         for y in 0..height {
             let pos = Pos::new(x, y);
 
             if !game.data.map[pos].block_move {
-                let count = floodfill(&game.data.map, pos, 3).len();
-                if count > 28 && count < 35 {
+                floodfill_capped_into(&game.data.map, pos, 3, max_count, &mut flood);
+                let count = flood.len();
+                if count > 28 && count < max_count {
                     potential_grass_pos.push(pos);
                 }
             }
@@ -604,8 +693,8 @@ fn place_grass(game: &mut Game, num_grass_to_place: usize, disperse: i32) {
         game.data.map[pos].surface = Surface::Grass;
 
         for _ in 0..4 {
-            let offset_pos = Pos::new(pos.x + rng_range_i32(&mut game.rng, 0, disperse),
-                                      pos.y + rng_range_i32(&mut game.rng, 0, disperse));
+            let offset_pos = Pos::new(pos.x + game.rng.range_i32("grass_x", 0, disperse),
+                                      pos.y + game.rng.range_i32("grass_y", 0, disperse));
             if game.data.map.is_within_bounds(offset_pos) &&
                !game.data.map[offset_pos].block_move {
                 game.data.map[offset_pos].surface = Surface::Grass;
@@ -613,6 +702,85 @@ fn place_grass(game: &mut Game, num_grass_to_place: usize, disperse: i32) {
         }
 
     }
+
+    return num_grass_to_place;
+}
+
+#[test]
+fn test_place_with_spacing() {
+    let map = Map::from_dims(20, 20);
+    let positions = map.get_all_pos();
+    let mut rng = GameRng::new(1);
+
+    let min_dist = 4;
+    let placed = place_with_spacing(&positions, 10, min_dist, &mut rng);
+    assert_eq!(10, placed.len());
+
+    for (index, pos) in placed.iter().enumerate() {
+        for other in placed[index + 1..].iter() {
+            assert!(distance(*pos, *other) >= min_dist);
+        }
+    }
+
+    // when there is not enough room, fewer positions are returned
+    let positions = vec!(Pos::new(0, 0), Pos::new(1, 0), Pos::new(2, 0));
+    let placed = place_with_spacing(&positions, 3, min_dist, &mut rng);
+    assert_eq!(1, placed.len());
+}
+
+#[test]
+fn test_place_grass_clusters() {
+    let config = Config::from_file("../config.yaml");
+    let mut game = Game::new(0, config);
+
+    // an open map has grass positions near each corner
+    game.data.map = Map::from_dims(20, 20);
+
+    let cmds = vec!(ProcCmd::Grass((2, 2), 0));
+    assert_eq!(2, place_grass_clusters(&mut game, &cmds));
+
+    let num_grass = game.data.map.get_all_pos()
+                                 .iter()
+                                 .filter(|pos| game.data.map[**pos].surface == Surface::Grass)
+                                 .count();
+    assert_eq!(2, num_grass);
+
+    // with no grass command, no grass is placed
+    let mut game = Game::new(0, game.config.clone());
+    game.data.map = Map::from_dims(20, 20);
+    assert_eq!(0, place_grass_clusters(&mut game, &Vec::new()));
+}
+
+#[test]
+fn test_proc_cmd_apply_config() {
+    let mut config = Config::from_file("../config.yaml");
+    let file_cmds = vec!(ProcCmd::Entities(EntityName::Gol, 1, 4),
+                         ProcCmd::Entities(EntityName::Pawn, 2, 4),
+                         ProcCmd::Grass((14, 18), 3),
+                         ProcCmd::Rubble(5));
+
+    // without config counts, the file's commands are kept
+    config.gol_count = None;
+    config.elf_count = None;
+    config.grass_clusters = None;
+    config.rubble_amount = None;
+    let mut cmds = file_cmds.clone();
+    ProcCmd::apply_config(&mut cmds, &config);
+    assert_eq!(file_cmds, cmds);
+
+    // config counts replace the file's, keeping the grass disperse distance
+    config.gol_count = Some((0, 1));
+    config.elf_count = Some((3, 3));
+    config.grass_clusters = Some((2, 2));
+    config.rubble_amount = Some(0);
+    ProcCmd::apply_config(&mut cmds, &config);
+    cmds.sort();
+    let mut expected = vec!(ProcCmd::Entities(EntityName::Gol, 0, 1),
+                            ProcCmd::Entities(EntityName::Pawn, 3, 3),
+                            ProcCmd::Grass((2, 2), 3),
+                            ProcCmd::Rubble(0));
+    expected.sort();
+    assert_eq!(expected, cmds);
 }
 
 fn find_available_tile(game: &mut Game) -> Option<Pos> {
@@ -622,7 +790,7 @@ fn find_available_tile(game: &mut Game) -> Option<Pos> {
     let mut index = 1.0;
     for pos in potential_pos {
         if game.data.has_blocking_entity(pos).is_none() {
-            if rng_range(&mut game.rng, 0.0, 1.0) < (1.0 / index) {
+            if game.rng.trial("available_tile", 1.0 / index) {
                 avail_pos = Some(pos);
             }
 
@@ -665,28 +833,96 @@ fn clear_path_to(game: &mut Game, start_pos: Pos, target_pos: Pos) {
     if let Some((results, _cost)) = path {
         for pos in results {
             if game.data.map[pos].block_move {
-                game.data.map[pos] = Tile::empty();
+                game.data.map[pos] = Tile::floor();
             }
         }
     }
 }
 
-fn place_key_and_goal(game: &mut Game, player_pos: Pos) {
-    // place goal and key
-    let key_pos = find_available_tile(game).unwrap();
-    game.data.map[key_pos] = Tile::empty();
+/// Pick a random position from the given positions that is not blocked by an entity.
+fn find_tile_in(game: &mut Game, positions: &Vec<Pos>) -> Option<Pos> {
+    let potential_pos = positions.iter()
+                                 .map(|pos| *pos)
+                                 .filter(|pos| game.data.has_blocking_entity(*pos).is_none())
+                                 .collect::<Vec<Pos>>();
+
+    if potential_pos.len() == 0 {
+        return None;
+    }
+
+    let index = game.rng.range_u32("tile_in", 0, potential_pos.len() as u32) as usize;
+    return Some(potential_pos[index]);
+}
+
+/// Place the key and goal in tiles reachable from the player. Only if there is no room
+/// in the player's region are they placed elsewhere with a path carved to them.
+/// Returns whether any carving was needed.
+fn place_key_and_goal(game: &mut Game, player_pos: Pos) -> bool {
+    let region = game.data.map.connected_regions()
+                              .into_iter()
+                              .find(|region| region.contains(&player_pos))
+                              .unwrap_or(Vec::new());
+
+    let mut carved = false;
+
+    // place key
+    let key_pos;
+    if let Some(pos) = find_tile_in(game, &region) {
+        key_pos = pos;
+    } else {
+        key_pos = find_available_tile(game).unwrap();
+        game.data.map[key_pos] = Tile::floor();
+        clear_path_to(game, player_pos, key_pos);
+        carved = true;
+    }
     make_key(&mut game.data.entities, &game.config, key_pos, &mut game.msg_log);
-    clear_path_to(game, player_pos, key_pos);
 
     // Find the goal position, ensuring it is not too close to the key
-    let mut goal_pos = find_available_tile(game).unwrap();
-    while distance(key_pos, goal_pos) < 4 {
-        goal_pos = find_available_tile(game).unwrap();
+    let goal_region = region.iter()
+                            .map(|pos| *pos)
+                            .filter(|pos| distance(key_pos, *pos) >= 4)
+                            .collect::<Vec<Pos>>();
+    if let Some(goal_pos) = find_tile_in(game, &goal_region) {
+        make_exit(&mut game.data.entities, &game.config, goal_pos, &mut game.msg_log);
+    } else {
+        let mut goal_pos = find_available_tile(game).unwrap();
+        while distance(key_pos, goal_pos) < 4 {
+            goal_pos = find_available_tile(game).unwrap();
+        }
+
+        game.data.map[goal_pos] = Tile::floor();
+        make_exit(&mut game.data.entities, &game.config, goal_pos, &mut game.msg_log);
+        clear_path_to(game, player_pos, goal_pos);
+        carved = true;
     }
 
-    game.data.map[goal_pos] = Tile::empty();
-    make_exit(&mut game.data.entities, &game.config, goal_pos, &mut game.msg_log);
-    clear_path_to(game, player_pos, goal_pos);
+    return carved;
+}
+
+#[test]
+fn test_place_key_and_goal_connected() {
+    let config = Config::from_file("../config.yaml");
+    let mut game = Game::new(0, config);
+    game.data.map = Map::from_dims(20, 20);
+
+    // a wall with a gap, so the map is fully connected but a carved path could cut through it
+    for y in 1..20 {
+        game.data.map[(10, y)] = Tile::wall();
+    }
+    let map_before = game.data.map.clone();
+
+    let player_id = game.data.find_by_name(EntityName::Player).unwrap();
+    let player_pos = Pos::new(2, 2);
+    game.data.entities.set_pos(player_id, player_pos);
+
+    assert!(!place_key_and_goal(&mut game, player_pos));
+    assert_eq!(map_before, game.data.map);
+
+    let key_id = game.data.find_by_name(EntityName::Key).unwrap();
+    let exit_id = game.data.find_by_name(EntityName::Exit).unwrap();
+    let key_pos = game.data.entities.pos[&key_id];
+    let exit_pos = game.data.entities.pos[&exit_id];
+    assert!(distance(key_pos, exit_pos) >= 4);
 }
 
 fn clear_island(game: &mut Game, island_radius: i32) {
@@ -753,8 +989,7 @@ fn process_block(block: Pos, structure: &mut Structure, blocks: &Vec<Pos>, seen:
 fn adjacent_blocks(block: Pos, blocks: &Vec<Pos>, seen: &HashSet<Pos>) -> Vec<Pos> {
     let mut result = Vec::new();
 
-    let adjacents = [move_x(block, 1), move_y(block, 1), move_x(block, -1), move_y(block, -1)];
-    for adj in adjacents.iter() {
+    for adj in block.neighbors4().iter() {
         if blocks.contains(adj) && !seen.contains(&adj) {
             result.push(*adj);
         }
@@ -925,51 +1160,76 @@ pub fn place_line(map: &mut Map, start: Pos, end: Pos, tile: Tile) -> Vec<Pos> {
     positions
 }
 
-pub fn add_obstacle(map: &mut Map, pos: Pos, obstacle: Obstacle, rng: &mut Rand32) {
+/// Place a tile at each of the given positions which is within the map,
+/// returning the positions that were placed.
+pub fn place_tiles(map: &mut Map, positions: &[Pos], tile: Tile) -> Vec<Pos> {
+    let mut placed = Vec::new();
+
+    for pos in positions.iter() {
+        if map.is_within_bounds(*pos) {
+            map[*pos] = tile;
+            placed.push(*pos);
+        }
+    }
+
+    return placed;
+}
+
+/// Add an obstacle to the map at the given position, returning the positions
+/// where walls were placed.
+pub fn add_obstacle(map: &mut Map, pos: Pos, obstacle: Obstacle, rng: &mut GameRng) -> Vec<Pos> {
     match obstacle {
         Obstacle::Block => {
             map.tiles[pos.x as usize][pos.y as usize] = Tile::wall();
+            return vec!(pos);
         }
 
         Obstacle::Wall => {
-            let end_pos = if rng_trial(rng, 0.5) {
+            let end_pos = if rng.trial("wall_dir", 0.5) {
                 move_x(pos, 3)
             } else {
                 move_y(pos, 3)
             };
-            place_line(map, pos, end_pos, Tile::wall());
+            return place_line(map, pos, end_pos, Tile::wall());
         }
 
         Obstacle::ShortWall => {
-            let end_pos = if rng_trial(rng, 0.5) {
+            let end_pos = if rng.trial("short_wall_dir", 0.5) {
                 move_x(pos, 3)
             } else {
                 move_y(pos, 3)
             };
-            place_line(map, pos, end_pos, Tile::short_wall());
+            return place_line(map, pos, end_pos, Tile::short_wall());
         }
 
         Obstacle::Square => {
-            place_block(map, pos, 2, Tile::wall());
+            return place_block(map, pos, 2, Tile::wall());
         }
 
         Obstacle::LShape => {
             let mut dir = 1;
-            if rng_trial(rng, 0.5) {
+            if rng.trial("lshape_dir", 0.5) {
                 dir = -1;
             }
 
-            if rng_trial(rng, 0.5) {
+            let mut positions = Vec::new();
+            if rng.trial("lshape_axis", 0.5) {
                 for x in 0..3 {
                     map.tiles[pos.x as usize + x][pos.y as usize] = Tile::wall();
+                    positions.push(Pos::new(pos.x + x as i32, pos.y));
                 }
                 map.tiles[pos.x as usize][(pos.y + dir) as usize] = Tile::wall();
+                positions.push(Pos::new(pos.x, pos.y + dir));
             } else {
                 for y in 0..3 {
                     map.tiles[pos.x as usize][pos.y as usize + y] = Tile::wall();
+                    positions.push(Pos::new(pos.x, pos.y + y as i32));
                 }
                 map.tiles[(pos.x + dir) as usize][pos.y as usize] = Tile::wall();
+                positions.push(Pos::new(pos.x + dir, pos.y));
             }
+
+            return positions;
         }
 
         Obstacle::Building => {
@@ -981,9 +1241,190 @@ pub fn add_obstacle(map: &mut Map, pos: Pos, obstacle: Obstacle, rng: &mut Rand3
             positions.append(&mut place_line(map, move_by(pos, Pos::new(-size, -size)), move_by(pos, Pos::new(size, -size)), Tile::wall()));
             positions.append(&mut place_line(map, move_by(pos, Pos::new(size, -size)),  move_by(pos, Pos::new(size,  size)), Tile::wall()));
 
-            for _ in 0..rng_range_u32(rng, 0, 10) {
-                positions.swap_remove(rng_range_u32(rng, 0, positions.len() as u32) as usize);
+            for _ in 0..rng.range_u32("building_gaps", 0, 10) {
+                positions.swap_remove(rng.range_u32("building_gap", 0, positions.len() as u32) as usize);
+            }
+
+            return positions;
+        }
+
+        Obstacle::Cross => {
+            let size = 2;
+
+            // a plus shape centered on pos, with arms of length 'size'
+            let mut positions = vec!(pos);
+            for offset in 1..=size {
+                positions.push(move_by(pos, Pos::new(offset, 0)));
+                positions.push(move_by(pos, Pos::new(-offset, 0)));
+                positions.push(move_by(pos, Pos::new(0, offset)));
+                positions.push(move_by(pos, Pos::new(0, -offset)));
             }
+
+            return place_tiles(map, &positions, Tile::wall());
+        }
+
+        Obstacle::Ring => {
+            let size: i32 = 2;
+
+            // a hollow square around pos, with an opening in the middle of each side
+            let mut positions = Vec::new();
+            for x in -size..=size {
+                for y in -size..=size {
+                    let on_edge = x.abs() == size || y.abs() == size;
+                    let is_opening = x == 0 || y == 0;
+                    if on_edge && !is_opening {
+                        positions.push(move_by(pos, Pos::new(x, y)));
+                    }
+                }
+            }
+
+            return place_tiles(map, &positions, Tile::wall());
+        }
+    }
+}
+
+#[cfg(test)]
+fn count_entities(game: &Game, name: EntityName) -> usize {
+    return game.data.entities.ids.iter().filter(|id| game.data.entities.name[*id] == name).count();
+}
+
+#[test]
+fn test_proc_cmd_island() {
+    let config = Config::from_file("../config.yaml");
+    let mut game = Game::new(0, config);
+    game.data.map = Map::from_dims(20, 20);
+
+    let player_pos = saturate_map(&mut game, &vec!(ProcCmd::Island(5)));
+
+    assert_eq!(TileType::Water, game.data.map[(0, 0)].tile_type);
+    assert_eq!(TileType::Water, game.data.map[(10, 16)].tile_type);
+    assert_eq!(TileType::Floor, game.data.map[(10, 10)].tile_type);
+    assert_eq!(TileType::Floor, game.data.map[(10, 14)].tile_type);
+
+    // the player is placed on the island
+    assert_eq!(TileType::Floor, game.data.map[player_pos].tile_type);
+}
+
+#[test]
+fn test_proc_cmd_entities() {
+    let config = Config::from_file("../config.yaml");
+    let mut game = Game::new(0, config);
+    game.data.map = Map::from_dims(20, 20);
+    let player_id = game.data.find_by_name(EntityName::Player).unwrap();
+    game.data.entities.set_pos(player_id, Pos::new(0, 0));
+
+    let cmds = vec!(ProcCmd::Entities(EntityName::Gol, 2, 2),
+                    ProcCmd::Entities(EntityName::Pawn, 0, 0));
+    place_monsters(&mut game, player_id, &cmds);
+
+    assert_eq!(2, count_entities(&game, EntityName::Gol));
+    assert_eq!(0, count_entities(&game, EntityName::Pawn));
+}
+
+#[test]
+fn test_proc_cmd_items() {
+    let config = Config::from_file("../config.yaml");
+    let mut game = Game::new(0, config);
+    game.data.map = Map::from_dims(20, 20);
+
+    let cmds = vec!(ProcCmd::Items(Item::Stone, 3, 3), ProcCmd::Items(Item::Dagger, 1, 1));
+    place_items(&mut game, &cmds);
+    assert_eq!(3, count_entities(&game, EntityName::Stone));
+    assert_eq!(1, count_entities(&game, EntityName::Dagger));
+
+    // MaxItems limits the total number of items placed
+    let mut game = Game::new(0, game.config.clone());
+    game.data.map = Map::from_dims(20, 20);
+
+    let cmds = vec!(ProcCmd::Items(Item::Stone, 5, 5), ProcCmd::MaxItems(3));
+    place_items(&mut game, &cmds);
+    assert_eq!(3, count_entities(&game, EntityName::Stone));
+}
+
+#[test]
+fn test_proc_cmd_traps() {
+    let config = Config::from_file("../config.yaml");
+    let mut game = Game::new(0, config);
+    game.data.map = Map::from_dims(20, 20);
+
+    let cmds = vec!(ProcCmd::Traps(Trap::Spikes, 2, 2));
+    place_traps(&mut game, &cmds);
+    assert_eq!(2, count_entities(&game, EntityName::SpikeTrap));
+}
+
+#[test]
+fn test_proc_cmd_columns() {
+    let config = Config::from_file("../config.yaml");
+    let mut game = Game::new(0, config);
+    game.data.map = Map::from_dims(20, 20);
+
+    // lay down enough lone walls that the column limit is always reached
+    for x in 0..9 {
+        for y in 0..4 {
+            game.data.map[(1 + x * 2, 2 + y * 4)] = Tile::wall();
         }
     }
+
+    let mut structures = find_structures(&game.data.map);
+    modify_structures(&mut game, &vec!(ProcCmd::Columns(0)), &mut structures);
+    assert_eq!(0, count_entities(&game, EntityName::Column));
+
+    let mut structures = find_structures(&game.data.map);
+    modify_structures(&mut game, &vec!(ProcCmd::Columns(2)), &mut structures);
+    assert_eq!(2, count_entities(&game, EntityName::Column));
+}
+
+#[test]
+fn test_add_obstacle_cross() {
+    let mut map = Map::from_dims(10, 10);
+    let mut rng = GameRng::new(0);
+
+    let placed = add_obstacle(&mut map, Pos::new(5, 5), Obstacle::Cross, &mut rng);
+    assert_eq!(9, placed.len());
+
+    for x in 0..10 {
+        for y in 0..10 {
+            let dx = (x - 5 as i32).abs();
+            let dy = (y - 5 as i32).abs();
+            let in_cross = (dx == 0 && dy <= 2) || (dy == 0 && dx <= 2);
+            assert_eq!(in_cross, map[(x, y)].tile_type == TileType::Wall);
+            assert_eq!(in_cross, placed.contains(&Pos::new(x, y)));
+        }
+    }
+
+    // only the arms within the map are placed
+    let mut map = Map::from_dims(10, 10);
+    let placed = add_obstacle(&mut map, Pos::new(0, 0), Obstacle::Cross, &mut rng);
+    assert_eq!(5, placed.len());
+    assert!(placed.iter().all(|pos| map.is_within_bounds(*pos)));
+}
+
+#[test]
+fn test_add_obstacle_ring() {
+    let mut map = Map::from_dims(10, 10);
+    let mut rng = GameRng::new(0);
+
+    let placed = add_obstacle(&mut map, Pos::new(5, 5), Obstacle::Ring, &mut rng);
+    assert_eq!(12, placed.len());
+
+    for x in 0..10 {
+        for y in 0..10 {
+            let dx = (x - 5 as i32).abs();
+            let dy = (y - 5 as i32).abs();
+            let in_ring = std::cmp::max(dx, dy) == 2 && dx != 0 && dy != 0;
+            assert_eq!(in_ring, map[(x, y)].tile_type == TileType::Wall);
+            assert_eq!(in_ring, placed.contains(&Pos::new(x, y)));
+        }
+    }
+
+    // the center and the openings are left clear
+    assert_eq!(TileType::Floor, map[(5, 5)].tile_type);
+    assert_eq!(TileType::Floor, map[(5, 3)].tile_type);
+    assert_eq!(TileType::Floor, map[(7, 5)].tile_type);
+
+    // only the part of the ring within the map is placed
+    let mut map = Map::from_dims(10, 10);
+    let placed = add_obstacle(&mut map, Pos::new(9, 9), Obstacle::Ring, &mut rng);
+    assert_eq!(3, placed.len());
+    assert!(placed.iter().all(|pos| map.is_within_bounds(*pos)));
 }
@@ -26,15 +26,13 @@ pub fn resolve_messages(data: &mut GameData,
 
     /* Handle Message Log */
     while let Some(msg) = msg_log.pop() {
+        msg_log.notify(&msg, data);
+
         match msg {
             Msg::Moved(entity_id, move_type, pos) => {
                process_moved_message(entity_id, move_type, pos, data, msg_log, config);
             }
 
-            Msg::Interact(entity_id, pos) => {
-               process_interaction(entity_id, pos, data, msg_log, config);
-            }
-
             Msg::Crushed(entity_id, pos) => {
                 crushed(entity_id, pos, data, msg_log, config);
             }
@@ -55,10 +53,10 @@ pub fn resolve_messages(data: &mut GameData,
             }
 
             Msg::ItemThrow(entity_id, item_id, start, end) => {
-                throw_item(entity_id, item_id, start, end, data, msg_log);
+                let landing_pos = throw_item(entity_id, item_id, start, end, data, msg_log, rng, config);
 
                 // NOTE the radius here is the stone radius, regardless of item type
-                msg_log.log_front(Msg::Sound(entity_id, end, config.sound_radius_stone, false));
+                msg_log.log_front(Msg::Sound(entity_id, landing_pos, config.sound_radius_stone, false));
             }
 
             Msg::JumpWall(entity_id, _start, end) => {
@@ -76,10 +74,8 @@ pub fn resolve_messages(data: &mut GameData,
                 pushed_entity(pusher, pushed, direction, push_amount, move_into, data, config, msg_log);
             }
 
-            Msg::Yell(entity_id) => {
-                let pos = data.entities.pos[&entity_id];
-                msg_log.log_front(Msg::Sound(entity_id, pos, config.yell_radius, true));
-                data.entities.took_turn[&entity_id] = true;
+            Msg::Yell(entity_id, pos, radius) => {
+                yell(entity_id, pos, radius, data, msg_log, config);
             }
 
             Msg::Killed(_attacker, attacked, _damage) => {
@@ -114,7 +110,7 @@ pub fn resolve_messages(data: &mut GameData,
             }
 
             Msg::TryMove(entity_id, direction, amount, move_mode) => {
-                resolve_try_move(entity_id, direction, amount, move_mode, data, msg_log);
+                resolve_try_move(entity_id, direction, amount, move_mode, data, msg_log, config);
             }
 
             Msg::PickUp(entity_id) => {
@@ -154,14 +150,20 @@ pub fn resolve_messages(data: &mut GameData,
                 let source_pos = data.entities.pos[&trap];
 
                 if let Some(blink_pos) = find_blink_pos(source_pos, rng, data) {
-                    data.entities.set_pos(entity_id, blink_pos);
+                    data.move_entity(entity_id, blink_pos);
                     data.entities.status[&entity_id].blinked = true;
                 }
             }
 
             Msg::Froze(entity_id, num_turns) => {
                 if entity_id == player_id || data.entities.ai.get(&entity_id).is_some() {
-                    data.entities.status[&entity_id].frozen = num_turns;
+                    data.entities.add_effect(entity_id, EffectKind::Frozen, num_turns);
+                }
+            }
+
+            Msg::ApplyEffect(entity_id, kind, num_turns) => {
+                if entity_id == player_id || data.entities.ai.get(&entity_id).is_some() {
+                    data.entities.add_effect(entity_id, kind, num_turns);
                 }
             }
 
@@ -237,11 +239,14 @@ pub fn resolve_messages(data: &mut GameData,
             }
 
             Msg::ChangeMoveMode(entity_id, increase) => {
-                change_move_mode(entity_id, increase, data, msg_log);
+                change_move_mode(entity_id, increase, data, msg_log, config);
             }
 
-            Msg::DropItem(entity_id, item_index) => {
-                inventory_drop_item(entity_id, item_index as usize, data, msg_log);
+            Msg::DropItem(entity_id, item_id) => {
+                let inventory = &data.entities.inventory[&entity_id];
+                if let Some(item_index) = inventory.iter().position(|id| *id == item_id) {
+                    inventory_drop_item(entity_id, item_index, data, msg_log);
+                }
             }
 
             Msg::GrassThrow(entity_id, direction) => {
@@ -250,7 +255,7 @@ pub fn resolve_messages(data: &mut GameData,
                     let pos = data.entities.pos[&entity_id];
 
                     for grass_pos in Cone::new(pos, direction, SKILL_GRASS_THROW_RADIUS as i32) {
-                        if data.map.is_within_bounds(grass_pos) && data.map[grass_pos].tile_type == TileType::Empty {
+                        if data.map.is_within_bounds(grass_pos) && data.map[grass_pos].is_floor() {
                             data.map[grass_pos].surface = Surface::Grass;
                         }
                     }
@@ -360,8 +365,8 @@ pub fn resolve_messages(data: &mut GameData,
 
                     let start_pos = data.entities.pos[&entity_id];
                     let end_pos = data.entities.pos[&target_id];
-                    data.entities.set_pos(entity_id, end_pos);
-                    data.entities.set_pos(target_id, start_pos);
+                    data.move_entity(entity_id, end_pos);
+                    data.move_entity(target_id, start_pos);
 
                     msg_log.log(Msg::SetFacing(entity_id, target_dir));
                     msg_log.log(Msg::SetFacing(target_id, entity_dir));
@@ -372,7 +377,7 @@ pub fn resolve_messages(data: &mut GameData,
 
             Msg::PassWall(entity_id, pos) => {
                 if use_energy(entity_id, data) {
-                    data.entities.set_pos(entity_id, pos);
+                    data.move_entity(entity_id, pos);
                     msg_log.log(Msg::MoveMode(entity_id, MoveMode::Walk));
                     msg_log.log(Msg::Moved(entity_id, MoveType::Move, pos));
 
@@ -412,13 +417,18 @@ pub fn resolve_messages(data: &mut GameData,
             }
 
             Msg::AiAttack(entity_id) => {
-                if let Behavior::Attacking(target_id) = data.entities.behavior[&entity_id] {
-                    resolve_ai_attack(entity_id, target_id, data, msg_log, config);
+                if let Behavior::Attacking(target_id, last_seen) = data.entities.behavior[&entity_id] {
+                    resolve_ai_attack(entity_id, target_id, last_seen, data, msg_log, config);
                 } else {
                     panic!("ai attacking but not in attack state!");
                 }
             }
 
+            Msg::EndTurn(_turn) => {
+                burn_torches(data, msg_log);
+                tick_durations(data, msg_log);
+            }
+
             _ => {
             }
         }
@@ -439,6 +449,86 @@ pub fn resolve_messages(data: &mut GameData,
     data.entities.messages[&player_id].clear();
 }
 
+/// Burn one unit of fuel from each torch that is held, or that was lit and then
+/// put down. A lit torch on the ground lights the tiles around it, and a torch
+/// that runs out of fuel goes out.
+fn burn_torches(data: &mut GameData, msg_log: &mut MsgLog) {
+    let mut held: Vec<EntityId> = Vec::new();
+    for inventory in data.entities.inventory.values() {
+        for item_id in inventory.iter().take(NUM_EQUIP_SLOTS) {
+            if data.entities.torch.contains_key(item_id) {
+                held.push(*item_id);
+            }
+        }
+    }
+
+    for (torch_id, torch) in data.entities.torch.iter_mut() {
+        let is_held = held.contains(torch_id);
+        if is_held && torch.fuel > 0 {
+            torch.lit = true;
+        }
+
+        if !torch.lit {
+            continue;
+        }
+
+        torch.fuel = torch.fuel.saturating_sub(1);
+
+        if torch.fuel == 0 {
+            torch.lit = false;
+            data.entities.status[torch_id].illuminate = 0;
+            msg_log.log(Msg::TorchBurnedOut(*torch_id));
+        } else if is_held {
+            // a held torch extends its holder's FOV instead
+            data.entities.status[torch_id].illuminate = 0;
+        } else {
+            data.entities.status[torch_id].illuminate = LANTERN_ILLUMINATE_RADIUS;
+        }
+    }
+}
+
+/// Count down the components that last a number of turns, removing a cloak or
+/// effect once it runs out. Poisoned entities take damage each turn.
+fn tick_durations(data: &mut GameData, msg_log: &mut MsgLog) {
+    let mut poisoned: Vec<EntityId> = Vec::new();
+
+    for entity_id in data.entities.ids.iter() {
+        if let Some(effects) = data.entities.effects.get_mut(entity_id) {
+            for effect in effects.iter_mut() {
+                if effect.kind == EffectKind::Poisoned {
+                    poisoned.push(*entity_id);
+                }
+                effect.turns_left = effect.turns_left.saturating_sub(1);
+            }
+            effects.retain(|effect| effect.turns_left > 0);
+        }
+
+        if let Some(mut status) = data.entities.status.get_mut(entity_id) {
+            if status.soft_steps > 0 {
+                status.soft_steps -= 1;
+            }
+        }
+
+        let mut cloak_expired = false;
+        if let Some(cloaked) = data.entities.cloaked.get_mut(entity_id) {
+            cloaked.turns_left = cloaked.turns_left.saturating_sub(1);
+            cloak_expired = cloaked.turns_left == 0;
+        }
+
+        if cloak_expired {
+            data.entities.cloaked.remove(entity_id);
+        }
+    }
+
+    for entity_id in poisoned {
+        if !data.entities.status[&entity_id].alive {
+            continue;
+        }
+
+        deal_damage(entity_id, entity_id, POISON_DAMAGE, data, msg_log);
+    }
+}
+
 fn hammer_swing(entity_id: EntityId, item_id: EntityId, pos: Pos, data: &mut GameData, msg_log: &mut MsgLog) {
     let entity_pos = data.entities.pos[&entity_id];
 
@@ -458,8 +548,7 @@ fn hammer_hit_entity(entity_id: EntityId, hit_entity: EntityId, data: &mut GameD
     let first = data.entities.pos[&entity_id];
     let second = data.entities.pos[&hit_entity];
 
-    let dxy = sub_pos(second, first);
-    let direction = Direction::from_dxy(dxy.x, dxy.y).unwrap();
+    let direction = Direction::from_positions(first, second).unwrap();
     let amount = 1;
     msg_log.log(Msg::Pushed(entity_id, hit_entity, direction, amount, false));
     msg_log.log_front(Msg::Sound(entity_id, second, config.sound_radius_hammer, true));
@@ -530,7 +619,7 @@ fn triggered(trigger: EntityId, data: &mut GameData) {
                 if data.map[neighbor].tile_type == TileType::Wall {
                     data.entities.status[&trigger].active = true;
 
-                    data.map[neighbor] = Tile::empty();
+                    data.map[neighbor] = Tile::floor();
 
                     data.entities.gate_pos[&trigger] = Some(neighbor);
                     break;
@@ -584,12 +673,15 @@ fn resolve_try_move(entity_id: EntityId,
                     amount: usize,
                     move_mode: MoveMode,
                     data: &mut GameData,
-                    msg_log: &mut MsgLog) {
+                    msg_log: &mut MsgLog,
+                    config: &Config) {
     // blinking uses up movement
     if data.entities.status[&entity_id].blinked {
         return;
     }
 
+    let (move_mode, amount) = move_energy(entity_id, move_mode, amount, data, msg_log, config);
+
     data.entities.move_mode[&entity_id] = move_mode;
 
     let reach = data.entities.movement[&entity_id];
@@ -611,10 +703,10 @@ fn resolve_try_move(entity_id: EntityId,
             resolve_try_movement(entity_id, direction, amount, move_mode, movement, data, msg_log);
         }
     } else {
-        // monsters that are not idle, but their movement does not change their
-        // position will return to idle.
+        // monsters that are not idle or patrolling, but their movement does not
+        // change their position will return to idle.
         if data.entities.behavior.get(&entity_id) != None &&
-           data.entities.behavior.get(&entity_id) != Some(&Behavior::Idle) {
+           !matches!(data.entities.behavior.get(&entity_id), Some(Behavior::Idle) | Some(Behavior::Patrol(_))) {
             // this takes up the monster's turn, as they already committed to this movement
             data.entities.took_turn[&entity_id] = true;
             msg_log.log(Msg::StateChange(entity_id, Behavior::Idle));
@@ -622,6 +714,38 @@ fn resolve_try_move(entity_id: EntityId,
     }
 }
 
+/// Running uses energy each step, and sneaking or waiting restores it up to
+/// a limit. An entity without enough energy to run walks instead. Returns the
+/// move mode and amount to actually move with.
+fn move_energy(entity_id: EntityId,
+               move_mode: MoveMode,
+               amount: usize,
+               data: &mut GameData,
+               msg_log: &mut MsgLog,
+               config: &Config) -> (MoveMode, usize) {
+    // skills such as sprint pay for their movement and end the turn
+    // before it is resolved, so they are not charged again here.
+    if data.entities.energy.get(&entity_id).is_none() || data.entities.took_turn[&entity_id] {
+        return (move_mode, amount);
+    }
+
+    if move_mode == MoveMode::Run && amount > 0 {
+        if !can_run(data, entity_id, config) {
+            msg_log.log(Msg::NotEnoughEnergy(entity_id));
+            msg_log.log(Msg::MoveMode(entity_id, MoveMode::Walk));
+            return (MoveMode::Walk, amount.min(MoveMode::Walk.move_amount()));
+        }
+
+        data.entities.energy[&entity_id] -= config.run_energy_cost;
+    } else if move_mode == MoveMode::Sneak || amount == 0 {
+        if data.entities.energy[&entity_id] < config.energy_regen_max {
+            data.entities.energy[&entity_id] += 1;
+        }
+    }
+
+    return (move_mode, amount);
+}
+
 fn resolve_try_movement(entity_id: EntityId,
                         direction: Direction,
                         amount: usize,
@@ -633,10 +757,11 @@ fn resolve_try_movement(entity_id: EntityId,
 
     match movement.typ {
         MoveType::Collide => {
-            data.entities.set_pos(entity_id, movement.pos);
+            data.move_entity(entity_id, movement.pos);
             msg_log.log(Msg::FaceTowards(entity_id, movement.pos));
 
-            msg_log.log(Msg::Collided(entity_id, movement.pos));
+            let blocked_pos = direction.offset_pos(movement.pos, 1);
+            msg_log.log(Msg::Collided(entity_id, blocked_pos));
         }
 
         MoveType::Pass => {
@@ -644,7 +769,14 @@ fn resolve_try_movement(entity_id: EntityId,
         }
 
         MoveType::WallKick => {
-            data.entities.set_pos(entity_id, movement.pos);
+            data.move_entity(entity_id, movement.pos);
+            data.entities.took_turn[&entity_id] = true;
+
+            // kicking off of the wall uses up all of the entity's momentum
+            if let Some(momentum) = data.entities.momentum.get_mut(&entity_id) {
+                momentum.mx = 0;
+                momentum.my = 0;
+            }
 
             // NOTE may need to set facing
             // NOTE could check for enemy and attack
@@ -685,7 +817,7 @@ fn resolve_try_movement(entity_id: EntityId,
     }
 
     // if entity is attacking, face their target after the move
-    if let Some(Behavior::Attacking(target_id)) = data.entities.behavior.get(&entity_id) {
+    if let Some(Behavior::Attacking(target_id, _last_seen)) = data.entities.behavior.get(&entity_id) {
         let target_pos = data.entities.pos[target_id];
         msg_log.log(Msg::FaceTowards(entity_id, target_pos));
     }
@@ -701,8 +833,7 @@ fn resolve_push_skill(entity_id: EntityId,
     let push_pos = direction.offset_pos(pos, 1);
     for other_id in data.has_entities(push_pos) {
         if data.entities.typ[&other_id] == EntityType::Enemy {
-            let dxy = sub_pos(push_pos, pos);
-            let direction = Direction::from_dxy(dxy.x, dxy.y).unwrap();
+            let direction = Direction::from_positions(pos, push_pos).unwrap();
             let move_into = false;
             msg_log.log(Msg::Pushed(entity_id, other_id, direction, amount, move_into));
             msg_log.log(Msg::Froze(other_id, SKILL_PUSH_STUN_TURNS));
@@ -715,7 +846,7 @@ fn resolve_blink(entity_id: EntityId, data: &mut GameData, rng: &mut Rand32, msg
     let entity_pos = data.entities.pos[&entity_id];
 
     if let Some(blink_pos) = find_blink_pos(entity_pos, rng, data) {
-        data.entities.set_pos(entity_id, blink_pos);
+        data.move_entity(entity_id, blink_pos);
     } else {
         msg_log.log(Msg::FailedBlink(entity_id));
     }
@@ -731,8 +862,7 @@ fn resolve_rubble(entity_id: EntityId, blocked: Blocked, data: &mut GameData, _m
     data.map[blocked.end_pos].chr = ' ' as u8;
 
     if blocked.wall_type != Wall::Empty {
-        let dxy = sub_pos(blocked.end_pos, entity_pos);
-        match Direction::from_dxy(dxy.x, dxy.y).unwrap() {
+        match Direction::from_positions(entity_pos, blocked.end_pos).unwrap() {
             Direction::Up => {
                 data.map[blocked.end_pos].bottom_wall = Wall::Empty;
             }
@@ -762,16 +892,14 @@ fn hammer_hit_wall(entity: EntityId, blocked: Blocked, data: &mut GameData, msg_
     let entity_pos = data.entities.pos[&entity];
     let hit_pos = blocked.end_pos;
     if data.map[hit_pos].block_move {
-        if data.map[hit_pos].surface == Surface::Floor {
-            data.map[hit_pos].surface = Surface::Rubble;
-        }
-
-        data.map[hit_pos].block_move = false;
-        data.map[hit_pos].chr = ' ' as u8;
+        // the wall is knocked down, leaving an open tile covered in rubble
+        let explored = data.map[hit_pos].explored;
+        data.map[hit_pos] = Tile::rubble();
+        data.map[hit_pos].explored = explored;
 
         let next_pos = next_from_to(entity_pos, hit_pos);
         msg_log.log_front(Msg::Crushed(entity, next_pos)); 
-        msg_log.log_front(Msg::Sound(entity, blocked.end_pos, config.sound_radius_attack, true)); 
+        msg_log.log_front(Msg::Sound(entity, blocked.end_pos, config.sound_radius_hammer, true)); 
     } else {
         let wall_loc: Pos;
         let left_wall: bool;
@@ -798,6 +926,7 @@ fn hammer_hit_wall(entity: EntityId, blocked: Blocked, data: &mut GameData, msg_
         }
 
         msg_log.log_front(Msg::Crushed(entity, blocked.end_pos));
+        msg_log.log_front(Msg::Sound(entity, blocked.end_pos, config.sound_radius_hammer, true)); 
     }
 }
 
@@ -955,34 +1084,67 @@ fn pick_item_up(entity_id: EntityId, data: &mut GameData, msg_log: &mut MsgLog)
 }
 
 fn place_trap(trap_id: EntityId, place_pos: Pos, data: &mut GameData) {
-    data.entities.set_pos(trap_id, place_pos);
+    data.move_entity(trap_id, place_pos);
     data.entities.armed[&trap_id] = true;
 }
 
+/// Find where a thrown item stops along its path. Items fly over short walls
+/// and grass, but each one it passes may deflect it with cover_deflect_chance,
+/// stopping the throw short.
+fn throw_landing_pos(start_pos: Pos,
+                     end_pos: Pos,
+                     data: &GameData,
+                     rng: &mut Rand32,
+                     config: &Config) -> Pos {
+    let mut prev_pos = start_pos;
+    for pos in line(start_pos, end_pos) {
+        if let Some(blocked) = data.map.move_blocked(prev_pos, pos, BlockedType::Move) {
+            let is_cover = !blocked.blocked_tile && blocked.wall_type == Wall::ShortWall;
+            if !is_cover || rng_trial(rng, config.cover_deflect_chance) {
+                return prev_pos;
+            }
+        }
+
+        if data.map[pos].surface == Surface::Grass && rng_trial(rng, config.cover_deflect_chance) {
+            return pos;
+        }
+
+        prev_pos = pos;
+    }
+
+    return prev_pos;
+}
+
 fn throw_item(player_id: EntityId,
               item_id: EntityId,
               start_pos: Pos,
               end_pos: Pos,
               data: &mut GameData,
-              msg_log: &mut MsgLog) {
+              msg_log: &mut MsgLog,
+              rng: &mut Rand32,
+              config: &Config) -> Pos {
     let throw_line = line(start_pos, end_pos);
 
     // get target position in direction of player click
-    let mut end_pos =
+    let end_pos =
         Pos::from(throw_line.into_iter().take(PLAYER_THROW_DIST).last().unwrap());
 
-    if let Some(blocked) = data.map.path_blocked_move(start_pos, end_pos) {
-        // the start pos of the blocked struct is the last reached position
-        end_pos = blocked.start_pos;
+    let mut end_pos = throw_landing_pos(start_pos, end_pos, data, rng, config);
+
+    // make sure the item lands on a tile it can occupy, such as when thrown off the map
+    if let Some(landing_pos) = data.map.closest_unblocked(end_pos, PLAYER_THROW_DIST as i32) {
+        end_pos = landing_pos;
     }
 
-    data.entities.set_pos(item_id, start_pos);
+    data.move_entity(item_id, start_pos);
 
     let movement = Movement::step_to(end_pos);
     msg_log.log(Msg::Moved(item_id, movement.typ, end_pos));
 
     data.entities.remove_item(player_id, item_id);
     data.entities.took_turn[&player_id] = true;
+
+    return end_pos;
 }
 
 fn find_blink_pos(pos: Pos, rng: &mut Rand32, data: &mut GameData) -> Option<Pos> {
@@ -1005,7 +1167,8 @@ fn find_blink_pos(pos: Pos, rng: &mut Rand32, data: &mut GameData) -> Option<Pos
 fn change_move_mode(entity_id: EntityId,
                     increase: bool,
                     data: &mut GameData,
-                    msg_log: &mut MsgLog) {
+                    msg_log: &mut MsgLog,
+                    config: &Config) {
     if increase {
         let holding_shield = data.using(entity_id, Item::Shield).is_some();
         let holding_hammer = data.using(entity_id, Item::Hammer).is_some();
@@ -1018,6 +1181,8 @@ fn change_move_mode(entity_id: EntityId,
 
         if new_move_mode == movement::MoveMode::Run && (holding_shield || holding_hammer) {
             msg_log.log(Msg::TriedRunWithHeavyEquipment);
+        } else if new_move_mode == movement::MoveMode::Run && !can_run(data, entity_id, config) {
+            msg_log.log(Msg::NotEnoughEnergy(entity_id));
         } else {
             msg_log.log(Msg::MoveMode(entity_id, new_move_mode));
         }
@@ -1044,7 +1209,7 @@ fn inventory_drop_item(entity_id: EntityId,
             for pos in positions {
                 if data.item_at_pos(pos).is_none() {
                     data.entities.remove_item(entity_id, item_id);
-                    data.entities.set_pos(item_id, pos);
+                    data.move_entity(item_id, pos);
 
                     found_tile = true;
                     break;
@@ -1062,27 +1227,6 @@ fn inventory_drop_item(entity_id: EntityId,
     }
 }
 
-fn process_interaction(entity_id: EntityId,
-                       interact_pos: Pos,
-                       data: &mut GameData, 
-                       msg_log: &mut MsgLog,
-                       _config: &Config) {
-    let pos = data.entities.pos[&entity_id];
-
-    if pos == interact_pos {
-        if let Some(_item_id) = data.item_at_pos(pos) {
-            msg_log.log(Msg::PickUp(entity_id));
-        }
-    } else {
-        for other_id in data.has_entity(interact_pos) {
-            if data.entities.trap.get(&other_id).is_some() {
-                msg_log.log(Msg::ArmDisarmTrap(entity_id, other_id));
-                break;
-            }
-        }
-    }
-}
-
 fn use_item(entity_id: EntityId,
             pos: Pos,
             item_id: EntityId,
@@ -1119,8 +1263,26 @@ fn use_item(entity_id: EntityId,
             msg_log.log(Msg::SwordSwing(entity_id, item_id, pos));
         }
 
+        Item::Spear => {
+            // the spear is used by moving towards an enemy, like the dagger
+        }
+
+        Item::Cloak => {
+            // the cloak toggles, using energy only when it is put on
+            if data.entities.cloaked.get(&entity_id).is_some() {
+                data.entities.cloaked.remove(&entity_id);
+            } else if use_energy(entity_id, data) {
+                data.entities.cloaked.insert(entity_id, Cloaked::new(CLOAK_TURNS));
+            }
+            data.entities.took_turn[&entity_id] = true;
+        }
+
+        Item::Torch => {
+            // the torch gives light while held, so it has no use action
+        }
+
         Item::Lantern => {
-            data.entities.pos[&item_id] = pos;
+            data.move_entity(item_id, pos);
         }
 
         Item::SpikeTrap => {
@@ -1152,26 +1314,23 @@ fn make_move_sound(entity_id: EntityId,
                    data: &mut GameData,
                    msg_log: &mut MsgLog,
                    config: &Config) {
-    let mut sound_radius;
-
-    match move_mode {
-        MoveMode::Sneak => sound_radius = config.sound_radius_sneak,
-        MoveMode::Walk => sound_radius = config.sound_radius_walk,
-        MoveMode::Run => sound_radius = config.sound_radius_run,
-    }
+    let mut sound_radius = move_mode.loudness(config);
 
     if data.map[pos].surface == Surface::Rubble {
         sound_radius += config.sound_rubble_radius;
     } else if data.map[pos].surface == Surface::Grass {
-        sound_radius -= config.sound_grass_radius;
+        sound_radius = sound_radius.saturating_sub(config.sound_grass_radius);
     }
 
     if data.entities.status[&entity_id].soft_steps > 0 {
-        sound_radius -= 1;
+        sound_radius = sound_radius.saturating_sub(1);
     }
 
-    msg_log.log_front(Msg::Sound(entity_id, pos, sound_radius, true));
-    msg_log.log_front(Msg::Sound(entity_id, original_pos, sound_radius, true));
+    // a silent step makes no sound at all
+    if sound_radius > 0 {
+        msg_log.log_front(Msg::Sound(entity_id, pos, sound_radius, true));
+        msg_log.log_front(Msg::Sound(entity_id, original_pos, sound_radius, true));
+    }
 }
 
 fn process_moved_message(entity_id: EntityId,
@@ -1183,9 +1342,17 @@ fn process_moved_message(entity_id: EntityId,
     let player_id = data.find_by_name(EntityName::Player).unwrap();
     let original_pos = data.entities.pos[&entity_id];
 
-    data.entities.set_pos(entity_id, pos);
+    data.move_entity(entity_id, pos);
     data.entities.took_turn[&entity_id] = true;
 
+    if let Some(momentum) = data.entities.momentum.get_mut(&entity_id) {
+        if pos == original_pos {
+            momentum.decay();
+        } else {
+            momentum.moved(pos.x - original_pos.x, pos.y - original_pos.y);
+        }
+    }
+
     if let Some(move_mode) = data.entities.move_mode.get(&entity_id) {
         if let Some(stance) = data.entities.stance.get(&entity_id) {
             if move_type == MoveType::Pass {
@@ -1230,8 +1397,7 @@ fn process_moved_message(entity_id: EntityId,
             msg_log.log_front(Msg::FaceTowards(entity_id, target_pos));
         }
     } else {
-        let diff = sub_pos(pos, original_pos);
-        if let Some(dir) = Direction::from_dxy(diff.x, diff.y) {
+        if let Some(dir) = Direction::from_positions(original_pos, pos) {
             msg_log.log_front(Msg::SetFacing(entity_id, dir));
         }
     }
@@ -1299,13 +1465,44 @@ fn resolve_triggered_traps(entity_id: EntityId,
 }
 
 
+/// A yell is a sound that also draws monsters in range toward the yeller. Monsters
+/// that are already attacking keep their target.
+fn yell(entity_id: EntityId, pos: Pos, radius: usize, data: &mut GameData, msg_log: &mut MsgLog, config: &Config) {
+    let yell_aoe = aoe_fill(&data.map, AoeEffect::Sound, pos, radius, config);
+
+    for heard_id in data.within_aoe(&yell_aoe) {
+        if heard_id == entity_id ||
+           data.entities.typ[&heard_id] != EntityType::Enemy ||
+           !data.entities.status[&heard_id].alive {
+            continue;
+        }
+
+        // the behavior is set directly, as a StateChange would start the
+        // monster's turn in the middle of the yeller's turn
+        if let Some(behavior) = data.entities.behavior.get_mut(&heard_id) {
+            if matches!(behavior, Behavior::Idle | Behavior::Investigating(_) | Behavior::Patrol(_)) {
+                *behavior = Behavior::Investigating(pos);
+            }
+        }
+    }
+
+    msg_log.log_front(Msg::Sound(entity_id, pos, radius, true));
+    data.entities.took_turn[&entity_id] = true;
+}
+
 fn resolve_ai_attack(entity_id: EntityId,
                      target_id: EntityId,
+                     last_seen: Pos,
                      data: &mut GameData,
                      msg_log: &mut MsgLog,
                      config: &Config) {
     let target_pos = data.entities.pos[&target_id];
 
+    let target_in_fov = ai_is_in_fov(entity_id, target_id, data, config);
+    if target_in_fov {
+        data.entities.behavior[&entity_id] = Behavior::Attacking(target_id, target_pos);
+    }
+
     let attack_reach = data.entities.attack[&entity_id];
     let can_hit_target =
         ai_can_hit_target(data, entity_id, target_pos, &attack_reach, config);
@@ -1316,10 +1513,11 @@ fn resolve_ai_attack(entity_id: EntityId,
     } else if let Some(_hit_pos) = can_hit_target {
         let attack_info = Attack::Attack(target_id);
         msg_log.log(Msg::TryAttack(entity_id, attack_info, target_pos));
-    } else if !ai_is_in_fov(entity_id, target_id, data, config) {
-        // if we lose the target, end the turn
+    } else if !target_in_fov {
+        // if we lose the target, end the turn and head to where they were last seen.
+        // the monster goes idle once it reaches that position.
         data.entities.took_turn[&entity_id] = true;
-        msg_log.log(Msg::StateChange(entity_id, Behavior::Investigating(target_pos)));
+        msg_log.log(Msg::StateChange(entity_id, Behavior::Investigating(last_seen)));
     } else {
         // can see target, but can't hit them. try to move to a position where we can hit them
         let maybe_pos = ai_move_to_attack_pos(entity_id, target_id, data, config);
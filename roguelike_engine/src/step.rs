@@ -1,3 +1,5 @@
+use oorandom::Rand32;
+
 use logging_timer::timer;
 
 use roguelike_core::types::*;
@@ -7,7 +9,11 @@ use roguelike_core::map::*;
 use roguelike_core::messaging::{Msg, MsgLog};
 use roguelike_core::movement::{Direction, MoveMode};
 #[cfg(test)]
+use roguelike_core::movement::Attack;
+#[cfg(test)]
 use roguelike_core::utils::*;
+#[cfg(test)]
+use roguelike_core::constants::*;
 
 
 use crate::game::*;
@@ -18,7 +24,7 @@ use crate::resolve::resolve_messages;
 use crate::make_map::*;
 
 
-pub fn step_logic(game: &mut Game) -> bool {
+pub fn step_logic(game: &mut Game) -> GameResult {
     // clean up removable entities
     clean_entities(&mut game.data.entities, &mut game.msg_log);
 
@@ -29,10 +35,11 @@ pub fn step_logic(game: &mut Game) -> bool {
         game.data.entities.status[id].blinked = false;
     }
 
-    game.msg_log.log_front(Msg::StartTurn);
+    game.msg_log.log_front(Msg::StartTurn(game.settings.turn_count));
 
     resolve_messages(&mut game.data, &mut game.msg_log, &mut game.rng, &game.config);
 
+    update_goals(&mut game.data);
     let won_level = level_exit_condition_met(&game.data);
 
     // resolve enemy action
@@ -40,28 +47,21 @@ pub fn step_logic(game: &mut Game) -> bool {
     if game.data.entities.took_turn[&player_id] &&
        game.data.entities.status[&player_id].alive &&
        !won_level {
-        step_ai(game);
+        advance_ai(&mut game.data, &mut game.msg_log, &mut game.rng, &game.config);
     }
     drop(monster);
 
+    // tick-based components are updated when EndTurn is resolved, so they only
+    // count down on steps where a turn actually passed.
+    if game.data.entities.took_turn[&player_id] {
+        game.msg_log.log(Msg::EndTurn(game.settings.turn_count));
+    }
+
     // send PlayerTurn action in case there is cleanup to perform, or another system
     // needs to know that the turn is finished.
     game.msg_log.log(Msg::PlayerTurn);
     resolve_messages(&mut game.data, &mut game.msg_log, &mut game.rng, &game.config);
 
-    // check status effects
-    for entity_id in game.data.entities.ids.iter() {
-        if let Some(mut status) = game.data.entities.status.get_mut(entity_id) {
-            if status.frozen > 0 {
-                status.frozen -= 1;
-            }
-
-            if status.soft_steps > 0 {
-                status.soft_steps -= 1;
-            }
-        }
-    }
-
     if game.data.entities.took_turn[&player_id] {
         game.settings.turn_count += 1;
     }
@@ -69,13 +69,43 @@ pub fn step_logic(game: &mut Game) -> bool {
     // perform count down of entities waiting to be removed
     game.data.entities.count_down();
 
-    return level_exit_condition_met(&game.data);
+    update_goals(&mut game.data);
+    if level_exit_condition_met(&game.data) {
+        return GameResult::Win;
+    }
+
+    if player_died(&game.data) {
+        return GameResult::Lose;
+    }
+
+    return GameResult::Continue;
+}
+
+/// Check whether the player has been killed.
+fn player_died(data: &GameData) -> bool {
+    let player_id = data.find_by_name(EntityName::Player).unwrap();
+
+    return !data.entities.status[&player_id].alive ||
+           data.entities.fighter[&player_id].hp <= 0;
+}
+
+/// Count the keys the player is currently holding.
+fn held_keys(data: &GameData) -> usize {
+    let player_id = data.find_by_name(EntityName::Player).unwrap();
+
+    return data.entities.inventory[&player_id]
+               .iter()
+               .filter(|item_id| data.entities.item.get(*item_id) == Some(&Item::Key))
+               .count();
+}
+
+/// Count the keys the player is holding towards the level's goals.
+fn update_goals(data: &mut GameData) {
+    data.goals.collected = held_keys(data);
 }
 
 /// Check whether the exit condition for the game is met.
 fn level_exit_condition_met(data: &GameData) -> bool {
-    // loop over objects in inventory, and check whether any
-    // are the key object.
     let player_id = data.find_by_name(EntityName::Player).unwrap();
     let player_pos = data.entities.pos[&player_id];
 
@@ -83,16 +113,62 @@ fn level_exit_condition_met(data: &GameData) -> bool {
     if let Some(exit_id) = data.find_by_name(EntityName::Exit) {
         let exit_pos = data.entities.pos[&exit_id];
 
-        let has_key = data.is_in_inventory(player_id, Item::Key).is_some();
-
         let on_exit_tile = exit_pos == player_pos;
 
-        exit_condition = has_key && on_exit_tile;
+        // count the held keys here rather than trusting the collected counter,
+        // which is only refreshed once per turn.
+        let mut goals = data.goals;
+        goals.collected = held_keys(data);
+
+        exit_condition = goals.complete() && on_exit_tile;
     }
 
     return exit_condition;
 }
 
+#[test]
+fn test_level_exit_goals() {
+    let config = Config::from_file("../config.yaml");
+    let mut game = Game::new(0, config);
+    make_map(&MapLoadConfig::Empty, &mut game);
+
+    let player_id = game.data.find_by_name(EntityName::Player).unwrap();
+    let exit_pos = Pos::new(3, 3);
+    make_exit(&mut game.data.entities, &game.config, exit_pos, &mut game.msg_log);
+    game.data.entities.set_pos(player_id, exit_pos);
+
+    let first_key = make_key(&mut game.data.entities, &game.config, Pos::new(0, 0), &mut game.msg_log);
+    let second_key = make_key(&mut game.data.entities, &game.config, Pos::new(1, 0), &mut game.msg_log);
+    game.data.goals = GoalTracker::new(2);
+
+    // no keys collected
+    update_goals(&mut game.data);
+    assert_eq!(0, game.data.goals.collected);
+    assert!(!level_exit_condition_met(&game.data));
+
+    // only some of the keys collected
+    game.data.entities.pick_up_item(player_id, first_key);
+    update_goals(&mut game.data);
+    assert_eq!(1, game.data.goals.collected);
+    assert!(!level_exit_condition_met(&game.data));
+
+    // all keys collected
+    game.data.entities.pick_up_item(player_id, second_key);
+    update_goals(&mut game.data);
+    assert!(game.data.goals.complete());
+    assert!(level_exit_condition_met(&game.data));
+
+    // the exit is only taken when standing on it
+    game.data.entities.set_pos(player_id, Pos::new(5, 5));
+    assert!(!level_exit_condition_met(&game.data));
+
+    // a level without required keys does not open the exit
+    game.data.entities.set_pos(player_id, exit_pos);
+    game.data.goals = GoalTracker::new(0);
+    update_goals(&mut game.data);
+    assert!(!level_exit_condition_met(&game.data));
+}
+
 #[test]
 pub fn test_game_step() {
     let mut config = Config::from_file("../config.yaml");
@@ -102,7 +178,7 @@ pub fn test_game_step() {
 
     let player_id = game.data.find_by_name(EntityName::Player).unwrap();
     make_map(&MapLoadConfig::Empty, &mut game);
-    game.data.entities.pos[&player_id] = Pos::new(0, 0);
+    game.data.entities.set_pos(player_id, Pos::new(0, 0));
 
     input_action = InputAction::Move(Direction::Right, MoveMode::Walk);
     game.step_game(input_action, 0.1);
@@ -133,7 +209,7 @@ pub fn test_running() {
     let player_id = game.data.find_by_name(EntityName::Player).unwrap();
     game.data.map = Map::from_dims(10, 10);
     let player_pos = Pos::new(4, 4);
-    game.data.entities.pos[&player_id] = player_pos;
+    game.data.entities.set_pos(player_id, player_pos);
 
     let gol_pos = Pos::new(4, 5);
     let gol = make_gol(&mut game.data.entities, &game.config, gol_pos, &mut game.msg_log);
@@ -167,6 +243,115 @@ pub fn test_running() {
     assert_eq!(Pos::new(6, 5), game.data.entities.pos[&pawn]);
 }
 
+#[test]
+pub fn test_wall_kick_off_tall_wall() {
+    let config = Config::from_file("../config.yaml");
+    let mut game = Game::new(0, config.clone());
+
+    let player_id = game.data.find_by_name(EntityName::Player).unwrap();
+    game.data.map = Map::from_dims(10, 10);
+    let player_pos = Pos::new(4, 4);
+    game.data.entities.set_pos(player_id, player_pos);
+
+    game.data.map[(5, 4)] = Tile::wall();
+
+    // running right with a downward drift kicks off the wall downwards
+    game.data.entities.momentum[&player_id].mx = 2;
+    game.data.entities.momentum[&player_id].my = 1;
+    game.step_game(InputAction::Move(Direction::Right, MoveMode::Run), 0.1);
+
+    assert_eq!(Pos::new(4, 5), game.data.entities.pos[&player_id]);
+    assert!(game.msg_log.turn_messages.iter().any(|msg| {
+        *msg == Msg::WallKick(player_id, Pos::new(4, 5))
+    }));
+    assert_eq!(0, game.data.entities.momentum[&player_id].magnitude());
+
+    // with the lower side blocked, the kick goes up instead
+    game.data.entities.set_pos(player_id, player_pos);
+    game.data.map[(4, 5)] = Tile::wall();
+    game.data.entities.momentum[&player_id].mx = 2;
+    game.step_game(InputAction::Move(Direction::Right, MoveMode::Run), 0.1);
+    assert_eq!(Pos::new(4, 3), game.data.entities.pos[&player_id]);
+}
+
+#[test]
+pub fn test_wall_kick_requires_running() {
+    let config = Config::from_file("../config.yaml");
+    let mut game = Game::new(0, config.clone());
+
+    let player_id = game.data.find_by_name(EntityName::Player).unwrap();
+    game.data.map = Map::from_dims(10, 10);
+    let player_pos = Pos::new(4, 4);
+    game.data.entities.set_pos(player_id, player_pos);
+
+    game.data.map[(5, 4)] = Tile::wall();
+    game.data.entities.momentum[&player_id].mx = 2;
+
+    game.step_game(InputAction::Move(Direction::Right, MoveMode::Walk), 0.1);
+
+    assert_eq!(player_pos, game.data.entities.pos[&player_id]);
+    assert!(!game.msg_log.turn_messages.iter().any(|msg| matches!(msg, Msg::WallKick(_, _))));
+
+    // running into a short wall jumps it rather than kicking off of it
+    game.data.map[(5, 4)] = Tile::floor();
+    game.data.map[(5, 4)].left_wall = Wall::ShortWall;
+    game.step_game(InputAction::Move(Direction::Right, MoveMode::Run), 0.1);
+    assert!(!game.msg_log.turn_messages.iter().any(|msg| matches!(msg, Msg::WallKick(_, _))));
+}
+
+#[test]
+pub fn test_running_uses_energy() {
+    let config = Config::from_file("../config.yaml");
+    let mut game = Game::new(0, config);
+    make_map(&MapLoadConfig::Empty, &mut game);
+
+    let player_id = game.data.find_by_name(EntityName::Player).unwrap();
+    game.data.entities.set_pos(player_id, Pos::new(0, 0));
+    game.data.entities.energy[&player_id] = 2 * game.config.run_energy_cost;
+
+    game.step_game(InputAction::Move(Direction::Right, MoveMode::Run), 0.1);
+    assert_eq!(game.config.run_energy_cost, game.data.entities.energy[&player_id]);
+
+    game.step_game(InputAction::Move(Direction::Down, MoveMode::Run), 0.1);
+    assert_eq!(0, game.data.entities.energy[&player_id]);
+    assert!(!can_run(&game.data, player_id, &game.config));
+
+    // with no energy left the player walks instead
+    let pos = game.data.entities.pos[&player_id];
+    game.step_game(InputAction::Move(Direction::Down, MoveMode::Run), 0.1);
+    assert!(game.msg_log.turn_messages.iter().any(|msg| *msg == Msg::NotEnoughEnergy(player_id)));
+    assert_eq!(MoveMode::Walk, game.data.entities.move_mode[&player_id]);
+    assert_ne!(pos, game.data.entities.pos[&player_id]);
+    assert_eq!(0, game.data.entities.energy[&player_id]);
+}
+
+#[test]
+pub fn test_sneaking_regenerates_energy() {
+    let mut config = Config::from_file("../config.yaml");
+    config.energy_regen_max = 3;
+    let mut game = Game::new(0, config);
+    make_map(&MapLoadConfig::Empty, &mut game);
+
+    let player_id = game.data.find_by_name(EntityName::Player).unwrap();
+    game.data.entities.set_pos(player_id, Pos::new(0, 0));
+    game.data.entities.energy[&player_id] = 0;
+
+    game.step_game(InputAction::Move(Direction::Right, MoveMode::Sneak), 0.1);
+    assert_eq!(1, game.data.entities.energy[&player_id]);
+
+    // walking does not restore energy, but waiting does
+    game.step_game(InputAction::Move(Direction::Right, MoveMode::Walk), 0.1);
+    assert_eq!(1, game.data.entities.energy[&player_id]);
+
+    game.step_game(InputAction::Pass(MoveMode::Walk), 0.1);
+    assert_eq!(2, game.data.entities.energy[&player_id]);
+
+    // energy only regenerates up to the configured maximum
+    game.step_game(InputAction::Pass(MoveMode::Sneak), 0.1);
+    game.step_game(InputAction::Pass(MoveMode::Sneak), 0.1);
+    assert_eq!(3, game.data.entities.energy[&player_id]);
+}
+
 #[test]
 pub fn test_hammer_small_wall() {
     let config = Config::from_file("../config.yaml");
@@ -176,7 +361,7 @@ pub fn test_hammer_small_wall() {
     let player_id = game.data.find_by_name(EntityName::Player).unwrap();
     game.data.map = Map::from_dims(10, 10);
     let player_pos = Pos::new(4, 4);
-    game.data.entities.pos[&player_id] = player_pos;
+    game.data.entities.set_pos(player_id, player_pos);
 
 
     game.data.map[player_pos].bottom_wall = Wall::ShortWall;
@@ -223,19 +408,522 @@ pub fn test_hammer_small_wall() {
     assert_ne!(Surface::Rubble, game.data.map[pawn_pos].surface);
 }
 
-fn step_ai(game: &mut Game) {
-    let ai_ids: Vec<EntityId> = game.data.entities.active_ais();
+#[test]
+pub fn test_spear_reach() {
+    let config = Config::from_file("../config.yaml");
+    let mut game = Game::new(0, config.clone());
+
+    let player_id = game.data.find_by_name(EntityName::Player).unwrap();
+    game.data.map = Map::from_dims(10, 10);
+    let player_pos = Pos::new(4, 4);
+    game.data.entities.set_pos(player_id, player_pos);
+
+    let gol = make_gol(&mut game.data.entities, &game.config, Pos::new(6, 4), &mut game.msg_log);
+
+    let spear = make_spear(&mut game.data.entities, &game.config, Pos::new(4, 7), &mut game.msg_log);
+    game.data.entities.inventory[&player_id].push_front(spear);
+
+    game.step_game(InputAction::Move(Direction::Right, MoveMode::Walk), 0.1);
+
+    // the thrust hits the gol two tiles away without moving the player
+    assert!(game.data.entities.is_dead(gol));
+    assert_eq!(player_pos, game.data.entities.pos[&player_id]);
+    assert!(game.msg_log.turn_messages.iter().any(|msg| {
+        matches!(msg, Msg::Attack(id, target, _) if *id == player_id && *target == gol)
+    }));
+}
+
+#[test]
+pub fn test_spear_blocked_by_wall() {
+    let config = Config::from_file("../config.yaml");
+    let mut game = Game::new(0, config.clone());
+
+    let player_id = game.data.find_by_name(EntityName::Player).unwrap();
+    game.data.map = Map::from_dims(10, 10);
+    let player_pos = Pos::new(4, 4);
+    game.data.entities.set_pos(player_id, player_pos);
+
+    let gol_pos = Pos::new(6, 4);
+    let gol = make_gol(&mut game.data.entities, &game.config, gol_pos, &mut game.msg_log);
+    let gol_hp = game.data.entities.fighter[&gol].hp;
+
+    // a wall between the intermediate tile and the gol blocks the thrust
+    game.data.map[gol_pos].left_wall = Wall::ShortWall;
+
+    let spear = make_spear(&mut game.data.entities, &game.config, Pos::new(4, 7), &mut game.msg_log);
+    game.data.entities.inventory[&player_id].push_front(spear);
+
+    game.step_game(InputAction::Move(Direction::Right, MoveMode::Walk), 0.1);
+
+    assert!(!game.data.entities.is_dead(gol));
+    assert_eq!(gol_hp, game.data.entities.fighter[&gol].hp);
+    assert_eq!(Pos::new(5, 4), game.data.entities.pos[&player_id]);
+}
+
+#[test]
+pub fn test_cloak_hides_player() {
+    let config = Config::from_file("../config.yaml");
+    let mut game = Game::new(0, config.clone());
+    make_map(&MapLoadConfig::Empty, &mut game);
+
+    let player_id = game.data.find_by_name(EntityName::Player).unwrap();
+    game.data.entities.set_pos(player_id, Pos::new(4, 4));
+
+    let gol = make_gol(&mut game.data.entities, &game.config, Pos::new(5, 4), &mut game.msg_log);
+    game.data.entities.direction[&gol] = Direction::Left;
+
+    let cloak = make_cloak(&mut game.data.entities, &game.config, Pos::new(4, 7), &mut game.msg_log);
+    game.data.entities.inventory[&player_id].push_back(cloak);
+    let cloak_index = game.data.entities.inventory[&player_id].len() - 1;
+
+    game.step_game(InputAction::UseItem(Direction::Left, cloak_index), 0.1);
+    assert!(game.data.is_cloaked(player_id));
+
+    // the player can still see the gol, but the gol does not notice the player
+    assert!(game.data.is_in_fov(player_id, gol, &game.config));
+    assert!(!ai_is_in_fov(gol, player_id, &mut game.data, &game.config));
+
+    game.step_game(InputAction::Pass(MoveMode::Sneak), 0.1);
+    assert!(!matches!(game.data.entities.behavior[&gol], Behavior::Attacking(_, _)));
+}
+
+#[test]
+pub fn test_cloak_expires() {
+    let config = Config::from_file("../config.yaml");
+    let mut game = Game::new(0, config.clone());
+    make_map(&MapLoadConfig::Empty, &mut game);
+
+    let player_id = game.data.find_by_name(EntityName::Player).unwrap();
+    game.data.entities.set_pos(player_id, Pos::new(4, 4));
+    game.data.entities.cloaked.insert(player_id, Cloaked::new(2));
+
+    game.step_game(InputAction::Pass(MoveMode::Sneak), 0.1);
+    assert!(game.data.is_cloaked(player_id));
+
+    game.step_game(InputAction::Pass(MoveMode::Sneak), 0.1);
+    assert!(!game.data.is_cloaked(player_id));
+    assert!(game.data.entities.cloaked.get(&player_id).is_none());
+}
+
+#[test]
+pub fn test_turn_boundary_messages() {
+    let config = Config::from_file("../config.yaml");
+    let mut game = Game::new(0, config.clone());
+    make_map(&MapLoadConfig::Empty, &mut game);
+
+    let player_id = game.data.find_by_name(EntityName::Player).unwrap();
+    game.data.entities.set_pos(player_id, Pos::new(4, 4));
+
+    let gol = make_gol(&mut game.data.entities, &game.config, Pos::new(8, 8), &mut game.msg_log);
+    game.data.entities.add_effect(gol, EffectKind::Frozen, 2);
+
+    game.step_game(InputAction::Pass(MoveMode::Sneak), 0.1);
+    assert!(game.msg_log.turn_messages.contains(&Msg::StartTurn(0)));
+    assert!(game.msg_log.turn_messages.contains(&Msg::EndTurn(0)));
+    assert_eq!(1, game.settings.turn_count);
+    assert_eq!(1, game.data.entities.effect_turns_left(gol, EffectKind::Frozen));
+
+    game.msg_log.clear();
+    game.step_game(InputAction::Pass(MoveMode::Sneak), 0.1);
+    assert!(game.msg_log.turn_messages.contains(&Msg::StartTurn(1)));
+    assert!(game.msg_log.turn_messages.contains(&Msg::EndTurn(1)));
+    assert!(!game.data.entities.has_effect(gol, EffectKind::Frozen));
+}
+
+#[test]
+pub fn test_frozen_monster_skips_turn() {
+    let config = Config::from_file("../config.yaml");
+    let mut game = Game::new(0, config.clone());
+    make_map(&MapLoadConfig::Empty, &mut game);
+
+    let player_id = game.data.find_by_name(EntityName::Player).unwrap();
+    game.data.entities.set_pos(player_id, Pos::new(4, 4));
+
+    // the gol attacks diagonally, so place it on the player's diagonal
+    let gol = make_gol(&mut game.data.entities, &game.config, Pos::new(5, 5), &mut game.msg_log);
+    game.data.entities.behavior[&gol] = Behavior::Attacking(player_id, Pos::new(4, 4));
+
+    let gol_attacked = |game: &Game| {
+        return game.msg_log.turn_messages.iter().any(|msg| {
+            matches!(msg, Msg::TryAttack(id, _, _) if *id == gol)
+        });
+    };
+
+    // the gol is frozen for this turn, so it does not attack
+    game.msg_log.log(Msg::Froze(gol, 1));
+    game.step_game(InputAction::Pass(MoveMode::Sneak), 0.1);
+    assert!(!gol_attacked(&game));
+    assert!(!game.data.entities.has_effect(gol, EffectKind::Frozen));
+
+    // once the effect expires the gol acts again
+    game.msg_log.clear();
+    game.step_game(InputAction::Pass(MoveMode::Sneak), 0.1);
+    assert!(gol_attacked(&game));
+}
+
+#[test]
+pub fn test_poison_damages_each_turn() {
+    let config = Config::from_file("../config.yaml");
+    let mut game = Game::new(0, config.clone());
+    make_map(&MapLoadConfig::Empty, &mut game);
+
+    let player_id = game.data.find_by_name(EntityName::Player).unwrap();
+    game.data.entities.set_pos(player_id, Pos::new(4, 4));
+
+    let gol = make_gol(&mut game.data.entities, &game.config, Pos::new(8, 8), &mut game.msg_log);
+    let gol_hp = game.data.entities.fighter[&gol].hp;
+
+    game.msg_log.log(Msg::ApplyEffect(gol, EffectKind::Poisoned, 2));
+    game.step_game(InputAction::Pass(MoveMode::Sneak), 0.1);
+    game.step_game(InputAction::Pass(MoveMode::Sneak), 0.1);
+    game.step_game(InputAction::Pass(MoveMode::Sneak), 0.1);
+
+    // poison lasts two turns
+    assert_eq!(gol_hp - 2 * POISON_DAMAGE, game.data.entities.fighter[&gol].hp);
+    assert!(!game.data.entities.has_effect(gol, EffectKind::Poisoned));
+}
+
+#[test]
+pub fn test_trap_triggers_on_step() {
+    let config = Config::from_file("../config.yaml");
+    let mut game = Game::new(0, config.clone());
+    make_map(&MapLoadConfig::Empty, &mut game);
+
+    let player_id = game.data.find_by_name(EntityName::Player).unwrap();
+    game.data.entities.set_pos(player_id, Pos::new(4, 4));
+
+    let trap = make_spike_trap(&mut game.data.entities, &game.config, Pos::new(5, 4), &mut game.msg_log);
+
+    game.step_game(InputAction::Move(Direction::Right, MoveMode::Sneak), 0.1);
+
+    assert_eq!(Pos::new(5, 4), game.data.entities.pos[&player_id]);
+    assert!(game.msg_log.turn_messages.iter().any(|msg| {
+        *msg == Msg::SpikeTrapTriggered(trap, player_id)
+    }));
+}
+
+#[test]
+pub fn test_disarm_trap() {
+    let config = Config::from_file("../config.yaml");
+    let mut game = Game::new(0, config.clone());
+    make_map(&MapLoadConfig::Empty, &mut game);
+
+    let player_id = game.data.find_by_name(EntityName::Player).unwrap();
+    game.data.entities.set_pos(player_id, Pos::new(4, 4));
+
+    let trap = make_sound_trap(&mut game.data.entities, &game.config, Pos::new(5, 4), &mut game.msg_log);
+    assert_eq!(Some(&true), game.data.entities.armed.get(&trap));
+
+    game.step_game(InputAction::Interact(Some(Direction::Right)), 0.1);
+    assert_eq!(Some(&false), game.data.entities.armed.get(&trap));
+
+    // a disarmed trap does not trigger when stepped on
+    game.step_game(InputAction::Move(Direction::Right, MoveMode::Sneak), 0.1);
+    assert_eq!(Pos::new(5, 4), game.data.entities.pos[&player_id]);
+    assert!(!game.msg_log.turn_messages.iter().any(|msg| {
+        matches!(msg, Msg::SoundTrapTriggered(_, _))
+    }));
+}
+
+#[test]
+pub fn test_throw_deflected_by_short_wall() {
+    let mut config = Config::from_file("../config.yaml");
+
+    for (deflect_chance, landing_pos) in vec!((1.0, Pos::new(5, 4)), (0.0, Pos::new(8, 4))) {
+        config.cover_deflect_chance = deflect_chance;
+        let mut game = Game::new(0, config.clone());
+        make_map(&MapLoadConfig::Empty, &mut game);
+
+        let player_id = game.data.find_by_name(EntityName::Player).unwrap();
+        let player_pos = Pos::new(4, 4);
+        game.data.entities.set_pos(player_id, player_pos);
+
+        // a short wall on the left side of (6, 4) is between the player and the target
+        game.data.map[Pos::new(6, 4)].left_wall = Wall::ShortWall;
+
+        let stone = make_stone(&mut game.data.entities, &game.config, Pos::new(4, 7), &mut game.msg_log);
+        game.data.entities.inventory[&player_id].push_front(stone);
+
+        game.msg_log.log(Msg::ItemThrow(player_id, stone, player_pos, Pos::new(8, 4)));
+        resolve_messages(&mut game.data, &mut game.msg_log, &mut game.rng, &game.config);
+
+        assert_eq!(landing_pos, game.data.entities.pos[&stone]);
+    }
+}
+
+#[test]
+pub fn test_wrap_edges_movement() {
+    let mut config = Config::from_file("../config.yaml");
+    config.wrap_edges = true;
+    let mut game = Game::new(0, config.clone());
+    make_map(&MapLoadConfig::Empty, &mut game);
+    assert!(game.data.map.wrap_edges);
+
+    let player_id = game.data.find_by_name(EntityName::Player).unwrap();
+    game.data.entities.set_pos(player_id, Pos::new(0, 4));
+
+    // moving off the left edge appears on the right edge
+    game.step_game(InputAction::Move(Direction::Left, MoveMode::Walk), 0.1);
+    assert_eq!(Pos::new(9, 4), game.data.entities.pos[&player_id]);
+
+    // and moving off the right edge returns to the left
+    game.step_game(InputAction::Move(Direction::Right, MoveMode::Walk), 0.1);
+    assert_eq!(Pos::new(0, 4), game.data.entities.pos[&player_id]);
+}
+
+#[test]
+pub fn test_torch_burns_out() {
+    let config = Config::from_file("../config.yaml");
+    let mut game = Game::new(0, config.clone());
+    make_map(&MapLoadConfig::Empty, &mut game);
+
+    let player_id = game.data.find_by_name(EntityName::Player).unwrap();
+    game.data.entities.set_pos(player_id, Pos::new(4, 4));
+    let base_radius = game.data.sight_radius(player_id, &game.config);
+
+    let torch = make_torch(&mut game.data.entities, &game.config, Pos::new(4, 7), &mut game.msg_log);
+    game.data.entities.torch[&torch].fuel = 2;
+
+    // a torch lying on the floor is not lit, so it does not burn
+    game.step_game(InputAction::Pass(MoveMode::Sneak), 0.1);
+    assert_eq!(2, game.data.entities.torch[&torch].fuel);
+    assert_eq!(0, game.data.entities.status[&torch].illuminate);
+
+    game.data.entities.inventory[&player_id].push_front(torch);
+    assert_eq!(base_radius + TORCH_FOV_RADIUS, game.data.sight_radius(player_id, &game.config));
+
+    game.step_game(InputAction::Pass(MoveMode::Sneak), 0.1);
+    assert_eq!(1, game.data.entities.torch[&torch].fuel);
+    assert_eq!(base_radius + TORCH_FOV_RADIUS, game.data.sight_radius(player_id, &game.config));
+
+    // once the fuel is gone the radius returns to normal and the torch gives no light
+    game.step_game(InputAction::Pass(MoveMode::Sneak), 0.1);
+    assert_eq!(0, game.data.entities.torch[&torch].fuel);
+    assert_eq!(base_radius, game.data.sight_radius(player_id, &game.config));
+    assert_eq!(0, game.data.entities.status[&torch].illuminate);
+    assert!(game.msg_log.turn_messages.iter().any(|msg| *msg == Msg::TorchBurnedOut(torch)));
+
+    // a burnt out torch stays out
+    game.step_game(InputAction::Pass(MoveMode::Sneak), 0.1);
+    assert_eq!(0, game.data.entities.torch[&torch].fuel);
+}
+
+#[test]
+pub fn test_placed_torch_keeps_burning() {
+    let config = Config::from_file("../config.yaml");
+    let mut game = Game::new(0, config.clone());
+    make_map(&MapLoadConfig::Empty, &mut game);
+
+    let player_id = game.data.find_by_name(EntityName::Player).unwrap();
+    game.data.entities.set_pos(player_id, Pos::new(4, 4));
+
+    let torch = make_torch(&mut game.data.entities, &game.config, Pos::new(-1, -1), &mut game.msg_log);
+    game.data.entities.inventory[&player_id].push_front(torch);
+
+    // holding the torch lights it
+    game.step_game(InputAction::Pass(MoveMode::Sneak), 0.1);
+    assert!(game.data.entities.torch[&torch].lit);
+    assert_eq!(TORCH_FUEL - 1, game.data.entities.torch[&torch].fuel);
+
+    // once put down it keeps burning and lights the tiles around it
+    game.data.entities.inventory[&player_id].clear();
+    game.data.entities.set_pos(torch, Pos::new(4, 5));
+    game.step_game(InputAction::Pass(MoveMode::Sneak), 0.1);
+    assert_eq!(TORCH_FUEL - 2, game.data.entities.torch[&torch].fuel);
+    assert_eq!(LANTERN_ILLUMINATE_RADIUS, game.data.entities.status[&torch].illuminate);
+}
+
+#[test]
+pub fn test_hammer_tall_wall() {
+    let config = Config::from_file("../config.yaml");
+    let mut game = Game::new(0, config.clone());
+
+    let player_id = game.data.find_by_name(EntityName::Player).unwrap();
+    game.data.map = Map::from_dims(10, 10);
+    let player_pos = Pos::new(4, 4);
+    game.data.entities.set_pos(player_id, player_pos);
+
+    let wall_pos = Pos::new(5, 4);
+    game.data.map[wall_pos] = Tile::wall();
+
+    let hammer = make_hammer(&mut game.data.entities, &game.config, Pos::new(4, 7), &mut game.msg_log);
+    game.data.entities.inventory[&player_id].push_front(hammer);
+
+    game.step_game(InputAction::UseItem(Direction::Right, 0), 0.1);
+
+    // the wall is replaced by rubble
+    assert_eq!(TileType::Floor, game.data.map[wall_pos].tile_type);
+    assert_eq!(Surface::Rubble, game.data.map[wall_pos].surface);
+    assert!(!game.data.map[wall_pos].block_move);
+    assert!(!game.data.map[wall_pos].block_sight);
+
+    // the hammer is used up
+    assert!(game.data.entities.inventory[&player_id].is_empty());
+}
+
+#[test]
+pub fn test_hammer_wall_sound() {
+    let config = Config::from_file("../config.yaml");
+    let mut game = Game::new(0, config.clone());
+
+    let player_id = game.data.find_by_name(EntityName::Player).unwrap();
+    game.data.map = Map::from_dims(10, 10);
+    let player_pos = Pos::new(4, 4);
+    game.data.entities.set_pos(player_id, player_pos);
+
+    let wall_pos = Pos::new(4, 3);
+    game.data.map[wall_pos] = Tile::wall();
+
+    let hammer = make_hammer(&mut game.data.entities, &game.config, Pos::new(4, 7), &mut game.msg_log);
+    game.data.entities.inventory[&player_id].push_front(hammer);
+
+    game.step_game(InputAction::UseItem(Direction::Up, 0), 0.1);
+
+    let sound_radius = game.config.sound_radius_hammer;
+    assert!(game.msg_log.turn_messages.iter().any(|msg| {
+        *msg == Msg::Sound(player_id, wall_pos, sound_radius, true)
+    }));
+}
+
+#[test]
+pub fn test_sneak_is_silent() {
+    let mut config = Config::from_file("../config.yaml");
+    config.map_load = MapLoadConfig::Empty;
+    let mut game = Game::new(0, config.clone());
+
+    let player_id = game.data.find_by_name(EntityName::Player).unwrap();
+    make_map(&MapLoadConfig::Empty, &mut game);
+    game.data.entities.set_pos(player_id, Pos::new(0, 0));
+
+    game.step_game(InputAction::Move(Direction::Right, MoveMode::Sneak), 0.1);
+    assert_eq!(Pos::new(1, 0), game.data.entities.pos[&player_id]);
+    assert!(!game.msg_log.turn_messages.iter().any(|msg| {
+        matches!(msg, Msg::Sound(id, _, _, _) if *id == player_id)
+    }));
+
+    game.step_game(InputAction::Move(Direction::Right, MoveMode::Walk), 0.1);
+    assert!(game.msg_log.turn_messages.iter().any(|msg| {
+        matches!(msg, Msg::Sound(id, _, _, _) if *id == player_id)
+    }));
+}
+
+/// Run a turn for each active AI, resolving the messages produced by each one.
+/// This does not depend on any display state, so it can be used to simulate
+/// AI turns without running the rest of the game.
+pub fn advance_ai(data: &mut GameData, msg_log: &mut MsgLog, rng: &mut Rand32, config: &Config) {
+    let ai_ids: Vec<EntityId> = data.entities.active_ais();
 
     for key in ai_ids.iter() {
-       ai_take_turn(*key, &mut game.data, &game.config, &mut game.msg_log);
+       // hasted monsters take more than one action each turn
+       for _ in 0..data.entities.actions_per_turn(*key) {
+           ai_take_turn(*key, data, config, msg_log);
 
-       resolve_messages(&mut game.data, &mut game.msg_log, &mut game.rng, &game.config);
+           resolve_messages(data, msg_log, rng, config);
+       }
 
        // if there are remaining messages for an entity, clear them
-       game.data.entities.messages[key].clear();
+       data.entities.messages[key].clear();
     }
 }
 
+#[test]
+fn test_advance_ai_attacks_adjacent_player() {
+    let config = Config::from_file("../config.yaml");
+    let mut game = Game::new(0, config);
+    make_map(&MapLoadConfig::Empty, &mut game);
+
+    let player_id = game.data.find_by_name(EntityName::Player).unwrap();
+    game.data.entities.set_pos(player_id, Pos::new(4, 4));
+
+    // the gol attacks diagonally, so place it on the player's diagonal
+    let gol = make_gol(&mut game.data.entities, &game.config, Pos::new(5, 5), &mut game.msg_log);
+    game.data.entities.behavior[&gol] = Behavior::Attacking(player_id, Pos::new(4, 4));
+    resolve_messages(&mut game.data, &mut game.msg_log, &mut game.rng, &game.config);
+    game.msg_log.clear();
+
+    advance_ai(&mut game.data, &mut game.msg_log, &mut game.rng, &game.config);
+
+    assert!(game.msg_log.turn_messages.iter().any(|msg| {
+        matches!(msg, Msg::TryAttack(id, Attack::Attack(target), _) if *id == gol && *target == player_id)
+    }));
+}
+
+#[test]
+fn test_ai_attack_chases_last_seen_pos() {
+    let config = Config::from_file("../config.yaml");
+    let mut game = Game::new(0, config);
+    make_map(&MapLoadConfig::Empty, &mut game);
+
+    let player_id = game.data.find_by_name(EntityName::Player).unwrap();
+    let last_seen = Pos::new(4, 4);
+    game.data.entities.set_pos(player_id, last_seen);
+
+    let gol_pos = Pos::new(3, 0);
+    let gol = make_gol(&mut game.data.entities, &game.config, gol_pos, &mut game.msg_log);
+    game.data.entities.behavior[&gol] = Behavior::Attacking(player_id, last_seen);
+
+    // the wall blocks sight once the player steps behind it
+    game.data.map[(3, 2)] = Tile::wall();
+    resolve_messages(&mut game.data, &mut game.msg_log, &mut game.rng, &game.config);
+    game.msg_log.clear();
+
+    game.step_game(InputAction::Move(Direction::Left, MoveMode::Walk), 0.1);
+    let player_pos = game.data.entities.pos[&player_id];
+    assert_eq!(Pos::new(3, 4), player_pos);
+    assert!(!ai_is_in_fov(gol, player_id, &mut game.data, &game.config));
+
+    // the gol heads for where the player was last seen, not where they are now
+    assert_eq!(Behavior::Investigating(last_seen), game.data.entities.behavior[&gol]);
+
+    game.step_game(InputAction::Pass(MoveMode::Walk), 0.1);
+    let new_gol_pos = game.data.entities.pos[&gol];
+    assert!(distance(new_gol_pos, last_seen) < distance(gol_pos, last_seen));
+}
+
+#[test]
+fn test_ai_patrol_waypoints() {
+    let config = Config::from_file("../config.yaml");
+    let mut game = Game::new(0, config);
+    make_map(&MapLoadConfig::Empty, &mut game);
+
+    // the player is well out of the gol's sight
+    let player_id = game.data.find_by_name(EntityName::Player).unwrap();
+    game.data.entities.set_pos(player_id, Pos::new(0, 9));
+
+    let gol = make_gol(&mut game.data.entities, &game.config, Pos::new(6, 1), &mut game.msg_log);
+    game.data.entities.patrol.insert(gol, vec!(Pos::new(8, 1), Pos::new(8, 3)));
+    game.data.entities.behavior[&gol] = Behavior::Patrol(0);
+    resolve_messages(&mut game.data, &mut game.msg_log, &mut game.rng, &game.config);
+    game.msg_log.clear();
+
+    // the gol walks to the first waypoint
+    game.step_game(InputAction::Pass(MoveMode::Walk), 0.1);
+    assert_eq!(Pos::new(7, 1), game.data.entities.pos[&gol]);
+    game.step_game(InputAction::Pass(MoveMode::Walk), 0.1);
+    assert_eq!(Pos::new(8, 1), game.data.entities.pos[&gol]);
+    assert_eq!(Behavior::Patrol(0), game.data.entities.behavior[&gol]);
+
+    // once there it targets the next waypoint
+    game.step_game(InputAction::Pass(MoveMode::Walk), 0.1);
+    assert_eq!(Behavior::Patrol(1), game.data.entities.behavior[&gol]);
+
+    game.step_game(InputAction::Pass(MoveMode::Walk), 0.1);
+    assert_eq!(Pos::new(8, 2), game.data.entities.pos[&gol]);
+    game.step_game(InputAction::Pass(MoveMode::Walk), 0.1);
+    assert_eq!(Pos::new(8, 3), game.data.entities.pos[&gol]);
+
+    // and wraps back to the first waypoint at the end of the route
+    game.step_game(InputAction::Pass(MoveMode::Walk), 0.1);
+    assert_eq!(Behavior::Patrol(0), game.data.entities.behavior[&gol]);
+    game.step_game(InputAction::Pass(MoveMode::Walk), 0.1);
+    assert_eq!(Pos::new(8, 2), game.data.entities.pos[&gol]);
+
+    // seeing the player ends the patrol
+    game.data.move_entity(player_id, Pos::new(8, 0));
+    game.step_game(InputAction::Pass(MoveMode::Walk), 0.1);
+    assert!(game.data.entities.behavior[&gol].is_aware());
+}
+
 #[test]
 fn test_ai_idle_player_in_fov() {
     let config = Config::from_file("../config.yaml");
@@ -246,7 +934,7 @@ fn test_ai_idle_player_in_fov() {
     let gol = make_gol(&mut game.data.entities, &game.config, start_pos, &mut game.msg_log);
 
     let player_id = game.data.find_by_name(EntityName::Player).unwrap();
-    game.data.entities.pos[&player_id] = add_pos(start_pos, Pos::new(1, 1));
+    game.data.entities.set_pos(player_id, add_pos(start_pos, Pos::new(1, 1)));
 
     game.msg_log.clear();
     ai_idle(gol, &mut game.data, &mut game.msg_log, &game.config);
@@ -254,7 +942,35 @@ fn test_ai_idle_player_in_fov() {
     let player_pos = game.data.entities.pos[&player_id];
 
     assert_eq!(game.msg_log.messages[0], Msg::FaceTowards(gol, player_pos));
-    assert_eq!(game.msg_log.messages[1], Msg::StateChange(gol, Behavior::Attacking(player_id)));
+    assert_eq!(game.msg_log.messages[1], Msg::StateChange(gol, Behavior::Attacking(player_id, player_pos)));
+}
+
+#[test]
+fn test_ai_idle_player_behind() {
+    let mut config = Config::from_file("../config.yaml");
+    config.monster_directional_fov = true;
+    let mut game = Game::new(0, config);
+    make_map(&MapLoadConfig::Empty, &mut game);
+
+    let start_pos = Pos::new(3, 3);
+    let gol = make_gol(&mut game.data.entities, &game.config, start_pos, &mut game.msg_log);
+    game.data.entities.direction[&gol] = Direction::Left;
+
+    // the player is right behind the gol
+    let player_id = game.data.find_by_name(EntityName::Player).unwrap();
+    game.data.entities.set_pos(player_id, add_pos(start_pos, Pos::new(1, 0)));
+
+    assert!(!game.data.is_in_fov(gol, player_id, &game.config));
+
+    game.msg_log.clear();
+    ai_idle(gol, &mut game.data, &mut game.msg_log, &game.config);
+    assert!(!game.msg_log.messages.iter().any(|msg| {
+        matches!(msg, Msg::StateChange(id, Behavior::Attacking(_, _)) if *id == gol)
+    }));
+
+    // without directional fov the gol sees all around it
+    game.config.monster_directional_fov = false;
+    assert!(game.data.is_in_fov(gol, player_id, &game.config));
 }
 
 #[test]
@@ -267,12 +983,12 @@ fn test_ai_idle_was_attacked() {
     let gol = make_gol(&mut game.data.entities, &game.config, start_pos, &mut game.msg_log);
 
     let player_id = game.data.find_by_name(EntityName::Player).unwrap();
-    game.data.entities.pos[&player_id] = add_pos(start_pos, Pos::new(1, 1));
+    game.data.entities.set_pos(player_id, add_pos(start_pos, Pos::new(1, 1)));
 
     game.msg_log.clear();
     // move the player a tile away
 
-    game.data.entities.pos[&player_id] = add_pos(start_pos, Pos::new(3, 0));
+    game.data.entities.set_pos(player_id, add_pos(start_pos, Pos::new(3, 0)));
 
     // place a wall between the player and the gol
     game.data.map[(2, 0)] = Tile::wall();
@@ -288,7 +1004,7 @@ fn test_ai_idle_was_attacked() {
 
     let player_pos = game.data.entities.pos[&player_id];
     assert_eq!(game.msg_log.messages[0], Msg::FaceTowards(gol, player_pos));
-    assert_eq!(game.msg_log.messages[1], Msg::StateChange(gol, Behavior::Attacking(player_id)));
+    assert_eq!(game.msg_log.messages[1], Msg::StateChange(gol, Behavior::Attacking(player_id, player_pos)));
 }
 
 #[test]
@@ -301,12 +1017,12 @@ fn test_ai_idle_heard_sound() {
     let gol = make_gol(&mut game.data.entities, &game.config, start_pos, &mut game.msg_log);
 
     let player_id = game.data.find_by_name(EntityName::Player).unwrap();
-    game.data.entities.pos[&player_id] = add_pos(start_pos, Pos::new(1, 1));
+    game.data.entities.set_pos(player_id, add_pos(start_pos, Pos::new(1, 1)));
 
     game.msg_log.clear();
 
     // move the player a tile away
-    game.data.entities.pos[&player_id] = add_pos(start_pos, Pos::new(3, 0));
+    game.data.entities.set_pos(player_id, add_pos(start_pos, Pos::new(3, 0)));
 
     // place a wall between the player and the gol
     game.data.map[(2, 0)] = Tile::wall();
@@ -326,6 +1042,29 @@ fn test_ai_idle_heard_sound() {
     assert_eq!(game.msg_log.messages[1], Msg::StateChange(gol, Behavior::Investigating(sound_pos)));
 }
 
+#[test]
+fn test_yell_alerts_monsters() {
+    let config = Config::from_file("../config.yaml");
+    let mut game = Game::new(0, config);
+    make_map(&MapLoadConfig::Empty, &mut game);
+
+    let player_id = game.data.find_by_name(EntityName::Player).unwrap();
+    let player_pos = Pos::new(8, 8);
+    game.data.entities.set_pos(player_id, player_pos);
+
+    let near_gol = make_gol(&mut game.data.entities, &game.config, Pos::new(8, 6), &mut game.msg_log);
+    let far_gol = make_gol(&mut game.data.entities, &game.config, Pos::new(0, 0), &mut game.msg_log);
+    game.msg_log.clear();
+
+    game.msg_log.log(Msg::Yell(player_id, player_pos, game.config.yell_radius));
+    resolve_messages(&mut game.data, &mut game.msg_log, &mut game.rng, &game.config);
+
+    // monsters in range investigate the yell, and those out of range do not hear it
+    assert_eq!(Behavior::Investigating(player_pos), game.data.entities.behavior[&near_gol]);
+    assert_eq!(Behavior::Idle, game.data.entities.behavior[&far_gol]);
+    assert!(game.data.entities.took_turn[&player_id]);
+}
+
 #[test]
 fn test_ai_investigate_player_in_fov() {
     let config = Config::from_file("../config.yaml");
@@ -338,7 +1077,7 @@ fn test_ai_investigate_player_in_fov() {
     game.data.entities.direction[&gol] = Direction::Right;
 
     let player_id = game.data.find_by_name(EntityName::Player).unwrap();
-    game.data.entities.pos[&player_id] = add_pos(start_pos, Pos::new(1, 1));
+    game.data.entities.set_pos(player_id, add_pos(start_pos, Pos::new(1, 1)));
 
     let player_pos = game.data.entities.pos[&player_id];
     game.data.entities.behavior[&gol] = Behavior::Investigating(player_pos);
@@ -348,7 +1087,7 @@ fn test_ai_investigate_player_in_fov() {
 
     assert_eq!(2, game.msg_log.messages.len());
     assert_eq!(game.msg_log.messages[0], Msg::FaceTowards(gol, player_pos));
-    assert_eq!(game.msg_log.messages[1], Msg::StateChange(gol, Behavior::Attacking(player_id)));
+    assert_eq!(game.msg_log.messages[1], Msg::StateChange(gol, Behavior::Attacking(player_id, player_pos)));
 }
 
 #[test]
@@ -364,13 +1103,13 @@ fn test_ai_investigate_not_in_fov_heard_sound() {
     game.data.entities.direction[&gol] = Direction::Right;
 
     let player_id = game.data.find_by_name(EntityName::Player).unwrap();
-    game.data.entities.pos[&player_id] = add_pos(start_pos, Pos::new(1, 1));
+    game.data.entities.set_pos(player_id, add_pos(start_pos, Pos::new(1, 1)));
 
     let player_pos = game.data.entities.pos[&player_id];
     game.data.entities.behavior[&gol] = Behavior::Investigating(player_pos);
 
     // move the player a tile away
-    game.data.entities.pos[&player_id] = add_pos(start_pos, Pos::new(3, 0));
+    game.data.entities.set_pos(player_id, add_pos(start_pos, Pos::new(3, 0)));
 
     // place a wall between the player and the gol
     game.data.map[(2, 0)] = Tile::wall();
@@ -396,7 +1135,7 @@ fn test_ai_investigate_moves() {
     game.data.entities.direction[&gol] = Direction::Right;
 
     let player_id = game.data.find_by_name(EntityName::Player).unwrap();
-    game.data.entities.pos[&player_id] = add_pos(start_pos, Pos::new(5, 1));
+    game.data.entities.set_pos(player_id, add_pos(start_pos, Pos::new(5, 1)));
 
     // place walls between the player and the gol
     game.data.map[(2, 0)] = Tile::wall();
@@ -433,3 +1172,99 @@ fn clean_entities(entities: &mut Entities, msg_log: &mut MsgLog) {
     }
 }
 
+#[test]
+pub fn test_level_exit_condition_met() {
+    let mut config = Config::from_file("../config.yaml");
+    config.map_load = MapLoadConfig::Empty;
+    let mut game = Game::new(0, config.clone());
+    make_map(&MapLoadConfig::Empty, &mut game);
+
+    let player_id = game.data.find_by_name(EntityName::Player).unwrap();
+    game.data.entities.set_pos(player_id, Pos::new(2, 2));
+
+    let exit_pos = Pos::new(3, 2);
+    make_exit(&mut game.data.entities, &config, exit_pos, &mut game.msg_log);
+    game.data.goals = GoalTracker::new(1);
+    assert!(!level_exit_condition_met(&game.data));
+
+    // standing on the exit without the key is not enough
+    game.data.entities.set_pos(player_id, exit_pos);
+    assert!(!level_exit_condition_met(&game.data));
+
+    let key = make_key(&mut game.data.entities, &config, Pos::new(-1, -1), &mut game.msg_log);
+    game.data.entities.pick_up_item(player_id, key);
+    assert!(level_exit_condition_met(&game.data));
+
+    // having the key but not being on the exit is not enough
+    game.data.entities.set_pos(player_id, Pos::new(2, 2));
+    assert!(!level_exit_condition_met(&game.data));
+}
+
+#[test]
+pub fn test_player_death_loses() {
+    let mut config = Config::from_file("../config.yaml");
+    config.map_load = MapLoadConfig::Empty;
+    let mut game = Game::new(0, config.clone());
+    make_map(&MapLoadConfig::Empty, &mut game);
+
+    let player_id = game.data.find_by_name(EntityName::Player).unwrap();
+    game.data.entities.set_pos(player_id, Pos::new(2, 2));
+
+    let result = game.step_game(InputAction::Pass(MoveMode::Walk), 0.1);
+    assert_eq!(GameResult::Continue, result);
+
+    game.data.entities.fighter[&player_id].hp = 0;
+    let result = game.step_game(InputAction::Pass(MoveMode::Walk), 0.1);
+    assert_eq!(GameResult::Lose, result);
+    assert_eq!(GameState::Lose, game.settings.state());
+}
+
+#[test]
+pub fn test_large_entity_blocks_sight() {
+    let mut config = Config::from_file("../config.yaml");
+    config.map_load = MapLoadConfig::Empty;
+    let mut game = Game::new(0, config.clone());
+    make_map(&MapLoadConfig::Empty, &mut game);
+
+    let player_id = game.data.find_by_name(EntityName::Player).unwrap();
+    game.data.entities.set_pos(player_id, Pos::new(1, 5));
+
+    let pawn = make_pawn(&mut game.data.entities, &config, Pos::new(4, 5), &mut game.msg_log);
+    assert!(game.data.is_in_fov(player_id, pawn, &config));
+
+    // an ordinary gol does not block sight
+    let gol = make_gol(&mut game.data.entities, &config, Pos::new(3, 5), &mut game.msg_log);
+    assert!(game.data.is_in_fov(player_id, gol, &config));
+    assert!(game.data.is_in_fov(player_id, pawn, &config));
+
+    // a large creature standing between the player and the pawn blocks sight of the pawn
+    game.data.entities.blocks_sight.insert(gol, true);
+    assert!(game.data.is_in_fov(player_id, gol, &config));
+    assert!(!game.data.is_in_fov(player_id, pawn, &config));
+}
+
+#[test]
+fn test_ai_flee_when_wounded() {
+    let config = Config::from_file("../config.yaml");
+    let mut game = Game::new(0, config);
+    make_map(&MapLoadConfig::Empty, &mut game);
+
+    let player_id = game.data.find_by_name(EntityName::Player).unwrap();
+    let player_pos = Pos::new(4, 4);
+    game.data.entities.set_pos(player_id, player_pos);
+
+    let gol_pos = Pos::new(5, 4);
+    let gol = make_gol(&mut game.data.entities, &game.config, gol_pos, &mut game.msg_log);
+    game.data.entities.behavior[&gol] = Behavior::Attacking(player_id, player_pos);
+    game.data.entities.fighter[&gol].hp = 1;
+
+    game.msg_log.clear();
+    basic_ai_take_turn(gol, &mut game.data, &mut game.msg_log, &game.config);
+    assert_eq!(game.msg_log.messages[0], Msg::StateChange(gol, Behavior::Fleeing(player_id)));
+
+    resolve_messages(&mut game.data, &mut game.msg_log, &mut game.rng, &game.config);
+
+    assert_eq!(Behavior::Fleeing(player_id), game.data.entities.behavior[&gol]);
+    let new_gol_pos = game.data.entities.pos[&gol];
+    assert!(distance_squared(new_gol_pos, player_pos) > distance_squared(gol_pos, player_pos));
+}
@@ -156,7 +156,7 @@ fn tile_from_ascii(tile_chr: char, left_wall: char, bottom_wall: char, pos: Pos,
     let mut tile;
     match tile_chr {
         ' ' | '\t' | '.' => {
-            tile = Tile::empty();
+            tile = Tile::floor();
         }
 
         ';' => {
@@ -176,47 +176,47 @@ fn tile_from_ascii(tile_chr: char, left_wall: char, bottom_wall: char, pos: Pos,
         }
 
         'I' => {
-            tile = Tile::empty();
+            tile = Tile::floor();
             let mut msg_log = MsgLog::new();
             make_column(&mut vault.data.entities, config, pos, &mut msg_log);
         }
 
         'p' => {
-            tile = Tile::empty();
+            tile = Tile::floor();
             let mut msg_log = MsgLog::new();
             make_pawn(&mut vault.data.entities, config, pos, &mut msg_log);
         }
 
         'g' => {
-            tile = Tile::empty();
+            tile = Tile::floor();
             let mut msg_log = MsgLog::new();
             make_gol(&mut vault.data.entities, config, pos, &mut msg_log);
         }
 
         'o' => {
-            tile = Tile::empty();
+            tile = Tile::floor();
             let mut msg_log = MsgLog::new();
             make_stone(&mut vault.data.entities, config, pos, &mut msg_log);
         }
 
         '*' => {
-            tile = Tile::empty();
+            tile = Tile::floor();
             // TODO trap
         }
 
         'S' => {
-            tile = Tile::empty();
+            tile = Tile::floor();
             // TODO Statue - choose from list of statues
         }
 
         'v' => {
-            tile = Tile::empty();
+            tile = Tile::floor();
             let mut msg_log = MsgLog::new();
             make_dagger(&mut vault.data.entities, config, pos, &mut msg_log);
         }
 
         _ => {
-            tile = Tile::empty();
+            tile = Tile::floor();
             eprintln!("Unexpected char '{}' in {}", tile_chr, pos);
         }
     }
@@ -9,6 +9,7 @@ use roguelike_core::messaging::*;
 use roguelike_engine::input::*;
 use roguelike_engine::game::*;
 use roguelike_engine::generation::*;
+use roguelike_engine::make_map::write_map_xp;
 
 
 // TODO
@@ -33,22 +34,22 @@ use roguelike_engine::generation::*;
 
 pub enum GameCmd {
     PlayerId,
-    Pos(u64),
-    SetPos(u64, i32, i32),
-    Hp(u64),
-    SetHp(u64, i32),
-    Facing(u64),
-    SetFacing(u64, Direction),
+    Pos(EntityId),
+    SetPos(EntityId, i32, i32),
+    Hp(EntityId),
+    SetHp(EntityId, i32),
+    Facing(EntityId),
+    SetFacing(EntityId, Direction),
     MapSize,
     TileWalls(i32, i32),
     SetTileWalls(i32, i32, TileType, Wall, Wall), // type, left, bottom
     Surface(i32, i32),
     SetSurface(i32, i32, Surface),
-    EntityName(u64),
-    EntityType(u64),
+    EntityName(EntityId),
+    EntityType(EntityId),
     Make(EntityName, i32, i32),
-    Remove(u64),
-    Kill(u64),
+    Remove(EntityId),
+    Kill(EntityId),
     Give(Item),
     ListEntities,
     ListEntitiesPos(i32, i32),
@@ -56,6 +57,7 @@ pub enum GameCmd {
     Ctrl(KeyDir),
     Alt(KeyDir),
     Shift(KeyDir),
+    SaveMap(String),
     Exit,
 }
 
@@ -73,15 +75,15 @@ impl FromStr for GameCmd {
         if cmd == "player_id" {
             return Ok(GameCmd::PlayerId);
         } else if cmd == "pos" {
-            let id = args[1].parse::<u64>().unwrap();
+            let id = args[1].parse::<EntityId>().unwrap();
             return Ok(GameCmd::Pos(id));
         } else if cmd == "set_pos" {
-            let id = args[1].parse::<u64>().unwrap();
+            let id = args[1].parse::<EntityId>().unwrap();
             let x  = args[2].parse::<i32>().unwrap();
             let y  = args[3].parse::<i32>().unwrap();
             return Ok(GameCmd::SetPos(id, x, y));
         } else if cmd == "hp" {
-            let id = args[1].parse::<u64>().unwrap();
+            let id = args[1].parse::<EntityId>().unwrap();
             if args.len() == 2 {
                 return Ok(GameCmd::Hp(id));
             } else {
@@ -89,7 +91,7 @@ impl FromStr for GameCmd {
                 return Ok(GameCmd::SetHp(id, hp));
             }
         } else if cmd == "facing" {
-            let id = args[1].parse::<u64>().unwrap();
+            let id = args[1].parse::<EntityId>().unwrap();
             if args.len() == 2 {
                 return Ok(GameCmd::Facing(id));
             } else {
@@ -119,10 +121,10 @@ impl FromStr for GameCmd {
             let surface  = args[3].parse::<Surface>().unwrap();
             return Ok(GameCmd::SetSurface(x, y, surface));
         } else if cmd == "entity_name" {
-            let id = args[1].parse::<u64>().unwrap();
+            let id = args[1].parse::<EntityId>().unwrap();
             return Ok(GameCmd::EntityName(id));
         } else if cmd == "entity_type" {
-            let id = args[1].parse::<u64>().unwrap();
+            let id = args[1].parse::<EntityId>().unwrap();
             return Ok(GameCmd::EntityType(id));
         } else if cmd == "make" {
             let entity_name = args[1].parse::<EntityName>().unwrap();
@@ -131,10 +133,10 @@ impl FromStr for GameCmd {
 
             return Ok(GameCmd::Make(entity_name, x, y));
         } else if cmd == "remove" {
-            let id = args[1].parse::<u64>().unwrap();
+            let id = args[1].parse::<EntityId>().unwrap();
             return Ok(GameCmd::Remove(id));
         } else if cmd == "kill" {
-            let id = args[1].parse::<u64>().unwrap();
+            let id = args[1].parse::<EntityId>().unwrap();
             return Ok(GameCmd::Kill(id));
         } else if cmd == "give" {
             let item = args[1].parse::<Item>().unwrap();
@@ -158,6 +160,10 @@ impl FromStr for GameCmd {
         } else if cmd == "shift" {
             let dir = args[1].parse::<KeyDir>().unwrap();
             return Ok(GameCmd::Shift(dir));
+        } else if cmd == "save_map" {
+            // the file name is taken from the original string to keep its case
+            let file_name = string.split(" ").nth(1).unwrap();
+            return Ok(GameCmd::SaveMap(file_name.to_string()));
         } else if cmd == "exit" {
             return Ok(GameCmd::Exit);
         }
@@ -214,6 +220,8 @@ impl GameCmd {
             return "alt";
         } else if matches!(self, GameCmd::Shift(_)) {
             return "shift";
+        } else if matches!(self, GameCmd::SaveMap(_)) {
+            return "save_map";
         } else if matches!(self, GameCmd::Exit) {
             return "exit";
         } else {
@@ -237,7 +245,7 @@ pub fn execute_game_command(command: &GameCmd, game: &mut Game) -> String {
         }
 
         GameCmd::SetPos(id, x, y) => {
-            game.data.entities.pos[id] = Pos::new(*x, *y);
+            game.data.move_entity(*id, Pos::new(*x, *y));
             return "".to_string();
         }
 
@@ -291,6 +299,13 @@ pub fn execute_game_command(command: &GameCmd, game: &mut Game) -> String {
             return "".to_string();
         }
 
+        GameCmd::SaveMap(file_name) => {
+            match write_map_xp(&game.config, &game.data.map, &game.data.entities, file_name) {
+                Ok(()) => return "".to_string(),
+                Err(err) => return format!("{} {}", name, err),
+            }
+        }
+
         GameCmd::Make(entity_name, x, y) => {
             let pos = Pos::new(*x, *y);
             let id = make_entity(&mut game.data.entities,
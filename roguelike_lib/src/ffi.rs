@@ -6,7 +6,7 @@ use std::ffi::CStr;
 
 use serde_json;
 
-use log::{LevelFilter, trace};
+use log::{LevelFilter, trace, error};
 use simple_logging;
 
 use roguelike_core::types::*;
@@ -45,10 +45,14 @@ pub extern "C" fn create_game(seed: u64, config_name: *mut i8, map_name: *mut i8
         let map_str = map_cstr.to_str().unwrap();
         trace!("loading map {}", map_str);
 
-        let pos = read_map_xp(&game.config, &mut game.data, &mut game.msg_log, map_str);
+        match read_map_xp(&game.config, &mut game.data, &mut game.msg_log, map_str) {
+            Ok(pos) => {
+                let player_id = game.data.find_by_name(EntityName::Player).unwrap();
+                game.data.move_entity(player_id, Pos::from(pos));
+            }
 
-        let player_id = game.data.find_by_name(EntityName::Player).unwrap();
-        game.data.entities.pos[&player_id] = Pos::from(pos);
+            Err(err) => error!("{}", err),
+        }
     }
 
     let raw_ptr = Box::into_raw(game);
@@ -1,3 +1,5 @@
+use indexmap::map::IndexMap;
+
 use roguelike_core::types::{Name, Pos};
 use roguelike_core::utils::{distance, move_towards};
 use roguelike_core::map::Aoe;
@@ -122,6 +124,27 @@ impl Animation {
         }
     }
 
+    pub fn sprite_key(&self) -> Option<SpriteKey> {
+        match self {
+            Animation::Loop(sprite_anim) => return Some(sprite_anim.sprite_key),
+            Animation::Between(sprite_anim, _, _, _, _) => return Some(sprite_anim.sprite_key),
+            Animation::Once(sprite_anim) => return Some(sprite_anim.sprite_key),
+            Animation::PlayEffect(_) => return None,
+        }
+    }
+
+    /// Check that the sprite sheet this animation draws from exists, so
+    /// playing it will not fail on a missing key.
+    pub fn is_valid<T>(&self, sprites: &IndexMap<SpriteKey, T>) -> Result<(), String> {
+        if let Some(sprite_key) = self.sprite_key() {
+            if !sprites.contains_key(&sprite_key) {
+                return Err(format!("Animation refers to sprite key {}, which is not loaded", sprite_key));
+            }
+        }
+
+        return Ok(());
+    }
+
     pub fn step(&mut self, pos: Pos, dt: f32, config: &Config) -> AnimationResult {
         let mut animation_result = AnimationResult::new();
         animation_result.pos = pos;
@@ -169,3 +192,23 @@ impl Animation {
     }
 }
 
+#[test]
+fn test_animation_is_valid() {
+    let mut sprites = IndexMap::new();
+    sprites.insert(0, "tiles");
+    sprites.insert(1, "stone");
+
+    let stone = SpriteAnim::new("stone".to_string(), 1, 0.0, 1.0, 1.0);
+    let sequence = vec!(Animation::Between(stone, Pos::new(0, 0), Pos::new(3, 0), 0.0, 1.0),
+                        Animation::PlayEffect(Effect::Beam(1, Pos::new(0, 0), Pos::new(3, 0))),
+                        Animation::Loop(stone));
+    assert!(sequence.iter().all(|anim| anim.is_valid(&sprites).is_ok()));
+
+    // once the sprite sheet is removed, animations using it are rejected
+    sprites.remove(&1);
+    assert!(sequence[0].is_valid(&sprites).is_err());
+    assert!(sequence[2].is_valid(&sprites).is_err());
+
+    // effects do not use a sprite sheet
+    assert!(sequence[1].is_valid(&sprites).is_ok());
+}
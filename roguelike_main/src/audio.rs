@@ -0,0 +1,296 @@
+use std::collections::HashMap;
+use std::io::Cursor;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use rodio::{Decoder, OutputStream, OutputStreamHandle, Source};
+use rodio::source::{Buffered, ChannelVolume};
+
+use roguelike_core::types::*;
+use roguelike_core::utils::distance;
+
+
+/// Sound clips are loaded from this directory, keyed by their file name
+/// without the extension.
+pub const SOUND_DIR: &str = "resources/sounds";
+
+/// The clip played when a sound reaches the player.
+pub const SOUND_CLIP: &str = "sound";
+
+/// The file the sound clip is loaded from.
+pub const SOUND_PATH: &str = "resources/sounds/sound.wav";
+
+
+/// Volume and left/right pan for a sound heard by the player. The volume falls off
+/// linearly with distance, reaching zero just past the sound's radius, and the pan
+/// goes from -1.0 (left) to 1.0 (right) based on the x offset of the sound.
+pub fn sound_volume_pan(sound_pos: Pos, player_pos: Pos, radius: usize) -> (f32, f32) {
+    let radius = radius.max(1) as f32;
+
+    let dist = distance(player_pos, sound_pos) as f32;
+    let volume = (1.0 - dist / (radius + 1.0)).max(0.0).min(1.0);
+
+    let x_offset = (sound_pos.x - player_pos.x) as f32;
+    let pan = (x_offset / radius).max(-1.0).min(1.0);
+
+    return (volume, pan);
+}
+
+/// Split a volume into left and right channel volumes given a pan value.
+/// A centered sound plays at full volume on both channels.
+pub fn channel_volumes(volume: f32, pan: f32) -> (f32, f32) {
+    let left = volume * (1.0 - pan).min(1.0);
+    let right = volume * (1.0 + pan).min(1.0);
+
+    return (left, right);
+}
+
+/// Enforces a minimum interval between plays of the same clip, so
+/// repeated events don't stack into noise.
+#[derive(Clone, Debug)]
+pub struct SoundCooldown {
+    pub interval: Duration,
+    last_played: HashMap<String, Instant>,
+}
+
+impl SoundCooldown {
+    pub fn new(interval: Duration) -> SoundCooldown {
+        return SoundCooldown { interval, last_played: HashMap::new() };
+    }
+
+    /// Check whether a clip may play at the given time, recording the play if so.
+    pub fn try_trigger(&mut self, clip_id: &str, now: Instant) -> bool {
+        if let Some(last) = self.last_played.get(clip_id) {
+            if now.duration_since(*last) < self.interval {
+                return false;
+            }
+        }
+
+        self.last_played.insert(clip_id.to_string(), now);
+        return true;
+    }
+}
+
+type Clip = Buffered<Decoder<Cursor<Vec<u8>>>>;
+
+/// Plays sound clips through a single output device. Each clip is decoded
+/// once when loaded and kept in memory.
+pub struct AudioSystem {
+    // the stream must be kept alive for the handle to play sounds
+    output: Option<(OutputStream, OutputStreamHandle)>,
+    clips: HashMap<String, Clip>,
+    cooldown: SoundCooldown,
+}
+
+impl AudioSystem {
+    /// Create the audio system using the default output device. If there is no
+    /// device, clips are still tracked but produce no sound.
+    pub fn new(cooldown: Duration) -> AudioSystem {
+        return AudioSystem {
+            output: OutputStream::try_default().ok(),
+            clips: HashMap::new(),
+            cooldown: SoundCooldown::new(cooldown),
+        };
+    }
+
+    pub fn load_clip(&mut self, clip_id: &str, path: &str) -> Result<(), String> {
+        let bytes = std::fs::read(path).map_err(|err| format!("Could not read sound '{}': {}", path, err))?;
+        return self.load_clip_bytes(clip_id, bytes).map_err(|err| format!("Could not decode sound '{}': {}", path, err));
+    }
+
+    /// Decode a clip from the bytes of an audio file.
+    pub fn load_clip_bytes(&mut self, clip_id: &str, bytes: Vec<u8>) -> Result<(), String> {
+        let decoder = Decoder::new(Cursor::new(bytes)).map_err(|err| err.to_string())?;
+
+        self.clips.insert(clip_id.to_string(), decoder.buffered());
+
+        return Ok(());
+    }
+
+    /// Load every clip in a directory, using each file's name without its
+    /// extension as the clip id. A missing directory loads no clips.
+    pub fn load_clips(&mut self, dir: &str) -> Result<(), String> {
+        if !Path::new(dir).is_dir() {
+            log::info!("No sound directory '{}', playing without sound", dir);
+            return Ok(());
+        }
+
+        let entries = std::fs::read_dir(dir).map_err(|err| format!("Could not read sound directory '{}': {}", dir, err))?;
+        for entry in entries {
+            let path = entry.map_err(|err| format!("Could not read sound directory '{}': {}", dir, err))?.path();
+
+            if let (Some(clip_id), Some(path_str)) = (path.file_stem().and_then(|stem| stem.to_str()), path.to_str()) {
+                self.load_clip(clip_id, path_str)?;
+            }
+        }
+
+        return Ok(());
+    }
+
+    /// Play a loaded clip, unless it was played within the cooldown interval.
+    /// Returns whether the clip was triggered.
+    pub fn play(&mut self, clip_id: &str) -> bool {
+        return self.play_with_volumes(clip_id, 1.0, 1.0);
+    }
+
+    /// Play a loaded clip with volume and pan set by its position relative to the player.
+    pub fn play_at(&mut self, clip_id: &str, sound_pos: Pos, player_pos: Pos, radius: usize) -> bool {
+        let (volume, pan) = sound_volume_pan(sound_pos, player_pos, radius);
+        if volume <= 0.0 {
+            return false;
+        }
+        let (left, right) = channel_volumes(volume, pan);
+
+        return self.play_with_volumes(clip_id, left, right);
+    }
+
+    /// Play the sound file at 'path' with volume and pan set by its position
+    /// relative to the player. The file is loaded the first time it is played,
+    /// keyed by its file name without the extension as in load_clips.
+    pub fn play_sound_at(&mut self, path: &str, sound_pos: Pos, player_pos: Pos, radius: usize) -> bool {
+        let clip_id = match Path::new(path).file_stem().and_then(|stem| stem.to_str()) {
+            Some(clip_id) => clip_id.to_string(),
+            None => return false,
+        };
+
+        if !self.clips.contains_key(&clip_id) {
+            if let Err(err) = self.load_clip(&clip_id, path) {
+                log::warn!("{}", err);
+                return false;
+            }
+        }
+
+        return self.play_at(&clip_id, sound_pos, player_pos, radius);
+    }
+
+    fn play_with_volumes(&mut self, clip_id: &str, left: f32, right: f32) -> bool {
+        if let Some(clip) = self.clips.get(clip_id) {
+            if !self.cooldown.try_trigger(clip_id, Instant::now()) {
+                return false;
+            }
+
+            if let Some((_stream, handle)) = &self.output {
+                let source = ChannelVolume::new(clip.clone().convert_samples::<f32>(), vec!(left, right));
+                if let Err(err) = handle.play_raw(source) {
+                    log::warn!("Could not play sound '{}': {}", clip_id, err);
+                }
+            }
+
+            return true;
+        }
+
+        return false;
+    }
+}
+
+#[test]
+fn test_sound_cooldown() {
+    let interval = Duration::from_millis(100);
+    let mut cooldown = SoundCooldown::new(interval);
+    let start = Instant::now();
+
+    // rapid-fire plays within the interval only trigger once
+    let triggered = (0..5).filter(|index| {
+        cooldown.try_trigger("step", start + Duration::from_millis(index * 10))
+    }).count();
+    assert_eq!(1, triggered);
+
+    // other clips have their own cooldown
+    assert!(cooldown.try_trigger("throw", start + Duration::from_millis(20)));
+
+    // once the interval passes the clip plays again
+    assert!(cooldown.try_trigger("step", start + interval));
+    assert!(!cooldown.try_trigger("step", start + interval + Duration::from_millis(50)));
+}
+
+#[test]
+fn test_audio_system_unknown_clip() {
+    let mut audio = AudioSystem::new(Duration::from_millis(100));
+    assert!(!audio.play("missing"));
+}
+
+#[test]
+fn test_audio_system_cooldown() {
+    // a short silent mono 16 bit wav file
+    let num_samples: u32 = 8;
+    let mut wav: Vec<u8> = Vec::new();
+    wav.extend(b"RIFF");
+    wav.extend(&(36 + num_samples * 2).to_le_bytes());
+    wav.extend(b"WAVEfmt ");
+    wav.extend(&16u32.to_le_bytes());
+    wav.extend(&1u16.to_le_bytes());
+    wav.extend(&1u16.to_le_bytes());
+    wav.extend(&8000u32.to_le_bytes());
+    wav.extend(&16000u32.to_le_bytes());
+    wav.extend(&2u16.to_le_bytes());
+    wav.extend(&16u16.to_le_bytes());
+    wav.extend(b"data");
+    wav.extend(&(num_samples * 2).to_le_bytes());
+    wav.extend(vec![0u8; num_samples as usize * 2]);
+
+    let mut audio = AudioSystem::new(Duration::from_secs(60));
+    audio.load_clip_bytes(SOUND_CLIP, wav).unwrap();
+
+    // sounds the player hears in quick succession only play once
+    let player_pos = Pos::new(5, 5);
+    assert!(audio.play_at(SOUND_CLIP, Pos::new(6, 5), player_pos, 4));
+    assert!(!audio.play_at(SOUND_CLIP, Pos::new(4, 5), player_pos, 4));
+    assert!(!audio.play(SOUND_CLIP));
+}
+
+#[test]
+fn test_play_sound_at() {
+    let mut audio = AudioSystem::new(Duration::from_secs(60));
+    let path = format!("../{}", SOUND_PATH);
+
+    // the shipped clip is loaded on first use, and shares the clip's cooldown
+    let player_pos = Pos::new(5, 5);
+    assert!(audio.play_sound_at(&path, Pos::new(7, 5), player_pos, 4));
+    assert!(audio.clips.contains_key(SOUND_CLIP));
+    assert!(!audio.play(SOUND_CLIP));
+
+    // sounds out of range and missing files do not play
+    let mut audio = AudioSystem::new(Duration::from_secs(60));
+    assert!(!audio.play_sound_at(&path, Pos::new(15, 5), player_pos, 4));
+    assert!(!audio.play_sound_at("../resources/sounds/missing.wav", player_pos, player_pos, 4));
+}
+
+#[test]
+fn test_sound_volume_pan() {
+    let player_pos = Pos::new(5, 5);
+
+    // a sound on the player is at full volume, centered
+    assert_eq!((1.0, 0.0), sound_volume_pan(player_pos, player_pos, 4));
+
+    // further sounds are quieter
+    let (near_volume, near_pan) = sound_volume_pan(Pos::new(6, 5), player_pos, 4);
+    let (far_volume, far_pan) = sound_volume_pan(Pos::new(8, 5), player_pos, 4);
+    assert!(near_volume > far_volume);
+    assert!(far_volume > 0.0);
+
+    // sounds to the right pan right, more so when further away
+    assert!(near_pan > 0.0);
+    assert!(far_pan > near_pan);
+
+    // sounds to the left pan left, and directly above or below are centered
+    let (_, left_pan) = sound_volume_pan(Pos::new(3, 5), player_pos, 4);
+    assert!(left_pan < 0.0);
+    let (_, above_pan) = sound_volume_pan(Pos::new(5, 2), player_pos, 4);
+    assert_eq!(0.0, above_pan);
+
+    // sounds beyond the radius are silent, and the pan is clamped
+    let (out_volume, out_pan) = sound_volume_pan(Pos::new(15, 5), player_pos, 4);
+    assert_eq!(0.0, out_volume);
+    assert_eq!(1.0, out_pan);
+}
+
+#[test]
+fn test_channel_volumes() {
+    assert_eq!((1.0, 1.0), channel_volumes(1.0, 0.0));
+    assert_eq!((0.0, 0.5), channel_volumes(0.5, 1.0));
+    assert_eq!((0.5, 0.0), channel_volumes(0.5, -1.0));
+
+    let (left, right) = channel_volumes(1.0, 0.5);
+    assert_eq!(1.0, right);
+    assert!(left < right);
+}
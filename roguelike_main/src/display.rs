@@ -16,33 +16,42 @@ use roguelike_core::messaging::*;
 use roguelike_core::map::*;
 use roguelike_core::utils::aoe_fill;
 use roguelike_core::movement::{Direction};
+use roguelike_core::perlin::Perlin;
 
 use crate::animation::{Sprite, Effect, SpriteKey, Animation, SpriteAnim, SpriteIndex};
+use crate::audio::{AudioSystem, SOUND_PATH};
+
+
+// heights in cells of the player and inventory areas beside the map
+pub const PLAYER_AREA_HEIGHT: u32 = 20;
+pub const INVENTORY_AREA_HEIGHT: u32 = 15;
 
 
 pub struct Display {
     pub state: DisplayState,
     pub targets: DisplayTargets,
     pub mouse_state: MouseState,
+    pub audio: AudioSystem,
 }
 
 impl Display {
-    pub fn new(canvas: WindowCanvas) -> Display {
-        return Display { state: DisplayState::new(),
-                         targets: DisplayTargets::new(canvas),
-                         mouse_state: Default::default(),
-        };
+    pub fn new(canvas: WindowCanvas, audio: AudioSystem) -> Result<Display, String> {
+        return Ok(Display { state: DisplayState::new(),
+                            targets: DisplayTargets::new(canvas)?,
+                            mouse_state: Default::default(),
+                            audio,
+        });
     }
 
     pub fn update_display(&mut self) {
         self.targets.canvas_panel.target.present();
     }
 
-    pub fn save_screenshot(&mut self, name: &str) {
+    pub fn save_screenshot(&mut self, name: &str) -> Result<(), String> {
         let format = PixelFormatEnum::RGB24;
-        let (width, height) = self.targets.canvas_panel.target.output_size().unwrap();
+        let (width, height) = self.targets.canvas_panel.target.output_size()?;
 
-        let pixels = self.targets.canvas_panel.target.read_pixels(None, format).unwrap();
+        let pixels = self.targets.canvas_panel.target.read_pixels(None, format)?;
 
         let mut shot = Image::new(width, height);
 
@@ -56,14 +65,14 @@ impl Display {
             }
         }
 
-        shot.save(format!("{}.bmp", name)).unwrap();
+        shot.save(format!("{}.bmp", name)).map_err(|err| err.to_string())?;
+
+        return Ok(());
     }
 
     pub fn add_spritesheet(&mut self, name: String, texture: Texture) {
-        let sprite_key = self.state.next_sprite_key;
         let sprite_sheet = SpriteSheet::new(name, texture);
-        self.state.next_sprite_key += 1;
-        self.state.sprites.insert(sprite_key, sprite_sheet);
+        self.state.add_sprite_sheet(sprite_sheet);
     }
 
     pub fn sprite_exists(&self, name: &str) -> bool {
@@ -102,10 +111,12 @@ impl Display {
         return anim;
     }
 
-    pub fn play_idle_animation(&mut self, entity_id: EntityId, data: &mut GameData, config: &Config) {
+    pub fn play_idle_animation(&mut self, entity_id: EntityId, data: &mut GameData, config: &Config) -> Result<(), String> {
         if let Some(anim) = self.get_idle_animation(entity_id, data, config) {
-            self.state.play_animation(entity_id, anim);
+            self.state.play_animation(entity_id, anim)?;
         }
+
+        return Ok(());
     }
 
     pub fn get_idle_animation(&mut self, entity_id: EntityId, data: &mut GameData, config: &Config) -> Option<Animation> {
@@ -149,9 +160,9 @@ impl Display {
         self.state.sound_tiles.clear();
     }
 
-    pub fn process_message(&mut self, msg: Msg, data: &mut GameData, config: &Config) {
+    pub fn process_message(&mut self, msg: Msg, data: &mut GameData, config: &Config) -> Result<(), String> {
         match msg {
-            Msg::StartTurn => {
+            Msg::StartTurn(_turn) => {
                 self.state.sound_tiles.clear();
             }
 
@@ -176,9 +187,10 @@ impl Display {
                         let sound_effect = Effect::Sound(sound_aoe, 0.0);
                         self.state.play_effect(sound_effect);
 
+                        self.audio.play_sound_at(SOUND_PATH, source_pos, player_pos, radius);
+
                         let pos = data.entities.pos[&cause_id];
-                        // NOTE it is slightly odd to look up this sprite sheet here...
-                        let tiles = self.state.lookup_spritekey("tiles");
+                        let tiles = self.state.tiles_key();
                         let impression_sprite = Sprite::new(ENTITY_UNKNOWN as u32, tiles);
                         self.state.impressions.push(Impression::new(impression_sprite, pos));
                     }
@@ -199,17 +211,24 @@ impl Display {
                 let item_anim = Animation::PlayEffect(Effect::Sound(sound_aoe, 0.0));
                 let loop_anim = Animation::Loop(item_sprite);
 
-                self.state.play_animation(item_id, move_anim);
-                self.state.append_animation(item_id, item_anim);
-                self.state.append_animation(item_id, loop_anim);
+                self.state.play_animation(item_id, move_anim)?;
+                self.state.append_animation(item_id, item_anim)?;
+                self.state.append_animation(item_id, loop_anim)?;
+            }
+
+            Msg::Collided(entity_id, blocked_pos) => {
+                if let Some(idle_anim) = self.get_idle_animation(entity_id, data, config) {
+                    let pos = data.entities.pos[&entity_id];
+                    self.state.play_bump(entity_id, idle_anim, pos, blocked_pos, config.bump_speed)?;
+                }
             }
 
             Msg::PickedUp(entity_id, _item_id) => {
-                self.play_idle_animation(entity_id, data, config);
+                self.play_idle_animation(entity_id, data, config)?;
             }
 
             Msg::Facing(entity_id, _pos) => {
-                self.play_idle_animation(entity_id, data, config);
+                self.play_idle_animation(entity_id, data, config)?;
             }
 
             Msg::Killed(_attacker, attacked, _damage) => {
@@ -219,7 +238,7 @@ impl Display {
                     let sprite_name = format!("{:?}_death", data.entities.name[&attacked]);
                     if self.sprite_exists(&sprite_name) {
                         let sprite = self.new_sprite(sprite_name, 1.0);
-                        self.state.play_animation(attacked, Animation::Once(sprite));
+                        self.state.play_animation(attacked, Animation::Once(sprite))?;
                     }
                 }
             }
@@ -229,10 +248,10 @@ impl Display {
                     let attack_sprite =
                         self.new_sprite("player_attack_hammer".to_string(), config.player_attack_hammer_speed);
                     let attack_anim = Animation::Once(attack_sprite);
-                    self.state.play_animation(entity_id, attack_anim);
+                    self.state.play_animation(entity_id, attack_anim)?;
 
                     if let Some(idle_anim) = self.get_idle_animation(entity_id, data, config) {
-                        self.state.append_animation(entity_id, idle_anim);
+                        self.state.append_animation(entity_id, idle_anim)?;
                     }
                 }
             }
@@ -242,10 +261,10 @@ impl Display {
                     let attack_sprite =
                         self.new_sprite("player_attack_dagger".to_string(), config.player_attack_speed);
                     let attack_anim = Animation::Once(attack_sprite);
-                    self.state.play_animation(entity_id, attack_anim);
+                    self.state.play_animation(entity_id, attack_anim)?;
 
                     if let Some(idle_anim) = self.get_idle_animation(entity_id, data, config) {
-                        self.state.append_animation(entity_id, idle_anim);
+                        self.state.append_animation(entity_id, idle_anim)?;
                     }
                 }
             }
@@ -253,7 +272,7 @@ impl Display {
             Msg::SwordSwing(entity_id, _item_id, _pos) => {
                 if data.entities.typ[&entity_id] == EntityType::Player {
                     if let Some(idle_anim) = self.get_idle_animation(entity_id, data, config) {
-                        self.state.play_animation(entity_id, idle_anim);
+                        self.state.play_animation(entity_id, idle_anim)?;
                     }
                 }
             }
@@ -263,10 +282,10 @@ impl Display {
                     let attack_sprite =
                         self.new_sprite("player_attack".to_string(), config.player_attack_speed);
                     let attack_anim = Animation::Once(attack_sprite);
-                    self.state.play_animation(attacker, attack_anim);
+                    self.state.play_animation(attacker, attack_anim)?;
 
                     if let Some(idle_anim) = self.get_idle_animation(attacker, data, config) {
-                        self.state.play_animation(attacker, idle_anim);
+                        self.state.play_animation(attacker, idle_anim)?;
                     }
                 } else {
                     let attacker_pos = data.entities.pos[&attacker];
@@ -282,7 +301,7 @@ impl Display {
 
             Msg::SpawnedObject(entity_id, _typ, _pos, _name, _facing) => {
                 if data.entities.ids.contains(&entity_id) {
-                    self.play_idle_animation(entity_id, data, config);
+                    self.play_idle_animation(entity_id, data, config)?;
                 }
             }
 
@@ -314,11 +333,10 @@ impl Display {
 
                 /* Remove impressions that are currently visible */
                 let mut impressions_visible = Vec::new();
+                let sight_radius = data.sight_radius(player_id, config);
                 for (index, impression) in self.state.impressions.iter().enumerate() {
-                    data.entities.status[&player_id].extra_fov += 1;
                     let is_in_fov_ext = 
-                       data.pos_in_fov(player_id, impression.pos, &config);
-                    data.entities.status[&player_id].extra_fov -= 1;
+                       data.pos_in_fov_radius(player_id, impression.pos, sight_radius + 1, config);
 
                     if is_in_fov_ext {
                         impressions_visible.push(index);
@@ -338,6 +356,8 @@ impl Display {
             _ => {
             }
         }
+
+        return Ok(());
     }
 }
 
@@ -510,10 +530,9 @@ impl Panel<Texture> {
     pub fn from_dims(texture_creator: &TextureCreator<WindowContext>, width: u32, height: u32, over_sample: u32) -> Panel<Texture> {
         let pixel_format = texture_creator.default_pixel_format();
 
+        let (pixel_width, pixel_height) = texture_dims((width, height), over_sample);
         let tex =
-            texture_creator.create_texture_target(pixel_format,
-                                                  width as u32 * FONT_WIDTH as u32 * over_sample,
-                                                  height as u32 * FONT_HEIGHT as u32 * over_sample).unwrap();
+            texture_creator.create_texture_target(pixel_format, pixel_width, pixel_height).unwrap();
         let panel = Panel::with_texture((width as u32, height as u32), tex);
 
         return panel;
@@ -532,9 +551,9 @@ impl Panel<Texture> {
 }
 
 impl Panel<WindowCanvas> {
-    pub fn with_canvas(cells: (u32, u32), canvas: WindowCanvas) -> Panel<WindowCanvas> {
-        let (width, height) = canvas.output_size().unwrap();
-        return Panel { cells, target: canvas, num_pixels: (width, height), dirty: true };
+    pub fn with_canvas(cells: (u32, u32), canvas: WindowCanvas) -> Result<Panel<WindowCanvas>, String> {
+        let (width, height) = canvas.output_size()?;
+        return Ok(Panel { cells, target: canvas, num_pixels: (width, height), dirty: true });
     }
 }
 
@@ -543,6 +562,13 @@ impl<T> Panel<T> {
         return (self.num_pixels.0 / self.cells.0, self.num_pixels.1 / self.cells.1);
     }
 
+    /// Update the panel for a new size in pixels, keeping each cell the size of a font character.
+    pub fn resize(&mut self, num_pixels: (u32, u32)) {
+        self.num_pixels = num_pixels;
+        self.cells = screen_cells(num_pixels);
+        self.dirty = true;
+    }
+
     pub fn with_target<S>(&self, target: S) -> Panel<S> {
         return Panel {
             target,
@@ -618,6 +644,102 @@ impl<T> Panel<T> {
 }
 
 
+/// The number of font-sized cells that fit in a screen of the given size in pixels.
+pub fn screen_cells(num_pixels: (u32, u32)) -> (u32, u32) {
+    return (num_pixels.0 / FONT_WIDTH as u32, num_pixels.1 / FONT_HEIGHT as u32);
+}
+
+/// The size in pixels of a panel's texture with the given cells, with each cell
+/// drawn over_sample times larger than a font character.
+pub fn texture_dims(cells: (u32, u32), over_sample: u32) -> (u32, u32) {
+    return (cells.0 * FONT_WIDTH as u32 * over_sample, cells.1 * FONT_HEIGHT as u32 * over_sample);
+}
+
+/// The over sample factor a panel's texture was created with.
+pub fn texture_over_sample(cells: (u32, u32), num_pixels: (u32, u32)) -> u32 {
+    return std::cmp::max(1, num_pixels.0 / (cells.0 * FONT_WIDTH as u32));
+}
+
+/// The smallest screen, in cells, that fits the info panels beside a map area
+/// large enough to hold the menu.
+pub fn min_screen_cells(info_panel: &Panel<()>, menu_panel: &Panel<()>) -> (u32, u32) {
+    let width = info_panel.cells.0 + menu_panel.cells.0;
+    let height = std::cmp::max(PLAYER_AREA_HEIGHT + INVENTORY_AREA_HEIGHT, menu_panel.cells.1);
+    return (width, height);
+}
+
+/// Screen cells for a canvas of the given size, never smaller than the layout
+/// needs. A smaller window draws the cells smaller rather than splitting
+/// areas that do not fit.
+fn clamp_screen_cells(num_pixels: (u32, u32), min_cells: (u32, u32)) -> (u32, u32) {
+    let cells = screen_cells(num_pixels);
+    return (std::cmp::max(cells.0, min_cells.0), std::cmp::max(cells.1, min_cells.1));
+}
+
+/// Split the screen into the map, player, inventory, and info areas, with the menu
+/// centered on the map. Returns (screen, map, player, remaining, inventory, info, menu).
+fn layout_areas(canvas_panel: &Panel<()>,
+                info_panel: &Panel<()>,
+                menu_panel: &Panel<()>) -> (Area, Area, Area, Area, Area, Area, Area) {
+    let screen_area = canvas_panel.area();
+    let (map_area, rest_area) = screen_area.split_right(info_panel.cells.0 as usize);
+    let (player_area, remaining_area) = rest_area.split_top(PLAYER_AREA_HEIGHT as usize);
+    let (inventory_area, info_area) = remaining_area.split_top(INVENTORY_AREA_HEIGHT as usize);
+
+    let menu_area = menu_panel.area();
+    let menu_area = map_area.centered(menu_area.width, menu_area.height);
+
+    return (screen_area, map_area, player_area, remaining_area, inventory_area, info_area, menu_area);
+}
+
+#[test]
+pub fn test_resize_target_dims() {
+    // the window size from a resize event determines the screen cells
+    assert_eq!((80, 45), screen_cells((1280, 720)));
+    assert_eq!((120, 67), screen_cells((1920, 1080)));
+
+    // recreated textures keep the same size as the originals
+    for (cells, over_sample) in vec!(((MAP_WIDTH as u32, MAP_HEIGHT as u32), 5), ((14, 15), 1), ((19, 20), 1)) {
+        let num_pixels = texture_dims(cells, over_sample);
+        assert_eq!(over_sample, texture_over_sample(cells, num_pixels));
+        assert_eq!(num_pixels, texture_dims(cells, texture_over_sample(cells, num_pixels)));
+    }
+}
+
+#[test]
+pub fn test_panel_resize() {
+    let mut panel = Panel { target: (), cells: (0, 0), num_pixels: (0, 0), dirty: false };
+
+    panel.resize((1120, 800));
+    assert_eq!((70, 50), panel.cells);
+    assert_eq!((FONT_WIDTH as u32, FONT_HEIGHT as u32), panel.cell_dims());
+    assert!(panel.dirty);
+
+    panel.resize((1600, 960));
+    assert_eq!((100, 60), panel.cells);
+    assert_eq!((FONT_WIDTH as u32, FONT_HEIGHT as u32), panel.cell_dims());
+
+    // the map takes the screen width not used by the info panels
+    let info_panel = Panel { target: (), cells: (14, 15), num_pixels: (14, 15), dirty: false };
+    let menu_panel = Panel { target: (), cells: (19, 20), num_pixels: (19, 20), dirty: false };
+    let (screen_area, map_area, _, _, _, _, menu_area) = layout_areas(&panel, &info_panel, &menu_panel);
+    assert_eq!(100, screen_area.width);
+    assert_eq!(86, map_area.width);
+    assert_eq!(60, map_area.height);
+    assert_eq!(19, menu_area.width);
+
+    // a window smaller than the layout keeps the minimum number of cells
+    let min_cells = min_screen_cells(&info_panel, &menu_panel);
+    assert_eq!((33, 35), min_cells);
+    panel.resize((100, 100));
+    panel.cells = clamp_screen_cells(panel.num_pixels, min_cells);
+    assert_eq!(min_cells, panel.cells);
+    let (screen_area, map_area, _, _, _, _, menu_area) = layout_areas(&panel, &info_panel, &menu_panel);
+    assert_eq!(33, screen_area.width);
+    assert_eq!(19, map_area.width);
+    assert_eq!(19, menu_area.width);
+}
+
 pub struct DisplayTargets {
     pub canvas_panel: Panel<WindowCanvas>,
 
@@ -640,7 +762,7 @@ pub struct DisplayTargets {
 }
 
 impl DisplayTargets {
-    pub fn new(canvas: WindowCanvas) -> DisplayTargets {
+    pub fn new(mut canvas: WindowCanvas) -> Result<DisplayTargets, String> {
 
         let texture_creator = canvas.texture_creator();
 
@@ -652,20 +774,22 @@ impl DisplayTargets {
         let info_width = 14;
 
         let info_panel = Panel::from_dims(&texture_creator, info_width, 15, 1);
-        let inventory_panel = Panel::from_dims(&texture_creator, info_width, 15, 1);
-        let player_panel = Panel::from_dims(&texture_creator, info_width, 20, 1);
+        let inventory_panel = Panel::from_dims(&texture_creator, info_width, INVENTORY_AREA_HEIGHT, 1);
+        let player_panel = Panel::from_dims(&texture_creator, info_width, PLAYER_AREA_HEIGHT, 1);
         let menu_panel = Panel::from_dims(&texture_creator, info_width + 5, 20, 1);
-        let canvas_panel = Panel::with_canvas((SCREEN_WIDTH / FONT_WIDTH as u32, SCREEN_HEIGHT / FONT_HEIGHT as u32), canvas);
+        // keep the window large enough for the panels to fit beside the map
+        let min_cells = min_screen_cells(&info_panel.unit(), &menu_panel.unit());
+        canvas.window_mut()
+              .set_minimum_size(min_cells.0 * FONT_WIDTH as u32, min_cells.1 * FONT_HEIGHT as u32)
+              .map_err(|err| err.to_string())?;
 
-        let screen_area = canvas_panel.area();
-        let (map_area, rest_area) = screen_area.split_right(info_panel.cells.0 as usize);
-        let (player_area, remaining_area) = rest_area.split_top(20);
-        let (inventory_area, info_area) = remaining_area.split_top(15);
+        let canvas_size = canvas.output_size()?;
+        let canvas_panel = Panel::with_canvas(clamp_screen_cells(canvas_size, min_cells), canvas)?;
 
-        let menu_area = menu_panel.area();
-        let menu_area = map_area.centered(menu_area.width, menu_area.height);
+        let (screen_area, map_area, player_area, remaining_area, inventory_area, info_area, menu_area) =
+            layout_areas(&canvas_panel.with_target(()), &info_panel.unit(), &menu_panel.unit());
 
-        return DisplayTargets {
+        return Ok(DisplayTargets {
             canvas_panel,
             texture_creator,
             background_panel,
@@ -682,7 +806,47 @@ impl DisplayTargets {
             inventory_area,
             info_area,
             menu_area,
-        };
+        });
+    }
+
+    /// Recompute the screen layout after the window changes size, and rebuild
+    /// the offscreen textures, as some renderers lose their contents on resize.
+    pub fn resize(&mut self, width: u32, height: u32) {
+        // the renderer's output size accounts for high-DPI displays
+        let num_pixels = self.canvas_panel.target.output_size().unwrap_or((width, height));
+        self.canvas_panel.resize(num_pixels);
+
+        let min_cells = min_screen_cells(&self.info_panel.unit(), &self.menu_panel.unit());
+        self.canvas_panel.cells = clamp_screen_cells(num_pixels, min_cells);
+
+        let (screen_area, map_area, player_area, remaining_area, inventory_area, info_area, menu_area) =
+            layout_areas(&self.canvas_panel.with_target(()), &self.info_panel.unit(), &self.menu_panel.unit());
+        self.screen_area = screen_area;
+        self.map_area = map_area;
+        self.player_area = player_area;
+        self.remaining_area = remaining_area;
+        self.inventory_area = inventory_area;
+        self.info_area = info_area;
+        self.menu_area = menu_area;
+
+        self.recreate_textures();
+    }
+
+    fn recreate_textures(&mut self) {
+        fn recreate(texture_creator: &TextureCreator<WindowContext>, panel: &Panel<Texture>) -> Panel<Texture> {
+            let over_sample = texture_over_sample(panel.cells, panel.num_pixels);
+            return Panel::from_dims(texture_creator, panel.cells.0, panel.cells.1, over_sample);
+        }
+
+        self.background_panel = recreate(&self.texture_creator, &self.background_panel);
+        self.map_panel = recreate(&self.texture_creator, &self.map_panel);
+        self.player_panel = recreate(&self.texture_creator, &self.player_panel);
+        self.info_panel = recreate(&self.texture_creator, &self.info_panel);
+        self.inventory_panel = recreate(&self.texture_creator, &self.inventory_panel);
+        self.menu_panel = recreate(&self.texture_creator, &self.menu_panel);
+
+        // the background is only drawn when dirty, so it must be redrawn into the new texture
+        self.background_panel.dirty = true;
     }
 
     pub fn mouse_pos(&self, x: i32, y: i32, map_width: i32, map_height: i32) -> Option<(i32, i32)> {
@@ -696,6 +860,9 @@ pub struct DisplayState {
     pub sprites: IndexMap<SpriteKey, SpriteSheet>,
     pub next_sprite_key: i64,
 
+    // the "tiles" sprite sheet is used by most render functions, so its key is kept
+    pub tiles_key: Option<SpriteKey>,
+
     // currently active effects
     pub effects: Vec<Effect>,
 
@@ -715,6 +882,15 @@ pub struct DisplayState {
 
     // tiles that heard a sound
     pub sound_tiles: Vec<Pos>,
+
+    // average frame rate, shown when config.draw_fps is set
+    pub fps: f32,
+
+    // map tiles as of the last time the static map layer was drawn
+    pub static_tiles: Vec<Vec<Tile>>,
+
+    // noise used to vary tile colors, along with the seed it was made from
+    tile_noise: Option<(u64, Perlin)>,
 }
 
 impl DisplayState {
@@ -723,6 +899,7 @@ impl DisplayState {
         return DisplayState {
             sprites: IndexMap::new(),
             next_sprite_key: 0,
+            tiles_key: None,
             effects: Vec::new(),
             animations: IndexMap::<EntityId, VecDeque<Animation>>::new(),
             next_anim_key: 0,
@@ -731,9 +908,68 @@ impl DisplayState {
             prev_turn_fov: Vec::new(),
             current_turn_fov: Vec::new(),
             sound_tiles: Vec::new(),
+            fps: 0.0,
+            static_tiles: Vec::new(),
+            tile_noise: None,
         };
     }
 
+    /// The noise used for tile colors. It is created once, and only made again
+    /// if the configured seed changes.
+    pub fn tile_noise(&mut self, config: &Config) -> &Perlin {
+        let seed = config.tile_noise_seed;
+        if self.tile_noise.as_ref().map_or(true, |(noise_seed, _)| *noise_seed != seed) {
+            self.tile_noise = Some((seed, Perlin::with_seed(seed)));
+        }
+
+        return &self.tile_noise.as_ref().unwrap().1;
+    }
+
+    pub fn add_sprite_sheet(&mut self, sprite_sheet: SpriteSheet) -> SpriteKey {
+        let sprite_key = self.next_sprite_key;
+        self.next_sprite_key += 1;
+
+        if sprite_sheet.name == "tiles" {
+            self.tiles_key = Some(sprite_key);
+        }
+
+        self.sprites.insert(sprite_key, sprite_sheet);
+
+        return sprite_key;
+    }
+
+    /// The key of the "tiles" sprite sheet, cached when it was loaded.
+    pub fn tiles_key(&self) -> SpriteKey {
+        return self.tiles_key.expect("The tiles sprite sheet was not loaded!");
+    }
+
+    pub fn tiles_sprite_mut(&mut self) -> &mut SpriteSheet {
+        let tiles_key = self.tiles_key();
+        return &mut self.sprites[&tiles_key];
+    }
+
+    /// Whether the static map layer needs to be drawn again, either because its
+    /// panel was marked dirty or because the map's tiles changed since the last
+    /// draw. Exploring tiles does not change how they are drawn, so it is ignored.
+    pub fn static_layer_dirty(&mut self, panel_dirty: bool, map: &Map) -> bool {
+        let same_tiles =
+            self.static_tiles.len() == map.tiles.len() &&
+            self.static_tiles.iter().zip(map.tiles.iter()).all(|(static_column, column)| {
+                static_column.len() == column.len() &&
+                static_column.iter().zip(column.iter()).all(|(static_tile, tile)| {
+                    let mut tile = *tile;
+                    tile.explored = static_tile.explored;
+                    return *static_tile == tile;
+                })
+            });
+
+        if !same_tiles {
+            self.static_tiles = map.tiles.clone();
+        }
+
+        return panel_dirty || !same_tiles;
+    }
+
     pub fn lookup_spritekey(&self, name: &str) -> SpriteKey {
         for (key, sprite_sheet) in self.sprites.iter() {
             if sprite_sheet.name == *name {
@@ -767,7 +1003,12 @@ impl DisplayState {
         self.effects.push(effect);
     }
 
-    pub fn play_animation(&mut self, entity_id: EntityId, animation: Animation) {
+    /// Replace an entity's animations with the given animation. An animation
+    /// which refers to a sprite sheet that is not loaded is rejected, leaving the
+    /// entity's current animations in place.
+    pub fn play_animation(&mut self, entity_id: EntityId, animation: Animation) -> Result<(), String> {
+        animation.is_valid(&self.sprites)?;
+
         // ensure that the entity has an animation vec. This is called on entity
         // spawn, so it is not necessary in the other animation functions.
         if self.animations.get(&entity_id).is_none() {
@@ -775,20 +1016,211 @@ impl DisplayState {
         }
 
         self.clear_animations(entity_id);
-        self.append_animation(entity_id, animation);
+        return self.append_animation(entity_id, animation);
     }
 
-    pub fn append_animation(&mut self, entity_id: EntityId, animation: Animation) {
-        self.animations[&entity_id].push_back(animation);
+    /// Add an animation to the end of an entity's animation sequence. The entity
+    /// must already have a sequence started with play_animation.
+    pub fn append_animation(&mut self, entity_id: EntityId, animation: Animation) -> Result<(), String> {
+        animation.is_valid(&self.sprites)?;
+
+        match self.animations.get_mut(&entity_id) {
+            Some(anims) => {
+                anims.push_back(animation);
+                return Ok(());
+            }
+
+            None => {
+                return Err(format!("Entity {} has no animation sequence to append to", entity_id));
+            }
+        }
+    }
+
+    /// Play a bump, where the entity lurches into the blocked tile and back,
+    /// and then return to the given idle animation.
+    pub fn play_bump(&mut self,
+                     entity_id: EntityId,
+                     idle_anim: Animation,
+                     pos: Pos,
+                     blocked_pos: Pos,
+                     blocks_per_sec: f32) -> Result<(), String> {
+        let sprite_anim = match idle_anim {
+            Animation::Loop(sprite_anim) => sprite_anim,
+            _ => return self.play_animation(entity_id, idle_anim),
+        };
+
+        self.play_animation(entity_id, Animation::Between(sprite_anim, pos, blocked_pos, 0.0, blocks_per_sec))?;
+        self.append_animation(entity_id, Animation::Between(sprite_anim, blocked_pos, pos, 0.0, blocks_per_sec))?;
+        return self.append_animation(entity_id, idle_anim);
     }
 
     pub fn clear_animations(&mut self, entity_id: EntityId) {
-        self.animations[&entity_id].clear();
+        if let Some(anims) = self.animations.get_mut(&entity_id) {
+            anims.clear();
+        }
     }
 
     pub fn pop_animation(&mut self, entity_id: EntityId) {
         self.animations[&entity_id].pop_front();
     }
+
+    /// Remove animation sequences which are finished, or whose entity no longer
+    /// exists, so the animation map does not grow without bound.
+    pub fn sweep_animations(&mut self, data: &GameData) {
+        self.animations.retain(|entity_id, anims| {
+            return !anims.is_empty() && data.entities.ids.contains(entity_id);
+        });
+    }
+}
+
+#[test]
+pub fn test_sweep_animations() {
+    let mut data = GameData::empty(10, 10);
+    let mut display_state = DisplayState::new();
+
+    let num_stones = 5;
+    let mut stones = Vec::new();
+    for index in 0..num_stones {
+        let stone = data.entities.create_entity(index, 0, EntityType::Item, ENTITY_STONE as char, Color::white(), EntityName::Stone, true);
+        stones.push(stone);
+
+        let sprite = SpriteAnim::new("stone".to_string(), 0, 0.0, 1.0, 1.0);
+        let anims = vec!(Animation::Between(sprite, Pos::new(0, 0), Pos::new(index, 0), 0.0, 1.0),
+                         Animation::Once(sprite));
+        display_state.animations.insert(stone, anims.into_iter().collect());
+    }
+    assert_eq!(num_stones as usize, display_state.animations.len());
+
+    // unfinished animations are kept
+    display_state.sweep_animations(&data);
+    assert_eq!(num_stones as usize, display_state.animations.len());
+
+    // finish each stone's animations
+    for stone in stones.iter() {
+        display_state.pop_animation(*stone);
+        display_state.pop_animation(*stone);
+    }
+    display_state.sweep_animations(&data);
+    assert_eq!(0, display_state.animations.len());
+
+    // animations for removed entities are also cleaned up
+    let sprite = SpriteAnim::new("stone".to_string(), 0, 0.0, 1.0, 1.0);
+    display_state.animations.insert(stones[0], vec!(Animation::Loop(sprite)).into_iter().collect());
+    data.entities.remove_entity(stones[0]);
+    display_state.sweep_animations(&data);
+    assert_eq!(0, display_state.animations.len());
+}
+
+#[test]
+pub fn test_play_animation_missing_sprite() {
+    let mut display_state = DisplayState::new();
+    let entity_id = EntityId(0);
+
+    // no sprite sheets are loaded, so the animation is rejected without panicking
+    let sprite = SpriteAnim::new("stone".to_string(), 0, 0.0, 1.0, 1.0);
+    assert!(display_state.play_animation(entity_id, Animation::Loop(sprite)).is_err());
+    assert!(display_state.animations.get(&entity_id).is_none());
+
+    // appending requires an existing animation sequence
+    let effect = Animation::PlayEffect(Effect::Beam(1, Pos::new(0, 0), Pos::new(1, 0)));
+    assert!(display_state.append_animation(entity_id, effect.clone()).is_err());
+
+    // effects do not use a sprite sheet, so they can always be played
+    assert!(display_state.play_animation(entity_id, effect).is_ok());
+    assert_eq!(1, display_state.animations[&entity_id].len());
+}
+
+#[test]
+pub fn test_collide_plays_bump() {
+    use roguelike_core::movement::MoveMode;
+    use roguelike_engine::game::Game;
+    use roguelike_engine::actions::InputAction;
+    use roguelike_engine::make_map::make_map;
+
+    let config = Config::from_file("../config.yaml");
+    let mut game = Game::new(0, config.clone());
+    make_map(&MapLoadConfig::Empty, &mut game);
+
+    let player_id = game.data.find_by_name(EntityName::Player).unwrap();
+    let player_pos = Pos::new(1, 1);
+    let wall_pos = Pos::new(2, 1);
+    game.data.move_entity(player_id, player_pos);
+    game.data.map[wall_pos] = Tile::wall();
+
+    game.step_game(InputAction::Move(Direction::Right, MoveMode::Walk), 0.1);
+    assert_eq!(player_pos, game.data.entities.pos[&player_id]);
+
+    // the display needs a window canvas, so run SDL without a real video device
+    std::env::set_var("SDL_VIDEODRIVER", "dummy");
+    let sdl_context = sdl2::init().unwrap();
+    let video = sdl_context.video().unwrap();
+    let window = video.window("test", config.screen_width, config.screen_height).hidden().build().unwrap();
+    let canvas = window.into_canvas().software().build().unwrap();
+    let texture_creator = canvas.texture_creator();
+
+    let audio = AudioSystem::new(std::time::Duration::from_millis(config.sound_cooldown_ms));
+    let mut display = Display::new(canvas, audio).unwrap();
+
+    let direction = game.data.entities.direction[&player_id];
+    let sheet_name = format!("{}_{}_{}", EntityName::Player, Stance::Standing, sheet_direction(direction));
+    let texture = texture_creator.create_texture_static(PixelFormatEnum::RGBA8888, 32, 32).unwrap();
+    display.add_spritesheet(sheet_name, texture);
+
+    let collided = Msg::Collided(player_id, wall_pos);
+    assert!(game.msg_log.turn_messages.contains(&collided));
+    display.process_message(collided, &mut game.data, &config).unwrap();
+
+    // the player moves into the wall and back, then returns to idling
+    let idle_anim = display.get_idle_animation(player_id, &mut game.data, &config).unwrap();
+    let sprite = match &idle_anim {
+        Animation::Loop(sprite) => *sprite,
+        _ => panic!("expected the player to idle in a loop"),
+    };
+
+    let anims = &display.state.animations[&player_id];
+    assert_eq!(3, anims.len());
+    assert_eq!(Animation::Between(sprite, player_pos, wall_pos, 0.0, config.bump_speed), anims[0]);
+    assert_eq!(Animation::Between(sprite, wall_pos, player_pos, 0.0, config.bump_speed), anims[1]);
+    assert_eq!(idle_anim, anims[2]);
+}
+
+#[test]
+pub fn test_tiles_key_cached() {
+    use sdl2::surface::Surface;
+
+    let canvas = Surface::new(32, 32, PixelFormatEnum::RGBA8888).unwrap().into_canvas().unwrap();
+    let texture_creator = canvas.texture_creator();
+
+    let mut display_state = DisplayState::new();
+    for name in ["stone", "tiles", "rubble"].iter() {
+        let texture = texture_creator.create_texture_static(PixelFormatEnum::RGBA8888, 32, 32).unwrap();
+        display_state.add_sprite_sheet(SpriteSheet::new(name.to_string(), texture));
+    }
+
+    assert_eq!(display_state.lookup_spritekey("tiles"), display_state.tiles_key());
+    assert_eq!("tiles", display_state.tiles_sprite_mut().name);
+}
+
+#[test]
+pub fn test_static_layer_dirty() {
+    let mut display_state = DisplayState::new();
+    let mut map = Map::from_dims(5, 5);
+
+    // the first draw always happens, and afterwards only when something changed
+    assert!(display_state.static_layer_dirty(false, &map));
+    assert!(!display_state.static_layer_dirty(false, &map));
+    assert!(display_state.static_layer_dirty(true, &map));
+
+    // exploring a tile does not change how it is drawn
+    map[(1, 1)].explored = true;
+    assert!(!display_state.static_layer_dirty(false, &map));
+
+    map[(1, 1)] = Tile::wall();
+    assert!(display_state.static_layer_dirty(false, &map));
+    assert!(!display_state.static_layer_dirty(false, &map));
+
+    map = Map::from_dims(6, 5);
+    assert!(display_state.static_layer_dirty(false, &map));
 }
 
 
@@ -1085,6 +1517,24 @@ pub fn cell_within_rect(rect: Rect, area_cell_dims: (i32, i32), pixel_pos: (i32,
     return None;
 }
 
+#[test]
+pub fn test_cell_within_rect() {
+    // a 10x5 cell map drawn at (100, 50) with 8x8 pixel cells
+    let rect = Rect::new(100, 50, 80, 40);
+    let dims = (10, 5);
+
+    assert_eq!(Some((0, 0)), cell_within_rect(rect, dims, (100, 50)));
+    assert_eq!(Some((0, 0)), cell_within_rect(rect, dims, (107, 57)));
+    assert_eq!(Some((1, 0)), cell_within_rect(rect, dims, (108, 50)));
+    assert_eq!(Some((3, 2)), cell_within_rect(rect, dims, (127, 69)));
+    assert_eq!(Some((9, 4)), cell_within_rect(rect, dims, (179, 89)));
+
+    // outside of the map area
+    assert_eq!(None, cell_within_rect(rect, dims, (99, 50)));
+    assert_eq!(None, cell_within_rect(rect, dims, (180, 50)));
+    assert_eq!(None, cell_within_rect(rect, dims, (100, 90)));
+}
+
 fn sheet_direction(direction: Direction) -> Direction {
     match direction {
         Direction::Up => return Direction::Up,
@@ -0,0 +1,56 @@
+use std::fmt;
+use std::error::Error;
+use std::io;
+
+
+/// Errors that can occur while setting up and running the game.
+#[derive(Debug)]
+pub enum EngineError {
+    Sdl(String),
+    TextureLoad { path: String, msg: String },
+    Config(String),
+    Io(io::Error),
+    Other(String),
+}
+
+impl fmt::Display for EngineError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EngineError::Sdl(msg) => write!(f, "SDL error: {}", msg),
+            EngineError::TextureLoad { path, msg } => write!(f, "Could not load texture '{}': {}", path, msg),
+            EngineError::Config(msg) => write!(f, "Configuration error: {}", msg),
+            EngineError::Io(err) => write!(f, "IO error: {}", err),
+            EngineError::Other(msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
+impl Error for EngineError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            EngineError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+/// Errors reported as strings by the game itself. SDL errors are also strings,
+/// so SDL calls map their errors to EngineError::Sdl explicitly.
+impl From<String> for EngineError {
+    fn from(msg: String) -> EngineError {
+        return EngineError::Other(msg);
+    }
+}
+
+impl From<io::Error> for EngineError {
+    fn from(err: io::Error) -> EngineError {
+        return EngineError::Io(err);
+    }
+}
+
+#[test]
+fn test_string_error_is_not_sdl() {
+    let err = EngineError::from("no vaults found".to_string());
+    assert!(matches!(err, EngineError::Other(_)));
+    assert_eq!("no vaults found", err.to_string());
+}
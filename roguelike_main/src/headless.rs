@@ -0,0 +1,69 @@
+use roguelike_core::types::*;
+#[cfg(test)]
+use roguelike_core::config::Config;
+#[cfg(test)]
+use roguelike_core::map::MapLoadConfig;
+#[cfg(test)]
+use roguelike_core::movement::{Direction, MoveMode};
+
+use roguelike_engine::game::*;
+use roguelike_engine::actions::*;
+#[cfg(test)]
+use roguelike_engine::make_map::make_map;
+
+
+/// Run the game without a display, stepping through the given actions in order.
+/// Each turn's messages are printed to stdout. The simulation stops early if
+/// the game ends, and the last GameResult is returned.
+pub fn run_headless(game: &mut Game, actions: &[InputAction], print_messages: bool) -> GameResult {
+    let mut result = GameResult::Continue;
+
+    for action in actions.iter() {
+        result = game.step_game(*action, 0.1);
+
+        if print_messages {
+            for msg in game.msg_log.turn_messages.iter() {
+                let msg_line = msg.msg_line(&game.data);
+                if msg_line.len() > 0 {
+                    println!("{}", msg_line);
+                }
+            }
+        }
+
+        game.msg_log.clear();
+
+        if result == GameResult::Stop || result == GameResult::Lose {
+            break;
+        }
+    }
+
+    return result;
+}
+
+#[test]
+pub fn test_run_headless() {
+    let mut config = Config::from_file("../config.yaml");
+    config.map_load = MapLoadConfig::Empty;
+    let mut game = Game::new(0, config.clone());
+    make_map(&config.map_load, &mut game);
+
+    let player_id = game.data.find_by_name(EntityName::Player).unwrap();
+    game.data.entities.set_pos(player_id, Pos::new(0, 0));
+
+    let actions = vec!(InputAction::Move(Direction::Right, MoveMode::Walk),
+                       InputAction::Move(Direction::Right, MoveMode::Walk),
+                       InputAction::Pass(MoveMode::Walk),
+                       InputAction::Move(Direction::Down, MoveMode::Walk));
+
+    let result = run_headless(&mut game, &actions[0..2], false);
+    assert_eq!(GameResult::Continue, result);
+    assert_eq!(Pos::new(2, 0), game.data.entities.pos[&player_id]);
+    assert_eq!(2, game.settings.turn_count);
+
+    // passing takes a turn without moving
+    let result = run_headless(&mut game, &actions[2..], false);
+    assert_eq!(GameResult::Continue, result);
+    assert_eq!(Pos::new(2, 1), game.data.entities.pos[&player_id]);
+    assert_eq!(4, game.settings.turn_count);
+    assert!(game.data.entities.status[&player_id].alive);
+}
@@ -1,4 +1,4 @@
-use sdl2::event::Event;
+use sdl2::event::{Event, WindowEvent};
 use sdl2::mouse::MouseButton;
 use sdl2::keyboard::Keycode;
 
@@ -67,6 +67,12 @@ pub fn translate_event(event: Event, game: &mut Game, display: &mut Display) ->
             return None;
         }
 
+        Event::Window {win_event: WindowEvent::Resized(width, height), ..} |
+        Event::Window {win_event: WindowEvent::SizeChanged(width, height), ..} => {
+            display.targets.resize(width as u32, height as u32);
+            return None;
+        }
+
         Event::MouseMotion {x, y, ..} => {
             display.mouse_state.x = x;
             display.mouse_state.y = y;
@@ -1,4 +1,5 @@
 use std::fs;
+use std::path::Path;
 
 use sdl2::image::LoadTexture;
 use sdl2::render::{WindowCanvas, Texture, TextureCreator};
@@ -8,38 +9,123 @@ use sdl2::ttf::Sdl2TtfContext;
 use roguelike_core::constants::*;
 
 use crate::display::*;
+use crate::error::EngineError;
 
 
 /// load all png images in the resources/animations directory as sprites.
 /// This assumes that all sprites have the same width and height. Otherwise we
 /// will need a configuration file of some kind to specify the dimensions of the sprites.
-pub fn load_sprites(texture_creator: &TextureCreator<WindowContext>, display: &mut Display) {
-    load_sprites_from("resources/animations".to_string(), texture_creator, display);
+pub fn load_sprites(texture_creator: &TextureCreator<WindowContext>, display: &mut Display) -> Result<(), EngineError> {
+    return load_sprites_from("resources/animations".to_string(), texture_creator, display);
 }
 
-pub fn load_sprites_from(path: String, texture_creator: &TextureCreator<WindowContext>, display: &mut Display) {
-    for entry in fs::read_dir(path).unwrap() {
-        let path = entry.unwrap().path();
+pub fn load_sprites_from(path: String, texture_creator: &TextureCreator<WindowContext>, display: &mut Display) -> Result<(), EngineError> {
+    for entry in fs::read_dir(path)? {
+        let path = entry?.path();
 
         let file_name = path.as_path().to_str().unwrap();
         let sprite_name = path.as_path().file_stem().unwrap().to_str().unwrap();
         if let Ok(metadata) = path.metadata() {
             if metadata.is_file() && file_name.ends_with("png") {
-                load_sprite(texture_creator, display, file_name, &sprite_name);
+                load_sprite(texture_creator, display, file_name, &sprite_name)?;
             } else if metadata.is_dir() {
                 // NOTE likely unnecessary String
-                load_sprites_from(path.to_str().unwrap().to_string(), texture_creator, display);
+                load_sprites_from(path.to_str().unwrap().to_string(), texture_creator, display)?;
             }
         }
     }
+
+    return Ok(());
 }
 
 pub fn load_sprite(texture_creator: &TextureCreator<WindowContext>,
                    display: &mut Display,
                    path: &str,
-                   sprite_name: &str) {
-    let texture = texture_creator.load_texture(path).expect("Could not load texture!");
+                   sprite_name: &str) -> Result<(), EngineError> {
+    check_texture_path(path)?;
+
+    let texture =
+        texture_creator.load_texture(path)
+                       .map_err(|msg| EngineError::TextureLoad { path: path.to_string(), msg })?;
     display.add_spritesheet(sprite_name.to_string(), texture);
+
+    return Ok(());
+}
+
+/// A single sprite sheet listed in the sprite manifest.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SpriteEntry {
+    pub name: String,
+    pub path: String,
+    pub frames: Option<usize>,
+}
+
+/// Parse a sprite manifest. Each line gives a sprite name, the path to its
+/// image, and optionally the number of frames in the sheet. Blank lines and
+/// lines starting with '#' are ignored.
+pub fn parse_sprite_manifest(contents: &str) -> Result<Vec<SpriteEntry>, EngineError> {
+    let mut entries = Vec::new();
+
+    for (line_num, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let parts = line.split_whitespace().collect::<Vec<&str>>();
+        if parts.len() < 2 || parts.len() > 3 {
+            return Err(EngineError::Config(format!("sprite manifest line {}: expected 'name path [frames]', found '{}'", line_num + 1, line)));
+        }
+
+        let mut frames = None;
+        if parts.len() == 3 {
+            let num_frames =
+                parts[2].parse::<usize>()
+                        .map_err(|_| EngineError::Config(format!("sprite manifest line {}: invalid frame count '{}'", line_num + 1, parts[2])))?;
+            frames = Some(num_frames);
+        }
+
+        entries.push(SpriteEntry { name: parts[0].to_string(), path: parts[1].to_string(), frames });
+    }
+
+    return Ok(entries);
+}
+
+/// Load every sprite sheet listed in a manifest file.
+pub fn load_sprite_manifest(manifest_path: &str,
+                            texture_creator: &TextureCreator<WindowContext>,
+                            display: &mut Display) -> Result<(), EngineError> {
+    let contents = fs::read_to_string(manifest_path)?;
+
+    for entry in parse_sprite_manifest(&contents)? {
+        load_sprite(texture_creator, display, &entry.path, &entry.name)?;
+
+        if let Some(frames) = entry.frames {
+            let sprite_key = display.state.lookup_spritekey(&entry.name);
+            display.state.sprites[&sprite_key].num_sprites = frames;
+        }
+    }
+
+    return Ok(());
+}
+
+/// Check that a texture file exists before handing it to SDL, so a missing
+/// file reports which texture was not found.
+pub fn check_texture_path(path: &str) -> Result<(), EngineError> {
+    if !Path::new(path).is_file() {
+        return Err(EngineError::TextureLoad { path: path.to_string(), msg: "file not found".to_string() });
+    }
+
+    return Ok(());
+}
+
+#[test]
+fn test_missing_texture_path() {
+    let path = "resources/not_a_texture.png";
+    match check_texture_path(path) {
+        Err(EngineError::TextureLoad { path: error_path, .. }) => assert_eq!(path, error_path),
+        result => panic!(format!("Expected a texture load error, got {:?}", result)),
+    }
 }
 
 /// load a ttf font file and render all ascii characters onto a 16x16 grid.
@@ -49,8 +135,11 @@ pub fn load_font(ttf_context: &Sdl2TtfContext,
                  texture_creator: &TextureCreator<WindowContext>,
                  canvas: &mut WindowCanvas,
                  file_name: String,
-                 font_size: u16) -> Texture {
-    let font = ttf_context.load_font(format!("resources/{}", file_name), font_size).expect("Could not load font file!");
+                 font_size: u16) -> Result<Texture, EngineError> {
+    let font_path = format!("resources/{}", file_name);
+    let font =
+        ttf_context.load_font(&font_path, font_size)
+                   .map_err(|msg| EngineError::TextureLoad { path: font_path.clone(), msg })?;
 
     let pixel_format = texture_creator.default_pixel_format();
 
@@ -75,6 +164,21 @@ pub fn load_font(ttf_context: &Sdl2TtfContext,
         }
     }).unwrap();
 
-    return font_texture;
+    return Ok(font_texture);
 }
 
+#[test]
+fn test_parse_sprite_manifest() {
+    let manifest = "# comment\n\ntiles resources/rustrogueliketiles.png\n  shadows resources/shadowtiles.png  \nfire resources/fire.png 8\n";
+
+    let entries = parse_sprite_manifest(manifest).unwrap();
+    let names = entries.iter().map(|entry| entry.name.clone()).collect::<Vec<String>>();
+    assert_eq!(vec!("tiles".to_string(), "shadows".to_string(), "fire".to_string()), names);
+
+    assert_eq!("resources/shadowtiles.png", entries[1].path);
+    assert_eq!(None, entries[0].frames);
+    assert_eq!(Some(8), entries[2].frames);
+
+    assert!(parse_sprite_manifest("tiles").is_err());
+    assert!(parse_sprite_manifest("fire resources/fire.png many").is_err());
+}
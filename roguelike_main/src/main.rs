@@ -6,10 +6,12 @@ mod keyboard;
 mod load;
 mod replay;
 mod animation;
+mod error;
+mod headless;
+mod audio;
 
-use std::fs;
 use std::io::{BufRead, Write};
-use std::time::{Duration, Instant, SystemTime};
+use std::time::{Duration, Instant};
 use std::path::Path;
 use std::str::FromStr;
 use std::thread;
@@ -22,7 +24,7 @@ use logging_timer::timer;
 use gumdrop::Options;
 
 use roguelike_core::types::*;
-use roguelike_core::config::Config;
+use roguelike_core::config::{Config, ConfigWatcher};
 use roguelike_core::constants::*;
 use roguelike_core::map::MapLoadConfig;
 
@@ -30,7 +32,7 @@ use roguelike_engine::game::*;
 use roguelike_engine::generation::*;
 use roguelike_engine::actions::*;
 use roguelike_engine::input::*;
-use roguelike_engine::make_map::{make_map, read_map_xp};
+use roguelike_engine::make_map::{make_map, reload_map_xp};
 use roguelike_engine::log::*;
 
 use roguelike_lib::commands::*;
@@ -40,9 +42,15 @@ use crate::render::*;
 use crate::display::*;
 use crate::load::*;
 use crate::replay::*;
+use crate::error::EngineError;
+use crate::headless::*;
+use crate::audio::*;
 
 
 pub const CONFIG_NAME: &str = "config.yaml";
+pub const CONFIG_POLL_MS: u64 = 250;
+pub const SPRITE_MANIFEST_NAME: &str = "resources/sprites.txt";
+pub const SETTINGS_NAME: &str = "settings.yaml";
 
 
 #[derive(Debug, Clone, Options)]
@@ -77,6 +85,9 @@ pub struct GameOptions {
     #[options(help = "procgen map config", short="g")]
     pub procgen_map: Option<String>,
 
+    #[options(help = "run without a display, taking actions from the replay file")]
+    pub headless: bool,
+
     #[options(help = "display help text")]
     pub help: bool,
 }
@@ -88,6 +99,9 @@ fn main() {
     let seed: u64 =
         if let Some(given_seed) = opts.seed {
             given_seed
+        } else if let Some(replay_seed) = opts.replay.as_ref().and_then(|replay_file| read_action_log_seed(replay_file)) {
+            // replays use the seed recorded with the actions, unless overridden
+            replay_seed
         } else {
             1
             // could add string input as a seed generator
@@ -103,41 +117,86 @@ fn main() {
                                       |level_str| LevelFilter::from_str(&level_str).expect("Log level unexpected!"));
     simple_logging::log_to_file("game.log", log_level).unwrap();
 
-    run(seed, opts).unwrap();
+    if let Err(err) = run(seed, opts) {
+        eprintln!("{}", err);
+        std::process::exit(1);
+    }
 }
 
-pub fn run(seed: u64, opts: GameOptions) -> Result<(), String> {
+pub fn run(seed: u64, opts: GameOptions) -> Result<(), EngineError> {
+    let config = Config::try_from_file(CONFIG_NAME).map_err(EngineError::Config)?;
+
+    if opts.headless {
+        return run_headless_game(seed, opts, config);
+    }
+
     /* Create SDL Context */
-    let sdl_context = sdl2::init()?;
-    let video = sdl_context.video()?;
-    let window = video.window("Rust Roguelike", SCREEN_WIDTH, SCREEN_HEIGHT)
-                      .position_centered().build().map_err(|e| e.to_string())?;
+    let sdl_context = sdl2::init().map_err(EngineError::Sdl)?;
+    let video = sdl_context.video().map_err(EngineError::Sdl)?;
+    let window = video.window("Rust Roguelike", config.screen_width, config.screen_height)
+                      .position_centered().resizable().build().map_err(|e| EngineError::Sdl(e.to_string()))?;
 
     let canvas = window.into_canvas()
                        .accelerated()
                        .build()
-                       .map_err(|e| e.to_string())?;
+                       .map_err(|e| EngineError::Sdl(e.to_string()))?;
     let texture_creator = canvas.texture_creator();
 
     /* Create Display Structures */
-    let mut display = Display::new(canvas);
+    let mut audio = AudioSystem::new(Duration::from_millis(config.sound_cooldown_ms));
+    if let Err(err) = audio.load_clips(SOUND_DIR) {
+        log::warn!("{}", err);
+    }
+    let mut display = Display::new(canvas, audio)?;
 
     /* Load Textures */
-    load_sprites(&texture_creator, &mut display);
-    load_sprite(&texture_creator, &mut display, "resources/rustrogueliketiles.png", "tiles");
-    load_sprite(&texture_creator, &mut display, "resources/shadowtiles.png", "shadows");
+    load_sprites(&texture_creator, &mut display)?;
+    load_sprite_manifest(SPRITE_MANIFEST_NAME, &texture_creator, &mut display)?;
 
-    let ttf_context = sdl2::ttf::init().expect("Could not init SDL2 TTF!");
+    let ttf_context = sdl2::ttf::init().map_err(|e| EngineError::Sdl(e.to_string()))?;
     let font_texture = load_font(&ttf_context,
                                  &texture_creator,
                                  &mut display.targets.canvas_panel.target,
                                  "Monoid.ttf".to_string(),
-                                 16);
+                                 16)?;
     display.add_spritesheet("font".to_string(), font_texture);
 
     /* Create Game Structure */
-    let config = Config::from_file(CONFIG_NAME);
-    let mut game = Game::new(seed, config.clone());
+    let (mut game, map_config) = create_game(seed, &opts, config)?;
+
+    /* Run Game or Take Screenshot */
+    if opts.screenshot {
+        make_map(&map_config, &mut game);
+        take_screenshot(&mut game, &mut display)?;
+        return Ok(());
+    } else if let Some(record_name) = opts.check {
+        let delay = opts.delay.unwrap_or(0);
+        let mut event_pump = sdl_context.event_pump().map_err(EngineError::Sdl)?;
+
+        if record_name == "all" {
+            return check_all_records(&mut game, &mut display, &mut event_pump, delay).map_err(EngineError::from);
+        } else {
+            return check_single_record(&mut game, &mut display, &mut event_pump, &record_name, delay).map_err(EngineError::from);
+        }
+    } else if let Some(record_name) = opts.rerecord {
+        let delay = opts.delay.unwrap_or(0);
+        let mut event_pump = sdl_context.event_pump().map_err(EngineError::Sdl)?;
+        if record_name == "all" {
+            return rerecord_all(&mut game, &mut display, &mut event_pump, delay).map_err(EngineError::from);
+        } else {
+            return rerecord_single(&mut game, &mut display, &mut event_pump, &record_name, delay).map_err(EngineError::from);
+        }
+    } else {
+        make_map(&map_config, &mut game);
+        let event_pump = sdl_context.event_pump().map_err(EngineError::Sdl)?;
+        return game_loop(game, display, seed, opts, event_pump).map_err(EngineError::from);
+    }
+}
+
+/// Create the game and determine its map configuration. This does not touch SDL,
+/// so it is shared between the display and headless modes.
+fn create_game(seed: u64, opts: &GameOptions, config: Config) -> Result<(Game, MapLoadConfig), EngineError> {
+    let mut game = Game::new(seed, config);
 
     game.load_vaults("resources/vaults/");
 
@@ -149,63 +208,66 @@ pub fn run(seed: u64, opts: GameOptions) -> Result<(), String> {
     if let Some(procgen_map) = opts.procgen_map.clone() {
         map_config = MapLoadConfig::ProcGen(procgen_map);
     } else {
-        map_config = config.map_load.clone();
+        map_config = game.config.map_load.clone();
     }
 
     if let Some(map_config_str) = &opts.map_config {
         let cli_map_config = map_config_str.parse::<MapLoadConfig>()
-                                           .expect("Could not parse map config option!");
+                                           .map_err(EngineError::Config)?;
         map_config = cli_map_config;
     }
 
     // save map config to a file
-    let mut map_config_file = std::fs::File::create(MAP_CONFIG_NAME).unwrap();
-    map_config_file.write_all(map_config.to_string().as_bytes()).unwrap();
+    let mut map_config_file = std::fs::File::create(MAP_CONFIG_NAME)?;
+    map_config_file.write_all(map_config.to_string().as_bytes())?;
 
-    /* Run Game or Take Screenshot */
-    if opts.screenshot {
-        make_map(&map_config, &mut game);
-        take_screenshot(&mut game, &mut display).unwrap();
-        return Ok(());
-    } else if let Some(record_name) = opts.check {
-        let delay = opts.delay.unwrap_or(0);
-        let mut event_pump = sdl_context.event_pump().unwrap();
+    return Ok((game, map_config));
+}
 
-        if record_name == "all" {
-            return check_all_records(&mut game, &mut display, &mut event_pump, delay);
-        } else {
-            return check_single_record(&mut game, &mut display, &mut event_pump, &record_name, delay);
-        }
-    } else if let Some(record_name) = opts.rerecord {
-        let delay = opts.delay.unwrap_or(0);
-        let mut event_pump = sdl_context.event_pump().unwrap();
-        if record_name == "all" {
-            return rerecord_all(&mut game, &mut display, &mut event_pump, delay);
-        } else {
-            return rerecord_single(&mut game, &mut display, &mut event_pump, &record_name, delay);
-        }
-    } else {
-        make_map(&map_config, &mut game);
-        let event_pump = sdl_context.event_pump().unwrap();
-        return game_loop(game, display, opts, event_pump);
+/// Run the actions from the replay file, if given, without creating a window,
+/// printing the message log as the game runs.
+fn run_headless_game(seed: u64, opts: GameOptions, config: Config) -> Result<(), EngineError> {
+    let (mut game, map_config) = create_game(seed, &opts, config)?;
+    make_map(&map_config, &mut game);
+
+    let mut actions = Vec::new();
+    if let Some(replay_file) = &opts.replay {
+        actions = read_action_log(&replay_file);
     }
+
+    let result = run_headless(&mut game, &actions, true);
+    println!("{:?} after {} turns", result, game.settings.turn_count);
+
+    return Ok(());
 }
 
-pub fn game_loop(mut game: Game, mut display: Display, opts: GameOptions, mut event_pump: sdl2::EventPump) -> Result<(), String> {
+pub fn game_loop(mut game: Game, mut display: Display, seed: u64, opts: GameOptions, mut event_pump: sdl2::EventPump) -> Result<(), String> {
     // read in the recorded action log, if one is provided
     let mut starting_actions = Vec::new();
     if let Some(replay_file) = &opts.replay {
         starting_actions = read_action_log(&replay_file);
+        // actions are popped off the end, so reverse to replay them in order
+        starting_actions.reverse();
     }
 
-    let mut config_modified_time = fs::metadata(CONFIG_NAME).unwrap().modified().unwrap();
+    let config_watcher = ConfigWatcher::spawn(CONFIG_NAME, Duration::from_millis(CONFIG_POLL_MS));
+
+    // restore the display settings from the last game. Replays keep the defaults
+    // so they render the same way they were recorded.
+    if opts.replay.is_none() {
+        if let Ok(saved_settings) = GameSettings::load(SETTINGS_NAME) {
+            game.settings.overlay = saved_settings.overlay;
+            game.settings.render_map = saved_settings.render_map;
+        }
+    }
 
     let mut log = Log::new();
+    log.log_seed(seed);
     let mut recording = Recording::new(&game);
 
     /* Setup FPS Throttling */
     let frame_ms = 1000 / game.config.frame_rate as u64;
-    let fps_throttler = Throttler::new(Duration::from_millis(frame_ms));
+    let mut fps_throttler = Throttler::new(Duration::from_millis(frame_ms));
 
     /* Set up Input Handling */
     let io_recv = spawn_input_reader();
@@ -238,6 +300,11 @@ pub fn game_loop(mut game: Game, mut display: Display, opts: GameOptions, mut ev
                     }
                 }
             }
+
+            // keep moving while a direction key is held
+            if input_action == InputAction::None {
+                input_action = game.input.repeat_held_move(frame_time, &game.settings, &game.config);
+            }
         }
 
         /* Misc */
@@ -258,7 +325,7 @@ pub fn game_loop(mut game: Game, mut display: Display, opts: GameOptions, mut ev
             let _logic_timer = timer!("LOGIC");
             let dt = Instant::now().duration_since(frame_time).as_secs_f32();
             frame_time = Instant::now();
-            game.step_game(input_action, dt);
+            let game_result = game.step_game(input_action, dt);
             
             if game.config.recording && input_action != InputAction::None {
                 recording.action(&game, input_action);
@@ -273,10 +340,10 @@ pub fn game_loop(mut game: Game, mut display: Display, opts: GameOptions, mut ev
                 log.log_msg(&format!("{}", msg));
             }
 
-            if game.settings.state == GameState::Win {
+            if game_result == GameResult::Win {
                 display.clear_level_state();
                 recording.clear();
-            } else if game.settings.state == GameState::Exit {
+            } else if game_result == GameResult::Stop {
                 game.settings.running = false;
             }
         }
@@ -292,16 +359,23 @@ pub fn game_loop(mut game: Game, mut display: Display, opts: GameOptions, mut ev
         /* Configuration */
         {
             let _config_timer = timer!("CONFIG");
-            reload_config(&mut config_modified_time, &mut game);
+            reload_config(&config_watcher, &mut game, &mut display);
         }
 
         /* Wait until the next tick to loop */
         {
             let _wait_timer = timer!("WAIT");
             fps_throttler.wait();
+            display.state.fps = fps_throttler.average_fps();
         }
     }
 
+    log.flush();
+
+    if let Err(err) = game.settings.save(SETTINGS_NAME) {
+        eprintln!("{}", err);
+    }
+
     // NOTE we could also just put these files in the right place to begin with...
     if let Some(record_name) = opts.record {
         // save recorded logs
@@ -327,24 +401,31 @@ fn save_record(record_name: &str) {
             .expect("Could not save map config!");
 }
 
-fn reload_config(config_modified_time: &mut SystemTime, game: &mut Game) {
+fn reload_config(config_watcher: &ConfigWatcher, game: &mut Game, display: &mut Display) {
     /* Reload map if configured to do so */
     if game.config.load_map_file_every_frame && Path::new("resources/map.xp").exists() {
         let player = game.data.find_by_name(EntityName::Player).unwrap();
 
         let map_file = format!("resources/{}", game.config.map_file);
-        game.data.entities.clear();
-        let player_pos = read_map_xp(&game.config, &mut game.data, &mut game.msg_log, &map_file);
-        game.data.entities.set_pos(player, Pos::from(player_pos));
+        match reload_map_xp(&game.config, &mut game.data, &mut game.msg_log, &map_file) {
+            Ok(player_pos) => game.data.move_entity(player, Pos::from(player_pos)),
+            Err(err) => log::error!("{}", err),
+        }
     }
 
     /* Reload Configuration */
-    if let Ok(current_config_modified_time) = fs::metadata(CONFIG_NAME) {
-        let current_config_modified_time = current_config_modified_time.modified().unwrap();
-        if current_config_modified_time != *config_modified_time {
-            *config_modified_time = current_config_modified_time;
-            game.config = Config::from_file(CONFIG_NAME);
+    if let Some(config) = config_watcher.latest() {
+        let changes = config.diff(&game.config);
+
+        if changes.screen {
+            log::warn!("Changes to the screen size or frame rate take effect on restart");
         }
+
+        if changes.redraw_background() {
+            display.targets.background_panel.dirty = true;
+        }
+
+        game.config = config;
     }
 }
 
@@ -354,14 +435,14 @@ pub fn take_screenshot(game: &mut Game, display: &mut Display) -> Result<(), Str
     game.step_game(InputAction::None, 0.0);
     render_all(display, game)?;
 
-    display.save_screenshot("screenshot");
+    display.save_screenshot("screenshot")?;
 
     return Ok(());
 }
 
 fn update_display(game: &mut Game, display: &mut Display) -> Result<(), String> {
     for msg in game.msg_log.turn_messages.iter() {
-        display.process_message(*msg, &mut game.data, &game.config);
+        display.process_message(*msg, &mut game.data, &game.config)?;
     }
 
     /* Draw the Game to the Screen */
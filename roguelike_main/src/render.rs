@@ -1,5 +1,3 @@
-use oorandom::Rand32;
-
 use sdl2::render::{BlendMode, WindowCanvas};
 use sdl2::rect::Rect;
 use sdl2::pixels::{Color as Sdl2Color};
@@ -9,12 +7,13 @@ use roguelike_core::map::*;
 use roguelike_core::constants::*;
 use roguelike_core::movement::*;
 use roguelike_core::config::*;
-use roguelike_core::utils::{item_primary_at, lerp_color, sub_pos, reach_by_mode, map_fill_metric};
+use roguelike_core::utils::{lerp_color, reach_by_mode, map_fill_metric, sort_inventory};
 use roguelike_core::perlin::Perlin;
 use roguelike_core::line::line;
 use roguelike_core::ai::*;
 
 use roguelike_engine::game::*;
+use roguelike_engine::actions::{overlay_flags, OVERLAY_NAMES, settings_flags, SETTINGS_NAMES};
 
 use crate::display::*;
 use crate::animation::{Sprite, Effect, Animation, AnimationResult};
@@ -43,12 +42,25 @@ pub fn render_all(display: &mut Display, game: &mut Game)  -> Result<(), String>
     /* Draw Menus */
     render_menus(display, game);
 
+    display.state.sweep_animations(&game.data);
+
     Ok(())
 }
 
 
 fn render_panels(display: &mut Display, game: &mut Game, _map_rect: Rect) {
-    let mouse_map_pos = game.settings.cursor;
+    /* Determine Mouse Position */
+    let (map_width, map_height) = game.data.map.size();
+    let mouse_map_pos =
+        display.targets.mouse_pos(display.mouse_state.x, display.mouse_state.y, map_width, map_height)
+                       .map(|pair| Pos::from(pair));
+
+    if let (Some(mouse_pos), Some(mouse_id)) = (mouse_map_pos, game.data.find_by_name(EntityName::Mouse)) {
+        game.data.entities.set_xy(mouse_id, mouse_pos.x, mouse_pos.y);
+    }
+
+    // the cursor takes precedence over the mouse when it is in use
+    let info_pos = game.settings.cursor.or(mouse_map_pos);
 
     let canvas = &mut display.targets.canvas_panel.target;
     let display_state = &mut display.state;
@@ -75,7 +87,7 @@ fn render_panels(display: &mut Display, game: &mut Game, _map_rect: Rect) {
             render_entity_type(EntityType::Other, &mut panel, display_state, game);
             render_impressions(&mut panel, display_state, game);
             render_effects(&mut panel, display_state, game);
-            render_overlays(&mut panel, display_state, game, mouse_map_pos);
+            render_overlays(&mut panel, display_state, game, info_pos);
         }).unwrap();
     }
 
@@ -108,7 +120,7 @@ fn render_panels(display: &mut Display, game: &mut Game, _map_rect: Rect) {
 
         canvas.with_texture_canvas(&mut display.targets.info_panel.target, |canvas| {
             let mut panel = panel.with_target(canvas);
-            render_info(&mut panel, display_state, game, mouse_map_pos);
+            render_info(&mut panel, display_state, game, info_pos);
         }).unwrap();
     }
 }
@@ -148,14 +160,20 @@ fn render_menus(display: &mut Display, game: &mut Game) {
     canvas_panel.target.with_texture_canvas(&mut menu_panel.target, |canvas| {
         let mut panel = panel.with_target(canvas);
 
-        if game.settings.state == GameState::Inventory {
+        if game.settings.state() == GameState::Inventory {
             panel.target.copy(&inventory_panel.target, None, None).unwrap();
-        } else if game.settings.state == GameState::SkillMenu {
+        } else if game.settings.state() == GameState::SkillMenu {
             render_skill_menu(&mut panel, display_state, game);
-        } else if game.settings.state == GameState::ClassMenu {
+        } else if game.settings.state() == GameState::ClassMenu {
             render_class_menu(&mut panel, display_state, game);
-        } else if game.settings.state == GameState::ConfirmQuit {
+        } else if game.settings.state() == GameState::OverlayMenu {
+            render_overlay_menu(&mut panel, display_state, game);
+        } else if game.settings.state() == GameState::SettingsMenu {
+            render_settings_menu(&mut panel, display_state, game);
+        } else if game.settings.state() == GameState::ConfirmQuit {
             render_confirm_quit(&mut panel, display_state, game);
+        } else if game.settings.state() == GameState::Lose {
+            render_game_over(&mut panel, display_state, game);
         } else {
             draw_menu = false;
         }
@@ -202,8 +220,7 @@ fn render_placard(panel: &mut Panel<&mut WindowCanvas>,
 
     let text_pos = Pos::new(text_start, 0);
 
-    let sprite_key = display_state.lookup_spritekey("tiles");
-    let tile_sprite = &mut display_state.sprites[&sprite_key];
+    let tile_sprite = display_state.tiles_sprite_mut();
 
     tile_sprite.draw_text(panel, &text, text_pos, config.color_dark_blue);
 }
@@ -320,20 +337,27 @@ fn render_player_info(panel: &mut Panel<&mut WindowCanvas>, display_state: &mut
     list.push(format!(""));
     list.push(format!("turn {}", game.settings.turn_count));
 
+    if game.data.goals.required > 0 {
+        list.push(format!("keys {}/{}", game.data.goals.collected, game.data.goals.required));
+    }
+
+    if game.config.draw_fps {
+        list.push(format!("fps {:.1}", display_state.fps));
+    }
+
     let text_pos = Pos::new(1, 5);
 
-    let sprite_key = display_state.lookup_spritekey("tiles");
-    let tile_sprite = &mut display_state.sprites[&sprite_key];
+    let tile_sprite = display_state.tiles_sprite_mut();
     tile_sprite.draw_text_list(panel, &list, text_pos, color);
 }
 
 fn render_info(panel: &mut Panel<&mut WindowCanvas>,
                display_state: &mut DisplayState,
                game: &mut Game,
-               _mouse_xy: Option<Pos>) {
+               info_pos: Option<Pos>) {
     render_placard(panel, display_state, "Info", &game.config);
 
-    if let Some(info_pos) = game.settings.cursor {
+    if let Some(info_pos) = info_pos {
         let text_color = game.config.color_soft_green;
 
         let player_id = game.data.find_by_name(EntityName::Player).unwrap();
@@ -348,10 +372,9 @@ fn render_info(panel: &mut Panel<&mut WindowCanvas>,
 
         let text_pos = Pos::new(1, y_pos);
 
-        let sprite_key = display_state.lookup_spritekey("tiles");
 
         {
-            let tile_sprite = &mut display_state.sprites[&sprite_key];
+            let tile_sprite = display_state.tiles_sprite_mut();
             tile_sprite.draw_text_list(panel, &text_list, text_pos, text_color);
         }
         text_list.clear();
@@ -382,6 +405,10 @@ fn render_info(panel: &mut Panel<&mut WindowCanvas>,
 
                 text_list.push(format!("{:?}", game.data.entities.name[obj_id]));
 
+                if let Some(fighter) = game.data.entities.fighter.get(obj_id) {
+                    text_list.push(format!("hp {}/{}", fighter.hp, fighter.max_hp));
+                }
+
                 text_list.push(format!(""));
 
                 // show facing direction for player and monsters
@@ -401,7 +428,7 @@ fn render_info(panel: &mut Panel<&mut WindowCanvas>,
             }
         }
 
-        let tile_sprite = &mut display_state.sprites[&sprite_key];
+        let tile_sprite = display_state.tiles_sprite_mut();
         let text_pos = Pos::new(1, y_pos);
         tile_sprite.draw_text_list(panel, &text_list, text_pos, text_color);
         text_list.push(format!(""));
@@ -451,8 +478,7 @@ fn render_skill_menu(panel: &mut Panel<&mut WindowCanvas>, display_state: &mut D
     let text_pos = Pos::new(2, y_pos);
     let color = game.config.color_light_grey;
 
-    let sprite_key = display_state.lookup_spritekey("tiles");
-    let tile_sprite = &mut display_state.sprites[&sprite_key];
+    let tile_sprite = display_state.tiles_sprite_mut();
 
     tile_sprite.draw_text_list(panel, &list, text_pos, color);
 }
@@ -471,8 +497,49 @@ fn render_class_menu(panel: &mut Panel<&mut WindowCanvas>, display_state: &mut D
     let text_pos = Pos::new(2, y_pos);
     let color = game.config.color_light_grey;
 
-    let sprite_key = display_state.lookup_spritekey("tiles");
-    let tile_sprite = &mut display_state.sprites[&sprite_key];
+    let tile_sprite = display_state.tiles_sprite_mut();
+
+    tile_sprite.draw_text_list(panel, &list, text_pos, color);
+}
+
+fn render_overlay_menu(panel: &mut Panel<&mut WindowCanvas>, display_state: &mut DisplayState, game: &mut Game) {
+    // Render header
+    render_placard(panel, display_state, "Overlays", &game.config);
+
+    let mut list = Vec::new();
+
+    let flags = overlay_flags(&game.settings, &game.config);
+    for (index, (name, enabled)) in OVERLAY_NAMES.iter().zip(flags.iter()).enumerate() {
+        let on_off = if *enabled { "on" } else { "off" };
+        list.push(format!("{} {} ({})", index, name, on_off));
+    }
+
+    let y_pos = 2;
+    let text_pos = Pos::new(2, y_pos);
+    let color = game.config.color_light_grey;
+
+    let tile_sprite = display_state.tiles_sprite_mut();
+
+    tile_sprite.draw_text_list(panel, &list, text_pos, color);
+}
+
+fn render_settings_menu(panel: &mut Panel<&mut WindowCanvas>, display_state: &mut DisplayState, game: &mut Game) {
+    // Render header
+    render_placard(panel, display_state, "Settings", &game.config);
+
+    let mut list = Vec::new();
+
+    let flags = settings_flags(&game.settings);
+    for (index, (name, enabled)) in SETTINGS_NAMES.iter().zip(flags.iter()).enumerate() {
+        let on_off = if *enabled { "on" } else { "off" };
+        list.push(format!("{} {} ({})", index, name, on_off));
+    }
+
+    let y_pos = 2;
+    let text_pos = Pos::new(2, y_pos);
+    let color = game.config.color_light_grey;
+
+    let tile_sprite = display_state.tiles_sprite_mut();
 
     tile_sprite.draw_text_list(panel, &list, text_pos, color);
 }
@@ -491,8 +558,29 @@ fn render_confirm_quit(panel: &mut Panel<&mut WindowCanvas>, display_state: &mut
     let text_pos = Pos::new(2, y_pos);
     let color = game.config.color_light_grey;
 
-    let sprite_key = display_state.lookup_spritekey("tiles");
-    let tile_sprite = &mut display_state.sprites[&sprite_key];
+    let tile_sprite = display_state.tiles_sprite_mut();
+
+    tile_sprite.draw_text_list(panel, &list, text_pos, color);
+}
+
+fn render_game_over(panel: &mut Panel<&mut WindowCanvas>, display_state: &mut DisplayState, game: &mut Game) {
+    // Render header
+    render_placard(panel, display_state, "Game Over", &game.config);
+
+    let mut list = Vec::new();
+
+    list.push("You died!".to_string());
+    list.push("".to_string());
+    list.push(format!("level: {}", game.settings.level_num));
+    list.push(format!("turns: {}", game.settings.turn_count));
+    list.push("".to_string());
+    list.push("q: quit game".to_string());
+
+    let y_pos = 2;
+    let text_pos = Pos::new(2, y_pos);
+    let color = game.config.color_light_grey;
+
+    let tile_sprite = display_state.tiles_sprite_mut();
 
     tile_sprite.draw_text_list(panel, &list, text_pos, color);
 }
@@ -504,25 +592,29 @@ fn render_inventory(panel: &mut Panel<&mut WindowCanvas>, display_state: &mut Di
 
     let player_id = game.data.find_by_name(EntityName::Player).unwrap();
 
-    let sprite_key = display_state.lookup_spritekey("tiles");
-    let tile_sprite = &mut display_state.sprites[&sprite_key];
+    let tile_sprite = display_state.tiles_sprite_mut();
 
-    // Render each object's name in inventory
+    // Render each object's name in inventory, grouped by item type
     let mut y_pos = 2;
-    let mut item_index = 0;
+    let mut last_group = None;
 
-    let mut index = 0;
-    while index < game.data.entities.inventory[&player_id].len() {
-        let obj_id = game.data.entities.inventory[&player_id][index];
-        index += 1;
+    for obj_id in sort_inventory(&game.data.entities, player_id) {
+        let item = game.data.entities.item[&obj_id];
+
+        if last_group != Some(item.group()) {
+            let header_pos = Pos::new(1, y_pos);
+            tile_sprite.draw_text(panel, &format!("{}", item.group()), header_pos, game.config.color_light_grey);
+            y_pos += 1;
+            last_group = Some(item.group());
+        }
 
         let color;
-        match &game.data.entities.item.get(&obj_id) {
-            Some(Item::Stone) => {
+        match item {
+            Item::Stone => {
                 color = game.config.color_light_grey;
             }
 
-            Some(Item::Key) => {
+            Item::Key => {
                 color = game.config.color_red;
             }
             
@@ -531,16 +623,19 @@ fn render_inventory(panel: &mut Panel<&mut WindowCanvas>, display_state: &mut Di
             }
         }
 
+        // the prompt is the item's inventory index, which is used to select it
+        let item_index = game.data.entities.inventory[&player_id].iter().position(|id| *id == obj_id).unwrap();
+
         // place prompt character
         tile_sprite.draw_char(panel,
-                              ('0' as u8 + item_index) as char,
+                              ('0' as u8 + item_index as u8) as char,
                               Pos::new(1, y_pos),
                               game.config.color_ice_blue);
 
         // place object name
         let text_pos = Pos::new(2, y_pos);
         let item_marker =
-            if index == 0 && item_primary_at(player_id, &mut game.data.entities, 0) {
+            if game.data.using(player_id, item) == Some(obj_id) && item.class() == ItemClass::Primary {
                 "<"
             } else {
                 ""
@@ -549,8 +644,6 @@ fn render_inventory(panel: &mut Panel<&mut WindowCanvas>, display_state: &mut Di
         tile_sprite.draw_text(panel, &item_text, text_pos, color);
         
         y_pos += 1;
-
-        item_index += 1;
     }
 
     if game.data.entities.inventory[&player_id].len() == 0 {
@@ -559,18 +652,19 @@ fn render_inventory(panel: &mut Panel<&mut WindowCanvas>, display_state: &mut Di
     }
 }
 
-/// render the background files, including water tiles
+/// Render the static map layer: tiles, surfaces, walls and their shadows. This
+/// only depends on the map, so it is drawn into the background panel once and
+/// then only again when the panel is dirty or the map changes.
 fn render_background(display: &mut Display, game: &mut Game) {
-    if !display.targets.background_panel.dirty {
+    let panel_dirty = display.targets.background_panel.dirty;
+    if !display.state.static_layer_dirty(panel_dirty, &game.data.map) {
         return;
     }
     display.targets.background_panel.dirty = false;
 
     let (map_width, map_height) = game.data.map.size();
 
-    let sprite_key = display.state.lookup_spritekey("tiles");
-    let sprite = &mut display.state.sprites[&sprite_key];
-
+    let display_state = &mut display.state;
     let canvas = &mut display.targets.canvas_panel.target;
 
     let panel = display.targets.background_panel.unit();
@@ -581,40 +675,32 @@ fn render_background(display: &mut Display, game: &mut Game) {
         let mut panel = panel.with_target(canvas);
         for y in 0..map_height {
             for x in 0..map_width {
-                let map_pos = Pos::new(x, y);
-
-                //let visible =
-                //    game.data.pos_in_fov(player_id, map_pos, &game.config) ||
-                //    game.settings.god_mode;
-
-                let tile = &game.data.map[(x, y)];
-                if tile.tile_type != TileType::Water {
-                    sprite.draw_char(&mut panel,
-                                     MAP_EMPTY_CHAR as char,
-                                     map_pos,
-                                     Color::white());
-                } else {
-                    sprite.draw_char(&mut panel, MAP_EMPTY_CHAR as char, map_pos, Color::white());
-                }
+                render_static_tile(&mut panel, display_state, game, Pos::new(x, y));
             }
         }
     }).unwrap();
 }
 
-fn render_surface(panel: &mut Panel<&mut WindowCanvas>, sprite: &mut SpriteSheet, surface: Surface, pos: Pos) {
-    match surface {
-        Surface::Rubble => {
-            sprite.draw_char(panel, MAP_RUBBLE as char, pos, Color::white());
-        }
+fn render_static_tile(panel: &mut Panel<&mut WindowCanvas>, display_state: &mut DisplayState, game: &mut Game, pos: Pos) {
+    let outline_color = Color::white().scale_alpha(1.0 / 8.0);
 
-        Surface::Grass => {
-            sprite.draw_char(panel, MAP_GRASS as char, pos, Color::white()); //game.config.color_light_green);
-        }
+    for (index, (chr, color)) in tile_glyphs(&game.data.map, pos).iter().enumerate() {
+        let sprite = display_state.tiles_sprite_mut();
+        sprite.draw_char(panel, *chr as char, pos, *color);
 
-        Surface::Floor => {
-            // Nothing to draw
+        // the outline goes over the base tile, and under everything else
+        if index == 0 {
+            draw_outline_tile(panel, pos, outline_color);
         }
     }
+
+    // shadows go under the walls on the tile's edges
+    render_wall_shadow(pos, panel, display_state, game);
+
+    for (chr, color) in wall_glyphs(&game.data.map, pos).iter() {
+        let sprite = display_state.tiles_sprite_mut();
+        sprite.draw_char(panel, *chr as char, pos, *color);
+    }
 }
 
 /// Render Wall Shadows (full tile and intertile walls, left and down)
@@ -693,61 +779,29 @@ fn render_wall_shadow(pos: Pos, panel: &mut Panel<&mut WindowCanvas>, display_st
     }
 }
 
-/// Render the map, with environment and walls
+/// Render the parts of the map that change with the player's FOV. The tiles
+/// themselves are drawn in the static layer by render_background.
 fn render_map(panel: &mut Panel<&mut WindowCanvas>, display_state: &mut DisplayState, game: &mut Game) {
     let player_id = game.data.find_by_name(EntityName::Player).unwrap();
 
     let (map_width, map_height) = game.data.map.size();
 
-    let sprite_key = display_state.lookup_spritekey("tiles");
+    let player_pos = game.data.entities.pos[&player_id];
+    let sight_radius = game.data.sight_radius(player_id, &game.config);
+
     for y in 0..map_height {
         for x in 0..map_width {
             let pos = Pos::new(x, y);
 
-            // draw an outline around the tile
-            {
-                let mut outline_color = Color::white();
-                outline_color.a /= 8;
-                draw_outline_tile(panel, pos, outline_color);
-            }
-
-            // Render game stuff
             let visible =
                 game.data.pos_in_fov(player_id, pos, &game.config) ||
                 game.settings.god_mode;
 
-            let tile = game.data.map[pos];
-
-            let chr = tile.chr;
-
-            // if the tile is not empty or water, draw it
-            if tile.tile_type == TileType::Water {
-                let sprite = &mut display_state.sprites[&sprite_key];
-                sprite.draw_char(panel, MAP_WATER as char, pos, Color::white());
-            } else if chr != MAP_EMPTY_CHAR {
-                let sprite = &mut display_state.sprites[&sprite_key];
-                sprite.draw_char(panel, chr as char, pos, Color::white());
-            }
-
-            {
-                let sprite = &mut display_state.sprites[&sprite_key];
-                render_surface(panel, sprite, tile.surface, pos);
-            }
-
-            render_wall_shadow(pos, panel, display_state, game);
-
-            /* draw the between-tile walls appropriate to this tile */
-            {
-                let sprite = &mut display_state.sprites[&sprite_key];
-                render_itertile_walls(panel, &mut game.data.map, sprite, pos, &game.config);
-            }
-
             // apply a FoW darkening to cells
             if game.config.fog_of_war && !visible {
-                game.data.entities.status[&player_id].extra_fov += 1;
+                // tiles just past the edge of the FOV are drawn lighter
                 let is_in_fov_ext = 
-                   game.data.pos_in_fov(player_id, pos, &game.config);
-                game.data.entities.status[&player_id].extra_fov -= 1;
+                   game.data.pos_in_fov_radius(player_id, pos, sight_radius + 1, &game.config);
 
                 let mut blackout_color = Color::black();
                 if is_in_fov_ext {
@@ -756,57 +810,54 @@ fn render_map(panel: &mut Panel<&mut WindowCanvas>, display_state: &mut DisplayS
                     blackout_color.a = game.config.explored_alpha
                 }
                 
-                let sprite = &mut display_state.sprites[&sprite_key];
+                let sprite = display_state.tiles_sprite_mut();
                 sprite.draw_char(panel, MAP_EMPTY_CHAR as char, pos, blackout_color);
+            } else if game.config.fog_of_war && !game.settings.god_mode {
+                // visible tiles darken toward the edge of the FOV radius
+                let fade_color = Color::black().with_alpha(fov_fade_alpha(pos, player_pos, sight_radius, game.config.fov_fade_alpha));
+                if fade_color.a > 0 {
+                    let sprite = display_state.tiles_sprite_mut();
+                    sprite.draw_char(panel, MAP_EMPTY_CHAR as char, pos, fade_color);
+                }
             }
         }
     }
 }
 
-fn render_itertile_walls(panel: &mut Panel<&mut WindowCanvas>,
-                         map: &Map,
-                         sprite: &mut SpriteSheet,
-                         pos: Pos,
-                         _config: &Config) {
-    let (x, y) = pos.to_tuple();
-    let tile = map[pos];
-    let wall_color = Color::white();
-
-    // Lower walls
-    if tile.bottom_wall == Wall::ShortWall {
-        sprite.draw_char(panel, MAP_THIN_WALL_BOTTOM as char, pos, wall_color);
-    } else if tile.bottom_wall == Wall::TallWall {
-        sprite.draw_char(panel, MAP_THICK_WALL_BOTTOM as char, pos, wall_color);
+/// The darkening applied to a visible tile, scaled by its distance from the
+/// player over the FOV radius. There is none on the player, and max_alpha at
+/// the edge of the radius or beyond.
+pub fn fov_fade_alpha(pos: Pos, player_pos: Pos, radius: i32, max_alpha: u8) -> u8 {
+    if radius <= 0 {
+        return max_alpha;
     }
 
-    // Left walls
-    if tile.left_wall == Wall::ShortWall {
-        sprite.draw_char(panel, MAP_THIN_WALL_LEFT as char, pos, wall_color);
-    } else if tile.left_wall == Wall::TallWall {
-        sprite.draw_char(panel, MAP_THICK_WALL_LEFT as char, pos, wall_color);
-    }
+    let x_dist = (pos.x - player_pos.x) as f32;
+    let y_dist = (pos.y - player_pos.y) as f32;
+    let dist = (x_dist * x_dist + y_dist * y_dist).sqrt();
 
-    // Right walls
-    if x + 1 < map.width() {
-        let right_pos = Pos::new(pos.x + 1, pos.y);
-        let right_tile = &map[right_pos];
-        if right_tile.left_wall == Wall::ShortWall {
-            sprite.draw_char(panel, MAP_THIN_WALL_RIGHT as char, pos, wall_color);
-        } else if right_tile.left_wall == Wall::TallWall {
-            sprite.draw_char(panel, MAP_THICK_WALL_RIGHT as char, pos, wall_color);
-        }
-    }
+    let ratio = (dist / radius as f32).min(1.0);
 
-    // Upper walls
-    if y - 1 >= 0 {
-        let up_pos = Pos::new(pos.x, pos.y - 1);
-        let up_tile = &map[up_pos];
-        if up_tile.bottom_wall == Wall::ShortWall {
-            sprite.draw_char(panel, MAP_THIN_WALL_TOP as char, pos, wall_color);
-        } else if up_tile.bottom_wall == Wall::TallWall {
-            sprite.draw_char(panel, MAP_THICK_WALL_TOP as char, pos, wall_color);
-        }
-    }
+    return (ratio * max_alpha as f32) as u8;
+}
+
+#[test]
+fn test_fov_fade_alpha() {
+    let player_pos = Pos::new(5, 5);
+
+    // no fade at the center, half at the middle, and full at the edge
+    assert_eq!(0, fov_fade_alpha(player_pos, player_pos, 4, 100));
+    assert_eq!(50, fov_fade_alpha(Pos::new(7, 5), player_pos, 4, 100));
+    assert_eq!(100, fov_fade_alpha(Pos::new(5, 1), player_pos, 4, 100));
+
+    // the fade increases with distance and is clamped past the radius
+    let near = fov_fade_alpha(Pos::new(6, 6), player_pos, 4, 100);
+    let far = fov_fade_alpha(Pos::new(7, 7), player_pos, 4, 100);
+    assert!(0 < near && near < far && far < 100);
+    assert_eq!(100, fov_fade_alpha(Pos::new(12, 5), player_pos, 4, 100));
+
+    // a max alpha of 0 disables the fade
+    assert_eq!(0, fov_fade_alpha(Pos::new(5, 1), player_pos, 4, 0));
 }
 
 /// Render each effect currently playing in the game
@@ -825,14 +876,14 @@ fn render_effects(panel: &mut Panel<&mut WindowCanvas>,
         let mut effect = display_state.effects[index].clone();
         match &mut effect {
             Effect::Sound(sound_aoe, sound_dt) => {
-                let mut highlight_color = game.config.color_warm_grey;
+                let sound_color = game.config.color_warm_grey.with_alpha(game.config.sound_alpha);
 
                 let radius = sound_aoe.positions.len();
                 let sound_interval = game.config.sound_timeout / radius as f32;
                 let cur_dist = *sound_dt / sound_interval;
                 for (dist, dist_positions) in sound_aoe.positions.iter().enumerate() {
-                    highlight_color.a =
-                        game.config.sound_alpha / ((dist as i16 - cur_dist as i16).abs() as u8 + 1);
+                    let dist_diff = (dist as i16 - cur_dist as i16).abs() as f32;
+                    let highlight_color = sound_color.scale_alpha(1.0 / (dist_diff + 1.0));
 
                     // NOTE(perf) with the new texture system, consider rendering a series of sound
                     //            tiles and simply pasting them, perhaps saving time from not
@@ -856,11 +907,9 @@ fn render_effects(panel: &mut Panel<&mut WindowCanvas>,
             }
 
             Effect::Beam(remaining, start, end) => {
-                let sprite_key = display_state.lookup_spritekey("tiles");
-                let tile_sprite = &mut display_state.sprites[&sprite_key];
+                let tile_sprite = display_state.tiles_sprite_mut();
 
-                let dxy = sub_pos(*end, *start);
-                let dir = Direction::from_dxy(dxy.x, dxy.y).unwrap();
+                let dir = Direction::from_positions(*start, *end).unwrap();
                 let rotation;
                 let sprite_index;
                 match dir {
@@ -916,9 +965,19 @@ fn render_entity(panel: &mut Panel<&mut WindowCanvas>,
                  entity_id: EntityId,
                  display_state: &mut DisplayState,
                  game: &mut Game) -> Option<Sprite> {
+    let pos = game.data.entities.pos[&entity_id];
+    return render_entity_pos(panel, entity_id, pos, display_state, game);
+}
+
+/// Render an entity as if it were at the given position. The entity itself is
+/// not moved, so the position index is left untouched.
+fn render_entity_pos(panel: &mut Panel<&mut WindowCanvas>,
+                     entity_id: EntityId,
+                     pos: Pos,
+                     display_state: &mut DisplayState,
+                     game: &mut Game) -> Option<Sprite> {
     let mut animation_result = AnimationResult::new();
 
-    let pos = game.data.entities.pos[&entity_id];
     animation_result.pos = pos;
 
     let player_id = game.data.find_by_name(EntityName::Player).unwrap();
@@ -930,8 +989,10 @@ fn render_entity(panel: &mut Panel<&mut WindowCanvas>,
            return None;
     }
 
+    let at_entity_pos = pos == game.data.entities.pos[&entity_id];
     let is_in_fov = 
-       game.data.is_in_fov(player_id, entity_id, &game.config) ||
+       (at_entity_pos && game.data.is_in_fov(player_id, entity_id, &game.config)) ||
+       (!at_entity_pos && (entity_id == player_id || game.data.pos_in_fov(player_id, pos, &game.config))) ||
        game.settings.god_mode;
 
     if is_in_fov {
@@ -950,6 +1011,11 @@ fn render_entity(panel: &mut Panel<&mut WindowCanvas>,
                             color = game.config.color_warm_grey;
                         }
 
+                        // cloaked entities are drawn semi-transparent
+                        if game.data.is_cloaked(entity_id) {
+                            color.a = game.config.cloak_alpha;
+                        }
+
                         display_state.draw_sprite(panel, sprite, animation_result.pos, color);
                     }
 
@@ -963,9 +1029,12 @@ fn render_entity(panel: &mut Panel<&mut WindowCanvas>,
                 display_state.animations.insert(entity_id, anims);
             }
         } else {
-            let color = game.data.entities.color[&entity_id];
+            let mut color = game.data.entities.color[&entity_id];
+            if game.data.is_cloaked(entity_id) {
+                color.a = game.config.cloak_alpha;
+            }
 
-            let tiles = display_state.lookup_spritekey("tiles");
+            let tiles = display_state.tiles_key();
             let chr = game.data.entities.chr[&entity_id];
             let sprite = Sprite::new(chr as u32, tiles);
             display_state.draw_sprite(panel, sprite, pos, color);
@@ -973,15 +1042,14 @@ fn render_entity(panel: &mut Panel<&mut WindowCanvas>,
         }
     } else {
         // if not in FoV, see if we need to add an impression for a golem
-        if game.data.entities.typ[&entity_id] == EntityType::Enemy {
-            game.data.entities.status[&player_id].extra_fov += 1;
+        if at_entity_pos && game.data.entities.typ[&entity_id] == EntityType::Enemy {
+            let sight_radius = game.data.sight_radius(player_id, &game.config);
             let is_in_fov_ext = 
-               game.data.is_in_fov(player_id, entity_id, &game.config);
-            game.data.entities.status[&player_id].extra_fov -= 1;
+               game.data.is_in_fov_radius(player_id, entity_id, sight_radius + 1, &game.config);
 
             if is_in_fov_ext {
                 if display_state.impressions.iter().all(|impresssion| impresssion.pos != pos) {
-                    let tiles = display_state.lookup_spritekey("tiles");
+                    let tiles = display_state.tiles_key();
                     let impression_sprite = Sprite::new(ENTITY_UNKNOWN as u32, tiles);
                     display_state.impressions.push(Impression::new(impression_sprite, pos));
                 }
@@ -1025,11 +1093,10 @@ fn render_overlays(panel: &mut Panel<&mut WindowCanvas>,
     let player_id = game.data.find_by_name(EntityName::Player).unwrap();
     let player_pos = game.data.entities.pos[&player_id];
 
-    let sprite_key = display_state.lookup_spritekey("tiles");
 
     // render a grid of numbers if enabled
     if game.config.overlay_directions {
-        let tile_sprite = &mut display_state.sprites[&sprite_key];
+        let tile_sprite = display_state.tiles_sprite_mut();
 
         let map_width = game.data.map.width();
         let map_height = game.data.map.height();
@@ -1052,13 +1119,21 @@ fn render_overlays(panel: &mut Panel<&mut WindowCanvas>,
         }
     }
 
+    // highlight the enemy selected with CycleTarget
+    if let Some(target_id) = game.settings.target {
+        if game.data.entities.ids.contains(&target_id) &&
+           game.data.is_in_fov(player_id, target_id, &game.config) {
+            let target_pos = game.data.entities.pos[&target_id];
+            draw_outline_tile(panel, target_pos, game.config.color_red);
+        }
+    }
+
     // render cursor if enabled
     if game.config.use_cursor {
         // render cursor itself
         if let Some(cursor_pos) = game.settings.cursor {
-            let tile_sprite = &mut display_state.sprites[&sprite_key];
-            let mut color = game.config.color_mint_green;
-            color.a = 230;
+            let tile_sprite = display_state.tiles_sprite_mut();
+            let color = game.config.color_mint_green.with_alpha(230);
             tile_sprite.draw_char(panel, ENTITY_CURSOR as char, cursor_pos, color);
 
             // render player ghost
@@ -1066,8 +1141,7 @@ fn render_overlays(panel: &mut Panel<&mut WindowCanvas>,
                 let alpha = game.data.entities.color[&player_id].a;
                 game.data.entities.color[&player_id].a = 100;
 
-                let dxy = sub_pos(cursor_pos, player_pos);
-                let direction = Direction::from_dxy(dxy.x, dxy.y).unwrap();
+                let direction = Direction::from_positions(player_pos, cursor_pos).unwrap();
 
                 let mut reach = reach_by_mode(MoveMode::Sneak);
                 if !game.input.cursor && game.input.shift {
@@ -1075,10 +1149,8 @@ fn render_overlays(panel: &mut Panel<&mut WindowCanvas>,
                 }
 
                 if let Some(player_ghost_pos) = reach.furthest_in_direction(player_pos, direction) {
-                    game.data.entities.pos[&player_id] = player_ghost_pos;
-                    render_entity(panel, player_id, display_state, game);
+                    render_entity_pos(panel, player_id, player_ghost_pos, display_state, game);
                     game.data.entities.color[&player_id].a = alpha;
-                    game.data.entities.pos[&player_id] = player_pos;
                 }
             }
         }
@@ -1086,7 +1158,7 @@ fn render_overlays(panel: &mut Panel<&mut WindowCanvas>,
 
     // render FOV if enabled
     if game.config.overlay_player_fov {
-        let tile_sprite = &mut display_state.sprites[&sprite_key];
+        let tile_sprite = display_state.tiles_sprite_mut();
 
         let map_width = game.data.map.width();
         let map_height = game.data.map.height();
@@ -1104,13 +1176,12 @@ fn render_overlays(panel: &mut Panel<&mut WindowCanvas>,
     }
 
     // Draw player action overlay. Could draw arrows to indicate how to reach each location
-    let mut highlight_color: Color = game.config.color_warm_grey;
-    highlight_color.a = game.config.highlight_player_move;
+    let highlight_color: Color = game.config.color_warm_grey.with_alpha(game.config.highlight_player_move);
 
     // draw direction overlays
     if game.config.draw_directional_arrow {
         let direction_color = Color::white();
-        let tile_sprite = &mut display_state.sprites[&sprite_key];
+        let tile_sprite = display_state.tiles_sprite_mut();
         let mut index = 0;
         while index < game.data.entities.ids.len() {
             let entity_id = game.data.entities.ids[index];
@@ -1201,13 +1272,30 @@ fn render_overlays(panel: &mut Panel<&mut WindowCanvas>,
                                      display_state,
                                      game,
                                      entity_id);
+
+               // label the entity with its current behavior
+               if let Some(behavior) = game.data.entities.behavior.get(&entity_id) {
+                   let tile_sprite = display_state.tiles_sprite_mut();
+                   tile_sprite.draw_sprite_direction(panel,
+                                                     behavior.short_code() as usize,
+                                                     Some(Direction::DownRight),
+                                                     pos,
+                                                     0.5,
+                                                     highlight_color,
+                                                     0.0);
+               }
             }
         }
+
+        // show the player's extended reach when holding a spear
+        if game.data.using(player_id, Item::Spear).is_some() {
+            render_attack_overlay(panel, display_state, game, player_id);
+        }
     }
 
     // draw mouse path overlays
     if let Some(mouse_id) = game.data.find_by_name(EntityName::Mouse) {
-        let tile_sprite = &mut display_state.sprites[&sprite_key];
+        let tile_sprite = display_state.tiles_sprite_mut();
 
         let mouse_pos = game.data.entities.pos[&mouse_id];
         let player_pos = game.data.entities.pos[&player_id];
@@ -1246,8 +1334,7 @@ fn render_overlays(panel: &mut Panel<&mut WindowCanvas>,
                 // draw a highlight on that square
                 // don't draw overlay on top of character
                 if movement.pos != game.data.entities.pos[&player_id] {
-                    let dxy = sub_pos(movement.pos, player_pos);
-                    let direction = Direction::from_dxy(dxy.x, dxy.y).unwrap();
+                    let direction = Direction::from_positions(player_pos, movement.pos).unwrap();
                     let shadow_cursor_pos = direction.offset_pos(player_pos, 1);
 
                     render_entity_at(player_id, shadow_cursor_pos, game, panel, display_state);
@@ -1266,8 +1353,7 @@ fn render_overlays(panel: &mut Panel<&mut WindowCanvas>,
 
     // Outline tiles within FOV for clarity
     if game.settings.overlay {
-        let mut highlight_color_fov = game.config.color_light_orange;
-        highlight_color_fov.a = game.config.grid_alpha_visible;
+        let highlight_color_fov = game.config.color_light_orange.with_alpha(game.config.grid_alpha_visible);
 
         for y in 0..game.data.map.height() {
             for x in 0..game.data.map.width() {
@@ -1290,15 +1376,14 @@ fn render_overlays(panel: &mut Panel<&mut WindowCanvas>,
     if game.config.overlay_floodfill {
         let font_key = display_state.lookup_spritekey("font");
 
-        let mut highlight_color = game.config.color_light_orange;
-        highlight_color.a = 50;
+        let highlight_color = game.config.color_light_orange.with_alpha(50);
         let fill_metric = map_fill_metric(&game.data.map);
 
         for (pos, near_count) in fill_metric {
             let amount = near_count as f32 / 50.0;
             let adj_color = lerp_color(game.config.color_ice_blue, game.config.color_red, amount);
 
-            let tile_sprite = &mut display_state.sprites[&sprite_key];
+            let tile_sprite = display_state.tiles_sprite_mut();
             tile_sprite.draw_char(panel, MAP_EMPTY_CHAR as char, pos, adj_color);
 
             let font_sprite = &mut display_state.sprites[&font_key];
@@ -1313,7 +1398,7 @@ fn render_overlays(panel: &mut Panel<&mut WindowCanvas>,
     {
         let alertness_color = game.config.color_pink;
         let scale = 0.5;
-        let tile_sprite = &mut display_state.sprites[&sprite_key];
+        let tile_sprite = display_state.tiles_sprite_mut();
         for entity_id in game.data.entities.ids.iter() {
             let pos = game.data.entities.pos[entity_id];
 
@@ -1323,17 +1408,15 @@ fn render_overlays(panel: &mut Panel<&mut WindowCanvas>,
 
             if game.data.is_in_fov(player_id, *entity_id, &game.config) {
                 let mut status_drawn: bool = false;
-                if let Some(status) = game.data.entities.status.get(entity_id) {
-                    if status.frozen > 0 {
-                        status_drawn = true;
-                        tile_sprite.draw_sprite_direction(panel,
-                                                          ASTERISK as usize,
-                                                          Some(Direction::UpRight),
-                                                          pos,
-                                                          scale,
-                                                          alertness_color,
-                                                          0.0);
-                    }
+                if game.data.entities.has_effect(*entity_id, EffectKind::Frozen) {
+                    status_drawn = true;
+                    tile_sprite.draw_sprite_direction(panel,
+                                                      ASTERISK as usize,
+                                                      Some(Direction::UpRight),
+                                                      pos,
+                                                      scale,
+                                                      alertness_color,
+                                                      0.0);
                 }
 
                 if status_drawn {
@@ -1352,7 +1435,7 @@ fn render_overlays(panel: &mut Panel<&mut WindowCanvas>,
                                                                   0.0);
                             }
 
-                            Behavior::Attacking(_) => {
+                            Behavior::Attacking(_, _) => {
                                 tile_sprite.draw_sprite_direction(panel,
                                                                   EXCLAMATION_POINT as usize,
                                                                   Some(Direction::UpRight),
@@ -1361,6 +1444,12 @@ fn render_overlays(panel: &mut Panel<&mut WindowCanvas>,
                                                                   alertness_color,
                                                                   0.0);
                             }
+
+                            Behavior::Fleeing(_) => {
+                            }
+
+                            Behavior::Patrol(_) => {
+                            }
                         }
                     }
                 }
@@ -1369,7 +1458,7 @@ fn render_overlays(panel: &mut Panel<&mut WindowCanvas>,
     }
 }
 
-fn empty_tile_color(config: &Config, pos: Pos, visible: bool, rng: &mut Rand32) -> Color {
+fn empty_tile_color(config: &Config, pos: Pos, visible: bool, noise: &Perlin) -> Color {
     let low_color;
     let high_color;
     if visible {
@@ -1380,16 +1469,32 @@ fn empty_tile_color(config: &Config, pos: Pos, visible: bool, rng: &mut Rand32)
         high_color = config.color_light_grey;
     }
 
-    let simplex = Perlin::new(rng);
     let color =
         lerp_color(low_color,
                    high_color,
-                   simplex.noise2d(pos.x as f64 / config.tile_noise_scaler,
-                                   pos.y as f64 / config.tile_noise_scaler) as f32);
+                   noise.fractal2d(pos.x as f64 / config.tile_noise_scaler,
+                                   pos.y as f64 / config.tile_noise_scaler,
+                                   config.tile_noise_octaves) as f32);
 
    return color;
 }
 
+#[test]
+fn test_empty_tile_color_deterministic() {
+    let mut config = Config::from_file("../config.yaml");
+    config.tile_noise_octaves = 3;
+    let mut display_state = DisplayState::new();
+    let pos = Pos::new(3, 7);
+
+    let first = empty_tile_color(&config, pos, true, display_state.tile_noise(&config));
+    let second = empty_tile_color(&config, pos, true, display_state.tile_noise(&config));
+    assert_eq!(first, second);
+
+    // noise made separately from the same seed gives the same color
+    let noise = Perlin::with_seed(config.tile_noise_seed);
+    assert_eq!(first, empty_tile_color(&config, pos, true, &noise));
+}
+
 fn tile_color(config: &Config, _x: i32, _y: i32, tile: &Tile, visible: bool) -> Color {
     let color = match (tile.tile_type, visible) {
         (TileType::Wall, true) =>
@@ -1397,10 +1502,10 @@ fn tile_color(config: &Config, _x: i32, _y: i32, tile: &Tile, visible: bool) ->
         (TileType::Wall, false) =>
             config.color_dark_brown,
 
-        (TileType::Empty, true) =>
+        (TileType::Floor, true) =>
             config.color_light_brown,
 
-        (TileType::Empty, false) =>
+        (TileType::Floor, false) =>
             config.color_dark_brown,
 
         (TileType::Water, true) =>
@@ -1431,13 +1536,11 @@ fn render_attack_overlay(panel: &mut Panel<&mut WindowCanvas>,
 
     let object_pos = game.data.entities.pos[&entity_id];
 
-    let mut attack_highlight_color = game.config.color_red;
-    attack_highlight_color.a = game.config.highlight_alpha_attack;
+    let attack_highlight_color = game.config.color_red.with_alpha(game.config.highlight_alpha_attack);
 
-    let sprite_key = display_state.lookup_spritekey("tiles");
-    let tile_sprite = &mut display_state.sprites[&sprite_key];
+    let tile_sprite = display_state.tiles_sprite_mut();
 
-    if let Some(reach) = game.data.entities.attack.get(&entity_id) {
+    if let Some(reach) = game.data.attack_reach(entity_id) {
         let attack_positions = 
             reach.offsets()
                  .iter()
@@ -1468,8 +1571,7 @@ fn render_fov_overlay(panel: &mut Panel<&mut WindowCanvas>,
                       entity_id: EntityId) {
     let player_id = game.data.find_by_name(EntityName::Player).unwrap();
 
-    let mut highlight_color = game.config.color_light_grey;
-    highlight_color.a = game.config.grid_alpha_overlay;
+    let highlight_color = game.config.color_light_grey.with_alpha(game.config.grid_alpha_overlay);
 
     for y in 0..game.data.map.height() {
         for x in 0..game.data.map.width() {
@@ -1494,11 +1596,9 @@ fn render_movement_overlay(panel: &mut Panel<&mut WindowCanvas>,
 
     let entity_pos = game.data.entities.pos[&entity_id];
 
-    let mut highlight_color = game.config.color_light_grey;
-    highlight_color.a = game.config.grid_alpha_overlay;
+    let highlight_color = game.config.color_light_grey.with_alpha(game.config.grid_alpha_overlay);
 
-    let sprite_key = display_state.lookup_spritekey("tiles");
-    let tile_sprite = &mut display_state.sprites[&sprite_key];
+    let tile_sprite = display_state.tiles_sprite_mut();
 
     if let Some(reach) = game.data.entities.movement.get(&entity_id) {
         for move_pos in reach.reachables(entity_pos) {
@@ -1518,16 +1618,11 @@ pub fn sdl2_color(color: Color) -> Sdl2Color {
 
 
 pub fn render_entity_at(entity_id: EntityId, render_pos: Pos, game: &mut Game, panel: &mut Panel<&mut WindowCanvas>, display_state: &mut DisplayState) {
-    let entity_pos = game.data.entities.pos[&entity_id];
-
     let alpha = game.data.entities.color[&entity_id].a;
     game.data.entities.color[&entity_id].a = 200;
 
-    game.data.entities.pos[&entity_id] = render_pos;
-
-    render_entity(panel, entity_id, display_state, game);
+    render_entity_pos(panel, entity_id, render_pos, display_state, game);
 
     game.data.entities.color[&entity_id].a = alpha;
-    game.data.entities.pos[&entity_id] = entity_pos;
 }
 
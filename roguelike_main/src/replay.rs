@@ -149,6 +149,50 @@ pub fn test_recording() {
     assert_eq!(starting_pos.y + 2, step3_2_pos.y);
 }
 
+#[test]
+pub fn test_record_and_replay() {
+    let mut config = Config::from_file("../config.yaml");
+    config.map_load = MapLoadConfig::TestPlayer;
+    let seed = 3;
+    let mut game = Game::new(seed, config.clone());
+    make_map(&config.map_load, &mut game);
+
+    let actions = vec!(InputAction::Move(Direction::Right, MoveMode::Walk),
+                       InputAction::Move(Direction::Down, MoveMode::Walk),
+                       InputAction::Pass(MoveMode::Walk),
+                       InputAction::Move(Direction::DownRight, MoveMode::Walk));
+
+    // record the seed and actions in the same format as the action log
+    let mut log_text = format!("{} {}\n", Log::SEED_PREFIX, seed);
+    for action in actions.iter() {
+        game.step_game(*action, 0.1);
+        log_text.push_str(&format!("{}\n", action));
+    }
+
+    let replay_path = std::env::temp_dir().join("test_record_and_replay_action_log.txt");
+    let replay_file = replay_path.to_str().unwrap();
+    fs::write(replay_file, log_text).unwrap();
+
+    // replay the log into a fresh game
+    let replay_seed = read_action_log_seed(replay_file).unwrap();
+    assert_eq!(seed, replay_seed);
+
+    let replay_actions = read_action_log(replay_file);
+    assert_eq!(actions, replay_actions);
+
+    let mut replay_game = Game::new(replay_seed, config.clone());
+    make_map(&config.map_load, &mut replay_game);
+    for action in replay_actions {
+        replay_game.step_game(action, 0.1);
+    }
+
+    let player_id = game.data.find_by_name(EntityName::Player).unwrap();
+    let replay_player_id = replay_game.data.find_by_name(EntityName::Player).unwrap();
+    assert_eq!(game.data.entities.pos[&player_id], replay_game.data.entities.pos[&replay_player_id]);
+
+    fs::remove_file(replay_file).unwrap();
+}
+
 
 pub const MAP_CONFIG_NAME: &str = "map_config.txt";
 
@@ -413,6 +457,20 @@ pub fn read_action_log(replay_file: &str) -> Vec<InputAction> {
     return starting_actions;
 }
 
+pub fn read_action_log_seed(replay_file: &str) -> Option<u64> {
+    let file =
+        std::fs::File::open(&replay_file).expect(&format!("Could not open replay file '{}'", &replay_file));
+    for line in std::io::BufReader::new(file).lines() {
+        let line = line.unwrap();
+        let args = line.split(" ").collect::<Vec<&str>>();
+        if args.len() == 2 && args[0] == Log::SEED_PREFIX {
+            return args[1].parse::<u64>().ok();
+        }
+    }
+
+    return None;
+}
+
 pub fn read_message_log(message_file: &str) -> Vec<String> {
     let mut message_lines = Vec::new();
 
@@ -1,11 +1,51 @@
 use std::thread;
 use std::sync::mpsc::{channel, Receiver};
 use std::time::{Duration, Instant};
+use std::collections::VecDeque;
 
 
+/// The number of frames averaged over when computing the frame rate.
+pub const FRAME_STATS_WINDOW: usize = 30;
+
+/// A rolling window of recent frame durations.
+#[derive(Clone, Debug)]
+pub struct FrameStats {
+    frame_times: VecDeque<Duration>,
+    window: usize,
+}
+
+impl FrameStats {
+    pub fn new(window: usize) -> FrameStats {
+        return FrameStats { frame_times: VecDeque::with_capacity(window), window };
+    }
+
+    pub fn record(&mut self, frame_time: Duration) {
+        if self.frame_times.len() == self.window {
+            self.frame_times.pop_front();
+        }
+        self.frame_times.push_back(frame_time);
+    }
+
+    pub fn last_frame_ms(&self) -> f32 {
+        return self.frame_times.back().map_or(0.0, |frame_time| frame_time.as_secs_f32() * 1000.0);
+    }
+
+    /// The average frames per second over the recorded window, or 0 before any frames.
+    pub fn average_fps(&self) -> f32 {
+        let total: Duration = self.frame_times.iter().sum();
+        if total.as_secs_f32() == 0.0 {
+            return 0.0;
+        }
+
+        return self.frame_times.len() as f32 / total.as_secs_f32();
+    }
+}
+
 pub struct Throttler {
     tick_receiver: Receiver<usize>,
     thread: thread::JoinHandle<()>,
+    last_frame: Instant,
+    stats: FrameStats,
 }
 
 impl Throttler {
@@ -44,11 +84,49 @@ impl Throttler {
         return Throttler {
             tick_receiver,
             thread,
+            last_frame: Instant::now(),
+            stats: FrameStats::new(FRAME_STATS_WINDOW),
         };
     }
 
-    pub fn wait(&self) {
+    pub fn wait(&mut self) {
         self.tick_receiver.recv().unwrap();
+
+        let current_time = Instant::now();
+        self.stats.record(current_time.duration_since(self.last_frame));
+        self.last_frame = current_time;
+    }
+
+    pub fn last_frame_ms(&self) -> f32 {
+        return self.stats.last_frame_ms();
+    }
+
+    pub fn average_fps(&self) -> f32 {
+        return self.stats.average_fps();
+    }
+}
+
+#[test]
+fn test_frame_stats_average_fps() {
+    let mut stats = FrameStats::new(4);
+    assert_eq!(0.0, stats.average_fps());
+    assert_eq!(0.0, stats.last_frame_ms());
+
+    for _ in 0..4 {
+        stats.record(Duration::from_millis(40));
+    }
+    assert!((stats.average_fps() - 25.0).abs() < 0.01);
+    assert!((stats.last_frame_ms() - 40.0).abs() < 0.01);
+
+    // slower frames push the oldest frames out of the window
+    stats.record(Duration::from_millis(100));
+    stats.record(Duration::from_millis(100));
+    assert!((stats.last_frame_ms() - 100.0).abs() < 0.01);
+    assert!((stats.average_fps() - 4.0 / 0.28).abs() < 0.01);
+
+    for _ in 0..4 {
+        stats.record(Duration::from_millis(100));
     }
+    assert!((stats.average_fps() - 10.0).abs() < 0.01);
 }
 